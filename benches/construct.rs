@@ -0,0 +1,47 @@
+// Tree construction, against the old approach of cloning each level before
+// reducing it. Run with `cargo bench --bench construct`.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use merkle_tree::{Data, Hash, Hasher, MerkleTree, Sha256Hasher, StorageMode};
+
+
+// What `construct` used to do: copy every level into the tree, then reduce the original
+fn cloning_levels(input: &[Data]) -> Vec<Vec<Hash>> {
+    let hasher = Sha256Hasher;
+    let mut nodes = Vec::new();
+    let mut new_nodes: Vec<Hash> = input.iter().map(|leaf| hasher.hash_leaf(leaf)).collect();
+    while new_nodes.len() > 1 {
+        nodes.push(new_nodes.clone());
+        new_nodes = new_nodes
+            .chunks(2)
+            .map(|pair| match pair {
+                [left, right] => hasher.hash_node(left, right),
+                _ => pair[0].clone(),
+            })
+            .collect();
+    }
+    nodes.push(new_nodes);
+    nodes
+}
+
+
+fn bench_construct(c: &mut Criterion) {
+    let mut group = c.benchmark_group("construct");
+    for size in [1 << 10, 1 << 14, 1 << 17] {
+        let data: Vec<Data> = (0..size as u32).map(|i| i.to_be_bytes().to_vec()).collect();
+        group.bench_with_input(BenchmarkId::new("in_place", size), &data, |b, data| {
+            b.iter(|| MerkleTree::construct(black_box(data)))
+        });
+        group.bench_with_input(BenchmarkId::new("cloning_levels", size), &data, |b, data| {
+            b.iter(|| cloning_levels(black_box(data)))
+        });
+        group.bench_with_input(BenchmarkId::new("root_only", size), &data, |b, data| {
+            b.iter(|| MerkleTree::construct_with_mode(black_box(data), Sha256Hasher, StorageMode::RootOnly))
+        });
+    }
+    group.finish();
+}
+
+
+criterion_group!(benches, bench_construct);
+criterion_main!(benches);
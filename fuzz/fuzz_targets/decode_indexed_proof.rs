@@ -0,0 +1,11 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use merkle_tree::IndexedProof;
+
+fuzz_target!(|bytes: &[u8]| {
+    if let Some(proof) = IndexedProof::decode(bytes) {
+        assert_eq!(IndexedProof::decode(&proof.encode()), Some(proof.clone()));
+        assert!(proof.to_proof().is_some());
+    }
+});
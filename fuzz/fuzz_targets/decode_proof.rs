@@ -0,0 +1,13 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use merkle_tree::Proof;
+
+// Whatever decode_untrusted accepts must be canonical: it encodes back to
+// the same bytes, and the lenient decoder agrees with it
+fuzz_target!(|bytes: &[u8]| {
+    if let Ok(proof) = Proof::decode_untrusted(bytes) {
+        assert_eq!(proof.encode(), bytes);
+        assert!(Proof::decode(bytes).is_some());
+    }
+});
@@ -0,0 +1,82 @@
+#ifndef MERKLE_TREE_H
+#define MERKLE_TREE_H
+
+/* Generated by cbindgen from src/ffi.rs. Do not edit by hand. */
+
+#include <stddef.h>
+#include <stdint.h>
+
+#define MERKLE_OK 0
+
+#define MERKLE_ERR_NULL -1
+
+#define MERKLE_ERR_NOT_FOUND -2
+
+#define MERKLE_ERR_BUFFER_TOO_SMALL -3
+
+/**
+ * Opaque handle to a tree owned by the library.
+ */
+typedef struct MerkleTreeHandle MerkleTreeHandle;
+
+/**
+ * Builds a tree over `count` leaves, where leaf i is `lens[i]` bytes at `leaves[i]`.
+ * Returns NULL if there are no leaves. Free the result with `merkle_free`.
+ *
+ * # Safety
+ * `leaves` and `lens` must point to `count` valid entries, each leaf readable for its length.
+ */
+struct MerkleTreeHandle *merkle_construct(const uint8_t *const *leaves,
+                                          const size_t *lens,
+                                          size_t count);
+
+/**
+ * Releases a tree returned by `merkle_construct`. Passing NULL is a no-op.
+ *
+ * # Safety
+ * `tree` must come from `merkle_construct` and not have been freed already.
+ */
+void merkle_free(struct MerkleTreeHandle *tree);
+
+/**
+ * Writes the root hash into `out`. `out_len` receives the hash length, even when
+ * the buffer is too small.
+ *
+ * # Safety
+ * `tree` must be a live handle, `out` writable for `out_cap` bytes and `out_len` writable.
+ */
+int32_t merkle_root(const struct MerkleTreeHandle *tree,
+                    uint8_t *out,
+                    size_t out_cap,
+                    size_t *out_len);
+
+/**
+ * Writes the encoded proof for `data` into `out`. `out_len` receives the encoded
+ * length, even when the buffer is too small.
+ *
+ * # Safety
+ * `tree` must be a live handle, `data` readable for `data_len` bytes, `out` writable
+ * for `out_cap` bytes and `out_len` writable.
+ */
+int32_t merkle_prove(const struct MerkleTreeHandle *tree,
+                     const uint8_t *data,
+                     size_t data_len,
+                     uint8_t *out,
+                     size_t out_cap,
+                     size_t *out_len);
+
+/**
+ * Returns 1 if the encoded proof leads from `data` to `root`, 0 otherwise,
+ * including when the proof is malformed.
+ *
+ * # Safety
+ * Each pointer must be readable for its length.
+ */
+int32_t merkle_verify_proof(const uint8_t *data,
+                            size_t data_len,
+                            const uint8_t *proof,
+                            size_t proof_len,
+                            const uint8_t *root,
+                            size_t root_len);
+
+#endif  /* MERKLE_TREE_H */
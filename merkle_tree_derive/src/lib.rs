@@ -0,0 +1,46 @@
+// `#[derive(Hashable)]` for structs. Each field's leaf bytes are written in
+// declaration order with a u32 length prefix, so two different field
+// splits can never produce the same bytes.
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Fields, Index};
+
+
+#[proc_macro_derive(Hashable)]
+pub fn derive_hashable(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let fields = match &input.data {
+        Data::Struct(data) => &data.fields,
+        _ => {
+            return syn::Error::new_spanned(&input, "Hashable can only be derived for structs")
+                .to_compile_error()
+                .into()
+        }
+    };
+    let accessors: Vec<_> = match fields {
+        Fields::Named(named) => named.named.iter().map(|f| {
+            let ident = f.ident.as_ref().unwrap();
+            quote!(#ident)
+        }).collect(),
+        Fields::Unnamed(unnamed) => (0..unnamed.unnamed.len()).map(|i| {
+            let index = Index::from(i);
+            quote!(#index)
+        }).collect(),
+        Fields::Unit => Vec::new(),
+    };
+
+    quote! {
+        impl #impl_generics ::merkle_tree::Hashable for #name #ty_generics #where_clause {
+            fn leaf_bytes(&self) -> ::std::borrow::Cow<'_, [u8]> {
+                let mut out = ::std::vec::Vec::new();
+                #( ::merkle_tree::hashable::put_field(&mut out, &self.#accessors); )*
+                ::std::borrow::Cow::Owned(out)
+            }
+        }
+    }
+    .into()
+}
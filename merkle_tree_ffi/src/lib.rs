@@ -0,0 +1,4 @@
+// Builds the C interface in `merkle_tree::ffi` as a library to link against,
+// with include/merkle_tree.h as its header.
+
+pub use merkle_tree::ffi::*;
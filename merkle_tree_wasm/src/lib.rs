@@ -0,0 +1,3 @@
+// Builds the JavaScript bindings in `merkle_tree::wasm` as a wasm module.
+
+pub use merkle_tree::wasm::*;
@@ -0,0 +1,162 @@
+// Nodes addressed by level and position, with positions as u64 so index
+// arithmetic holds for trees past 2^32 leaves on any target, and for trees
+// whose levels never sit in memory. Level 0 is the leaves. A tree of
+// `tree_size` leaves has ceil(tree_size / 2^level) nodes on each level, the
+// last of them promoted unchanged when it has no sibling.
+//
+// Only addressing is u64. The in-memory types, `Position`, the leaf index
+// and the stored and forest trees, keep usize indices since their levels
+// are vectors, which can't hold more than usize elements anyway. Decoders
+// read indices from the wire as u64 and reject any that don't fit in usize,
+// so a 32-bit target refuses a proof about a larger tree instead of
+// truncating its index.
+
+use crate::{Hash, HashDirection, Hasher, MerkleTree};
+use std::ops::Range;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeAddress {
+    pub level: u32,
+    pub position: u64,
+}
+
+
+// Nodes on `level` of a tree of `tree_size` leaves
+pub fn level_width(tree_size: u64, level: u32) -> u64 {
+    match 1u64.checked_shl(level) {
+        Some(span) => tree_size.div_ceil(span),
+        None => tree_size.min(1),
+    }
+}
+
+
+// Levels above the leaves
+pub fn tree_depth(tree_size: u64) -> u32 {
+    if tree_size <= 1 {
+        0
+    } else {
+        u64::BITS - (tree_size - 1).leading_zeros()
+    }
+}
+
+
+// The sibling of each node on the path from leaf `index` to the root, leaf
+// first, with the side it goes on. Levels where the path is promoted are
+// skipped.
+pub fn proof_path(index: u64, tree_size: u64) -> Vec<(NodeAddress, HashDirection)> {
+    let mut path = Vec::new();
+    let mut node = NodeAddress::leaf(index);
+    while level_width(tree_size, node.level) > 1 {
+        let sibling = node.sibling();
+        if node.is_right() {
+            path.push((sibling, HashDirection::Left));
+        } else if sibling.exists_in(tree_size) {
+            path.push((sibling, HashDirection::Right));
+        }
+        node = node.parent();
+    }
+    path
+}
+
+
+impl NodeAddress {
+    pub fn leaf(index: u64) -> NodeAddress {
+        NodeAddress { level: 0, position: index }
+    }
+
+
+    pub fn parent(self) -> NodeAddress {
+        NodeAddress { level: self.level + 1, position: self.position / 2 }
+    }
+
+
+    // None for a leaf
+    pub fn children(self) -> Option<(NodeAddress, NodeAddress)> {
+        let level = self.level.checked_sub(1)?;
+        let left = self.position.checked_mul(2)?;
+        Some((NodeAddress { level, position: left }, NodeAddress { level, position: left + 1 }))
+    }
+
+
+    pub fn sibling(self) -> NodeAddress {
+        NodeAddress { position: self.position ^ 1, ..self }
+    }
+
+
+    pub fn is_right(self) -> bool {
+        self.position % 2 == 1
+    }
+
+
+    pub fn exists_in(self, tree_size: u64) -> bool {
+        self.position < level_width(tree_size, self.level)
+    }
+
+
+    // The leaves under this node, None if the tree has no such node
+    pub fn leaf_range(self, tree_size: u64) -> Option<Range<u64>> {
+        if !self.exists_in(tree_size) {
+            return None;
+        }
+        // An existing node's first leaf is below tree_size, so this can't
+        // overflow; past level 63 only position 0 exists
+        let start = self.position.checked_shl(self.level).unwrap_or(0);
+        let end = 1u64.checked_shl(self.level).map_or(tree_size, |span| start.saturating_add(span).min(tree_size));
+        Some(start..end)
+    }
+}
+
+
+impl<H: Hasher> MerkleTree<H> {
+    // The node at `address`, None if it's past this tree or on a level the
+    // storage mode didn't keep
+    pub fn node(&self, address: NodeAddress) -> Option<&Hash> {
+        let level = self.level(usize::try_from(address.level).ok()?)?;
+        level.get(usize::try_from(address.position).ok()?)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Data;
+
+
+    #[test]
+    fn test_paths_match_tree() {
+        for n in 1..=13u64 {
+            let data: Vec<Data> = (0..n as u8).map(|i| vec![i]).collect();
+            let tree = MerkleTree::construct(&data);
+            assert_eq!(tree_depth(n) as usize, tree.depth());
+            for index in 0..n {
+                let proof = tree.prove_index(index as usize).unwrap();
+                let path = proof_path(index, n);
+                assert_eq!(path.len(), proof.len());
+                for ((address, direction), (proof_direction, hash)) in path.iter().zip(&proof.hashes) {
+                    assert_eq!(direction, proof_direction);
+                    assert_eq!(tree.node(*address), Some(&**hash));
+                }
+            }
+            let top = NodeAddress { level: tree_depth(n), position: 0 };
+            assert_eq!(top.leaf_range(n), Some(0..n));
+            assert_eq!(tree.node(top), Some(&tree.root()));
+        }
+    }
+
+    #[test]
+    fn test_addresses_past_32_bits() {
+        let tree_size = (1u64 << 40) + 3;
+        let last = NodeAddress::leaf(tree_size - 1);
+        // The last leaf pairs with nothing until it meets the full left subtree
+        assert_eq!(proof_path(tree_size - 1, tree_size).len(), 2);
+        assert_eq!(proof_path(5, tree_size).len(), 41);
+        assert_eq!(tree_depth(tree_size), 41);
+        assert_eq!(level_width(tree_size, 40), 2);
+        assert_eq!(level_width(tree_size, 64), 1);
+        assert_eq!(last.parent().parent().leaf_range(tree_size), Some((1 << 40)..tree_size));
+        assert!(!last.sibling().exists_in(tree_size));
+        assert_eq!(NodeAddress { level: 1, position: (1 << 39) + 2 }.children().unwrap().1.leaf_range(tree_size), None);
+    }
+}
@@ -0,0 +1,122 @@
+// Construction from async sources, for trees built over downloads or object
+// store reads. Leaves are hashed as they arrive and the task yields to the
+// runtime every few hundred leaves, so a large build shares its worker thread
+// instead of holding it. Proving from a tree that only kept its leaves can
+// cost O(n) hashes, so that runs on the blocking pool.
+
+use crate::{Data, Error, Hasher, MerkleTree, Proof, TreeBuilder};
+use std::io;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio_stream::{Stream, StreamExt};
+
+
+// Leaves hashed between yields back to the runtime
+const YIELD_EVERY: usize = 256;
+
+
+impl<H: Hasher> MerkleTree<H> {
+    // Splits everything `reader` produces into `chunk_size` leaves. An empty
+    // reader gives one empty leaf, as `MerkleWriter` does. `progress` gets
+    // the number of leaves hashed so far every time the task yields.
+    pub async fn construct_from_reader<R: AsyncRead + Unpin>(
+        mut reader: R,
+        chunk_size: usize,
+        hasher: H,
+        mut progress: impl FnMut(usize),
+    ) -> io::Result<MerkleTree<H>> {
+        assert!(chunk_size > 0, "chunk size must be positive");
+        let mut builder = TreeBuilder::with_hasher(hasher);
+        let mut chunk = vec![0; chunk_size];
+        loop {
+            let mut filled = 0;
+            while filled < chunk_size {
+                match reader.read(&mut chunk[filled..]).await? {
+                    0 => break,
+                    n => filled += n,
+                }
+            }
+            if filled > 0 || builder.leaf_count() == 0 {
+                builder.push(&chunk[..filled].to_vec());
+                pause(builder.leaf_count(), &mut progress).await;
+            }
+            if filled < chunk_size {
+                break;
+            }
+        }
+        progress(builder.leaf_count());
+        Ok(builder.finish())
+    }
+
+
+    // Uses each item of `leaves` as a leaf
+    pub async fn construct_from_stream<S: Stream<Item = Data> + Unpin>(
+        mut leaves: S,
+        hasher: H,
+        mut progress: impl FnMut(usize),
+    ) -> MerkleTree<H> {
+        let mut builder = TreeBuilder::with_hasher(hasher);
+        while let Some(leaf) = leaves.next().await {
+            builder.push(&leaf);
+            pause(builder.leaf_count(), &mut progress).await;
+        }
+        progress(builder.leaf_count());
+        builder.finish()
+    }
+}
+
+
+async fn pause(leaves_hashed: usize, progress: &mut impl FnMut(usize)) {
+    if leaves_hashed.is_multiple_of(YIELD_EVERY) {
+        progress(leaves_hashed);
+        tokio::task::yield_now().await;
+    }
+}
+
+
+// Proves the leaf at `index` on the blocking pool, leaving the async
+// workers free while the siblings are hashed
+pub async fn prove_in_background<H>(tree: Arc<MerkleTree<H>>, index: usize) -> Result<Proof<'static>, Error>
+where
+    H: Hasher + Send + Sync + 'static,
+{
+    let task = tokio::task::spawn_blocking(move || tree.prove_recompute(index).map(Proof::into_owned));
+    task.await.unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Sha256Hasher, StorageMode};
+
+
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(future)
+    }
+
+    #[test]
+    fn test_construct_from_reader() {
+        let file: Vec<u8> = (0..70_000u32).map(|i| (i % 256) as u8).collect();
+        let mut reports = Vec::new();
+        let tree = block_on(MerkleTree::construct_from_reader(&file[..], 64, Sha256Hasher, |n| reports.push(n))).unwrap();
+        let chunks: Vec<Data> = file.chunks(64).map(<[u8]>::to_vec).collect();
+        assert_eq!(tree.root(), MerkleTree::construct(&chunks).root());
+        assert_eq!(reports, [256, 512, 768, 1024, 1094]);
+
+        let empty = block_on(MerkleTree::construct_from_reader(&[][..], 64, Sha256Hasher, |_| {})).unwrap();
+        assert_eq!(empty.root(), MerkleTree::construct(&[Data::new()]).root());
+    }
+
+    #[test]
+    fn test_construct_from_stream_and_prove() {
+        let data: Vec<Data> = (0..300u16).map(|i| i.to_be_bytes().to_vec()).collect();
+        let tree = block_on(MerkleTree::construct_from_stream(tokio_stream::iter(data.clone()), Sha256Hasher, |_| {}));
+        assert_eq!(tree.root(), MerkleTree::construct(&data).root());
+
+        let tree = Arc::new(MerkleTree::construct_with_mode(&data, Sha256Hasher, StorageMode::LeavesOnly));
+        let proof = block_on(prove_in_background(tree.clone(), 123)).unwrap();
+        assert!(MerkleTree::verify_proof(&data[123], &proof, &tree.root()));
+        assert!(block_on(prove_in_background(tree, 300)).is_err());
+    }
+}
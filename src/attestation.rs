@@ -0,0 +1,285 @@
+// A compact statement of a tree head for services to gossip and compare:
+// the root, the tree size, which hash function built it and how. Two heads
+// only describe the same tree when all four agree, so a SHA-256 root is
+// never mistaken for a Poseidon one of the same length, nor a padded tree
+// for a promoted one.
+//
+//   magic "MTRA" | version: u8 | hasher id: u16 | flags: u8 |
+//   tree size: u64 | root length: u8 | root
+//
+// Integers are big endian. Decoding is strict: unknown versions, hasher
+// ids and flag bits, roots of the wrong length for the hasher and trailing
+// bytes are all rejected rather than skipped.
+
+use crate::ct::hashes_equal;
+use crate::{Hash, Hasher, MerkleTree};
+use std::fmt;
+
+
+const MAGIC: &[u8; 4] = b"MTRA";
+const VERSION: u8 = 1;
+const FIXED_LEN: usize = 4 + 1 + 2 + 1 + 8 + 1;
+
+
+// Hash functions by wire id. Ids from 0x8000 up are for private use and are
+// passed through with no length check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HasherId {
+    Sha256,
+    Tiger,
+    Poseidon,
+    Keccak256,
+    Sha3_256,
+    Private(u16),
+}
+
+
+impl HasherId {
+    pub fn to_u16(self) -> u16 {
+        match self {
+            HasherId::Sha256 => 1,
+            HasherId::Tiger => 2,
+            HasherId::Poseidon => 3,
+            HasherId::Keccak256 => 4,
+            HasherId::Sha3_256 => 5,
+            HasherId::Private(id) => id,
+        }
+    }
+
+
+    pub fn from_u16(id: u16) -> Option<HasherId> {
+        match id {
+            1 => Some(HasherId::Sha256),
+            2 => Some(HasherId::Tiger),
+            3 => Some(HasherId::Poseidon),
+            4 => Some(HasherId::Keccak256),
+            5 => Some(HasherId::Sha3_256),
+            0x8000.. => Some(HasherId::Private(id)),
+            _ => None,
+        }
+    }
+
+
+    // Length of the roots this hasher makes, if known
+    pub fn hash_len(self) -> Option<usize> {
+        match self {
+            HasherId::Sha256 | HasherId::Poseidon | HasherId::Keccak256 | HasherId::Sha3_256 => Some(32),
+            HasherId::Tiger => Some(24),
+            HasherId::Private(_) => None,
+        }
+    }
+}
+
+
+// How the tree was shaped and hashed, one bit each
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PolicyFlags(u8);
+
+
+impl PolicyFlags {
+    // Leaves and nodes hashed with the RFC 6962 prefixes
+    pub const DOMAIN_SEPARATED: PolicyFlags = PolicyFlags(0x01);
+    // An odd last node carried up a level rather than paired
+    pub const PROMOTE_ODD: PolicyFlags = PolicyFlags(0x02);
+    // Leaves padded with empty subtrees to a power of two
+    pub const PADDED: PolicyFlags = PolicyFlags(0x04);
+    const KNOWN: u8 = 0x07;
+
+
+    pub fn empty() -> PolicyFlags {
+        PolicyFlags(0)
+    }
+
+
+    pub fn bits(self) -> u8 {
+        self.0
+    }
+
+
+    // None if any reserved bit is set
+    pub fn from_bits(bits: u8) -> Option<PolicyFlags> {
+        (bits & !Self::KNOWN == 0).then_some(PolicyFlags(bits))
+    }
+
+
+    pub fn contains(self, other: PolicyFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+
+    pub fn union(self, other: PolicyFlags) -> PolicyFlags {
+        PolicyFlags(self.0 | other.0)
+    }
+
+
+    // The flags a `MerkleTree` built with `hasher` has
+    pub(crate) fn for_hasher<H: Hasher>(hasher: &H) -> PolicyFlags {
+        if hasher.domain_separated() {
+            PolicyFlags::DOMAIN_SEPARATED.union(PolicyFlags::PROMOTE_ODD)
+        } else {
+            PolicyFlags::PROMOTE_ODD
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AttestationError {
+    Truncated,
+    BadMagic,
+    UnsupportedVersion(u8),
+    UnknownHasher(u16),
+    ReservedFlags(u8),
+    RootLength { expected: usize, found: usize },
+    TrailingBytes,
+}
+
+
+impl fmt::Display for AttestationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AttestationError::Truncated => write!(f, "attestation is truncated"),
+            AttestationError::BadMagic => write!(f, "not an attestation"),
+            AttestationError::UnsupportedVersion(version) => write!(f, "unsupported attestation version {}", version),
+            AttestationError::UnknownHasher(id) => write!(f, "unknown hasher id {}", id),
+            AttestationError::ReservedFlags(bits) => write!(f, "reserved flag bits set in {:#04x}", bits),
+            AttestationError::RootLength { expected, found } => {
+                write!(f, "root is {} bytes but the hasher makes {}", found, expected)
+            }
+            AttestationError::TrailingBytes => write!(f, "bytes after the end of the attestation"),
+        }
+    }
+}
+
+
+impl std::error::Error for AttestationError {}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attestation {
+    pub root: Hash,
+    pub tree_size: u64,
+    pub hasher: HasherId,
+    pub flags: PolicyFlags,
+}
+
+
+impl Attestation {
+    pub fn encode(&self) -> Vec<u8> {
+        assert!(self.root.len() <= u8::MAX as usize, "root too long to attest");
+        let mut out = Vec::with_capacity(FIXED_LEN + self.root.len());
+        out.extend_from_slice(MAGIC);
+        out.push(VERSION);
+        out.extend_from_slice(&self.hasher.to_u16().to_be_bytes());
+        out.push(self.flags.bits());
+        out.extend_from_slice(&self.tree_size.to_be_bytes());
+        out.push(self.root.len() as u8);
+        out.extend_from_slice(&self.root);
+        out
+    }
+
+
+    pub fn decode(bytes: &[u8]) -> Result<Attestation, AttestationError> {
+        if bytes.len() < FIXED_LEN {
+            return Err(AttestationError::Truncated);
+        }
+        if &bytes[..4] != MAGIC {
+            return Err(AttestationError::BadMagic);
+        }
+        if bytes[4] != VERSION {
+            return Err(AttestationError::UnsupportedVersion(bytes[4]));
+        }
+        let id = u16::from_be_bytes([bytes[5], bytes[6]]);
+        let hasher = HasherId::from_u16(id).ok_or(AttestationError::UnknownHasher(id))?;
+        let flags = PolicyFlags::from_bits(bytes[7]).ok_or(AttestationError::ReservedFlags(bytes[7]))?;
+        let tree_size = u64::from_be_bytes(bytes[8..16].try_into().unwrap());
+        let root_len = bytes[16] as usize;
+        if let Some(expected) = hasher.hash_len().filter(|&expected| expected != root_len) {
+            return Err(AttestationError::RootLength { expected, found: root_len });
+        }
+        let root = bytes[FIXED_LEN..].get(..root_len).ok_or(AttestationError::Truncated)?;
+        if bytes.len() > FIXED_LEN + root_len {
+            return Err(AttestationError::TrailingBytes);
+        }
+        Ok(Attestation { root: root.to_vec(), tree_size, hasher, flags })
+    }
+
+
+    // Whether both describe the same tree: same configuration, size and root
+    pub fn matches(&self, other: &Attestation) -> bool {
+        self.hasher == other.hasher
+            && self.flags == other.flags
+            && self.tree_size == other.tree_size
+            && hashes_equal(&self.root, &other.root)
+    }
+}
+
+
+impl<H: Hasher> MerkleTree<H> {
+    // Attests to this tree's head. The hash function can't be told from the
+    // hasher's type, so the caller names it; whether leaves and nodes are
+    // domain separated is taken from the hasher.
+    pub fn attest(&self, hasher: HasherId) -> Attestation {
+        Attestation {
+            root: self.root(),
+            tree_size: self.leaf_count() as u64,
+            hasher,
+            flags: PolicyFlags::for_hasher(&self.hasher),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Data, Legacy, Sha256Hasher};
+
+
+    #[test]
+    fn test_attestation_roundtrip() {
+        let data: Vec<Data> = (0..6u8).map(|i| vec![i]).collect();
+        let attestation = MerkleTree::construct(&data).attest(HasherId::Sha256);
+        let encoded = attestation.encode();
+        assert_eq!(encoded.len(), FIXED_LEN + 32);
+        let decoded = Attestation::decode(&encoded).unwrap();
+        assert_eq!(decoded, attestation);
+        assert!(decoded.matches(&attestation));
+
+        let other = MerkleTree::construct(&data[..5]).attest(HasherId::Sha256);
+        assert!(!other.matches(&attestation));
+        let padded = Attestation { flags: attestation.flags.union(PolicyFlags::PADDED), ..attestation.clone() };
+        assert!(!padded.matches(&attestation));
+
+        let private = Attestation { hasher: HasherId::Private(0x8001), root: vec![7; 5], ..attestation };
+        assert_eq!(Attestation::decode(&private.encode()), Ok(private));
+    }
+
+    #[test]
+    fn test_attestation_flags_follow_hasher() {
+        let data: Vec<Data> = (0..4u8).map(|i| vec![i]).collect();
+        let legacy = MerkleTree::construct_with(&data, Legacy(Sha256Hasher)).attest(HasherId::Sha256);
+        assert_eq!(legacy.flags, PolicyFlags::PROMOTE_ODD);
+        let current = MerkleTree::construct(&data).attest(HasherId::Sha256);
+        assert!(current.flags.contains(PolicyFlags::DOMAIN_SEPARATED));
+        assert!(!legacy.matches(&Attestation { root: legacy.root.clone(), ..current }));
+    }
+
+    #[test]
+    fn test_decode_is_strict() {
+        let data: Vec<Data> = (0..3u8).map(|i| vec![i]).collect();
+        let encoded = MerkleTree::construct(&data).attest(HasherId::Sha256).encode();
+        let with = |i: usize, byte: u8| {
+            let mut bytes = encoded.clone();
+            bytes[i] = byte;
+            Attestation::decode(&bytes)
+        };
+        assert_eq!(with(0, b'X'), Err(AttestationError::BadMagic));
+        assert_eq!(with(4, 2), Err(AttestationError::UnsupportedVersion(2)));
+        assert_eq!(with(6, 9), Err(AttestationError::UnknownHasher(9)));
+        assert_eq!(with(7, 0x83), Err(AttestationError::ReservedFlags(0x83)));
+        assert_eq!(with(6, 2), Err(AttestationError::RootLength { expected: 24, found: 32 }));
+        assert_eq!(Attestation::decode(&encoded[..encoded.len() - 1]), Err(AttestationError::Truncated));
+        assert_eq!(Attestation::decode(&[encoded.clone(), vec![0]].concat()), Err(AttestationError::TrailingBytes));
+    }
+}
@@ -0,0 +1,219 @@
+// Remote integrity checks. An auditor who trusts a root sends a challenge
+// naming some leaves and a fresh nonce; the storer answers with those
+// leaves, their proofs, and a binding hash over the nonce and the leaves.
+// Each nonce is accepted once and only before its deadline, so an old
+// answer can't be replayed, and the binding ties every answer to the
+// challenge it was made for.
+
+use crate::ct::hashes_equal;
+use crate::sampling::sample_indices;
+use crate::strict::StrictError;
+use crate::{Data, Hash, Hasher, MerkleTree, Proof, Sha256Hasher};
+use sha2::Digest;
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Instant;
+
+
+const BINDING_TAG: &[u8] = b"merkle_tree audit v1";
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Challenge {
+    pub indices: Vec<usize>,
+    pub nonce: Vec<u8>,
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditResponse {
+    pub nonce: Vec<u8>,
+    // One leaf and proof per challenged index, in the challenge's order
+    pub items: Vec<(Data, Proof<'static>)>,
+    pub binding: Hash,
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AuditError {
+    // No outstanding challenge has this nonce, or it was already answered
+    UnknownNonce,
+    Expired,
+    WrongCount { len: usize, expected: usize },
+    BadBinding,
+    // The leaf for challenged index `index` didn't prove
+    NotIncluded { index: usize, reason: StrictError },
+    // The proof for challenged index `index` is for another leaf
+    WrongLeaf { index: usize },
+}
+
+
+impl fmt::Display for AuditError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AuditError::UnknownNonce => write!(f, "no outstanding challenge has this nonce"),
+            AuditError::Expired => write!(f, "the challenge expired before the response"),
+            AuditError::WrongCount { len, expected } => write!(f, "response has {} leaves but {} were asked for", len, expected),
+            AuditError::BadBinding => write!(f, "response isn't bound to the challenge"),
+            AuditError::NotIncluded { index, reason } => write!(f, "leaf {} isn't in the tree: {}", index, reason),
+            AuditError::WrongLeaf { index } => write!(f, "response for leaf {} proves another leaf", index),
+        }
+    }
+}
+
+
+impl std::error::Error for AuditError {}
+
+
+// SHA-256 over the tag, the nonce, and each index with its leaf
+pub fn binding_hash(nonce: &[u8], indices: &[usize], leaves: &[Data]) -> Hash {
+    let mut hasher = sha2::Sha256::new();
+    hasher.update(BINDING_TAG);
+    hasher.update((nonce.len() as u64).to_be_bytes());
+    hasher.update(nonce);
+    for (index, leaf) in indices.iter().zip(leaves) {
+        hasher.update((*index as u64).to_be_bytes());
+        hasher.update((leaf.len() as u64).to_be_bytes());
+        hasher.update(leaf);
+    }
+    hasher.finalize().to_vec()
+}
+
+
+pub struct Auditor<H = Sha256Hasher> {
+    root: Hash,
+    tree_size: usize,
+    hasher: H,
+    // Challenges not yet answered, by nonce, with their deadlines
+    outstanding: HashMap<Vec<u8>, (Challenge, Instant)>,
+}
+
+
+impl Auditor {
+    pub fn new(root: Hash, tree_size: usize) -> Auditor {
+        Self::with_hasher(root, tree_size, Sha256Hasher)
+    }
+}
+
+
+impl<H: Hasher> Auditor<H> {
+    pub fn with_hasher(root: Hash, tree_size: usize, hasher: H) -> Auditor<H> {
+        Auditor { root, tree_size, hasher, outstanding: HashMap::new() }
+    }
+
+
+    // Challenges the given leaves. The nonce must be unpredictable to the
+    // storer, such as 32 random bytes; None if it's already outstanding.
+    pub fn issue(&mut self, indices: Vec<usize>, nonce: Vec<u8>, deadline: Instant) -> Option<Challenge> {
+        if self.outstanding.contains_key(&nonce) {
+            return None;
+        }
+        let challenge = Challenge { indices, nonce };
+        self.outstanding.insert(challenge.nonce.clone(), (challenge.clone(), deadline));
+        Some(challenge)
+    }
+
+
+    // Challenges `k` leaves picked from the nonce, as `sample_indices` picks
+    pub fn issue_sampled(&mut self, k: usize, nonce: Vec<u8>, deadline: Instant) -> Option<Challenge> {
+        let indices = sample_indices(&self.root, self.tree_size, &nonce, k);
+        self.issue(indices, nonce, deadline)
+    }
+
+
+    pub fn outstanding(&self) -> usize {
+        self.outstanding.len()
+    }
+
+
+    // Checks a response received at `now`. The challenge is used up either
+    // way, so a failed response can't be retried.
+    pub fn check(&mut self, response: &AuditResponse, now: Instant) -> Result<(), AuditError> {
+        let (challenge, deadline) = self.outstanding.remove(&response.nonce).ok_or(AuditError::UnknownNonce)?;
+        if now > deadline {
+            return Err(AuditError::Expired);
+        }
+        if response.items.len() != challenge.indices.len() {
+            return Err(AuditError::WrongCount { len: response.items.len(), expected: challenge.indices.len() });
+        }
+        let leaves: Vec<Data> = response.items.iter().map(|(leaf, _)| leaf.clone()).collect();
+        if !hashes_equal(&binding_hash(&challenge.nonce, &challenge.indices, &leaves), &response.binding) {
+            return Err(AuditError::BadBinding);
+        }
+        for (&index, (leaf, proof)) in challenge.indices.iter().zip(&response.items) {
+            MerkleTree::verify_proof_strict_with(leaf, proof, &self.root, &self.hasher)
+                .map_err(|reason| AuditError::NotIncluded { index, reason })?;
+            if proof.position().is_none_or(|p| p.index != index || p.tree_size != self.tree_size) {
+                return Err(AuditError::WrongLeaf { index });
+            }
+        }
+        Ok(())
+    }
+}
+
+
+impl<H: Hasher> MerkleTree<H> {
+    // Answers a challenge from the leaf data this tree was built over. None
+    // if an index is past the tree.
+    pub fn answer_challenge(&self, challenge: &Challenge, data: &[Data]) -> Option<AuditResponse> {
+        let mut items = Vec::with_capacity(challenge.indices.len());
+        for &index in &challenge.indices {
+            let proof = self.prove_index(index)?.into_owned().with_position(index, self.leaf_count);
+            items.push((data.get(index)?.clone(), proof));
+        }
+        let leaves: Vec<Data> = items.iter().map(|(leaf, _)| leaf.clone()).collect();
+        let binding = binding_hash(&challenge.nonce, &challenge.indices, &leaves);
+        Some(AuditResponse { nonce: challenge.nonce.clone(), items, binding })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+
+    fn setup() -> (Vec<Data>, MerkleTree, Auditor, Instant) {
+        let data: Vec<Data> = (0..20u8).map(|i| vec![i; 4]).collect();
+        let tree = MerkleTree::construct(&data);
+        let auditor = Auditor::new(tree.root(), data.len());
+        (data, tree, auditor, Instant::now() + Duration::from_secs(60))
+    }
+
+    #[test]
+    fn test_audit_roundtrip() {
+        let (data, tree, mut auditor, deadline) = setup();
+        let challenge = auditor.issue_sampled(5, b"nonce-1".to_vec(), deadline).unwrap();
+        assert_eq!(challenge.indices.len(), 5);
+        assert!(auditor.issue(vec![0], b"nonce-1".to_vec(), deadline).is_none());
+        let response = tree.answer_challenge(&challenge, &data).unwrap();
+        assert_eq!(auditor.check(&response, Instant::now()), Ok(()));
+        // Answered once, so a replay finds nothing outstanding
+        assert_eq!(auditor.check(&response, Instant::now()), Err(AuditError::UnknownNonce));
+        assert_eq!(auditor.outstanding(), 0);
+    }
+
+    #[test]
+    fn test_audit_rejections() {
+        let (data, tree, mut auditor, deadline) = setup();
+        let old = auditor.issue(vec![3, 7], b"old".to_vec(), deadline).unwrap();
+        let old_response = tree.answer_challenge(&old, &data).unwrap();
+
+        // An answer to one challenge presented for another
+        auditor.issue(vec![3, 7], b"new".to_vec(), deadline).unwrap();
+        let replayed = AuditResponse { nonce: b"new".to_vec(), ..old_response.clone() };
+        assert_eq!(auditor.check(&replayed, Instant::now()), Err(AuditError::BadBinding));
+
+        assert_eq!(auditor.check(&old_response, deadline + Duration::from_secs(1)), Err(AuditError::Expired));
+
+        let challenge = auditor.issue(vec![3, 7], b"swap".to_vec(), deadline).unwrap();
+        let mut swapped = tree.answer_challenge(&challenge, &data).unwrap();
+        swapped.items.swap(0, 1);
+        let leaves: Vec<Data> = vec![data[3].clone(), data[7].clone()];
+        swapped.items[0].0 = leaves[0].clone();
+        swapped.items[1].0 = leaves[1].clone();
+        swapped.binding = binding_hash(b"swap", &[3, 7], &leaves);
+        assert!(matches!(auditor.check(&swapped, Instant::now()), Err(AuditError::NotIncluded { index: 3, .. })));
+    }
+}
@@ -0,0 +1,95 @@
+// Which SHA-256 implementation is in use. All hashing goes through the
+// `sha2` crate, which switches to SHA-NI or the ARMv8 SHA-2 instructions at
+// runtime when the CPU has them. The `soft-sha256` feature builds `sha2`
+// with `force-soft` instead, for machines where the accelerated path must
+// be avoided; that choice is made at build time, as `sha2` offers no way to
+// make it at runtime. `Sha256Hasher` is the one SHA-256 hasher either way,
+// and reports the backend selected here.
+
+use std::sync::OnceLock;
+
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CpuFeatures {
+    pub sha_ni: bool,
+    pub arm_sha2: bool,
+}
+
+
+impl CpuFeatures {
+    #[allow(unused_mut)]
+    pub fn detect() -> CpuFeatures {
+        let mut features = CpuFeatures::default();
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        {
+            features.sha_ni = std::is_x86_feature_detected!("sha");
+        }
+        #[cfg(target_arch = "aarch64")]
+        {
+            features.arm_sha2 = std::arch::is_aarch64_feature_detected!("sha2");
+        }
+        features
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Sha256Backend {
+    ShaNi,
+    ArmSha2,
+    Software,
+}
+
+
+impl Sha256Backend {
+    // The backend `sha2` uses on this CPU, as built
+    pub fn detect() -> Sha256Backend {
+        let features = CpuFeatures::detect();
+        if cfg!(feature = "soft-sha256") {
+            Sha256Backend::Software
+        } else if features.sha_ni {
+            Sha256Backend::ShaNi
+        } else if features.arm_sha2 {
+            Sha256Backend::ArmSha2
+        } else {
+            Sha256Backend::Software
+        }
+    }
+
+
+    // `detect`, run once per process, since the CPU doesn't change
+    pub fn selected() -> Sha256Backend {
+        static SELECTED: OnceLock<Sha256Backend> = OnceLock::new();
+        *SELECTED.get_or_init(Sha256Backend::detect)
+    }
+
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Sha256Backend::ShaNi => "sha-ni",
+            Sha256Backend::ArmSha2 => "armv8-sha2",
+            Sha256Backend::Software => "software",
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Counting, Data, Hasher, Layered, MerkleTree, Sha256Hasher};
+
+
+    #[test]
+    fn test_backend_selection() {
+        let data: Vec<Data> = (0..6u8).map(|i| vec![i]).collect();
+        assert_eq!(Sha256Backend::selected(), Sha256Backend::detect());
+        if cfg!(feature = "soft-sha256") {
+            assert_eq!(Sha256Backend::selected(), Sha256Backend::Software);
+        }
+        assert_eq!(Sha256Hasher.backend(), Sha256Backend::selected().name());
+        let tree = MerkleTree::construct_with(&data, Layered::new(Sha256Hasher, Counting::new()));
+        assert_eq!(tree.root(), MerkleTree::construct(&data).root());
+        assert_eq!(tree.hasher().stats().backend, Some(Sha256Backend::selected().name()));
+    }
+}
@@ -0,0 +1,62 @@
+// Proving many leaves at once. The levels are looked up, or rebuilt from
+// the leaves, once for the whole batch instead of once per proof, and with
+// the `rayon` feature the paths are collected in parallel. Each path goes
+// through `path_proof`, which bounds its length as `prove` does.
+
+use crate::{path_proof, Error, Hash, Hasher, MerkleTree, Proof};
+use std::borrow::Cow;
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+
+impl<H: Hasher> MerkleTree<H> {
+    // Proofs for the leaves at `indices`, in the same order. Fails on the
+    // first index out of range, or if the storage mode kept only the root.
+    pub fn prove_many(&self, indices: &[usize]) -> Result<Vec<Proof<'_>>, Error> {
+        if let Some(&index) = indices.iter().find(|&&index| index >= self.leaf_count) {
+            return Err(Error::IndexOutOfRange { index, leaf_count: self.leaf_count });
+        }
+        Ok(match self.levels().ok_or(Error::NotStored)? {
+            Cow::Borrowed(levels) => paths(levels, indices),
+            // Levels rebuilt from the leaves don't outlive this call
+            Cow::Owned(levels) => paths(&levels, indices).into_iter().map(Proof::into_owned).collect(),
+        })
+    }
+}
+
+
+#[cfg(feature = "rayon")]
+fn paths<'a>(levels: &'a [Vec<Hash>], indices: &[usize]) -> Vec<Proof<'a>> {
+    indices.par_iter().map(|&index| path_proof(levels, index)).collect()
+}
+
+
+#[cfg(not(feature = "rayon"))]
+fn paths<'a>(levels: &'a [Vec<Hash>], indices: &[usize]) -> Vec<Proof<'a>> {
+    indices.iter().map(|&index| path_proof(levels, index)).collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{max_proof_len, Data, Sha256Hasher, StorageMode};
+
+
+    #[test]
+    fn test_prove_many() {
+        let data: Vec<Data> = (0..200u8).map(|i| vec![i]).collect();
+        let indices: Vec<usize> = (0..200).rev().step_by(7).collect();
+        for mode in [StorageMode::Full, StorageMode::LeavesOnly] {
+            let tree = MerkleTree::construct_with_mode(&data, Sha256Hasher, mode);
+            let proofs = tree.prove_many(&indices).unwrap();
+            for (&index, proof) in indices.iter().zip(&proofs) {
+                assert_eq!(proof.hashes, tree.prove(&data[index]).unwrap().hashes);
+                assert!(proof.len() <= max_proof_len(200));
+            }
+            assert_eq!(tree.prove_many(&[3, 200]).err(), Some(Error::IndexOutOfRange { index: 200, leaf_count: 200 }));
+        }
+        let root_only = MerkleTree::construct_with_mode(&data, Sha256Hasher, StorageMode::RootOnly);
+        assert_eq!(root_only.prove_many(&[0]).err(), Some(Error::NotStored));
+    }
+}
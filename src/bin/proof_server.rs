@@ -0,0 +1,70 @@
+// A small HTTP proof service over a tree loaded at startup from a file
+// written by `MerkleTree::export_leaf_hashes`:
+//
+//   proof-server <leaf hashes file> [address]
+//
+//   GET /root                              root hash, hex
+//   GET /prove/{index}                     proof for leaf `index`, hex of `Proof::encode`
+//   GET /verify?data=<hex>&proof=<hex>     "true" or "false" against the served root
+//
+// Proofs claim their position and are cached, so hot leaves are proven once.
+
+use axum::extract::{Path, RawQuery, State};
+use axum::http::StatusCode;
+use axum::routing::get;
+use axum::Router;
+use merkle_tree::{CachedProver, MerkleTree, Proof, Sha256Hasher};
+use std::sync::Arc;
+
+
+const CACHED_PROOFS: usize = 4096;
+
+
+type Prover = Arc<CachedProver<Sha256Hasher>>;
+
+
+async fn root(State(prover): State<Prover>) -> String {
+    prover.tree().root_hex()
+}
+
+
+async fn prove(State(prover): State<Prover>, Path(index): Path<usize>) -> Result<String, StatusCode> {
+    let proof = prover.prove(index).ok_or(StatusCode::NOT_FOUND)?;
+    Ok(proof.to_hex())
+}
+
+
+async fn verify(State(prover): State<Prover>, RawQuery(query): RawQuery) -> Result<String, StatusCode> {
+    let (mut data, mut proof) = (None, None);
+    for pair in query.unwrap_or_default().split('&') {
+        match pair.split_once('=') {
+            Some(("data", value)) => data = hex::decode(value).ok(),
+            Some(("proof", value)) => proof = Proof::from_hex(value),
+            _ => {}
+        }
+    }
+    let (data, proof) = data.zip(proof).ok_or(StatusCode::BAD_REQUEST)?;
+    Ok(MerkleTree::verify_leaf(&data, &proof, &prover.tree().root_hash()).to_string())
+}
+
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = std::env::args().skip(1);
+    let path = args.next().ok_or("usage: proof-server <leaf hashes file> [address]")?;
+    let address = args.next().unwrap_or_else(|| "127.0.0.1:3000".to_string());
+
+    let file = std::io::BufReader::new(std::fs::File::open(&path)?);
+    let tree = MerkleTree::import_leaf_hashes(file, Sha256Hasher)?;
+    eprintln!("serving {} on {}", tree, address);
+    let prover = Arc::new(CachedProver::new(tree, CACHED_PROOFS));
+
+    let app = Router::new()
+        .route("/root", get(root))
+        .route("/prove/{index}", get(prove))
+        .route("/verify", get(verify))
+        .with_state(prover);
+    let listener = tokio::net::TcpListener::bind(&address).await?;
+    axum::serve(listener, app).await?;
+    Ok(())
+}
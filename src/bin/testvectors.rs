@@ -0,0 +1,7 @@
+// Writes this crate's cross-implementation test vectors as JSON:
+//
+//   testvectors > vectors.json
+
+fn main() -> std::io::Result<()> {
+    merkle_tree::testvectors::write_json(std::io::stdout().lock())
+}
@@ -0,0 +1,266 @@
+// Incremental construction with checkpoints. The builder hashes each pair
+// of nodes as soon as both exist, so at any moment it holds every complete
+// node of the final tree. A checkpoint persists those levels; resuming from
+// one skips all the hashing done before it.
+//
+// Checkpoint layout:
+//   magic "MTCK" | version: u8 | hasher id: u32-prefixed hash | level count: u32 |
+//   per level: node count: u32, then each hash with a u32 length prefix |
+//   SHA-256 of everything before it
+//
+// The hasher id is `hasher_id` of the builder's hasher. A checkpoint
+// resumed with another hasher would otherwise finish into a mixed tree.
+
+use crate::encoding::{put_bytes, take_hash};
+use crate::{hasher_id, Data, Hash, Hasher, MerkleTree, Sha256Hasher};
+use sha2::Digest;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+
+const MAGIC: &[u8; 4] = b"MTCK";
+const VERSION: u8 = 2;
+
+
+pub struct TreeBuilder<H = Sha256Hasher> {
+    // Complete nodes at each level, leaves first
+    levels: Vec<Vec<Hash>>,
+    hasher: H,
+}
+
+
+#[derive(Debug)]
+pub enum CheckpointError {
+    Io(io::Error),
+    // The checkpoint failed its integrity checks
+    Corrupt(&'static str),
+    // The checkpoint was written with a different hasher
+    HasherMismatch,
+}
+
+
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CheckpointError::Io(e) => write!(f, "checkpoint io error: {}", e),
+            CheckpointError::Corrupt(reason) => write!(f, "corrupt checkpoint: {}", reason),
+            CheckpointError::HasherMismatch => write!(f, "checkpoint was written with a different hasher"),
+        }
+    }
+}
+
+
+impl std::error::Error for CheckpointError {}
+
+
+impl From<io::Error> for CheckpointError {
+    fn from(e: io::Error) -> CheckpointError {
+        CheckpointError::Io(e)
+    }
+}
+
+
+impl TreeBuilder {
+    pub fn new() -> TreeBuilder {
+        Self::with_hasher(Sha256Hasher)
+    }
+}
+
+
+impl Default for TreeBuilder {
+    fn default() -> TreeBuilder {
+        TreeBuilder::new()
+    }
+}
+
+
+impl<H: Hasher> TreeBuilder<H> {
+    pub fn with_hasher(hasher: H) -> TreeBuilder<H> {
+        TreeBuilder { levels: vec![Vec::new()], hasher }
+    }
+
+
+    // Hashes one more leaf, and any parents it completes
+    pub fn push(&mut self, data: &Data) {
+        let leaf_hash = self.hasher.hash_leaf(data);
+        self.push_hash(leaf_hash);
+    }
+
+
+    // Appends an already hashed leaf
+    pub fn push_hash(&mut self, leaf_hash: Hash) {
+        self.levels[0].push(leaf_hash);
+        let mut level = 0;
+        while self.levels[level].len().is_multiple_of(2) {
+            let nodes = &self.levels[level];
+            let parent = self.hasher.hash_node(&nodes[nodes.len() - 2], &nodes[nodes.len() - 1]);
+            if self.levels.len() == level + 1 {
+                self.levels.push(Vec::new());
+            }
+            self.levels[level + 1].push(parent);
+            level += 1;
+        }
+    }
+
+
+    pub fn leaf_count(&self) -> usize {
+        self.levels[0].len()
+    }
+
+
+    pub fn hasher(&self) -> &H {
+        &self.hasher
+    }
+
+
+    // Completes the right edge of every level and returns the tree.
+    // Only O(log n) hashes are left to do at this point.
+    pub fn finish(self) -> MerkleTree<H> {
+        let mut stored = self.levels.into_iter();
+        let mut nodes = vec![stored.next().unwrap()];
+        loop {
+            let below = nodes.last().unwrap();
+            if below.len() <= 1 {
+                break;
+            }
+            let mut level = stored.next().unwrap_or_default();
+            match &below[level.len() * 2..] {
+                [left, right] => level.push(self.hasher.hash_node(left, right)),
+                [last] => level.push(last.clone()),
+                _ => {}
+            }
+            nodes.push(level);
+        }
+        MerkleTree::from_levels(nodes, self.hasher)
+    }
+
+
+    // Writes everything hashed so far
+    pub fn checkpoint<W: Write>(&self, mut w: W) -> io::Result<()> {
+        let mut out = MAGIC.to_vec();
+        out.push(VERSION);
+        put_bytes(&mut out, &hasher_id(&self.hasher));
+        out.extend_from_slice(&(self.levels.len() as u32).to_be_bytes());
+        for level in &self.levels {
+            out.extend_from_slice(&(level.len() as u32).to_be_bytes());
+            for hash in level {
+                put_bytes(&mut out, hash);
+            }
+        }
+        let digest = sha2::Sha256::digest(&out);
+        w.write_all(&out)?;
+        w.write_all(&digest)
+    }
+
+
+    // Restores a builder from a checkpoint, checking its digest and that the
+    // level sizes are consistent with each other
+    pub fn resume<R: Read>(mut r: R, hasher: H) -> Result<TreeBuilder<H>, CheckpointError> {
+        let mut bytes = Vec::new();
+        r.read_to_end(&mut bytes)?;
+        if bytes.len() < 32 {
+            return Err(CheckpointError::Corrupt("truncated"));
+        }
+        let (body, digest) = bytes.split_at(bytes.len() - 32);
+        if sha2::Sha256::digest(body).as_slice() != digest {
+            return Err(CheckpointError::Corrupt("digest mismatch"));
+        }
+        if body.len() < 9 || &body[..4] != MAGIC {
+            return Err(CheckpointError::Corrupt("bad magic"));
+        }
+        if body[4] != VERSION {
+            return Err(CheckpointError::Corrupt("unsupported version"));
+        }
+
+        let mut input = &body[5..];
+        let id = take_hash(&mut input).ok_or(CheckpointError::Corrupt("truncated"))?;
+        if id != hasher_id(&hasher) {
+            return Err(CheckpointError::HasherMismatch);
+        }
+        let level_count = take_u32(&mut input)?;
+        let mut levels = Vec::new();
+        for _ in 0..level_count {
+            let node_count = take_u32(&mut input)?;
+            let level = (0..node_count)
+                .map(|_| take_hash(&mut input).ok_or(CheckpointError::Corrupt("truncated level")))
+                .collect::<Result<Vec<Hash>, _>>()?;
+            levels.push(level);
+        }
+        if !input.is_empty() {
+            return Err(CheckpointError::Corrupt("trailing bytes"));
+        }
+        if levels.is_empty() || levels.windows(2).any(|pair| pair[1].len() != pair[0].len() / 2) {
+            return Err(CheckpointError::Corrupt("inconsistent level sizes"));
+        }
+        Ok(TreeBuilder { levels, hasher })
+    }
+}
+
+
+fn take_u32(input: &mut &[u8]) -> Result<usize, CheckpointError> {
+    if input.len() < 4 {
+        return Err(CheckpointError::Corrupt("truncated"));
+    }
+    let (value, rest) = input.split_at(4);
+    *input = rest;
+    Ok(u32::from_be_bytes(value.try_into().unwrap()) as usize)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Legacy;
+
+
+    #[test]
+    fn test_builder_matches_construct() {
+        for n in 1..=33 {
+            let data: Vec<Data> = (0..n as u8).map(|i| vec![i]).collect();
+            let mut builder = TreeBuilder::new();
+            for leaf in &data {
+                builder.push(leaf);
+            }
+            let tree = builder.finish();
+            let expected = MerkleTree::construct(&data);
+            assert_eq!(tree.nodes, expected.nodes);
+            assert!(MerkleTree::verify_proof(&data[n - 1], &tree.prove(&data[n - 1]).unwrap(), &tree.root()));
+        }
+    }
+
+    #[test]
+    fn test_checkpoint_and_resume() {
+        let data: Vec<Data> = (0..21u8).map(|i| vec![i]).collect();
+        let mut builder = TreeBuilder::new();
+        for leaf in &data[..13] {
+            builder.push(leaf);
+        }
+        let mut checkpoint = Vec::new();
+        builder.checkpoint(&mut checkpoint).unwrap();
+
+        let mut resumed = TreeBuilder::resume(&checkpoint[..], Sha256Hasher).unwrap();
+        assert_eq!(resumed.leaf_count(), 13);
+        for leaf in &data[13..] {
+            resumed.push(leaf);
+        }
+        assert_eq!(resumed.finish().root(), MerkleTree::construct(&data).root());
+
+        let mut corrupted = checkpoint.clone();
+        corrupted[20] ^= 1;
+        assert!(matches!(TreeBuilder::resume(&corrupted[..], Sha256Hasher), Err(CheckpointError::Corrupt(_))));
+        assert!(TreeBuilder::resume(&checkpoint[..10], Sha256Hasher).is_err());
+    }
+
+    #[test]
+    fn test_resume_rejects_other_hasher() {
+        let mut builder = TreeBuilder::new();
+        for i in 0..5u8 {
+            builder.push(&vec![i]);
+        }
+        let mut checkpoint = Vec::new();
+        builder.checkpoint(&mut checkpoint).unwrap();
+
+        let resumed = TreeBuilder::resume(&checkpoint[..], Legacy(Sha256Hasher));
+        assert!(matches!(resumed, Err(CheckpointError::HasherMismatch)));
+    }
+}
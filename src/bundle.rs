@@ -0,0 +1,203 @@
+// A file holding every leaf's proof at once, for handing out proofs
+// offline. Proofs share most of their nodes, so rather than a proof per
+// leaf it stores each node of the tree once, level by level, with an index
+// of where each level starts. A reader seeks to the O(log n) siblings of a
+// leaf to rebuild its proof without loading the rest.
+//
+//   magic "MTPB" | version: u8 | hash_len: u8 | leaf count: u64 |
+//   level count: u8 | level offsets: u64 each | nodes, leaves first
+//
+// Integers are big endian and offsets count from the start of the file.
+// Level widths follow from the leaf count, and the reader rejects an index
+// that disagrees with them.
+
+use crate::export::ImportError;
+use crate::invariants::bounded_proof;
+use crate::{Error, Hash, HashDirection, Hasher, MerkleTree, Proof};
+use std::borrow::Cow;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+
+const MAGIC: &[u8; 4] = b"MTPB";
+const VERSION: u8 = 1;
+const HEADER_LEN: u64 = 4 + 1 + 1 + 8 + 1;
+
+
+// Widths of each level of a tree over `leaf_count` leaves, leaves first
+fn level_widths(leaf_count: u64) -> Vec<u64> {
+    let mut widths = vec![leaf_count];
+    while let Some(&width) = widths.last().filter(|&&width| width > 1) {
+        widths.push(width.div_ceil(2));
+    }
+    widths
+}
+
+
+impl<H: Hasher> MerkleTree<H> {
+    // Writes every node of the tree with the index a `ProofBundle` reads.
+    // Fails with `Error::NotStored` if the tree kept no leaves.
+    pub fn export_all_proofs<W: Write>(&self, mut w: W) -> io::Result<()> {
+        let levels = self.levels().ok_or_else(|| io::Error::other(Error::NotStored))?;
+        let hash_len = levels.last().and_then(|top| top.first()).map_or(0, Vec::len);
+        if hash_len > u8::MAX as usize || levels.len() > u8::MAX as usize {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "tree too large to bundle"));
+        }
+
+        w.write_all(MAGIC)?;
+        w.write_all(&[VERSION, hash_len as u8])?;
+        w.write_all(&(self.leaf_count as u64).to_be_bytes())?;
+        w.write_all(&[levels.len() as u8])?;
+        let mut offset = HEADER_LEN + 8 * levels.len() as u64;
+        for level in levels.iter() {
+            w.write_all(&offset.to_be_bytes())?;
+            offset += (level.len() * hash_len) as u64;
+        }
+        for hash in levels.iter().flatten() {
+            w.write_all(hash)?;
+        }
+        Ok(())
+    }
+}
+
+
+// Reads single proofs out of an `export_all_proofs` file
+pub struct ProofBundle<R> {
+    reader: R,
+    hash_len: usize,
+    leaf_count: usize,
+    widths: Vec<u64>,
+    offsets: Vec<u64>,
+}
+
+
+impl<R: Read + Seek> ProofBundle<R> {
+    // Reads and checks the header and index. Nodes are read as proofs ask
+    // for them.
+    pub fn open(mut reader: R) -> Result<ProofBundle<R>, ImportError> {
+        let mut header = [0u8; HEADER_LEN as usize];
+        reader.read_exact(&mut header).map_err(|_| ImportError::Malformed("truncated header"))?;
+        if &header[..4] != MAGIC {
+            return Err(ImportError::Malformed("bad magic"));
+        }
+        if header[4] != VERSION {
+            return Err(ImportError::Malformed("unsupported version"));
+        }
+        let hash_len = header[5] as usize;
+        let leaf_count = u64::from_be_bytes(header[6..14].try_into().unwrap());
+        let widths = level_widths(leaf_count);
+        if header[14] as usize != widths.len() {
+            return Err(ImportError::Malformed("level count doesn't match leaf count"));
+        }
+        if hash_len == 0 && leaf_count > 0 {
+            return Err(ImportError::Malformed("zero hash length"));
+        }
+
+        let mut offsets = Vec::with_capacity(widths.len());
+        let mut expected = HEADER_LEN + 8 * widths.len() as u64;
+        for width in &widths {
+            let mut offset = [0u8; 8];
+            reader.read_exact(&mut offset).map_err(|_| ImportError::Malformed("truncated index"))?;
+            if u64::from_be_bytes(offset) != expected {
+                return Err(ImportError::Malformed("level offsets don't match leaf count"));
+            }
+            offsets.push(expected);
+            expected = width
+                .checked_mul(hash_len as u64)
+                .and_then(|len| len.checked_add(expected))
+                .ok_or(ImportError::Malformed("leaf count too large"))?;
+        }
+        if reader.seek(SeekFrom::End(0))? != expected {
+            return Err(ImportError::Malformed("leaf count doesn't match length"));
+        }
+        let leaf_count = usize::try_from(leaf_count)
+            .map_err(|_| ImportError::Malformed("leaf count too large for this platform"))?;
+        Ok(ProofBundle { reader, hash_len, leaf_count, widths, offsets })
+    }
+
+
+    pub fn leaf_count(&self) -> usize {
+        self.leaf_count
+    }
+
+
+    // None for an empty tree
+    pub fn root(&mut self) -> io::Result<Option<Hash>> {
+        if self.leaf_count == 0 {
+            return Ok(None);
+        }
+        self.node(self.widths.len() - 1, 0).map(Some)
+    }
+
+
+    // The proof for leaf `index`, claiming its position, or None past the
+    // last leaf. The bundle isn't checked against a root; verify the proof
+    // against one you trust.
+    pub fn prove(&mut self, index: usize) -> io::Result<Option<Proof<'static>>> {
+        if index >= self.leaf_count {
+            return Ok(None);
+        }
+        let mut hashes = Vec::new();
+        let mut idx = index as u64;
+        for level in 0..self.widths.len() - 1 {
+            if idx % 2 == 1 {
+                hashes.push((HashDirection::Left, Cow::Owned(self.node(level, idx - 1)?)));
+            } else if idx + 1 < self.widths[level] {
+                hashes.push((HashDirection::Right, Cow::Owned(self.node(level, idx + 1)?)));
+            }
+            idx /= 2;
+        }
+        Ok(Some(bounded_proof(hashes, self.leaf_count).with_position(index, self.leaf_count)))
+    }
+
+
+    fn node(&mut self, level: usize, idx: u64) -> io::Result<Hash> {
+        let mut hash = vec![0u8; self.hash_len];
+        self.reader.seek(SeekFrom::Start(self.offsets[level] + idx * self.hash_len as u64))?;
+        self.reader.read_exact(&mut hash)?;
+        Ok(hash)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Data, Sha256Hasher, StorageMode};
+    use std::io::Cursor;
+
+
+    #[test]
+    fn test_bundle_proofs() {
+        for n in 0..=9 {
+            let data: Vec<Data> = (0..n as u8).map(|i| vec![i]).collect();
+            let tree = MerkleTree::construct_with_mode(&data, Sha256Hasher, StorageMode::LeavesOnly);
+            let mut bundle = Vec::new();
+            tree.export_all_proofs(&mut bundle).unwrap();
+
+            let mut reader = ProofBundle::open(Cursor::new(bundle)).unwrap();
+            assert_eq!(reader.leaf_count(), n);
+            assert_eq!(reader.root().unwrap(), (n > 0).then(|| tree.root()));
+            for (i, proof) in tree.iter_proofs().enumerate() {
+                assert_eq!(reader.prove(i).unwrap(), Some(proof));
+            }
+            assert_eq!(reader.prove(n).unwrap(), None);
+        }
+    }
+
+    #[test]
+    fn test_bundle_rejects_bad_index() {
+        let data: Vec<Data> = (0..5u8).map(|i| vec![i]).collect();
+        let mut bundle = Vec::new();
+        MerkleTree::construct(&data).export_all_proofs(&mut bundle).unwrap();
+        assert!(ProofBundle::open(Cursor::new(&bundle)).is_ok());
+
+        let open = |bytes: Vec<u8>| ProofBundle::open(Cursor::new(bytes)).err();
+        let mut moved = bundle.clone();
+        moved[HEADER_LEN as usize + 15] += 32;
+        assert!(matches!(open(moved), Some(ImportError::Malformed(_))));
+        assert!(matches!(open(bundle[..bundle.len() - 1].to_vec()), Some(ImportError::Malformed(_))));
+        let mut miscounted = bundle.clone();
+        miscounted[13] = 9;
+        assert!(matches!(open(miscounted), Some(ImportError::Malformed(_))));
+    }
+}
@@ -0,0 +1,143 @@
+// A proof server's front for a tree: proofs for recently asked leaves are
+// kept in a least-recently-used cache, so hot leaves are proven once and
+// then handed out as shared copies. Changing any leaf changes one node on
+// every other leaf's path, so updates clear the whole cache.
+
+use crate::{Data, Error, Hasher, MerkleTree, Proof};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+
+struct Lru {
+    // Leaf index to its proof and when it was last used
+    entries: HashMap<usize, (Arc<Proof<'static>>, u64)>,
+    // Last use to leaf index, oldest first
+    order: BTreeMap<u64, usize>,
+    clock: u64,
+}
+
+
+impl Lru {
+    fn get(&mut self, index: usize) -> Option<Arc<Proof<'static>>> {
+        let (proof, used) = self.entries.get_mut(&index)?;
+        self.order.remove(used);
+        self.clock += 1;
+        *used = self.clock;
+        self.order.insert(self.clock, index);
+        Some(Arc::clone(proof))
+    }
+
+
+    fn insert(&mut self, index: usize, proof: Arc<Proof<'static>>, capacity: usize) {
+        // Two threads can miss on the same leaf; the later proof replaces it
+        if let Some((_, used)) = self.entries.remove(&index) {
+            self.order.remove(&used);
+        }
+        if self.entries.len() >= capacity {
+            if let Some((_, oldest)) = self.order.pop_first() {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.clock += 1;
+        self.order.insert(self.clock, index);
+        self.entries.insert(index, (proof, self.clock));
+    }
+
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+}
+
+
+pub struct CachedProver<H> {
+    tree: MerkleTree<H>,
+    cache: Mutex<Lru>,
+    capacity: usize,
+}
+
+
+impl<H: Hasher> CachedProver<H> {
+    // Caches proofs for up to `capacity` leaves
+    pub fn new(tree: MerkleTree<H>, capacity: usize) -> CachedProver<H> {
+        assert!(capacity > 0, "cache must hold at least one proof");
+        let cache = Lru { entries: HashMap::new(), order: BTreeMap::new(), clock: 0 };
+        CachedProver { tree, cache: Mutex::new(cache), capacity }
+    }
+
+
+    pub fn tree(&self) -> &MerkleTree<H> {
+        &self.tree
+    }
+
+
+    pub fn into_inner(self) -> MerkleTree<H> {
+        self.tree
+    }
+
+
+    // Proves the leaf at `index`, claiming its position
+    pub fn prove(&self, index: usize) -> Option<Arc<Proof<'static>>> {
+        if let Some(proof) = self.lock().get(index) {
+            return Some(proof);
+        }
+        // Proven outside the lock so a miss doesn't hold up hits
+        let proof = self.tree.prove_index(index)?.into_owned().with_position(index, self.tree.leaf_count());
+        let proof = Arc::new(proof);
+        self.lock().insert(index, Arc::clone(&proof), self.capacity);
+        Some(proof)
+    }
+
+
+    // Leaves with a cached proof
+    pub fn cached(&self) -> usize {
+        self.lock().entries.len()
+    }
+
+
+    pub fn update(&mut self, index: usize, data: &Data) -> Result<(), Error> {
+        self.tree.update(index, data)?;
+        self.lock().clear();
+        Ok(())
+    }
+
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Lru> {
+        // A panic mid-insert leaves at worst a stale ordering entry
+        self.cache.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    #[test]
+    fn test_cached_proofs() {
+        let mut data: Vec<Data> = (0..10u8).map(|i| vec![i]).collect();
+        let mut prover = CachedProver::new(MerkleTree::construct(&data), 2);
+        let first = prover.prove(3).unwrap();
+        assert!(Arc::ptr_eq(&first, &prover.prove(3).unwrap()));
+        assert!(MerkleTree::verify_proof(&data[3], &first, &prover.tree().root()));
+        assert!(prover.prove(10).is_none());
+
+        // 3 was used more recently than 5, so 5 goes when 7 comes in
+        prover.prove(5).unwrap();
+        prover.prove(3).unwrap();
+        let seven = prover.prove(7).unwrap();
+        assert_eq!(prover.cached(), 2);
+        assert!(Arc::ptr_eq(&first, &prover.prove(3).unwrap()));
+        assert!(Arc::ptr_eq(&seven, &prover.prove(7).unwrap()));
+
+        data[0] = vec![100];
+        prover.update(0, &data[0]).unwrap();
+        assert_eq!(prover.cached(), 0);
+        let fresh = prover.prove(3).unwrap();
+        assert!(!Arc::ptr_eq(&first, &fresh));
+        assert!(MerkleTree::verify_proof(&data[3], &fresh, &prover.tree().root()));
+        assert!(!MerkleTree::verify_proof(&data[3], &first, &prover.tree().root()));
+    }
+}
@@ -0,0 +1,157 @@
+// A stable encoding of a tree's full node set, for content addressing and
+// comparing trees across machines. Only the nodes are encoded: the leaf
+// index is rebuilt from the leaves, and pins and storage mode are local
+// choices rather than content, so equal trees always encode equally.
+//
+//   magic "MTCN" | version: u8 | hash_len: u8 | leaf count: u64 |
+//   nodes, level by level, leaves first
+//
+// The leaf count is big endian and fixes every level's width. Decoding
+// accepts only what encoding produces: the inner nodes must be exactly
+// what the leaves hash to.
+
+use crate::storage::build_levels;
+use crate::{Error, Hash, Hasher, MerkleTree, Sha256Hasher};
+use std::fmt;
+
+
+const MAGIC: &[u8; 4] = b"MTCN";
+const VERSION: u8 = 1;
+const HEADER_LEN: usize = 4 + 1 + 1 + 8;
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CanonicalError(pub &'static str);
+
+
+impl fmt::Display for CanonicalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid canonical tree: {}", self.0)
+    }
+}
+
+
+impl std::error::Error for CanonicalError {}
+
+
+impl MerkleTree {
+    pub fn from_canonical_bytes(bytes: &[u8]) -> Result<MerkleTree, CanonicalError> {
+        Self::from_canonical_bytes_with(bytes, Sha256Hasher)
+    }
+}
+
+
+impl<H: Hasher> MerkleTree<H> {
+    // Fails with `Error::NotStored` for root-only trees
+    pub fn canonical_bytes(&self) -> Result<Vec<u8>, Error> {
+        let levels = self.levels().ok_or(Error::NotStored)?;
+        let hash_len = levels[0].first().map_or(0, Vec::len);
+        assert!(hash_len <= u8::MAX as usize, "hash too long to encode");
+
+        let node_count: usize = levels.iter().map(Vec::len).sum();
+        let mut out = Vec::with_capacity(HEADER_LEN + node_count * hash_len);
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&[VERSION, hash_len as u8]);
+        out.extend_from_slice(&(self.leaf_count as u64).to_be_bytes());
+        for hash in levels.iter().flatten() {
+            out.extend_from_slice(hash);
+        }
+        Ok(out)
+    }
+
+
+    // Rebuilds a full tree from `canonical_bytes`, rehashing the leaves to
+    // check every inner node
+    pub fn from_canonical_bytes_with(bytes: &[u8], hasher: H) -> Result<MerkleTree<H>, CanonicalError> {
+        if bytes.len() < HEADER_LEN || &bytes[..4] != MAGIC {
+            return Err(CanonicalError("bad magic"));
+        }
+        if bytes[4] != VERSION {
+            return Err(CanonicalError("unsupported version"));
+        }
+        let hash_len = bytes[5] as usize;
+        let leaf_count = usize::try_from(u64::from_be_bytes(bytes[6..HEADER_LEN].try_into().unwrap()))
+            .map_err(|_| CanonicalError("too large for this platform"))?;
+        if (hash_len == 0) != (leaf_count == 0) {
+            return Err(CanonicalError("hash length doesn't match leaf count"));
+        }
+        if leaf_count > 0 && hash_len != hasher.hash(&[]).len() {
+            return Err(CanonicalError("hash length doesn't match the hasher"));
+        }
+
+        let body = &bytes[HEADER_LEN..];
+        let leaves_len = leaf_count.checked_mul(hash_len).filter(|&len| len <= body.len());
+        let leaves_len = leaves_len.ok_or(CanonicalError("truncated leaves"))?;
+        let leaves: Vec<Hash> = body[..leaves_len].chunks(hash_len.max(1)).map(<[u8]>::to_vec).collect();
+        let levels = build_levels(leaves, &hasher);
+        let node_count: usize = levels.iter().map(Vec::len).sum();
+        if body.len() != node_count * hash_len {
+            return Err(CanonicalError("length doesn't match leaf count"));
+        }
+        let inner = body[leaves_len..].chunks(hash_len.max(1));
+        if !levels[1..].iter().flatten().map(Vec::as_slice).eq(inner) {
+            return Err(CanonicalError("inner nodes don't match the leaves"));
+        }
+        Ok(MerkleTree::from_levels(levels, hasher))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Data, StorageMode};
+
+
+    #[test]
+    fn test_canonical_roundtrip() {
+        for n in 0..=9 {
+            let data: Vec<Data> = (0..n as u8).map(|i| vec![i % 3]).collect();
+            let tree = MerkleTree::construct(&data);
+            let bytes = tree.canonical_bytes().unwrap();
+            let leaves_only = MerkleTree::construct_with_mode(&data, Sha256Hasher, StorageMode::LeavesOnly);
+            assert_eq!(leaves_only.canonical_bytes().unwrap(), bytes);
+            let decoded = MerkleTree::from_canonical_bytes(&bytes).unwrap();
+            assert_eq!(decoded, tree);
+            assert_eq!(decoded.canonical_bytes().unwrap(), bytes);
+        }
+        let root_only = MerkleTree::construct_with_mode(&[b"a"], Sha256Hasher, StorageMode::RootOnly);
+        assert_eq!(root_only.canonical_bytes(), Err(Error::NotStored));
+    }
+
+    #[test]
+    fn test_canonical_rejects_inconsistent_nodes() {
+        let data: Vec<Data> = (0..5u8).map(|i| vec![i]).collect();
+        let bytes = MerkleTree::construct(&data).canonical_bytes().unwrap();
+        let decode = |bytes: &[u8]| MerkleTree::from_canonical_bytes(bytes).err();
+
+        let mut tampered = bytes.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert_eq!(decode(&tampered), Some(CanonicalError("inner nodes don't match the leaves")));
+        assert_eq!(decode(&bytes[..bytes.len() - 32]), Some(CanonicalError("length doesn't match leaf count")));
+        assert_eq!(decode(&bytes[..HEADER_LEN + 10]), Some(CanonicalError("truncated leaves")));
+    }
+
+    #[test]
+    fn test_canonical_rejects_other_hash_length() {
+        // SHA-256 cut to 16 bytes
+        struct Short;
+        impl Hasher for Short {
+            fn hash(&self, data: &[u8]) -> Hash {
+                Sha256Hasher.hash(data)[..16].to_vec()
+            }
+        }
+
+        let data: Vec<Data> = (0..4u8).map(|i| vec![i]).collect();
+        let bytes = MerkleTree::construct(&data).canonical_bytes().unwrap();
+        let decoded = MerkleTree::from_canonical_bytes_with(&bytes, Short).err();
+        assert_eq!(decoded, Some(CanonicalError("hash length doesn't match the hasher")));
+
+        let short = MerkleTree::construct_with(&data, Short).canonical_bytes().unwrap();
+        assert!(MerkleTree::from_canonical_bytes_with(&short, Short).is_ok());
+        assert_eq!(
+            MerkleTree::from_canonical_bytes(&short).err(),
+            Some(CanonicalError("hash length doesn't match the hasher"))
+        );
+    }
+}
@@ -0,0 +1,135 @@
+// A set of trusted roots of one growing tree, for clients that may lag
+// behind the latest published root. A proof made against any remembered
+// root is accepted, and the verifier learns how far behind the newest root
+// it was. The set can be bounded so only the most recent roots are trusted.
+
+use crate::ct::hashes_equal;
+use crate::{Hash, Hashable, Hasher, Proof, Sha256Hasher};
+use std::collections::VecDeque;
+
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Checkpoints {
+    // Oldest first
+    roots: VecDeque<Hash>,
+    limit: Option<usize>,
+}
+
+
+impl Checkpoints {
+    pub fn new() -> Checkpoints {
+        Checkpoints::default()
+    }
+
+
+    // Remembers at most `limit` roots, forgetting the oldest first
+    pub fn bounded(limit: usize) -> Checkpoints {
+        assert!(limit > 0, "a bounded set must hold at least one root");
+        Checkpoints { roots: VecDeque::new(), limit: Some(limit) }
+    }
+
+
+    // Roots given oldest first
+    pub fn from_roots<I: IntoIterator<Item = Hash>>(roots: I) -> Checkpoints {
+        let mut checkpoints = Checkpoints::new();
+        roots.into_iter().for_each(|root| checkpoints.push(root));
+        checkpoints
+    }
+
+
+    // Trusts a newly published root
+    pub fn push(&mut self, root: Hash) {
+        if self.limit == Some(self.roots.len()) {
+            self.roots.pop_front();
+        }
+        self.roots.push_back(root);
+    }
+
+
+    pub fn latest(&self) -> Option<&Hash> {
+        self.roots.back()
+    }
+
+
+    // The root `age` publications before the newest, 0 being the newest
+    pub fn get(&self, age: usize) -> Option<&Hash> {
+        self.roots.len().checked_sub(age + 1).and_then(|i| self.roots.get(i))
+    }
+
+
+    pub fn len(&self) -> usize {
+        self.roots.len()
+    }
+
+
+    pub fn is_empty(&self) -> bool {
+        self.roots.is_empty()
+    }
+
+
+    // The age of `root` if it's trusted, 0 being the newest
+    pub fn age_of(&self, root: &Hash) -> Option<usize> {
+        self.roots.iter().rev().position(|trusted| hashes_equal(trusted, root))
+    }
+
+
+    // The age of the root the proof leads to, or None if it leads to no
+    // trusted root
+    pub fn verify<T: Hashable + ?Sized>(&self, data: &T, proof: &Proof) -> Option<usize> {
+        self.verify_with(data, proof, &Sha256Hasher)
+    }
+
+
+    pub fn verify_with<T: Hashable + ?Sized, H: Hasher>(&self, data: &T, proof: &Proof, hasher: &H) -> Option<usize> {
+        self.age_of(&proof.compute_root_with(data, hasher))
+    }
+
+
+    // Like `verify_with`, first rejecting proofs with more than `max_depth`
+    // hashes, such as `max_proof_len` of the newest tree size
+    pub fn verify_bounded<T: Hashable + ?Sized, H: Hasher>(&self, data: &T, proof: &Proof, hasher: &H, max_depth: usize) -> Option<usize> {
+        if proof.len() > max_depth {
+            return None;
+        }
+        self.verify_with(data, proof, hasher)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Data, MerkleTree};
+
+
+    #[test]
+    fn test_verify_against_older_roots() {
+        let data: Vec<Data> = (0..10u8).map(|i| vec![i]).collect();
+        let trees: Vec<MerkleTree> = [4, 7, 10].iter().map(|&n| MerkleTree::construct(&data[..n])).collect();
+        let checkpoints = Checkpoints::from_roots(trees.iter().map(MerkleTree::root));
+        assert_eq!(checkpoints.latest(), Some(&trees[2].root()));
+        assert_eq!(checkpoints.get(2), Some(&trees[0].root()));
+        assert!(checkpoints.get(3).is_none());
+
+        for (age, tree) in trees.iter().rev().enumerate() {
+            let proof = tree.prove(&data[2]).unwrap();
+            assert_eq!(checkpoints.verify(&data[2], &proof), Some(age));
+            assert_eq!(checkpoints.verify_bounded(&data[2], &proof, &Sha256Hasher, 4), Some(age));
+            assert!(checkpoints.verify_bounded(&data[2], &proof, &Sha256Hasher, 1).is_none());
+            assert!(checkpoints.verify(&data[3], &proof).is_none());
+        }
+        let proof = trees[2].prove(&data[9]).unwrap();
+        assert!(Checkpoints::new().verify(&data[9], &proof).is_none());
+    }
+
+    #[test]
+    fn test_bounded_forgets_oldest() {
+        let roots: Vec<Hash> = (0..5u8).map(|i| vec![i; 32]).collect();
+        let mut checkpoints = Checkpoints::bounded(3);
+        roots.iter().cloned().for_each(|root| checkpoints.push(root));
+        assert_eq!(checkpoints.len(), 3);
+        assert!(checkpoints.age_of(&roots[1]).is_none());
+        assert_eq!(checkpoints.age_of(&roots[2]), Some(2));
+        assert_eq!(checkpoints.age_of(&roots[4]), Some(0));
+    }
+}
@@ -0,0 +1,240 @@
+// Borsh and bincode encodings of proofs, for embedding them in on-chain
+// transactions. Both encode the same fields in the same order: the list of
+// (direction, hash) steps, then the optional position. Directions are one
+// byte, 0 for left and 1 for right. Decoding always gives owned hashes.
+//
+// A multiproof is its leaf count, its leaf indices and its hashes, with
+// counts and indices as u64. Decoding rejects indices that don't ascend or
+// fall past the leaf count, as `MultiProof::decode` does.
+
+use crate::{Hash, HashDirection, MultiProof, Position, Proof};
+use std::borrow::Cow;
+
+
+fn direction_byte(direction: HashDirection) -> u8 {
+    match direction {
+        HashDirection::Left => 0,
+        HashDirection::Right => 1,
+    }
+}
+
+
+fn direction_from_byte(byte: u8) -> Option<HashDirection> {
+    match byte {
+        0 => Some(HashDirection::Left),
+        1 => Some(HashDirection::Right),
+        _ => None,
+    }
+}
+
+
+fn steps<'p>(proof: &'p Proof) -> Vec<(u8, &'p Hash)> {
+    proof.hashes.iter().map(|(direction, hash)| (direction_byte(*direction), hash.as_ref())).collect()
+}
+
+
+fn from_steps<'a>(steps: Vec<(u8, Hash)>, position: Option<(u64, u64)>) -> Option<Proof<'a>> {
+    let hashes = steps
+        .into_iter()
+        .map(|(direction, hash)| Some((direction_from_byte(direction)?, Cow::Owned(hash))))
+        .collect::<Option<_>>()?;
+    let position = match position {
+        Some((index, tree_size)) => {
+            Some(Position { index: usize::try_from(index).ok()?, tree_size: usize::try_from(tree_size).ok()? })
+        }
+        None => None,
+    };
+    Some(Proof { hashes, position })
+}
+
+
+fn position_pair(proof: &Proof) -> Option<(u64, u64)> {
+    proof.position.map(|p| (p.index as u64, p.tree_size as u64))
+}
+
+
+fn multiproof_indices(proof: &MultiProof) -> Vec<u64> {
+    proof.indices().iter().map(|&index| index as u64).collect()
+}
+
+
+fn multiproof_from_parts(leaf_count: u64, indices: Vec<u64>, hashes: Vec<Hash>) -> Option<MultiProof> {
+    let indices = indices.into_iter().map(|index| usize::try_from(index).ok()).collect::<Option<_>>()?;
+    MultiProof::from_parts(usize::try_from(leaf_count).ok()?, indices, hashes)
+}
+
+
+#[cfg(feature = "borsh")]
+mod borsh_impl {
+    use super::*;
+    use borsh::{BorshDeserialize, BorshSerialize};
+    use std::io::{self, Read, Write};
+
+
+    impl BorshSerialize for Proof<'_> {
+        fn serialize<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+            steps(self).serialize(writer)?;
+            position_pair(self).serialize(writer)
+        }
+    }
+
+
+    impl BorshDeserialize for Proof<'_> {
+        fn deserialize_reader<R: Read>(reader: &mut R) -> io::Result<Self> {
+            let steps = Vec::<(u8, Hash)>::deserialize_reader(reader)?;
+            let position = Option::<(u64, u64)>::deserialize_reader(reader)?;
+            from_steps(steps, position).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "bad hash direction"))
+        }
+    }
+
+
+    impl BorshSerialize for MultiProof {
+        fn serialize<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+            (self.leaf_count() as u64).serialize(writer)?;
+            multiproof_indices(self).serialize(writer)?;
+            self.hashes().serialize(writer)
+        }
+    }
+
+
+    impl BorshDeserialize for MultiProof {
+        fn deserialize_reader<R: Read>(reader: &mut R) -> io::Result<Self> {
+            let leaf_count = u64::deserialize_reader(reader)?;
+            let indices = Vec::<u64>::deserialize_reader(reader)?;
+            let hashes = Vec::<Hash>::deserialize_reader(reader)?;
+            multiproof_from_parts(leaf_count, indices, hashes)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "bad leaf indices"))
+        }
+    }
+}
+
+
+#[cfg(feature = "bincode")]
+mod bincode_impl {
+    use super::*;
+    use bincode::de::{BorrowDecoder, Decoder};
+    use bincode::enc::Encoder;
+    use bincode::error::{DecodeError, EncodeError};
+    use bincode::{BorrowDecode, Decode, Encode};
+
+
+    impl Encode for Proof<'_> {
+        fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), EncodeError> {
+            steps(self).encode(encoder)?;
+            position_pair(self).encode(encoder)
+        }
+    }
+
+
+    impl<Context> Decode<Context> for Proof<'_> {
+        fn decode<D: Decoder<Context = Context>>(decoder: &mut D) -> Result<Self, DecodeError> {
+            let steps = Vec::<(u8, Hash)>::decode(decoder)?;
+            let position = Option::<(u64, u64)>::decode(decoder)?;
+            from_steps(steps, position).ok_or(DecodeError::Other("bad hash direction"))
+        }
+    }
+
+
+    impl<'de, Context> BorrowDecode<'de, Context> for Proof<'_> {
+        fn borrow_decode<D: BorrowDecoder<'de, Context = Context>>(decoder: &mut D) -> Result<Self, DecodeError> {
+            <Self as Decode<Context>>::decode(decoder)
+        }
+    }
+
+
+    impl Encode for MultiProof {
+        fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), EncodeError> {
+            (self.leaf_count() as u64).encode(encoder)?;
+            multiproof_indices(self).encode(encoder)?;
+            self.hashes().encode(encoder)
+        }
+    }
+
+
+    impl<Context> Decode<Context> for MultiProof {
+        fn decode<D: Decoder<Context = Context>>(decoder: &mut D) -> Result<Self, DecodeError> {
+            let leaf_count = u64::decode(decoder)?;
+            let indices = Vec::<u64>::decode(decoder)?;
+            let hashes = Vec::<Hash>::decode(decoder)?;
+            multiproof_from_parts(leaf_count, indices, hashes).ok_or(DecodeError::Other("bad leaf indices"))
+        }
+    }
+
+
+    impl<'de, Context> BorrowDecode<'de, Context> for MultiProof {
+        fn borrow_decode<D: BorrowDecoder<'de, Context = Context>>(decoder: &mut D) -> Result<Self, DecodeError> {
+            <Self as Decode<Context>>::decode(decoder)
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::{Data, MerkleTree, MultiProof, Proof};
+
+
+    fn example() -> (Vec<Data>, MerkleTree) {
+        let data: Vec<Data> = (0..7u8).map(|i| vec![i]).collect();
+        let tree = MerkleTree::construct(&data);
+        (data, tree)
+    }
+
+    // A multiproof for leaves 1, 4 and 5, with the leaves in order
+    fn example_multiproof(data: &[Data], tree: &MerkleTree) -> (MultiProof, Vec<Data>) {
+        let leaves: Vec<Data> = [1, 4, 5].iter().map(|&i| data[i].clone()).collect();
+        let proofs: Vec<Proof> = leaves.iter().map(|leaf| tree.prove_with_position(leaf).unwrap()).collect();
+        (MultiProof::aggregate(&proofs).unwrap(), leaves)
+    }
+
+    #[cfg(feature = "borsh")]
+    #[test]
+    fn test_borsh_roundtrip() {
+        let (data, tree) = example();
+        for leaf in &data {
+            let proof = tree.prove_with_position(leaf).unwrap();
+            let bytes = borsh::to_vec(&proof).unwrap();
+            let decoded: Proof = borsh::from_slice(&bytes).unwrap();
+            assert_eq!(decoded.position(), proof.position());
+            assert!(MerkleTree::verify_proof(leaf, &decoded, &tree.root()));
+        }
+        let mut bytes = borsh::to_vec(&tree.prove(&data[0]).unwrap()).unwrap();
+        bytes[4] = 7;
+        assert!(borsh::from_slice::<Proof>(&bytes).is_err());
+
+        let (multiproof, leaves) = example_multiproof(&data, &tree);
+        let bytes = borsh::to_vec(&multiproof).unwrap();
+        let decoded: MultiProof = borsh::from_slice(&bytes).unwrap();
+        assert_eq!(decoded.indices(), multiproof.indices());
+        assert!(decoded.verify(&leaves, &tree.root()));
+        // The first index, after the leaf count and the index count, moved
+        // past the second
+        let mut swapped = bytes.clone();
+        swapped[12..20].copy_from_slice(&4u64.to_le_bytes());
+        assert!(borsh::from_slice::<MultiProof>(&swapped).is_err());
+    }
+
+    #[cfg(feature = "bincode")]
+    #[test]
+    fn test_bincode_roundtrip() {
+        let (data, tree) = example();
+        let config = bincode::config::standard();
+        for leaf in &data {
+            let proof = tree.prove(leaf).unwrap();
+            let bytes = bincode::encode_to_vec(&proof, config).unwrap();
+            let (decoded, read): (Proof, usize) = bincode::decode_from_slice(&bytes, config).unwrap();
+            assert_eq!(read, bytes.len());
+            assert!(MerkleTree::verify_proof(leaf, &decoded, &tree.root()));
+        }
+
+        let (multiproof, leaves) = example_multiproof(&data, &tree);
+        let bytes = bincode::encode_to_vec(&multiproof, config).unwrap();
+        let (decoded, read): (MultiProof, usize) = bincode::decode_from_slice(&bytes, config).unwrap();
+        assert_eq!(read, bytes.len());
+        assert!(decoded.verify(&leaves, &tree.root()));
+        let beyond = MultiProof::from_parts(2, vec![0, 1], Vec::new()).unwrap();
+        let mut bytes = bincode::encode_to_vec(&beyond, config).unwrap();
+        bytes[0] = 1;
+        assert!(bincode::decode_from_slice::<MultiProof, _>(&bytes, config).is_err());
+    }
+}
@@ -0,0 +1,239 @@
+// Trees of trees, for sharded data where each shard keeps its own tree. A
+// parent tree takes the shard roots as its leaf data, so a proof for an
+// item is the shard proof up to the shard root followed by the parent proof
+// from that root up. Chained proofs do the same through any number of
+// nested trees. Single subtrees can also be pulled out of a tree as trees
+// of their own.
+
+use crate::ct::hashes_equal;
+use crate::{Hash, Hashable, Hasher, MerkleTree, Proof, Sha256Hasher, StorageMode};
+
+
+// An item's path through its shard and then through the parent tree
+#[derive(Debug)]
+pub struct ComposedProof<'a> {
+    pub shard: usize,
+    pub inner: Proof<'a>,
+    pub outer: Proof<'a>,
+}
+
+
+impl ComposedProof<'_> {
+    pub fn compute_root_with<T: Hashable + ?Sized, H: Hasher>(&self, data: &T, hasher: &H) -> Hash {
+        let shard_root = self.inner.compute_root_with(data, hasher);
+        self.outer.compute_root_with(&shard_root, hasher)
+    }
+
+
+    pub fn verify<T: Hashable + ?Sized>(&self, data: &T, root: &Hash) -> bool {
+        self.verify_with(data, root, &Sha256Hasher)
+    }
+
+
+    pub fn verify_with<T: Hashable + ?Sized, H: Hasher>(&self, data: &T, root: &Hash, hasher: &H) -> bool {
+        let shard_matches = self.outer.position().is_none_or(|position| position.index == self.shard);
+        shard_matches
+            && self.inner.matches_position()
+            && self.outer.matches_position()
+            && hashes_equal(&self.compute_root_with(data, hasher), root)
+    }
+}
+
+
+// A path through nested trees, innermost first: each proof leads from its
+// tree's leaf to its root, which is a leaf of the next tree out
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChainedProof<'a> {
+    pub links: Vec<Proof<'a>>,
+}
+
+
+impl<'a> ChainedProof<'a> {
+    pub fn new(innermost: Proof<'a>) -> ChainedProof<'a> {
+        ChainedProof { links: vec![innermost] }
+    }
+
+
+    // Adds the proof of the current outermost root in its parent tree
+    pub fn then(mut self, outer: Proof<'a>) -> ChainedProof<'a> {
+        self.links.push(outer);
+        self
+    }
+
+
+    // Nesting levels crossed, one per tree
+    pub fn depth(&self) -> usize {
+        self.links.len()
+    }
+
+
+    // The outermost root the chain leads to, None if it has no links
+    pub fn compute_root_with<T: Hashable + ?Sized, H: Hasher>(&self, data: &T, hasher: &H) -> Option<Hash> {
+        let (innermost, outer) = self.links.split_first()?;
+        let root = innermost.compute_root_with(data, hasher);
+        Some(outer.iter().fold(root, |root, link| link.compute_root_with(&root, hasher)))
+    }
+
+
+    pub fn verify<T: Hashable + ?Sized>(&self, data: &T, root: &Hash) -> bool {
+        self.verify_with(data, root, &Sha256Hasher)
+    }
+
+
+    // Checks the whole chain in one call, including any positions the
+    // links claim. An empty chain proves nothing.
+    pub fn verify_with<T: Hashable + ?Sized, H: Hasher>(&self, data: &T, root: &Hash, hasher: &H) -> bool {
+        self.links.iter().all(Proof::matches_position)
+            && self.compute_root_with(data, hasher).is_some_and(|computed| hashes_equal(&computed, root))
+    }
+}
+
+
+impl<'a> From<ComposedProof<'a>> for ChainedProof<'a> {
+    fn from(proof: ComposedProof<'a>) -> ChainedProof<'a> {
+        ChainedProof::new(proof.inner).then(proof.outer)
+    }
+}
+
+
+impl MerkleTree {
+    pub fn of_roots(children: &[&MerkleTree]) -> MerkleTree {
+        Self::of_roots_with(children, Sha256Hasher)
+    }
+}
+
+
+impl<H: Hasher> MerkleTree<H> {
+    // A parent tree whose leaf i is the root of `children[i]`
+    pub fn of_roots_with<C: Hasher>(children: &[&MerkleTree<C>], hasher: H) -> MerkleTree<H> {
+        let roots: Vec<Hash> = children.iter().map(|child| child.root()).collect();
+        Self::construct_with(&roots, hasher)
+    }
+
+
+    // The node at `index` on `level`, leaves being level 0. A subtree at the
+    // right edge may have fewer than 2^level leaves.
+    pub fn subtree_root(&self, level: usize, index: usize) -> Option<Hash> {
+        self.level(level)?.get(index).cloned()
+    }
+
+
+    // The subtree under the node at `index` on `level`, as a tree of its
+    // own with the same root
+    pub fn subtree(&self, level: usize, index: usize) -> Option<MerkleTree<H>>
+    where
+        H: Clone,
+    {
+        if self.mode != StorageMode::Full {
+            return None;
+        }
+        self.subtree_root(level, index)?;
+        let mut nodes = Vec::new();
+        for below in 0..=level {
+            let span = 1 << (level - below);
+            let nodes_here = &self.nodes[below];
+            let range = (index * span).min(nodes_here.len())..((index + 1) * span).min(nodes_here.len());
+            nodes.push(nodes_here[range].to_vec());
+            // A right-edge subtree tops out early; the levels above only
+            // promote its root
+            if nodes.last().unwrap().len() == 1 {
+                break;
+            }
+        }
+        Some(MerkleTree::from_levels(nodes, self.hasher.clone()))
+    }
+
+
+    // Proves `data` sits in `child`, and `child` is shard `shard` of this tree
+    pub fn prove_composed<'a, T: Hashable + ?Sized>(
+        &'a self,
+        shard: usize,
+        child: &'a MerkleTree<H>,
+        data: &T,
+    ) -> Option<ComposedProof<'a>> {
+        let shard_leaf = self.hasher.hash_leaf(&child.root());
+        if !hashes_equal(self.leaf_hash(shard)?, &shard_leaf) {
+            return None;
+        }
+        // By index, since shards with equal roots share a leaf hash
+        let outer = self.prove_index(shard)?.with_position(shard, self.leaf_count);
+        Some(ComposedProof { shard, inner: child.prove(data)?, outer })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Data;
+
+
+    #[test]
+    fn test_composed_proofs() {
+        let shards: Vec<Vec<Data>> = (0..3u8).map(|s| (0..(4 + s)).map(|i| vec![s, i]).collect()).collect();
+        let trees: Vec<MerkleTree> = shards.iter().map(|shard| MerkleTree::construct(shard)).collect();
+        let parent = MerkleTree::of_roots(&trees.iter().collect::<Vec<_>>());
+        let root = parent.root();
+
+        for (s, shard) in shards.iter().enumerate() {
+            assert_eq!(parent.subtree_root(0, s), Some(Sha256Hasher.hash_leaf(&trees[s].root())));
+            for leaf in shard {
+                let proof = parent.prove_composed(s, &trees[s], leaf).unwrap();
+                assert!(proof.verify(leaf, &root));
+                assert!(!proof.verify(&vec![9, 9], &root));
+            }
+        }
+        // A shard proof presented as another shard's
+        let mut wrong = parent.prove_composed(1, &trees[1], &shards[1][0]).unwrap();
+        wrong.shard = 2;
+        assert!(!wrong.verify(&shards[1][0], &root));
+        assert!(parent.prove_composed(0, &trees[1], &shards[1][0]).is_none());
+    }
+
+    #[test]
+    fn test_subtree() {
+        let data: Vec<Data> = (0..11u8).map(|i| vec![i]).collect();
+        let tree = MerkleTree::construct(&data);
+        for level in 0..=tree.depth() {
+            for index in 0..tree.level(level).unwrap().len() {
+                let subtree = tree.subtree(level, index).unwrap();
+                let span = 1 << level;
+                let leaves = &data[(index * span).min(11)..((index + 1) * span).min(11)];
+                assert_eq!(subtree.root(), MerkleTree::construct(leaves).root());
+                assert_eq!(Some(subtree.root()), tree.subtree_root(level, index));
+                assert_eq!(subtree.check_invariants(), Ok(()));
+            }
+        }
+        assert!(tree.subtree(2, 3).is_none());
+        assert!(tree.subtree(5, 0).is_none());
+    }
+
+    #[test]
+    fn test_chained_proofs() {
+        // Items in shards, shards in regions, regions under one root
+        let items: Vec<Data> = (0..5u8).map(|i| vec![i]).collect();
+        let shard = MerkleTree::construct(&items);
+        let shards: Vec<MerkleTree> = (0..3u8).map(|s| MerkleTree::construct(&[vec![s, 9], vec![s, 8]])).collect();
+        let region = MerkleTree::of_roots(&[&shards[0], &shard, &shards[1]]);
+        let top = MerkleTree::of_roots(&[&shards[2], &region]);
+
+        let chain = ChainedProof::new(shard.prove(&items[3]).unwrap())
+            .then(region.prove(&shard.root()).unwrap().with_position(1, 3))
+            .then(top.prove(&region.root()).unwrap());
+        assert_eq!(chain.depth(), 3);
+        assert!(chain.verify(&items[3], &top.root()));
+        assert!(!chain.verify(&items[2], &top.root()));
+        assert!(!ChainedProof::new(shard.prove(&items[3]).unwrap()).verify(&items[3], &top.root()));
+
+        let mut moved = chain.clone();
+        moved.links[1] = moved.links[1].clone().with_position(2, 3);
+        assert!(!moved.verify(&items[3], &top.root()));
+
+        let empty = ChainedProof { links: Vec::new() };
+        assert_eq!(empty.compute_root_with(&items[3], &Sha256Hasher), None);
+        assert!(!empty.verify(&items[3], &top.root()));
+
+        let composed = region.prove_composed(1, &shard, &items[0]).unwrap();
+        assert!(ChainedProof::from(composed).verify(&items[0], &region.root()));
+    }
+}
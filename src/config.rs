@@ -0,0 +1,196 @@
+// Everything a verifier must agree on besides the root: the hash function,
+// the arity, how odd nodes and domain separation are handled, and the chunk
+// size when leaves are chunks of a file. Publishing the config's digest next
+// to the root lets a verifier built differently say so, instead of every
+// proof just failing.
+//
+//   magic "MTCF" | version: u8 | hasher id: u16 | arity: u8 | flags: u8 |
+//   chunk size: u32
+//
+// Integers are big endian and a chunk size of 0 means leaves aren't
+// chunks. The encoding is canonical: decoding accepts only what encoding
+// produces, so equal configs always have equal digests.
+
+use crate::attestation::{HasherId, PolicyFlags};
+use crate::{Hash, Hasher, MerkleTree};
+use sha2::Digest;
+use std::fmt;
+
+
+const MAGIC: &[u8; 4] = b"MTCF";
+const VERSION: u8 = 1;
+const ENCODED_LEN: usize = 4 + 1 + 2 + 1 + 1 + 4;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TreeConfig {
+    pub hasher: HasherId,
+    pub arity: u8,
+    pub flags: PolicyFlags,
+    pub chunk_size: u32,
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigError {
+    Length(usize),
+    BadMagic,
+    UnsupportedVersion(u8),
+    UnknownHasher(u16),
+    ReservedFlags(u8),
+    // Below two, or odd nodes both promoted and padded
+    Invalid,
+}
+
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Length(len) => write!(f, "config is {} bytes, not {}", len, ENCODED_LEN),
+            ConfigError::BadMagic => write!(f, "not a tree config"),
+            ConfigError::UnsupportedVersion(version) => write!(f, "unsupported config version {}", version),
+            ConfigError::UnknownHasher(id) => write!(f, "unknown hasher id {}", id),
+            ConfigError::ReservedFlags(bits) => write!(f, "reserved flag bits set in {:#04x}", bits),
+            ConfigError::Invalid => write!(f, "config describes no tree"),
+        }
+    }
+}
+
+
+impl std::error::Error for ConfigError {}
+
+
+impl TreeConfig {
+    // This crate's binary trees over SHA-256, leaves not chunked
+    pub fn sha256() -> TreeConfig {
+        TreeConfig {
+            hasher: HasherId::Sha256,
+            arity: 2,
+            flags: PolicyFlags::DOMAIN_SEPARATED.union(PolicyFlags::PROMOTE_ODD),
+            chunk_size: 0,
+        }
+    }
+
+
+    pub fn with_chunk_size(self, chunk_size: u32) -> TreeConfig {
+        TreeConfig { chunk_size, ..self }
+    }
+
+
+    pub fn is_valid(&self) -> bool {
+        let odd = PolicyFlags::PROMOTE_ODD.union(PolicyFlags::PADDED);
+        self.arity >= 2 && !self.flags.contains(odd)
+    }
+
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(ENCODED_LEN);
+        out.extend_from_slice(MAGIC);
+        out.push(VERSION);
+        out.extend_from_slice(&self.hasher.to_u16().to_be_bytes());
+        out.push(self.arity);
+        out.push(self.flags.bits());
+        out.extend_from_slice(&self.chunk_size.to_be_bytes());
+        out
+    }
+
+
+    pub fn decode(bytes: &[u8]) -> Result<TreeConfig, ConfigError> {
+        if bytes.len() != ENCODED_LEN {
+            return Err(ConfigError::Length(bytes.len()));
+        }
+        if &bytes[..4] != MAGIC {
+            return Err(ConfigError::BadMagic);
+        }
+        if bytes[4] != VERSION {
+            return Err(ConfigError::UnsupportedVersion(bytes[4]));
+        }
+        let id = u16::from_be_bytes([bytes[5], bytes[6]]);
+        let hasher = HasherId::from_u16(id).ok_or(ConfigError::UnknownHasher(id))?;
+        let flags = PolicyFlags::from_bits(bytes[8]).ok_or(ConfigError::ReservedFlags(bytes[8]))?;
+        let chunk_size = u32::from_be_bytes(bytes[9..13].try_into().unwrap());
+        let config = TreeConfig { hasher, arity: bytes[7], flags, chunk_size };
+        if !config.is_valid() {
+            return Err(ConfigError::Invalid);
+        }
+        Ok(config)
+    }
+
+
+    // SHA-256 of the encoding, whatever hasher the config names
+    pub fn config_digest(&self) -> Hash {
+        sha2::Sha256::digest(self.encode()).to_vec()
+    }
+
+
+    // Names of the settings that differ, for telling a user why proofs
+    // from a peer won't verify
+    pub fn differences(&self, other: &TreeConfig) -> Vec<&'static str> {
+        let mut differences = Vec::new();
+        if self.hasher != other.hasher {
+            differences.push("hasher");
+        }
+        if self.arity != other.arity {
+            differences.push("arity");
+        }
+        if self.flags != other.flags {
+            differences.push("flags");
+        }
+        if self.chunk_size != other.chunk_size {
+            differences.push("chunk size");
+        }
+        differences
+    }
+}
+
+
+impl<H: Hasher> MerkleTree<H> {
+    // This tree's config. As with `attest`, the caller names the hash
+    // function and the flags come from the hasher.
+    pub fn config(&self, hasher: HasherId) -> TreeConfig {
+        TreeConfig { hasher, flags: PolicyFlags::for_hasher(&self.hasher), ..TreeConfig::sha256() }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Legacy, Sha256Hasher};
+
+
+    #[test]
+    fn test_config_roundtrip() {
+        let config = TreeConfig::sha256().with_chunk_size(1024);
+        let encoded = config.encode();
+        assert_eq!(encoded.len(), ENCODED_LEN);
+        assert_eq!(TreeConfig::decode(&encoded), Ok(config));
+        assert_eq!(config.config_digest(), TreeConfig::decode(&encoded).unwrap().config_digest());
+
+        let tiger = MerkleTree::construct(&[b"a"]).config(HasherId::Tiger);
+        assert_ne!(tiger.config_digest(), TreeConfig::sha256().config_digest());
+        assert_eq!(config.differences(&tiger), vec!["hasher", "chunk size"]);
+        assert!(config.differences(&config).is_empty());
+
+        let legacy = MerkleTree::construct_with(&[b"a"], Legacy(Sha256Hasher)).config(HasherId::Sha256);
+        assert_eq!(legacy.flags, PolicyFlags::PROMOTE_ODD);
+        assert_eq!(TreeConfig::sha256().differences(&legacy), vec!["flags"]);
+    }
+
+    #[test]
+    fn test_config_decode_is_strict() {
+        let encoded = TreeConfig::sha256().encode();
+        let with = |i: usize, byte: u8| {
+            let mut bytes = encoded.clone();
+            bytes[i] = byte;
+            TreeConfig::decode(&bytes)
+        };
+        assert_eq!(with(0, b'X'), Err(ConfigError::BadMagic));
+        assert_eq!(with(4, 2), Err(ConfigError::UnsupportedVersion(2)));
+        assert_eq!(with(6, 9), Err(ConfigError::UnknownHasher(9)));
+        assert_eq!(with(7, 1), Err(ConfigError::Invalid));
+        assert_eq!(with(8, 0x07), Err(ConfigError::Invalid));
+        assert_eq!(with(8, 0x10), Err(ConfigError::ReservedFlags(0x10)));
+        assert_eq!(TreeConfig::decode(&encoded[1..]), Err(ConfigError::Length(12)));
+    }
+}
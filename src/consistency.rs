@@ -0,0 +1,231 @@
+// Proofs about earlier sizes of a tree that only grows, as in RFC 6962:
+// inclusion in the tree as it was at `tree_size` leaves, and consistency
+// proofs that a tree of `new_size` leaves starts with the tree of
+// `old_size`. The promoted shape splits every subtree at the largest power
+// of two below its size, which is the RFC's split, so the hashes match.
+// The prefix trees are rebuilt from the stored levels: every node that lies
+// entirely inside the prefix is shared, and only the right edge is rehashed.
+
+use crate::ct::hashes_equal;
+use crate::invariants::bounded_proof;
+use crate::{Hash, HashDirection, Hasher, MerkleTree, Proof, Sha256Hasher, StorageMode};
+use std::borrow::Cow;
+
+
+// The hashes linking a tree of `old_size` leaves to one of `new_size`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConsistencyProof {
+    pub old_size: usize,
+    pub new_size: usize,
+    pub hashes: Vec<Hash>,
+}
+
+
+impl ConsistencyProof {
+    pub fn verify(&self, old_root: &Hash, new_root: &Hash) -> bool {
+        self.verify_with(old_root, new_root, &Sha256Hasher)
+    }
+
+
+    // The verification algorithm of RFC 9162, section 2.1.4.2
+    pub fn verify_with<H: Hasher>(&self, old_root: &Hash, new_root: &Hash, hasher: &H) -> bool {
+        let (old_size, new_size) = (self.old_size, self.new_size);
+        if old_size > new_size {
+            return false;
+        }
+        if old_size == 0 {
+            // Every tree extends the empty one
+            return self.hashes.is_empty();
+        }
+        if old_size == new_size {
+            return self.hashes.is_empty() && hashes_equal(old_root, new_root);
+        }
+
+        // A power-of-two old tree is itself a node of the new one, so the
+        // proof leaves it out
+        let mut path = Vec::with_capacity(self.hashes.len() + 1);
+        if old_size.is_power_of_two() {
+            path.push(old_root);
+        }
+        path.extend(&self.hashes);
+        let Some((first, rest)) = path.split_first() else {
+            return false;
+        };
+
+        let (mut fnode, mut snode) = (old_size - 1, new_size - 1);
+        while fnode & 1 == 1 {
+            (fnode, snode) = (fnode >> 1, snode >> 1);
+        }
+        let (mut old_hash, mut new_hash) = ((*first).clone(), (*first).clone());
+        for &sibling in rest {
+            if snode == 0 {
+                return false;
+            }
+            if fnode & 1 == 1 || fnode == snode {
+                old_hash = hasher.hash_node(sibling, &old_hash);
+                new_hash = hasher.hash_node(sibling, &new_hash);
+                while fnode & 1 == 0 && fnode != 0 {
+                    (fnode, snode) = (fnode >> 1, snode >> 1);
+                }
+            } else {
+                new_hash = hasher.hash_node(&new_hash, sibling);
+            }
+            (fnode, snode) = (fnode >> 1, snode >> 1);
+        }
+        snode == 0 && hashes_equal(&old_hash, old_root) && hashes_equal(&new_hash, new_root)
+    }
+}
+
+
+impl<H: Hasher> MerkleTree<H> {
+    // The root the tree had when it held its first `tree_size` leaves. The
+    // empty tree's root is the hash of no bytes. None past the current size
+    // or when the storage mode dropped the inner levels.
+    pub fn root_at_size(&self, tree_size: usize) -> Option<Hash> {
+        self.check_prefix(tree_size)?;
+        if tree_size == 0 {
+            return Some(self.hasher.hash(&[]));
+        }
+        Some(self.range_hash(0, tree_size))
+    }
+
+
+    // Proves the leaf at `index` was in the tree of the first `tree_size`
+    // leaves. The proof claims that position.
+    pub fn prove_at_size(&self, index: usize, tree_size: usize) -> Option<Proof<'static>> {
+        self.check_prefix(tree_size)?;
+        if index >= tree_size {
+            return None;
+        }
+        let mut hashes = Vec::new();
+        self.inclusion_path(index, 0, tree_size, &mut hashes);
+        Some(bounded_proof(hashes, tree_size).with_position(index, tree_size))
+    }
+
+
+    // Proves this tree starts with the tree of its first `old_size` leaves
+    pub fn prove_consistency(&self, old_size: usize) -> Option<ConsistencyProof> {
+        self.prove_consistency_between(old_size, self.leaf_count)
+    }
+
+
+    // Proves the tree of the first `new_size` leaves starts with the tree of
+    // the first `old_size`
+    pub fn prove_consistency_between(&self, old_size: usize, new_size: usize) -> Option<ConsistencyProof> {
+        self.check_prefix(new_size)?;
+        if old_size > new_size {
+            return None;
+        }
+        let mut hashes = Vec::new();
+        if old_size > 0 && old_size < new_size {
+            self.consistency_path(old_size, 0, new_size, true, &mut hashes);
+        }
+        Some(ConsistencyProof { old_size, new_size, hashes })
+    }
+
+
+    fn check_prefix(&self, tree_size: usize) -> Option<()> {
+        (self.mode == StorageMode::Full && tree_size <= self.leaf_count).then_some(())
+    }
+
+
+    // The root of the leaves in [start, end), where `start` is aligned to
+    // the subtree's height as it always is under the RFC's split
+    fn range_hash(&self, start: usize, end: usize) -> Hash {
+        let len = end - start;
+        let height = len.next_power_of_two().trailing_zeros() as usize;
+        // The stored node covers the range unless the range stops short of
+        // the node's own right edge
+        if end == (start + (1 << height)).min(self.leaf_count) {
+            return self.nodes[height][start >> height].clone();
+        }
+        let split = start + (len.next_power_of_two() >> 1);
+        self.hasher.hash_node(&self.range_hash(start, split), &self.range_hash(split, end))
+    }
+
+
+    // RFC 6962 PATH, leaf first
+    fn inclusion_path(&self, index: usize, start: usize, size: usize, out: &mut Vec<(HashDirection, Cow<'static, Hash>)>) {
+        if size == 1 {
+            return;
+        }
+        let split = size.next_power_of_two() >> 1;
+        if index < split {
+            self.inclusion_path(index, start, split, out);
+            out.push((HashDirection::Right, Cow::Owned(self.range_hash(start + split, start + size))));
+        } else {
+            self.inclusion_path(index - split, start + split, size - split, out);
+            out.push((HashDirection::Left, Cow::Owned(self.range_hash(start, start + split))));
+        }
+    }
+
+
+    // RFC 6962 SUBPROOF, where `whole` says the old tree is this exact
+    // subtree and so needn't be sent
+    fn consistency_path(&self, old_size: usize, start: usize, size: usize, whole: bool, out: &mut Vec<Hash>) {
+        if old_size == size {
+            if !whole {
+                out.push(self.range_hash(start, start + size));
+            }
+            return;
+        }
+        let split = size.next_power_of_two() >> 1;
+        if old_size <= split {
+            self.consistency_path(old_size, start, split, whole, out);
+            out.push(self.range_hash(start + split, start + size));
+        } else {
+            self.consistency_path(old_size - split, start + split, size - split, false, out);
+            out.push(self.range_hash(start, start + split));
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Data;
+
+
+    #[test]
+    fn test_prefix_roots_and_inclusion() {
+        let data: Vec<Data> = (0..13u8).map(|i| vec![i]).collect();
+        let tree = MerkleTree::construct(&data);
+        assert_eq!(tree.root_at_size(0), Some(Sha256Hasher.hash(&[])));
+        assert!(tree.root_at_size(14).is_none());
+        for size in 1..=data.len() {
+            let prefix = MerkleTree::construct(&data[..size]);
+            assert_eq!(tree.root_at_size(size), Some(prefix.root()));
+            for (index, leaf) in data[..size].iter().enumerate() {
+                let proof = tree.prove_at_size(index, size).unwrap();
+                assert_eq!(proof.hashes, prefix.prove(leaf).unwrap().hashes);
+                assert!(MerkleTree::verify_proof(leaf, &proof, &prefix.root()));
+            }
+            assert!(tree.prove_at_size(size, size).is_none());
+        }
+    }
+
+    #[test]
+    fn test_consistency() {
+        let data: Vec<Data> = (0..13u8).map(|i| vec![i]).collect();
+        let tree = MerkleTree::construct(&data);
+        for new_size in 0..=data.len() {
+            let new_root = tree.root_at_size(new_size).unwrap();
+            for old_size in 0..=new_size {
+                let old_root = tree.root_at_size(old_size).unwrap();
+                let proof = tree.prove_consistency_between(old_size, new_size).unwrap();
+                assert!(proof.verify(&old_root, &new_root), "{} -> {}", old_size, new_size);
+                if old_size > 0 && old_size < new_size {
+                    assert!(!proof.verify(&new_root, &new_root));
+                    let mut short = proof.clone();
+                    short.hashes.pop();
+                    assert!(!short.verify(&old_root, &new_root));
+                }
+            }
+        }
+        // RFC 6962's worked example: 3 leaves into 7 takes c, d, g, l
+        let proof = tree.prove_consistency_between(3, 7).unwrap();
+        assert_eq!(proof.hashes.len(), 4);
+        assert!(tree.prove_consistency(14).is_none());
+    }
+}
@@ -0,0 +1,215 @@
+// Conversion to and from ICS-23 proofs, so IBC verifiers can check
+// membership in a tree. ICS-23 commits to key/value pairs, so a leaf here
+// is the key followed by the value: hashing 0x00 | key | value as ICS-23
+// leaves it unprefixed and unhashed gives exactly this crate's leaf hash.
+// Each sibling becomes an inner op with the node prefix, the sibling going
+// in the prefix when it's on the left and in the suffix when on the right.
+// Promoted nodes have no sibling and take no step. Both key and value must
+// be non-empty, as ICS-23 requires.
+//
+// Only existence proofs have a counterpart here; non-existence, batch and
+// compressed commitment proofs are refused rather than guessed at.
+
+use crate::hasher::{LEAF_PREFIX, NODE_PREFIX};
+use crate::{Data, HashDirection, Hasher, MerkleTree, Proof, Sha256Hasher};
+use ics23::commitment_proof::Proof as Ics23Proof;
+use ics23::{CommitmentProof, ExistenceProof, HashOp, InnerOp, InnerSpec, LeafOp, LengthOp, ProofSpec};
+use std::borrow::Cow;
+
+
+// A hasher ICS-23 has a hash op for. Trees without the leaf and node
+// prefixes, such as `Legacy` and `SortedKeccak`, can't be described.
+pub trait Ics23Hasher: Hasher {
+    fn hash_op(&self) -> HashOp;
+}
+
+
+impl Ics23Hasher for Sha256Hasher {
+    fn hash_op(&self) -> HashOp {
+        HashOp::Sha256
+    }
+}
+
+
+#[cfg(feature = "keccak")]
+impl Ics23Hasher for crate::keccak::Keccak256Hasher {
+    fn hash_op(&self) -> HashOp {
+        HashOp::Keccak256
+    }
+}
+
+
+fn leaf_op<H: Ics23Hasher>(hasher: &H) -> LeafOp {
+    LeafOp {
+        hash: hasher.hash_op().into(),
+        prehash_key: HashOp::NoHash.into(),
+        prehash_value: HashOp::NoHash.into(),
+        length: LengthOp::NoPrefix.into(),
+        prefix: vec![LEAF_PREFIX],
+    }
+}
+
+
+// The spec verifiers should check these proofs against
+pub fn proof_spec() -> ProofSpec {
+    proof_spec_with(&Sha256Hasher)
+}
+
+
+pub fn proof_spec_with<H: Ics23Hasher>(hasher: &H) -> ProofSpec {
+    let inner = InnerSpec {
+        child_order: vec![0, 1],
+        child_size: hasher.hash(&[]).len() as i32,
+        min_prefix_length: 1,
+        max_prefix_length: 1,
+        empty_child: vec![],
+        hash: hasher.hash_op().into(),
+    };
+    ProofSpec {
+        leaf_spec: Some(leaf_op(hasher)),
+        inner_spec: Some(inner),
+        min_depth: 0,
+        max_depth: 0,
+        prehash_key_before_comparison: false,
+    }
+}
+
+
+impl Proof<'_> {
+    // This proof for the leaf `key | value` as an ICS-23 existence proof
+    pub fn to_ics23(&self, key: &[u8], value: &[u8]) -> ExistenceProof {
+        self.to_ics23_with(key, value, &Sha256Hasher)
+    }
+
+
+    pub fn to_ics23_with<H: Ics23Hasher>(&self, key: &[u8], value: &[u8], hasher: &H) -> ExistenceProof {
+        let path = self
+            .hashes
+            .iter()
+            .map(|(direction, hash)| {
+                let (prefix, suffix) = match direction {
+                    HashDirection::Left => ([&[NODE_PREFIX], &hash[..]].concat(), vec![]),
+                    HashDirection::Right => (vec![NODE_PREFIX], hash.to_vec()),
+                };
+                InnerOp { hash: hasher.hash_op().into(), prefix, suffix }
+            })
+            .collect();
+        ExistenceProof { key: key.to_vec(), value: value.to_vec(), leaf: Some(leaf_op(hasher)), path }
+    }
+
+
+    // A proof from an ICS-23 existence proof laid out as `to_ics23` makes
+    // them, None for any other layout
+    pub fn from_ics23(proof: &ExistenceProof) -> Option<Proof<'static>> {
+        Self::from_ics23_with(proof, &Sha256Hasher)
+    }
+
+
+    pub fn from_ics23_with<H: Ics23Hasher>(proof: &ExistenceProof, hasher: &H) -> Option<Proof<'static>> {
+        if proof.leaf.as_ref() != Some(&leaf_op(hasher)) {
+            return None;
+        }
+        let hash_len = hasher.hash(&[]).len();
+        let hashes = proof
+            .path
+            .iter()
+            .map(|step| {
+                if step.hash != hasher.hash_op() as i32 || step.prefix.first() != Some(&NODE_PREFIX) {
+                    return None;
+                }
+                match (step.prefix.len(), step.suffix.len()) {
+                    (1, len) if len == hash_len => Some((HashDirection::Right, Cow::Owned(step.suffix.clone()))),
+                    (len, 0) if len == 1 + hash_len => Some((HashDirection::Left, Cow::Owned(step.prefix[1..].to_vec()))),
+                    _ => None,
+                }
+            })
+            .collect::<Option<_>>()?;
+        Some(Proof { hashes, position: None })
+    }
+
+
+    // The proof inside an ICS-23 commitment proof, which must be an
+    // existence proof laid out as `prove_ics23` makes them
+    pub fn from_commitment_proof(proof: &CommitmentProof) -> Option<Proof<'static>> {
+        Self::from_commitment_proof_with(proof, &Sha256Hasher)
+    }
+
+
+    pub fn from_commitment_proof_with<H: Ics23Hasher>(proof: &CommitmentProof, hasher: &H) -> Option<Proof<'static>> {
+        match proof.proof.as_ref()? {
+            Ics23Proof::Exist(exist) => Self::from_ics23_with(exist, hasher),
+            Ics23Proof::Nonexist(_) | Ics23Proof::Batch(_) | Ics23Proof::Compressed(_) => None,
+        }
+    }
+}
+
+
+impl<H: Ics23Hasher> MerkleTree<H> {
+    // Proves the leaf `key | value` as an ICS-23 commitment proof
+    pub fn prove_ics23(&self, key: &[u8], value: &[u8]) -> Option<CommitmentProof> {
+        let leaf: Data = [key, value].concat();
+        let exist = self.prove(&leaf)?.to_ics23_with(key, value, self.hasher());
+        Some(CommitmentProof { proof: Some(Ics23Proof::Exist(exist)) })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ics23::HostFunctionsManager;
+
+
+    #[test]
+    fn test_ics23_membership() {
+        let pairs: Vec<(Data, Data)> = (0..7u8).map(|i| (vec![b'k', i], vec![b'v', i, i])).collect();
+        let leaves: Vec<Data> = pairs.iter().map(|(key, value)| [&key[..], &value[..]].concat()).collect();
+        let tree = MerkleTree::construct(&leaves);
+        let (spec, root) = (proof_spec(), tree.root());
+        for (key, value) in &pairs {
+            let proof = tree.prove_ics23(key, value).unwrap();
+            assert!(ics23::verify_membership::<HostFunctionsManager>(&proof, &spec, &root, key, value));
+            assert!(!ics23::verify_membership::<HostFunctionsManager>(&proof, &spec, &root, key, b"other"));
+        }
+
+        let proof = tree.prove(&leaves[6]).unwrap();
+        let exist = proof.to_ics23(&pairs[6].0, &pairs[6].1);
+        assert_eq!(Proof::from_ics23(&exist), Some(proof.into_owned()));
+        let mut hashed_value = exist.clone();
+        hashed_value.leaf.as_mut().unwrap().prehash_value = HashOp::Sha256.into();
+        assert!(Proof::from_ics23(&hashed_value).is_none());
+    }
+
+    #[test]
+    fn test_commitment_proof_roundtrip() {
+        let leaves: Vec<Data> = (0..5u8).map(|i| vec![b'k', i, b'v']).collect();
+        let tree = MerkleTree::construct(&leaves);
+        let commitment = tree.prove_ics23(&[b'k', 3], b"v").unwrap();
+        let proof = Proof::from_commitment_proof(&commitment).unwrap();
+        assert_eq!(proof, tree.prove(&leaves[3]).unwrap().into_owned());
+        assert!(MerkleTree::verify_proof(&leaves[3], &proof, &tree.root()));
+
+        let nonexist = CommitmentProof { proof: Some(Ics23Proof::Nonexist(Default::default())) };
+        assert!(Proof::from_commitment_proof(&nonexist).is_none());
+        let batch = CommitmentProof { proof: Some(Ics23Proof::Batch(Default::default())) };
+        assert!(Proof::from_commitment_proof(&batch).is_none());
+        assert!(Proof::from_commitment_proof(&CommitmentProof { proof: None }).is_none());
+    }
+
+    #[cfg(feature = "keccak")]
+    #[test]
+    fn test_ics23_keccak() {
+        use crate::keccak::Keccak256Hasher;
+
+        let leaves: Vec<Data> = (0..6u8).map(|i| vec![b'k', i, b'v']).collect();
+        let tree = MerkleTree::construct_with(&leaves, Keccak256Hasher);
+        let spec = proof_spec_with(&Keccak256Hasher);
+        let commitment = tree.prove_ics23(&[b'k', 4], b"v").unwrap();
+        assert!(ics23::verify_membership::<HostFunctionsManager>(&commitment, &spec, &tree.root(), &[b'k', 4], b"v"));
+        assert!(!ics23::verify_membership::<HostFunctionsManager>(&commitment, &proof_spec(), &tree.root(), &[b'k', 4], b"v"));
+
+        let proof = Proof::from_commitment_proof_with(&commitment, &Keccak256Hasher).unwrap();
+        assert!(MerkleTree::verify_proof_with(&leaves[4], &proof, &tree.root(), &Keccak256Hasher));
+        assert!(Proof::from_commitment_proof(&commitment).is_none());
+    }
+}
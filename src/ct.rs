@@ -0,0 +1,26 @@
+// Constant-time comparison for the hashes verifiers check. A plain `==`
+// stops at the first differing byte, so its timing tells an attacker how
+// much of a forged hash was right. Lengths aren't secret, so hashes of
+// different lengths are rejected without comparing.
+
+use subtle::ConstantTimeEq;
+
+
+pub fn hashes_equal(a: &[u8], b: &[u8]) -> bool {
+    a.ct_eq(b).into()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    #[test]
+    fn test_hashes_equal() {
+        assert!(hashes_equal(&[1, 2, 3], &[1, 2, 3]));
+        assert!(!hashes_equal(&[1, 2, 3], &[1, 2, 4]));
+        assert!(!hashes_equal(&[1, 2, 3], &[1, 2]));
+        assert!(hashes_equal(&[], &[]));
+    }
+}
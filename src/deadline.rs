@@ -0,0 +1,99 @@
+// Deadlines for the expensive construction paths. Work stops at the first
+// check past the deadline and reports how far it got.
+
+use crate::{reduce_level, Data, Hash, Hasher, MerkleTree};
+use std::fmt;
+use std::time::Instant;
+
+
+// Hashes between clock reads, so checking stays cheap next to the hashing
+const CHECK_EVERY: usize = 64;
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DeadlineExceeded {
+    // Leaves hashed before giving up
+    pub leaves_hashed: usize,
+    // Levels above the leaves that were complete
+    pub levels_built: usize,
+}
+
+
+impl fmt::Display for DeadlineExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "deadline exceeded after {} leaves and {} levels", self.leaves_hashed, self.levels_built)
+    }
+}
+
+
+impl std::error::Error for DeadlineExceeded {}
+
+
+pub(crate) struct Deadline {
+    at: Instant,
+    ops: usize,
+}
+
+
+impl Deadline {
+    pub(crate) fn new(at: Instant) -> Deadline {
+        Deadline { at, ops: 0 }
+    }
+
+
+    // Counts one unit of work, reading the clock every `CHECK_EVERY` of them
+    pub(crate) fn passed(&mut self) -> bool {
+        self.ops += 1;
+        self.ops.is_multiple_of(CHECK_EVERY) && Instant::now() >= self.at
+    }
+
+
+    pub(crate) fn passed_now(&self) -> bool {
+        Instant::now() >= self.at
+    }
+}
+
+
+impl<H: Hasher> MerkleTree<H> {
+    // Like `construct_with`, but gives up once `deadline` has passed
+    pub fn construct_with_deadline(input: &[Data], hasher: H, deadline: Instant) -> Result<MerkleTree<H>, DeadlineExceeded> {
+        let mut clock = Deadline::new(deadline);
+        let mut leaves = Vec::with_capacity(input.len());
+        for data in input {
+            if clock.passed() {
+                return Err(DeadlineExceeded { leaves_hashed: leaves.len(), levels_built: 0 });
+            }
+            leaves.push(hasher.hash_leaf(data));
+        }
+
+        let mut levels: Vec<Vec<Hash>> = vec![leaves];
+        while levels.last().unwrap().len() > 1 {
+            if clock.passed_now() {
+                return Err(DeadlineExceeded { leaves_hashed: input.len(), levels_built: levels.len() - 1 });
+            }
+            let next = reduce_level(levels.last().unwrap(), &hasher);
+            levels.push(next);
+        }
+        Ok(MerkleTree::from_levels(levels, hasher))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Sha256Hasher;
+    use std::time::Duration;
+
+
+    #[test]
+    fn test_construct_with_deadline() {
+        let data: Vec<Data> = (0..200u8).map(|i| vec![i]).collect();
+        let later = Instant::now() + Duration::from_secs(60);
+        let tree = MerkleTree::construct_with_deadline(&data, Sha256Hasher, later).unwrap();
+        assert_eq!(tree.nodes, MerkleTree::construct(&data).nodes);
+
+        let result = MerkleTree::construct_with_deadline(&data, Sha256Hasher, Instant::now());
+        assert_eq!(result.err(), Some(DeadlineExceeded { leaves_hashed: CHECK_EVERY - 1, levels_built: 0 }));
+    }
+}
@@ -0,0 +1,153 @@
+// A fixed-depth, append-only tree in the style of the Ethereum deposit
+// contract. Only the rightmost filled subtree at each height is kept, so
+// memory is O(depth) however many leaves have been appended. Roots match
+// the contract's `get_deposit_root` and SSZ merkleization of the leaves
+// with a limit of 2^depth.
+
+use crate::ssz::mix_in_length;
+use crate::{Hash, Hasher, Sha256Hasher, ZeroHashes};
+use std::fmt;
+
+
+// The depth the deposit contract uses
+pub const DEPOSIT_CONTRACT_DEPTH: usize = 32;
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TreeFull {
+    pub capacity: u64,
+}
+
+
+impl fmt::Display for TreeFull {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "tree already holds its maximum of {} leaves", self.capacity)
+    }
+}
+
+
+impl std::error::Error for TreeFull {}
+
+
+pub struct DepositTree<H = Sha256Hasher> {
+    // branch[h] is the root of the last complete subtree of height h
+    branch: Vec<Hash>,
+    zeros: ZeroHashes,
+    count: u64,
+    hasher: H,
+}
+
+
+impl DepositTree {
+    pub fn new(depth: usize) -> DepositTree {
+        Self::new_with(depth, Sha256Hasher)
+    }
+}
+
+
+impl<H: Hasher> DepositTree<H> {
+    pub fn new_with(depth: usize, hasher: H) -> DepositTree<H> {
+        let zeros = ZeroHashes::ssz(depth, &hasher);
+        Self::new_with_zeros(depth, hasher, zeros)
+    }
+
+
+    // Shares a precomputed SSZ zero table at least `depth` deep
+    pub fn new_with_zeros(depth: usize, hasher: H, zeros: ZeroHashes) -> DepositTree<H> {
+        assert!(depth < 64, "depth must be below 64");
+        assert!(zeros.depth() >= depth, "zero table is shallower than the tree");
+        DepositTree { branch: zeros.as_slice()[..depth].to_vec(), zeros, count: 0, hasher }
+    }
+
+
+    pub fn depth(&self) -> usize {
+        self.branch.len()
+    }
+
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+
+    // Like the contract, one short of 2^depth so the count fits in depth bits
+    pub fn capacity(&self) -> u64 {
+        (1u64 << self.depth()) - 1
+    }
+
+
+    // Appends a leaf, returning its index. Hashes at most `depth` times.
+    pub fn push(&mut self, leaf: Hash) -> Result<u64, TreeFull> {
+        if self.count >= self.capacity() {
+            return Err(TreeFull { capacity: self.capacity() });
+        }
+        let index = self.count;
+        self.count += 1;
+        let mut size = self.count;
+        let mut node = leaf;
+        for height in 0..self.depth() {
+            if size & 1 == 1 {
+                self.branch[height] = node;
+                break;
+            }
+            node = self.hasher.hash_concat(&self.branch[height], &node);
+            size /= 2;
+        }
+        Ok(index)
+    }
+
+
+    // Root of the leaves padded with zero chunks to 2^depth
+    pub fn root(&self) -> Hash {
+        let mut node = self.zeros[0].clone();
+        let mut size = self.count;
+        for height in 0..self.depth() {
+            node = if size & 1 == 1 {
+                self.hasher.hash_concat(&self.branch[height], &node)
+            } else {
+                self.hasher.hash_concat(&node, &self.zeros[height])
+            };
+            size /= 2;
+        }
+        node
+    }
+
+
+    // The contract's `get_deposit_root`: the root with the count mixed in
+    pub fn deposit_root(&self) -> Hash {
+        mix_in_length(&self.root(), self.count as usize, &self.hasher)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SszTree;
+    use sha2::{Digest, Sha256};
+
+
+    #[test]
+    fn test_deposit_root() {
+        let mut tree = DepositTree::new(DEPOSIT_CONTRACT_DEPTH);
+        assert_eq!(hex::encode(tree.deposit_root()), "d70a234731285c6804c2a4f56711ddb8c82c99740f207854891028af34e27e5e");
+
+        let leaves: Vec<Hash> = (0..3u8).map(|i| Sha256::digest([i]).to_vec()).collect();
+        for (i, leaf) in leaves.iter().enumerate() {
+            assert_eq!(tree.push(leaf.clone()), Ok(i as u64));
+        }
+        assert_eq!(hex::encode(tree.deposit_root()), "d3d10838d1bcc72ba43735a0f785dc4eaa61f142ef05b18592dcee6cac11f31b");
+    }
+
+    #[test]
+    fn test_matches_ssz_and_fills_up() {
+        let mut tree = DepositTree::new(4);
+        for i in 0..15u8 {
+            tree.push(vec![i; 32]).unwrap();
+            let leaves: Vec<Hash> = (0..=i).map(|j| vec![j; 32]).collect();
+            let ssz = SszTree::merkleize_with(&leaves, Some(16), Sha256Hasher).unwrap();
+            assert_eq!(tree.root(), ssz.root());
+        }
+        assert_eq!(tree.push(vec![0; 32]), Err(TreeFull { capacity: 15 }));
+    }
+}
@@ -0,0 +1,70 @@
+use crate::{Error, Hasher, MerkleTree};
+
+
+impl<H: Hasher> MerkleTree<H> {
+    // Indices of the leaves that differ between the two trees, in order.
+    // Trees of the same size are walked from the root down, skipping every
+    // subtree whose hashes already agree; otherwise the leaves are compared
+    // directly and the extra leaves of the larger tree all count as changed.
+    pub fn diff(&self, other: &MerkleTree<H>) -> Result<Vec<usize>, Error> {
+        if self.leaf_count == other.leaf_count && self.nodes.last() == other.nodes.last() {
+            return Ok(Vec::new());
+        }
+        let (ours, theirs) = match (self.levels(), other.levels()) {
+            (Some(ours), Some(theirs)) => (ours, theirs),
+            _ => return Err(Error::NotStored),
+        };
+
+        if self.leaf_count != other.leaf_count {
+            let common = self.leaf_count.min(other.leaf_count);
+            let changed = (0..common).filter(|&i| ours[0][i] != theirs[0][i]);
+            return Ok(changed.chain(common..self.leaf_count.max(other.leaf_count)).collect());
+        }
+
+        let mut changed = Vec::new();
+        // Right child pushed first so leaves come out in order
+        let mut pending = vec![(ours.len() - 1, 0)];
+        while let Some((level, idx)) = pending.pop() {
+            if ours[level][idx] == theirs[level][idx] {
+                continue;
+            }
+            if level == 0 {
+                changed.push(idx);
+                continue;
+            }
+            if idx * 2 + 1 < ours[level - 1].len() {
+                pending.push((level - 1, idx * 2 + 1));
+            }
+            pending.push((level - 1, idx * 2));
+        }
+        Ok(changed)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Data, Sha256Hasher, StorageMode};
+
+
+    #[test]
+    fn test_diff() {
+        let data: Vec<Data> = (0..11u8).map(|i| vec![i]).collect();
+        let tree = MerkleTree::construct(&data);
+        assert_eq!(tree.diff(&MerkleTree::construct(&data)), Ok(vec![]));
+
+        let mut changed = data.clone();
+        for i in [1, 6, 10] {
+            changed[i] = vec![100 + i as u8];
+        }
+        let other = MerkleTree::construct_with_mode(&changed, Sha256Hasher, StorageMode::LeavesOnly);
+        assert_eq!(tree.diff(&other), Ok(vec![1, 6, 10]));
+
+        let shorter = MerkleTree::construct(&changed[..8]);
+        assert_eq!(tree.diff(&shorter), Ok(vec![1, 6, 8, 9, 10]));
+
+        let root_only = MerkleTree::construct_with_mode(&changed, Sha256Hasher, StorageMode::RootOnly);
+        assert_eq!(tree.diff(&root_only), Err(Error::NotStored));
+    }
+}
@@ -0,0 +1,249 @@
+// Differential testing of the tree against a naive reference implementation.
+// The reference splits recursively at the largest power of two below the leaf
+// count, which yields the same roots as the level-by-level reduction without
+// sharing any of its code. Splitting at the largest power of the arity does
+// the same for the k-ary tree, and dropping the prefixes for Legacy.
+
+use crate::{Data, Hash, HashDirection, Hasher, KaryStep, KaryTree, Legacy, MerkleTree, Sha256Hasher, StorageMode};
+use sha2::Digest;
+
+
+// Where the tree and the reference disagreed
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mismatch {
+    pub seed: u64,
+    pub iteration: usize,
+    pub leaf_count: usize,
+    pub scheme: Scheme,
+    pub kind: MismatchKind,
+}
+
+
+// Which tree construction disagreed with the reference
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scheme {
+    Standard,
+    Legacy,
+    Storage(StorageMode),
+    Kary(usize),
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MismatchKind {
+    Root,
+    Verify,
+    Proof { leaf: usize },
+    VerifyProof { leaf: usize },
+    ForgedProofAccepted { leaf: usize },
+}
+
+
+// Runs `iterations` randomized trees of up to `max_leaves` leaves through every
+// prove/verify path of every scheme and compares them with the reference
+pub fn run(seed: u64, iterations: usize, max_leaves: usize) -> Result<(), Mismatch> {
+    let mut rng = XorShift::new(seed);
+    for iteration in 0..iterations {
+        let leaf_count = 1 + rng.below(max_leaves.max(1));
+        let input: Vec<Data> = (0..leaf_count)
+            .map(|i| {
+                // Prefix with the index so leaves are distinct
+                let mut leaf = (i as u64).to_le_bytes().to_vec();
+                leaf.extend((0..rng.below(48)).map(|_| rng.next() as u8));
+                leaf
+            })
+            .collect();
+        let mismatch = |scheme| move |kind| Mismatch { seed, iteration, leaf_count, scheme, kind };
+
+        check_binary(&input, &MerkleTree::construct(&input), true).map_err(mismatch(Scheme::Standard))?;
+        check_binary(&input, &MerkleTree::construct_with(&input, Legacy(Sha256Hasher)), false)
+            .map_err(mismatch(Scheme::Legacy))?;
+        for mode in [StorageMode::LeavesOnly, StorageMode::RootOnly] {
+            let tree = MerkleTree::construct_with_mode(&input, Sha256Hasher, mode);
+            check_binary(&input, &tree, true).map_err(mismatch(Scheme::Storage(mode)))?;
+        }
+        for arity in [3, 4] {
+            check_kary(&input, arity).map_err(mismatch(Scheme::Kary(arity)))?;
+        }
+    }
+    Ok(())
+}
+
+
+fn check_binary<H: Hasher>(input: &[Data], tree: &MerkleTree<H>, prefixed: bool) -> Result<(), MismatchKind> {
+    let hasher = tree.hasher();
+    let leaves: Vec<Hash> = input.iter().map(|leaf| reference_hash(leaf, prefixed)).collect();
+    let root = reference_root(&leaves, 2, prefixed);
+    if tree.root() != root {
+        return Err(MismatchKind::Root);
+    }
+    if !MerkleTree::verify_with(input, &root, hasher) {
+        return Err(MismatchKind::Verify);
+    }
+    // A root-only tree has nothing to prove from
+    if tree.storage_mode() == StorageMode::RootOnly {
+        return Ok(());
+    }
+
+    for (leaf, data) in input.iter().enumerate() {
+        let proof = tree.prove(data).ok_or(MismatchKind::Proof { leaf })?;
+        let got: Vec<(HashDirection, Hash)> = proof.hashes.iter().map(|(d, h)| (*d, h.to_vec())).collect();
+        if got != reference_proof(&leaves, leaf, prefixed) {
+            return Err(MismatchKind::Proof { leaf });
+        }
+        if !MerkleTree::verify_proof_with(data, &proof, &root, hasher) {
+            return Err(MismatchKind::VerifyProof { leaf });
+        }
+        let mut forged = data.clone();
+        forged.push(0xff);
+        if MerkleTree::verify_proof_with(&forged, &proof, &root, hasher) {
+            return Err(MismatchKind::ForgedProofAccepted { leaf });
+        }
+    }
+    Ok(())
+}
+
+
+fn check_kary(input: &[Data], arity: usize) -> Result<(), MismatchKind> {
+    let tree = KaryTree::construct(input, arity);
+    let leaves: Vec<Hash> = input.iter().map(|leaf| reference_hash(leaf, true)).collect();
+    let root = reference_root(&leaves, arity, true);
+    if tree.root() != root {
+        return Err(MismatchKind::Root);
+    }
+
+    for (leaf, data) in input.iter().enumerate() {
+        let proof = tree.prove(data).ok_or(MismatchKind::Proof { leaf })?;
+        if proof.steps != reference_kary_proof(&leaves, leaf, arity) {
+            return Err(MismatchKind::Proof { leaf });
+        }
+        if !KaryTree::verify_proof(data, &proof, &root) {
+            return Err(MismatchKind::VerifyProof { leaf });
+        }
+        let mut forged = data.clone();
+        forged.push(0xff);
+        if KaryTree::verify_proof(&forged, &proof, &root) {
+            return Err(MismatchKind::ForgedProofAccepted { leaf });
+        }
+    }
+    Ok(())
+}
+
+
+// Legacy trees hash leaves and nodes with no prefix
+fn reference_hash(data: &[u8], prefixed: bool) -> Hash {
+    let mut hasher = sha2::Sha256::new();
+    if prefixed {
+        hasher.update([0x00]);
+    }
+    hasher.update(data);
+    hasher.finalize().to_vec()
+}
+
+
+fn reference_parent(children: &[Hash], prefixed: bool) -> Hash {
+    let mut hasher = sha2::Sha256::new();
+    if prefixed {
+        hasher.update([0x01]);
+    }
+    for child in children {
+        hasher.update(child);
+    }
+    hasher.finalize().to_vec()
+}
+
+
+// Size of each child subtree when splitting n > 1 leaves
+fn split_point(n: usize, arity: usize) -> usize {
+    let mut k = 1;
+    while k * arity < n {
+        k *= arity;
+    }
+    k
+}
+
+
+fn reference_root(leaves: &[Hash], arity: usize, prefixed: bool) -> Hash {
+    if leaves.len() == 1 {
+        return leaves[0].clone();
+    }
+    let children: Vec<Hash> = leaves
+        .chunks(split_point(leaves.len(), arity))
+        .map(|chunk| reference_root(chunk, arity, prefixed))
+        .collect();
+    reference_parent(&children, prefixed)
+}
+
+
+// Sibling path from the leaf up to the root
+fn reference_proof(leaves: &[Hash], index: usize, prefixed: bool) -> Vec<(HashDirection, Hash)> {
+    if leaves.len() == 1 {
+        return Vec::new();
+    }
+    let k = split_point(leaves.len(), 2);
+    if index < k {
+        let mut path = reference_proof(&leaves[..k], index, prefixed);
+        path.push((HashDirection::Right, reference_root(&leaves[k..], 2, prefixed)));
+        path
+    } else {
+        let mut path = reference_proof(&leaves[k..], index - k, prefixed);
+        path.push((HashDirection::Left, reference_root(&leaves[..k], 2, prefixed)));
+        path
+    }
+}
+
+
+// Groups of siblings from the leaf up to the root
+fn reference_kary_proof(leaves: &[Hash], index: usize, arity: usize) -> Vec<KaryStep> {
+    if leaves.len() == 1 {
+        return Vec::new();
+    }
+    let k = split_point(leaves.len(), arity);
+    let position = index / k;
+    let chunks: Vec<&[Hash]> = leaves.chunks(k).collect();
+    let mut path = reference_kary_proof(chunks[position], index % k, arity);
+    let siblings = chunks
+        .iter()
+        .enumerate()
+        .filter(|&(i, _)| i != position)
+        .map(|(_, chunk)| reference_root(chunk, arity, true))
+        .collect();
+    path.push(KaryStep { position, siblings });
+    path
+}
+
+
+// Small deterministic generator so runs are reproducible from the seed alone
+struct XorShift(u64);
+
+
+impl XorShift {
+    fn new(seed: u64) -> XorShift {
+        XorShift(seed ^ 0x9e37_79b9_7f4a_7c15 | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    #[test]
+    fn test_difftest_run() {
+        for seed in 0..4 {
+            assert_eq!(run(seed, 20, 40), Ok(()));
+        }
+    }
+}
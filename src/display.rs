@@ -0,0 +1,157 @@
+// Formatting for trees and proofs. Hashes print as their first few bytes in
+// hex, so a tree or proof fits on a log line; the alternate form (`{:#}`)
+// prints them in full.
+//
+// `Hash` itself stays a `Vec<u8>` alias, which formats as a list of numbers.
+// The newtype for hashes is `NodeHash` (types.rs), which formats like the
+// hashes here; see there for why the alias stays.
+
+use crate::render::SHORT_HASH_BYTES;
+use crate::{HashDirection, Hasher, MerkleTree, Proof};
+use std::fmt;
+
+
+// A hash shown as hex, shortened unless formatted with `{:#}`
+#[derive(Clone, Copy, PartialEq)]
+pub struct HexHash<'a>(pub &'a [u8]);
+
+
+impl fmt::Display for HexHash<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if f.alternate() || self.0.len() <= SHORT_HASH_BYTES {
+            write!(f, "{}", hex::encode(self.0))
+        } else {
+            write!(f, "{}…", hex::encode(&self.0[..SHORT_HASH_BYTES]))
+        }
+    }
+}
+
+
+impl fmt::Debug for HexHash<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+
+// The root, or "empty" for a tree with no leaves, where `root` would panic
+struct RootHash<'a>(Option<&'a [u8]>);
+
+
+impl fmt::Display for RootHash<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(root) => fmt::Display::fmt(&HexHash(root), f),
+            None => write!(f, "empty"),
+        }
+    }
+}
+
+
+impl fmt::Debug for RootHash<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+
+impl<H: Hasher> MerkleTree<H> {
+    fn display_root(&self) -> RootHash<'_> {
+        RootHash(self.nodes.last().and_then(|level| level.first()).map(Vec::as_slice))
+    }
+}
+
+
+impl<H: Hasher> fmt::Debug for MerkleTree<H> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MerkleTree")
+            .field("leaf_count", &self.leaf_count)
+            .field("depth", &self.depth())
+            .field("mode", &self.mode)
+            .field("root", &self.display_root())
+            .finish()
+    }
+}
+
+
+impl<H: Hasher> fmt::Display for MerkleTree<H> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "tree of {} leaves with root ", self.leaf_count)?;
+        fmt::Display::fmt(&self.display_root(), f)
+    }
+}
+
+
+// Trees are equal when they keep the same nodes the same way, whatever
+// their hashers
+impl<H> PartialEq for MerkleTree<H> {
+    fn eq(&self, other: &Self) -> bool {
+        self.leaf_count == other.leaf_count && self.mode == other.mode && self.nodes == other.nodes && self.pins == other.pins
+    }
+}
+
+
+impl fmt::Debug for Proof<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let hashes: Vec<_> = self.hashes.iter().map(|(direction, hash)| (direction, HexHash(hash))).collect();
+        f.debug_struct("Proof").field("hashes", &hashes).field("position", &self.position).finish()
+    }
+}
+
+
+// One step per hash, `L` or `R` for its side, then the position if claimed:
+// `[L 1a2b3c4d…, R 5e6f7a8b…] at 3 of 9`
+impl fmt::Display for Proof<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[")?;
+        for (i, (direction, hash)) in self.hashes.iter().enumerate() {
+            let side = match direction {
+                HashDirection::Left => "L",
+                HashDirection::Right => "R",
+            };
+            write!(f, "{}{} ", if i > 0 { ", " } else { "" }, side)?;
+            fmt::Display::fmt(&HexHash(hash), f)?;
+        }
+        write!(f, "]")?;
+        if let Some(position) = self.position {
+            write!(f, " at {} of {}", position.index, position.tree_size)?;
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Data, Sha256Hasher, StorageMode};
+
+
+    #[test]
+    fn test_formatting() {
+        let data: Vec<Data> = (0..9u8).map(|i| vec![i]).collect();
+        let tree = MerkleTree::construct(&data);
+        let root = hex::encode(tree.root());
+        assert_eq!(tree.to_string(), format!("tree of 9 leaves with root {}…", &root[..8]));
+        assert_eq!(format!("{:#}", tree), format!("tree of 9 leaves with root {}", root));
+        assert!(format!("{:?}", tree).contains("leaf_count: 9, depth: 4"));
+
+        let proof = tree.prove_with_position(&data[8]).unwrap();
+        let sibling = hex::encode(&tree.level(3).unwrap()[0][..4]);
+        assert_eq!(proof.to_string(), format!("[L {}…] at 8 of 9", sibling));
+        assert_eq!(proof.clone(), proof);
+
+        let empty = MerkleTree::construct::<Data>(&[]);
+        assert_eq!(empty.to_string(), "tree of 0 leaves with root empty");
+        assert!(format!("{:?}", empty).contains("root: empty"));
+    }
+
+    #[test]
+    fn test_structural_equality() {
+        let data: Vec<Data> = (0..5u8).map(|i| vec![i]).collect();
+        let tree = MerkleTree::construct(&data);
+        assert_eq!(tree, MerkleTree::construct(&data));
+        assert_ne!(tree, MerkleTree::construct(&data[..4]));
+        assert_ne!(tree, MerkleTree::construct_with_mode(&data, Sha256Hasher, StorageMode::RootOnly));
+    }
+}
@@ -0,0 +1,448 @@
+// Compact binary encoding of proofs:
+//
+//   count: u8 | hash_len: u8 | direction bitmap: ceil(count / 8) bytes | hashes
+//
+// Bit i of the bitmap (least significant first) is set when hash i goes on
+// the left. All hashes of a proof come from one hasher, so they share a length.
+// A proof that claims a position is followed by index: u64 | tree_size: u64.
+//
+// For JSON and other text formats, roots, proofs and receipts also come as
+// lowercase hex and as standard padded base64 of the same bytes.
+
+use crate::{Data, Hash, HashDirection, Hasher, MerkleTree, Position, Proof, Receipt, SignedTreeHead};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use std::borrow::Cow;
+use std::fmt;
+
+
+// Bounds for `decode_untrusted`. No tree of up to 2^64 leaves needs more
+// hashes, and no hash function in use makes longer digests.
+pub const MAX_UNTRUSTED_HASHES: usize = 64;
+pub const MAX_UNTRUSTED_HASH_LEN: usize = 64;
+
+
+// Why `decode_untrusted` turned down its input
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DecodeError {
+    Truncated { needed: usize, available: usize },
+    TooManyHashes { count: usize, max: usize },
+    // Zero with hashes present, nonzero with none, or over the maximum
+    HashLength { len: usize },
+    TrailingBytes { extra: usize },
+    // Bits past the last hash are set in the direction bitmap
+    PaddingBits,
+    // The claimed position doesn't fit this platform or the tree
+    BadPosition { index: u64, tree_size: u64 },
+}
+
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::Truncated { needed, available } => {
+                write!(f, "proof needs {} bytes but only {} are present", needed, available)
+            }
+            DecodeError::TooManyHashes { count, max } => write!(f, "proof has {} hashes, over the limit of {}", count, max),
+            DecodeError::HashLength { len } => write!(f, "hash length {} isn't valid for this proof", len),
+            DecodeError::TrailingBytes { extra } => write!(f, "{} bytes after the end of the proof", extra),
+            DecodeError::PaddingBits => write!(f, "direction bitmap has bits set past the last hash"),
+            DecodeError::BadPosition { index, tree_size } => {
+                write!(f, "position {} of {} leaves isn't valid", index, tree_size)
+            }
+        }
+    }
+}
+
+
+impl std::error::Error for DecodeError {}
+
+
+pub fn hash_from_hex(text: &str) -> Option<Hash> {
+    hex::decode(text).ok()
+}
+
+
+pub fn hash_from_base64(text: &str) -> Option<Hash> {
+    STANDARD.decode(text).ok()
+}
+
+
+impl<H: Hasher> MerkleTree<H> {
+    pub fn root_hex(&self) -> String {
+        hex::encode(self.root())
+    }
+
+
+    pub fn root_base64(&self) -> String {
+        STANDARD.encode(self.root())
+    }
+}
+
+
+impl Proof<'_> {
+    pub fn encode(&self) -> Vec<u8> {
+        let count = self.hashes.len();
+        assert!(count <= u8::MAX as usize, "proof too long to encode");
+        let hash_len = self.hashes.first().map_or(0, |(_, hash)| hash.len());
+        assert!(hash_len <= u8::MAX as usize, "hash too long to encode");
+
+        let mut out = vec![count as u8, hash_len as u8];
+        let mut bitmap = vec![0u8; count.div_ceil(8)];
+        for (i, (direction, hash)) in self.hashes.iter().enumerate() {
+            assert_eq!(hash.len(), hash_len, "proof hashes must share a length");
+            if *direction == HashDirection::Left {
+                bitmap[i / 8] |= 1 << (i % 8);
+            }
+        }
+        out.extend(bitmap);
+        for (_, hash) in self.hashes.iter() {
+            out.extend_from_slice(hash);
+        }
+        if let Some(Position { index, tree_size }) = self.position {
+            out.extend_from_slice(&(index as u64).to_be_bytes());
+            out.extend_from_slice(&(tree_size as u64).to_be_bytes());
+        }
+        out
+    }
+
+
+    // Decodes a proof, returning None unless `bytes` is exactly one well-formed proof
+    pub fn decode(bytes: &[u8]) -> Option<Proof<'static>> {
+        let (&count, rest) = bytes.split_first()?;
+        let (&hash_len, rest) = rest.split_first()?;
+        let (count, hash_len) = (count as usize, hash_len as usize);
+        let bitmap_len = count.div_ceil(8);
+        let body_len = bitmap_len + count * hash_len;
+        let position = match rest.len().checked_sub(body_len)? {
+            0 => None,
+            16 => Some(Position {
+                index: usize::try_from(u64::from_be_bytes(rest[body_len..body_len + 8].try_into().ok()?)).ok()?,
+                tree_size: usize::try_from(u64::from_be_bytes(rest[body_len + 8..].try_into().ok()?)).ok()?,
+            }),
+            _ => return None,
+        };
+        let (bitmap, hashes) = rest[..body_len].split_at(bitmap_len);
+        let hashes = (0..count)
+            .map(|i| {
+                let direction = if bitmap[i / 8] & (1 << (i % 8)) != 0 {
+                    HashDirection::Left
+                } else {
+                    HashDirection::Right
+                };
+                (direction, Cow::Owned(hashes[i * hash_len..(i + 1) * hash_len].to_vec()))
+            })
+            .collect();
+        Some(Proof { hashes, position })
+    }
+
+
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.encode())
+    }
+
+
+    pub fn from_hex(text: &str) -> Option<Proof<'static>> {
+        Proof::decode(&hex::decode(text).ok()?)
+    }
+
+
+    pub fn to_base64(&self) -> String {
+        STANDARD.encode(self.encode())
+    }
+
+
+    pub fn from_base64(text: &str) -> Option<Proof<'static>> {
+        Proof::decode(&STANDARD.decode(text).ok()?)
+    }
+
+
+    // Like `decode`, for bytes from an untrusted peer. Every length is
+    // checked against the input and the bounds above before anything is
+    // allocated, so no input can make it allocate more than
+    // MAX_UNTRUSTED_HASHES * MAX_UNTRUSTED_HASH_LEN bytes, and the encoding
+    // must be canonical: an empty proof has a zero hash length and unused
+    // bitmap bits are clear.
+    pub fn decode_untrusted(bytes: &[u8]) -> Result<Proof<'static>, DecodeError> {
+        let truncated = |needed: usize| DecodeError::Truncated { needed, available: bytes.len() };
+        let [count, hash_len, rest @ ..] = bytes else {
+            return Err(truncated(2));
+        };
+        let (count, hash_len) = (*count as usize, *hash_len as usize);
+        if count > MAX_UNTRUSTED_HASHES {
+            return Err(DecodeError::TooManyHashes { count, max: MAX_UNTRUSTED_HASHES });
+        }
+        if (count == 0) != (hash_len == 0) || hash_len > MAX_UNTRUSTED_HASH_LEN {
+            return Err(DecodeError::HashLength { len: hash_len });
+        }
+        let bitmap_len = count.div_ceil(8);
+        let body_len = bitmap_len + count * hash_len;
+        if rest.len() < body_len {
+            return Err(truncated(2 + body_len));
+        }
+        let position = match rest.len() - body_len {
+            0 => None,
+            extra if extra < 16 => return Err(truncated(2 + body_len + 16)),
+            16 => {
+                let index = u64::from_be_bytes(rest[body_len..body_len + 8].try_into().unwrap());
+                let tree_size = u64::from_be_bytes(rest[body_len + 8..].try_into().unwrap());
+                let bad = DecodeError::BadPosition { index, tree_size };
+                let position = Position {
+                    index: usize::try_from(index).map_err(|_| bad)?,
+                    tree_size: usize::try_from(tree_size).map_err(|_| bad)?,
+                };
+                if position.index >= position.tree_size {
+                    return Err(bad);
+                }
+                Some(position)
+            }
+            extra => return Err(DecodeError::TrailingBytes { extra: extra - 16 }),
+        };
+        let (bitmap, hashes) = rest[..body_len].split_at(bitmap_len);
+        if !count.is_multiple_of(8) && bitmap[bitmap_len - 1] >> (count % 8) != 0 {
+            return Err(DecodeError::PaddingBits);
+        }
+        let hashes = hashes
+            .chunks(hash_len.max(1))
+            .enumerate()
+            .map(|(i, hash)| {
+                let direction = if bitmap[i / 8] & (1 << (i % 8)) != 0 { HashDirection::Left } else { HashDirection::Right };
+                (direction, Cow::Owned(hash.to_vec()))
+            })
+            .collect();
+        Ok(Proof { hashes, position })
+    }
+
+
+    // Copies any borrowed hashes so the proof can outlive its tree
+    pub fn into_owned(self) -> Proof<'static> {
+        let hashes = self.hashes
+            .into_iter()
+            .map(|(direction, hash)| (direction, Cow::Owned(hash.into_owned())))
+            .collect();
+        Proof { hashes, position: self.position }
+    }
+}
+
+
+// Signed tree heads are the signed message followed by the signature with
+// a u32 length prefix
+impl SignedTreeHead {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = self.signed_bytes();
+        put_bytes(&mut out, &self.signature);
+        out
+    }
+
+
+    pub fn decode(mut bytes: &[u8]) -> Option<SignedTreeHead> {
+        let tree_size = usize::try_from(take_u64(&mut bytes)?).ok()?;
+        let timestamp = take_u64(&mut bytes)?;
+        let key_id = take_data(&mut bytes)?;
+        let root = take_hash(&mut bytes)?;
+        let signature = take_data(&mut bytes)?;
+        bytes.is_empty().then_some(SignedTreeHead { tree_size, timestamp, key_id, root, signature })
+    }
+}
+
+
+// Receipts are the data, root and encoded proof, each with a u32 length
+// prefix, then the encoded signed tree head with one if the receipt has it
+impl Receipt<'_> {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        put_bytes(&mut out, &self.data);
+        put_bytes(&mut out, &self.root);
+        put_bytes(&mut out, &self.proof.encode());
+        if let Some(head) = &self.head {
+            put_bytes(&mut out, &head.encode());
+        }
+        out
+    }
+
+
+    pub fn decode(mut bytes: &[u8]) -> Option<Receipt<'static>> {
+        let data = take_data(&mut bytes)?;
+        let root = take_hash(&mut bytes)?;
+        let proof = Proof::decode(take_bytes(&mut bytes)?)?;
+        let head = if bytes.is_empty() { None } else { Some(SignedTreeHead::decode(take_bytes(&mut bytes)?)?) };
+        bytes.is_empty().then_some(Receipt { data, proof, root, head })
+    }
+
+
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.encode())
+    }
+
+
+    pub fn from_hex(text: &str) -> Option<Receipt<'static>> {
+        Receipt::decode(&hex::decode(text).ok()?)
+    }
+
+
+    pub fn to_base64(&self) -> String {
+        STANDARD.encode(self.encode())
+    }
+
+
+    pub fn from_base64(text: &str) -> Option<Receipt<'static>> {
+        Receipt::decode(&STANDARD.decode(text).ok()?)
+    }
+}
+
+
+// Appends a u32 length prefix followed by the bytes
+pub(crate) fn put_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    out.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+    out.extend_from_slice(bytes);
+}
+
+
+// Reads bytes written by `put_bytes`, advancing `input` past them
+pub(crate) fn take_bytes<'b>(input: &mut &'b [u8]) -> Option<&'b [u8]> {
+    if input.len() < 4 {
+        return None;
+    }
+    let (len, rest) = input.split_at(4);
+    let len = u32::from_be_bytes(len.try_into().ok()?) as usize;
+    if rest.len() < len {
+        return None;
+    }
+    let (bytes, rest) = rest.split_at(len);
+    *input = rest;
+    Some(bytes)
+}
+
+
+fn take_u64(input: &mut &[u8]) -> Option<u64> {
+    let (value, rest) = input.split_first_chunk::<8>()?;
+    *input = rest;
+    Some(u64::from_be_bytes(*value))
+}
+
+
+pub(crate) fn take_data(input: &mut &[u8]) -> Option<Data> {
+    take_bytes(input).map(<[u8]>::to_vec)
+}
+
+
+pub(crate) fn take_hash(input: &mut &[u8]) -> Option<Hash> {
+    take_data(input)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MerkleTree;
+
+
+    #[test]
+    fn test_proof_encoding_roundtrip() {
+        for n in 1..=20 {
+            let data: Vec<Data> = (0..n as u8).map(|i| vec![i]).collect();
+            let tree = MerkleTree::construct(&data);
+            for leaf in &data {
+                let encoded = tree.prove(leaf).unwrap().encode();
+                let decoded = Proof::decode(&encoded).unwrap();
+                assert!(MerkleTree::verify_proof(leaf, &decoded, &tree.root()));
+                assert_eq!(decoded.encode(), encoded);
+            }
+        }
+    }
+
+    #[test]
+    fn test_proof_decode_rejects_malformed() {
+        let data: Vec<Data> = (0..9u8).map(|i| vec![i]).collect();
+        let tree = MerkleTree::construct(&data);
+        let encoded = tree.prove(&data[3]).unwrap().encode();
+        assert!(Proof::decode(&[]).is_none());
+        assert!(Proof::decode(&encoded[..encoded.len() - 1]).is_none());
+        assert!(Proof::decode(&[encoded.clone(), vec![0]].concat()).is_none());
+        assert_eq!(Proof::decode(&[0, 0]).unwrap().encode(), vec![0, 0]);
+    }
+
+    #[test]
+    fn test_positioned_proof_encoding() {
+        let data: Vec<Data> = (0..6u8).map(|i| vec![i]).collect();
+        let tree = MerkleTree::construct(&data);
+        let encoded = tree.prove_with_position(&data[5]).unwrap().encode();
+        let decoded = Proof::decode(&encoded).unwrap();
+        assert_eq!(decoded.position(), Some(Position { index: 5, tree_size: 6 }));
+        assert!(MerkleTree::verify_proof(&data[5], &decoded, &tree.root()));
+        assert!(Proof::decode(&encoded[..encoded.len() - 1]).is_none());
+    }
+
+    #[test]
+    fn test_receipt_encoding_roundtrip() {
+        let data: Vec<Data> = (0..5u8).map(|i| vec![i]).collect();
+        let tree = MerkleTree::construct(&data);
+        let mut receipt = Receipt { data: data[4].clone(), proof: tree.prove(&data[4]).unwrap(), root: tree.root(), head: None };
+        let encoded = receipt.encode();
+        let decoded = Receipt::decode(&encoded).unwrap();
+        assert_eq!((&decoded.data, &decoded.root), (&receipt.data, &receipt.root));
+        assert_eq!(decoded.encode(), encoded);
+        assert!(Receipt::decode(&encoded[1..]).is_none());
+
+        let head = SignedTreeHead { tree_size: 5, timestamp: 7, key_id: b"k".to_vec(), root: tree.root(), signature: vec![1; 8] };
+        receipt.head = Some(head.clone());
+        let encoded = receipt.encode();
+        assert_eq!(Receipt::decode(&encoded).unwrap().head, Some(head));
+        assert!(Receipt::decode(&encoded[..encoded.len() - 1]).is_none());
+    }
+
+    #[test]
+    fn test_text_encodings() {
+        let data: Vec<Data> = (0..7u8).map(|i| vec![i]).collect();
+        let tree = MerkleTree::construct(&data);
+        let root = tree.root();
+        assert_eq!(hash_from_hex(&tree.root_hex()), Some(root.clone()));
+        assert_eq!(hash_from_base64(&tree.root_base64()), Some(root.clone()));
+        assert!(hash_from_hex("xyz").is_none());
+
+        let proof = tree.prove_with_position(&data[6]).unwrap();
+        assert_eq!(proof.to_hex(), hex::encode(proof.encode()));
+        for decoded in [Proof::from_hex(&proof.to_hex()), Proof::from_base64(&proof.to_base64())] {
+            let decoded = decoded.unwrap();
+            assert_eq!(decoded.position(), proof.position());
+            assert!(MerkleTree::verify_proof(&data[6], &decoded, &root));
+        }
+        assert!(Proof::from_base64("not base64!").is_none());
+
+        let receipt = Receipt { data: data[2].clone(), proof: tree.prove(&data[2]).unwrap(), root, head: None };
+        assert_eq!(Receipt::from_hex(&receipt.to_hex()).unwrap().encode(), receipt.encode());
+        assert_eq!(Receipt::from_base64(&receipt.to_base64()).unwrap().encode(), receipt.encode());
+    }
+
+    #[test]
+    fn test_decode_untrusted() {
+        let data: Vec<Data> = (0..9u8).map(|i| vec![i]).collect();
+        let tree = MerkleTree::construct(&data);
+        let encoded = tree.prove_with_position(&data[3]).unwrap().encode();
+        let decoded = Proof::decode_untrusted(&encoded).unwrap();
+        assert!(MerkleTree::verify_proof(&data[3], &decoded, &tree.root()));
+        assert_eq!(decoded.encode(), encoded);
+
+        // Every cut of a valid proof is refused, except the one dropping
+        // just the position
+        for len in 0..encoded.len() {
+            assert_eq!(Proof::decode_untrusted(&encoded[..len]).is_ok(), len == encoded.len() - 16);
+        }
+        assert_eq!(Proof::decode_untrusted(&[]).err(), Some(DecodeError::Truncated { needed: 2, available: 0 }));
+        assert_eq!(Proof::decode_untrusted(&[65, 32]).err(), Some(DecodeError::TooManyHashes { count: 65, max: 64 }));
+        assert_eq!(Proof::decode_untrusted(&[0, 5]).err(), Some(DecodeError::HashLength { len: 5 }));
+        assert_eq!(Proof::decode_untrusted(&[1, 0]).err(), Some(DecodeError::HashLength { len: 0 }));
+        assert_eq!(Proof::decode_untrusted(&[0, 0]).unwrap().len(), 0);
+        assert_eq!(
+            Proof::decode_untrusted(&[encoded.clone(), vec![0]].concat()).err(),
+            Some(DecodeError::TrailingBytes { extra: 1 })
+        );
+        let mut padded = encoded.clone();
+        padded[2] |= 0x80;
+        assert_eq!(Proof::decode_untrusted(&padded).err(), Some(DecodeError::PaddingBits));
+        let mut past_end = encoded.clone();
+        let at = past_end.len() - 16;
+        past_end[at..at + 8].copy_from_slice(&9u64.to_be_bytes());
+        assert_eq!(Proof::decode_untrusted(&past_end).err(), Some(DecodeError::BadPosition { index: 9, tree_size: 9 }));
+    }
+}
@@ -0,0 +1,146 @@
+// Encrypted delivery of receipts through untrusted brokers.
+//
+// The receipt is encrypted to the recipient's X25519 key with a fresh
+// ephemeral key per envelope, and sealed with ChaCha20-Poly1305. The root
+// stays in the clear so brokers can route on it, and is bound to the
+// ciphertext as associated data so it can't be swapped in transit.
+
+use crate::ct::hashes_equal;
+use crate::encoding::{put_bytes, take_bytes, take_hash};
+use crate::{verify_receipt, Hash, PolicyError, Receipt, VerificationPolicy};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use rand_core::{CryptoRng, RngCore};
+use sha2::Digest;
+
+pub use x25519_dalek::{PublicKey, StaticSecret};
+
+
+const KEY_CONTEXT: &[u8] = b"merkle_tree envelope v1";
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Envelope {
+    pub root: Hash,
+    pub ephemeral_public: [u8; 32],
+    pub ciphertext: Vec<u8>,
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EnvelopeError {
+    Malformed,
+    // Wrong recipient key, or the envelope was tampered with
+    Decrypt,
+    // The receipt inside commits to a different root than the envelope
+    RootMismatch,
+    Policy(PolicyError),
+}
+
+
+impl Envelope {
+    // Encrypts the receipt to `recipient`
+    pub fn seal<R: RngCore + CryptoRng>(receipt: &Receipt, recipient: &PublicKey, rng: R) -> Envelope {
+        let ephemeral = StaticSecret::random_from_rng(rng);
+        let ephemeral_public = PublicKey::from(&ephemeral);
+        let cipher = cipher(&ephemeral.diffie_hellman(recipient).to_bytes(), &ephemeral_public, recipient);
+        let payload = Payload { msg: &receipt.encode(), aad: &receipt.root };
+        let ciphertext = cipher
+            .encrypt(Nonce::from_slice(&[0; 12]), payload)
+            .expect("encryption of an in-memory buffer can't fail");
+        Envelope {
+            root: receipt.root.clone(),
+            ephemeral_public: ephemeral_public.to_bytes(),
+            ciphertext,
+        }
+    }
+
+
+    // Decrypts the receipt and verifies it against the policy in one step
+    pub fn open(&self, secret: &StaticSecret, policy: &VerificationPolicy) -> Result<Receipt<'static>, EnvelopeError> {
+        let ephemeral_public = PublicKey::from(self.ephemeral_public);
+        let recipient = PublicKey::from(secret);
+        let cipher = cipher(&secret.diffie_hellman(&ephemeral_public).to_bytes(), &ephemeral_public, &recipient);
+        let payload = Payload { msg: &self.ciphertext, aad: &self.root };
+        let plaintext = cipher
+            .decrypt(Nonce::from_slice(&[0; 12]), payload)
+            .map_err(|_| EnvelopeError::Decrypt)?;
+
+        let receipt = Receipt::decode(&plaintext).ok_or(EnvelopeError::Malformed)?;
+        if !hashes_equal(&receipt.root, &self.root) {
+            return Err(EnvelopeError::RootMismatch);
+        }
+        verify_receipt(&receipt, policy).map_err(EnvelopeError::Policy)?;
+        Ok(receipt)
+    }
+
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        put_bytes(&mut out, &self.root);
+        out.extend_from_slice(&self.ephemeral_public);
+        put_bytes(&mut out, &self.ciphertext);
+        out
+    }
+
+
+    pub fn decode(mut bytes: &[u8]) -> Option<Envelope> {
+        let root = take_hash(&mut bytes)?;
+        if bytes.len() < 32 {
+            return None;
+        }
+        let (ephemeral_public, mut rest) = bytes.split_at(32);
+        let ciphertext = take_bytes(&mut rest)?.to_vec();
+        rest.is_empty().then_some(Envelope {
+            root,
+            ephemeral_public: ephemeral_public.try_into().ok()?,
+            ciphertext,
+        })
+    }
+}
+
+
+// Each envelope has its own ephemeral key, so a fixed nonce is never reused under one key
+fn cipher(shared: &[u8; 32], ephemeral_public: &PublicKey, recipient: &PublicKey) -> ChaCha20Poly1305 {
+    let key = sha2::Sha256::new()
+        .chain_update(KEY_CONTEXT)
+        .chain_update(shared)
+        .chain_update(ephemeral_public.as_bytes())
+        .chain_update(recipient.as_bytes())
+        .finalize();
+    ChaCha20Poly1305::new(Key::from_slice(&key))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Data, MerkleTree};
+    use rand_core::OsRng;
+
+
+    #[test]
+    fn test_seal_and_open() {
+        let data: Vec<Data> = (0..6u8).map(|i| vec![i]).collect();
+        let tree = MerkleTree::construct(&data);
+        let receipt = Receipt { data: data[2].clone(), proof: tree.prove(&data[2]).unwrap(), root: tree.root(), head: None };
+
+        let secret = StaticSecret::random_from_rng(OsRng);
+        let envelope = Envelope::seal(&receipt, &PublicKey::from(&secret), OsRng);
+        assert_eq!(envelope.root, tree.root());
+
+        let envelope = Envelope::decode(&envelope.encode()).unwrap();
+        let opened = envelope.open(&secret, &VerificationPolicy::default()).unwrap();
+        assert_eq!(opened.data, data[2]);
+
+        let strict = VerificationPolicy { max_proof_depth: Some(1), ..Default::default() };
+        assert_eq!(envelope.open(&secret, &strict).err(), Some(EnvelopeError::Policy(PolicyError::ProofTooDeep)));
+
+        let other = StaticSecret::random_from_rng(OsRng);
+        assert_eq!(envelope.open(&other, &VerificationPolicy::default()).err(), Some(EnvelopeError::Decrypt));
+
+        let mut rerouted = envelope.clone();
+        rerouted.root = vec![0; 32];
+        assert_eq!(rerouted.open(&secret, &VerificationPolicy::default()).err(), Some(EnvelopeError::Decrypt));
+    }
+}
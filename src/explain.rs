@@ -0,0 +1,140 @@
+// A step-by-step trace of verifying a proof, for debugging mismatches with
+// another implementation. Each step records the hashes going into one node
+// and the hash coming out, so two traces of the same proof can be lined up
+// to find the first step where they disagree.
+
+use crate::address::proof_path;
+use crate::ct::hashes_equal;
+use crate::display::HexHash;
+use crate::{Hash, HashDirection, Hashable, Hasher, Proof, Sha256Hasher};
+use std::fmt;
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExplainStep {
+    // Tree level of the node hashed, one for the leaf's parent. Only known
+    // from a proof that claims its position; otherwise the step's number,
+    // which differs once a promoted level is skipped.
+    pub level: usize,
+    // Side the sibling goes on
+    pub direction: HashDirection,
+    // The hash carried up from the step before, or the leaf hash
+    pub current: Hash,
+    pub sibling: Hash,
+    pub output: Hash,
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Explanation {
+    pub leaf_hash: Hash,
+    pub steps: Vec<ExplainStep>,
+    // The root the proof folds to and the root it was checked against
+    pub computed_root: Hash,
+    pub expected_root: Hash,
+}
+
+
+impl Explanation {
+    pub fn matches(&self) -> bool {
+        hashes_equal(&self.computed_root, &self.expected_root)
+    }
+
+
+    // The first step whose output differs from `other`'s, such as a trace
+    // from another implementation, or where one trace runs out. None if
+    // every step agrees; compare `leaf_hash` to see if they start apart.
+    pub fn diverges_from(&self, other: &Explanation) -> Option<usize> {
+        let first = self.steps.iter().zip(&other.steps).position(|(a, b)| a.output != b.output);
+        first.or((self.steps.len() != other.steps.len()).then(|| self.steps.len().min(other.steps.len())))
+    }
+}
+
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "leaf      {}", HexHash(&self.leaf_hash))?;
+        for step in &self.steps {
+            let (left, right) = match step.direction {
+                HashDirection::Left => (&step.sibling, &step.current),
+                HashDirection::Right => (&step.current, &step.sibling),
+            };
+            writeln!(f, "level {:<3} H({} | {}) = {}", step.level, HexHash(left), HexHash(right), HexHash(&step.output))?;
+        }
+        let verdict = if self.matches() { "matches" } else { "differs from" };
+        write!(f, "root      {} {} {}", HexHash(&self.computed_root), verdict, HexHash(&self.expected_root))
+    }
+}
+
+
+impl Proof<'_> {
+    pub fn explain<T: Hashable + ?Sized>(&self, data: &T, root: &Hash) -> Explanation {
+        self.explain_with(data, root, &Sha256Hasher)
+    }
+
+
+    // Folds the proof over `data` as `compute_root_with` does, recording
+    // every step
+    pub fn explain_with<T: Hashable + ?Sized, H: Hasher>(&self, data: &T, root: &Hash, hasher: &H) -> Explanation {
+        let leaf_hash = hasher.hash_leaf(&data.leaf_bytes());
+        let levels: Option<Vec<usize>> = self.position().filter(|_| self.matches_position()).map(|position| {
+            let path = proof_path(position.index as u64, position.tree_size as u64);
+            path.iter().map(|(sibling, _)| sibling.level as usize + 1).collect()
+        });
+
+        let mut current = leaf_hash.clone();
+        let mut steps = Vec::with_capacity(self.hashes.len());
+        for (i, (direction, sibling)) in self.hashes.iter().enumerate() {
+            let output = match direction {
+                HashDirection::Left => hasher.hash_node(sibling, &current),
+                HashDirection::Right => hasher.hash_node(&current, sibling),
+            };
+            let level = levels.as_ref().map_or(i + 1, |levels| levels[i]);
+            steps.push(ExplainStep { level, direction: *direction, current, sibling: sibling.to_vec(), output: output.clone() });
+            current = output;
+        }
+        Explanation { leaf_hash, steps, computed_root: current, expected_root: root.clone() }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Data, Legacy, MerkleTree};
+
+
+    #[test]
+    fn test_explain() {
+        let data: Vec<Data> = (0..5u8).map(|i| vec![i]).collect();
+        let tree = MerkleTree::construct(&data);
+        let root = tree.root();
+        // Leaf 4's parent is promoted twice, so its one step is at level 3
+        let proof = tree.prove_with_position(&data[4]).unwrap();
+        let explanation = proof.explain(&data[4], &root);
+        assert!(explanation.matches());
+        assert_eq!(explanation.steps.len(), 1);
+        assert_eq!((explanation.steps[0].level, explanation.steps[0].direction), (3, HashDirection::Left));
+        assert_eq!(explanation.steps[0].output, root);
+
+        let display = explanation.to_string();
+        assert_eq!(display.lines().count(), 3);
+        assert!(display.ends_with(&format!("matches {}", HexHash(&root))));
+    }
+
+    #[test]
+    fn test_divergence_between_hashers() {
+        let data: Vec<Data> = (0..4u8).map(|i| vec![i]).collect();
+        let tree = MerkleTree::construct(&data);
+        let proof = tree.prove(&data[1]).unwrap();
+        let ours = proof.explain(&data[1], &tree.root());
+        assert_eq!(ours.steps[1].level, 2);
+        assert_eq!(ours.diverges_from(&ours), None);
+        let theirs = proof.explain_with(&data[1], &tree.root(), &Legacy(Sha256Hasher));
+        assert!(!theirs.matches());
+        assert_ne!(ours.leaf_hash, theirs.leaf_hash);
+        assert_eq!(ours.diverges_from(&theirs), Some(0));
+        let short = Explanation { steps: ours.steps[..1].to_vec(), ..ours.clone() };
+        assert_eq!(ours.diverges_from(&short), Some(1));
+    }
+}
@@ -0,0 +1,142 @@
+// Interchange format for a tree's leaf hashes, so a mirror can rebuild the
+// tree without the original data and check it against the exporter's root.
+//
+//   magic "MTLH" | version: u8 | hash_len: u8 | leaf hashes, in order |
+//   trailer: root (hash_len bytes) | leaf count: u64
+
+use crate::ct::hashes_equal;
+use crate::storage::build_levels;
+use crate::{Error, Hash, Hasher, MerkleTree, StorageMode};
+use std::fmt;
+use std::io::{self, Read, Write};
+
+
+const MAGIC: &[u8; 4] = b"MTLH";
+const VERSION: u8 = 1;
+
+
+#[derive(Debug)]
+pub enum ImportError {
+    Io(io::Error),
+    Malformed(&'static str),
+    // The rebuilt tree's root differs from the one in the trailer
+    RootMismatch,
+}
+
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImportError::Io(e) => write!(f, "leaf hash import io error: {}", e),
+            ImportError::Malformed(reason) => write!(f, "malformed leaf hash export: {}", reason),
+            ImportError::RootMismatch => write!(f, "rebuilt root doesn't match the exported root"),
+        }
+    }
+}
+
+
+impl std::error::Error for ImportError {}
+
+
+impl From<io::Error> for ImportError {
+    fn from(e: io::Error) -> ImportError {
+        ImportError::Io(e)
+    }
+}
+
+
+impl<H: Hasher> MerkleTree<H> {
+    // Streams every leaf hash followed by the root and leaf count.
+    // Fails with `Error::NotStored` if the tree kept no leaves.
+    pub fn export_leaf_hashes<W: Write>(&self, mut w: W) -> io::Result<()> {
+        if self.mode == StorageMode::RootOnly {
+            return Err(io::Error::other(Error::NotStored));
+        }
+        let leaves = &self.nodes[0];
+        let hash_len = leaves.first().map_or(0, Vec::len);
+        if hash_len > u8::MAX as usize {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "hash too long to export"));
+        }
+
+        w.write_all(MAGIC)?;
+        w.write_all(&[VERSION, hash_len as u8])?;
+        for leaf in leaves {
+            w.write_all(leaf)?;
+        }
+        if self.leaf_count > 0 {
+            w.write_all(&self.root())?;
+        }
+        w.write_all(&(self.leaf_count as u64).to_be_bytes())
+    }
+
+
+    // Rebuilds a tree from `export_leaf_hashes` output, rejecting it unless
+    // the rebuilt root matches the trailer
+    pub fn import_leaf_hashes<R: Read>(mut r: R, hasher: H) -> Result<MerkleTree<H>, ImportError> {
+        let mut bytes = Vec::new();
+        r.read_to_end(&mut bytes)?;
+        if bytes.len() < 6 + 8 || &bytes[..4] != MAGIC {
+            return Err(ImportError::Malformed("bad magic"));
+        }
+        if bytes[4] != VERSION {
+            return Err(ImportError::Malformed("unsupported version"));
+        }
+        let hash_len = bytes[5] as usize;
+        let (body, count) = bytes[6..].split_at(bytes.len() - 6 - 8);
+        let leaf_count = usize::try_from(u64::from_be_bytes(count.try_into().unwrap()))
+            .map_err(|_| ImportError::Malformed("leaf count too large for this platform"))?;
+        if hash_len == 0 && leaf_count > 0 {
+            return Err(ImportError::Malformed("zero hash length"));
+        }
+        // Leaf hashes, then the root unless the tree is empty
+        let root_len = if leaf_count > 0 { hash_len } else { 0 };
+        let expected_len = leaf_count.checked_mul(hash_len).and_then(|len| len.checked_add(root_len));
+        if expected_len != Some(body.len()) {
+            return Err(ImportError::Malformed("leaf count doesn't match length"));
+        }
+
+        let (leaves, root) = body.split_at(body.len() - root_len);
+        let leaves: Vec<Hash> = leaves.chunks(hash_len.max(1)).map(<[u8]>::to_vec).collect();
+        let tree = MerkleTree::from_levels(build_levels(leaves, &hasher), hasher);
+        if leaf_count > 0 && !hashes_equal(&tree.root(), root) {
+            return Err(ImportError::RootMismatch);
+        }
+        Ok(tree)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Data, Sha256Hasher};
+
+
+    #[test]
+    fn test_export_import_roundtrip() {
+        for n in 0..=9 {
+            let data: Vec<Data> = (0..n as u8).map(|i| vec![i]).collect();
+            let tree = MerkleTree::construct_with_mode(&data, Sha256Hasher, StorageMode::LeavesOnly);
+            let mut exported = Vec::new();
+            tree.export_leaf_hashes(&mut exported).unwrap();
+            let imported = MerkleTree::import_leaf_hashes(&exported[..], Sha256Hasher).unwrap();
+            assert_eq!(imported.nodes, MerkleTree::construct(&data).nodes);
+        }
+    }
+
+    #[test]
+    fn test_import_rejects_tampering() {
+        let data: Vec<Data> = (0..5u8).map(|i| vec![i]).collect();
+        let mut exported = Vec::new();
+        MerkleTree::construct(&data).export_leaf_hashes(&mut exported).unwrap();
+
+        let mut tampered = exported.clone();
+        tampered[10] ^= 1;
+        assert!(matches!(MerkleTree::import_leaf_hashes(&tampered[..], Sha256Hasher), Err(ImportError::RootMismatch)));
+        let truncated = [&exported[..38], &exported[exported.len() - 8..]].concat();
+        assert!(matches!(MerkleTree::import_leaf_hashes(&truncated[..], Sha256Hasher), Err(ImportError::Malformed(_))));
+
+        let root_only = MerkleTree::construct_with_mode(&data, Sha256Hasher, StorageMode::RootOnly);
+        assert!(root_only.export_leaf_hashes(Vec::new()).is_err());
+    }
+}
@@ -0,0 +1,184 @@
+// The downloading side of chunked files: given only a trusted root and the
+// chunk size, pull chunks with their proofs from an untrusted source, check
+// each before keeping it, and put the file back together. Chunks are split
+// as `MerkleWriter` and `stream::encode` split them, with an empty file
+// being one empty chunk.
+//
+// The chunk count comes from the first proof's claimed tree size. A source
+// that claims the wrong size can't pass off a different file: every chunk
+// up to that size must then prove as a leaf under the root, which only the
+// real chunks do.
+
+use crate::strict::StrictError;
+use crate::{Data, Hash, Hasher, MerkleTree, Proof, Sha256Hasher};
+use std::fmt;
+
+
+// Where chunks come from, such as a peer or a mirror. Proofs must claim
+// the chunk's position, as `prove_with_position` and `CachedProver` make
+// them.
+pub trait ChunkSource {
+    type Error;
+
+    fn fetch_chunk(&mut self, index: usize) -> Result<(Data, Proof<'static>), Self::Error>;
+}
+
+
+impl<F, E> ChunkSource for F
+where
+    F: FnMut(usize) -> Result<(Data, Proof<'static>), E>,
+{
+    type Error = E;
+
+    fn fetch_chunk(&mut self, index: usize) -> Result<(Data, Proof<'static>), E> {
+        self(index)
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FetchError<E> {
+    // The source couldn't supply chunk `index`
+    Source { index: usize, error: E },
+    // Chunk `index` didn't prove against the root
+    Rejected { index: usize, reason: StrictError },
+    // Chunk `index` was proven for another position or tree size
+    WrongPosition { index: usize },
+    // Chunk `index` was too long, or short without being the last
+    ChunkLength { index: usize, len: usize },
+}
+
+
+impl<E: fmt::Display> fmt::Display for FetchError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FetchError::Source { index, error } => write!(f, "fetching chunk {} failed: {}", index, error),
+            FetchError::Rejected { index, reason } => write!(f, "chunk {} rejected: {}", index, reason),
+            FetchError::WrongPosition { index } => write!(f, "chunk {} came with a proof for another position", index),
+            FetchError::ChunkLength { index, len } => write!(f, "chunk {} has the wrong length {}", index, len),
+        }
+    }
+}
+
+
+impl<E: fmt::Debug + fmt::Display> std::error::Error for FetchError<E> {}
+
+
+pub struct ChunkFetcher<H = Sha256Hasher> {
+    root: Hash,
+    chunk_size: usize,
+    hasher: H,
+}
+
+
+impl ChunkFetcher {
+    pub fn new(root: Hash, chunk_size: usize) -> ChunkFetcher {
+        Self::with_hasher(root, chunk_size, Sha256Hasher)
+    }
+}
+
+
+impl<H: Hasher> ChunkFetcher<H> {
+    pub fn with_hasher(root: Hash, chunk_size: usize, hasher: H) -> ChunkFetcher<H> {
+        assert!(chunk_size > 0, "chunk size must be positive");
+        ChunkFetcher { root, chunk_size, hasher }
+    }
+
+
+    // Fetches and checks every chunk in order, returning the whole file
+    pub fn fetch<S: ChunkSource>(&self, source: &mut S) -> Result<Vec<u8>, FetchError<S::Error>> {
+        let mut file = Vec::new();
+        let (first, tree_size) = self.fetch_verified(source, 0, None)?;
+        file.extend_from_slice(&first);
+        for index in 1..tree_size {
+            file.extend_from_slice(&self.fetch_verified(source, index, Some(tree_size))?.0);
+        }
+        Ok(file)
+    }
+
+
+    // Fetches chunk `index` and checks it against the root, returning it
+    // with the tree size its proof claims. `tree_size` pins that size once
+    // it's known.
+    pub fn fetch_verified<S: ChunkSource>(
+        &self,
+        source: &mut S,
+        index: usize,
+        tree_size: Option<usize>,
+    ) -> Result<(Data, usize), FetchError<S::Error>> {
+        let (chunk, proof) = source.fetch_chunk(index).map_err(|error| FetchError::Source { index, error })?;
+        MerkleTree::verify_proof_strict_with(&chunk, &proof, &self.root, &self.hasher)
+            .map_err(|reason| FetchError::Rejected { index, reason })?;
+        // Strict verification has checked a position is there
+        let position = proof.position().unwrap();
+        if position.index != index || tree_size.is_some_and(|size| size != position.tree_size) {
+            return Err(FetchError::WrongPosition { index });
+        }
+        let last = index + 1 == position.tree_size;
+        let fits = chunk.len() == self.chunk_size || (last && chunk.len() < self.chunk_size && (index == 0 || !chunk.is_empty()));
+        if !fits {
+            return Err(FetchError::ChunkLength { index, len: chunk.len() });
+        }
+        Ok((chunk, position.tree_size))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CachedProver, MerkleWriter};
+    use std::io::Write;
+
+
+    fn serve(file: &[u8], chunk_size: usize) -> (Hash, Vec<Data>, CachedProver<Sha256Hasher>) {
+        let chunks: Vec<Data> =
+            if file.is_empty() { vec![Vec::new()] } else { file.chunks(chunk_size).map(<[u8]>::to_vec).collect() };
+        let mut writer = MerkleWriter::new(chunk_size);
+        writer.write_all(file).unwrap();
+        let root = writer.finalize();
+        let prover = CachedProver::new(MerkleTree::construct(&chunks), 4);
+        assert_eq!(prover.tree().root(), root);
+        (root, chunks, prover)
+    }
+
+    #[test]
+    fn test_fetch_reassembles_file() {
+        for len in [0, 1, 15, 16, 17, 100] {
+            let file: Vec<u8> = (0..len).map(|i| (i * 7) as u8).collect();
+            let (root, chunks, prover) = serve(&file, 16);
+            let mut source = |index: usize| match chunks.get(index) {
+                Some(chunk) => Ok((chunk.clone(), (*prover.prove(index).unwrap()).clone())),
+                None => Err("no such chunk"),
+            };
+            assert_eq!(ChunkFetcher::new(root, 16).fetch(&mut source), Ok(file));
+        }
+    }
+
+    #[test]
+    fn test_fetch_rejects_bad_chunks() {
+        let file: Vec<u8> = (0..100u8).collect();
+        let (root, chunks, prover) = serve(&file, 16);
+        let fetcher = ChunkFetcher::new(root, 16);
+
+        let mut corrupt = |index: usize| {
+            let mut chunk = chunks[index].clone();
+            if index == 3 {
+                chunk[0] ^= 1;
+            }
+            Ok::<_, ()>((chunk, (*prover.prove(index).unwrap()).clone()))
+        };
+        assert!(matches!(fetcher.fetch(&mut corrupt), Err(FetchError::Rejected { index: 3, .. })));
+
+        // A real chunk sent for the wrong index
+        let mut shuffled = |index: usize| Ok::<_, ()>((chunks[index ^ 1].clone(), (*prover.prove(index ^ 1).unwrap()).clone()));
+        assert_eq!(fetcher.fetch(&mut shuffled), Err(FetchError::WrongPosition { index: 0 }));
+        let mut failing = |index: usize| Err::<(Data, Proof<'static>), _>(index);
+        assert_eq!(fetcher.fetch(&mut failing), Err(FetchError::Source { index: 0, error: 0 }));
+        // Right data, wrong chunk size for this fetcher
+        assert_eq!(
+            ChunkFetcher::new(prover.tree().root(), 32).fetch(&mut corrupt),
+            Err(FetchError::ChunkLength { index: 0, len: 16 })
+        );
+    }
+}
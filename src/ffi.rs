@@ -0,0 +1,171 @@
+// C interface for embedding the tree in C/C++ services. The header at
+// include/merkle_tree.h is generated from this file with cbindgen (see
+// cbindgen.toml). Proofs cross the boundary in the compact encoding from
+// `Proof::encode`. Doc comments here are copied into the header.
+
+use crate::{Data, MerkleTree, Proof};
+use std::slice;
+
+
+pub const MERKLE_OK: i32 = 0;
+pub const MERKLE_ERR_NULL: i32 = -1;
+pub const MERKLE_ERR_NOT_FOUND: i32 = -2;
+pub const MERKLE_ERR_BUFFER_TOO_SMALL: i32 = -3;
+
+
+/// Opaque handle to a tree owned by the library.
+pub struct MerkleTreeHandle {
+    tree: MerkleTree,
+}
+
+
+unsafe fn bytes<'a>(ptr: *const u8, len: usize) -> &'a [u8] {
+    if len == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(ptr, len)
+    }
+}
+
+
+// Copies `src` into the caller's buffer, always reporting the size needed
+unsafe fn write_out(src: &[u8], out: *mut u8, out_cap: usize, out_len: *mut usize) -> i32 {
+    *out_len = src.len();
+    if src.len() > out_cap {
+        return MERKLE_ERR_BUFFER_TOO_SMALL;
+    }
+    if !src.is_empty() {
+        slice::from_raw_parts_mut(out, src.len()).copy_from_slice(src);
+    }
+    MERKLE_OK
+}
+
+
+/// Builds a tree over `count` leaves, where leaf i is `lens[i]` bytes at `leaves[i]`.
+/// Returns NULL if there are no leaves. Free the result with `merkle_free`.
+///
+/// # Safety
+/// `leaves` and `lens` must point to `count` valid entries, each leaf readable for its length.
+#[no_mangle]
+pub unsafe extern "C" fn merkle_construct(leaves: *const *const u8, lens: *const usize, count: usize) -> *mut MerkleTreeHandle {
+    if count == 0 || leaves.is_null() || lens.is_null() {
+        return std::ptr::null_mut();
+    }
+    let leaves = slice::from_raw_parts(leaves, count);
+    let lens = slice::from_raw_parts(lens, count);
+    let input: Vec<Data> = leaves.iter().zip(lens).map(|(&leaf, &len)| bytes(leaf, len).to_vec()).collect();
+    Box::into_raw(Box::new(MerkleTreeHandle { tree: MerkleTree::construct(&input) }))
+}
+
+
+/// Releases a tree returned by `merkle_construct`. Passing NULL is a no-op.
+///
+/// # Safety
+/// `tree` must come from `merkle_construct` and not have been freed already.
+#[no_mangle]
+pub unsafe extern "C" fn merkle_free(tree: *mut MerkleTreeHandle) {
+    if !tree.is_null() {
+        drop(Box::from_raw(tree));
+    }
+}
+
+
+/// Writes the root hash into `out`. `out_len` receives the hash length, even when
+/// the buffer is too small.
+///
+/// # Safety
+/// `tree` must be a live handle, `out` writable for `out_cap` bytes and `out_len` writable.
+#[no_mangle]
+pub unsafe extern "C" fn merkle_root(tree: *const MerkleTreeHandle, out: *mut u8, out_cap: usize, out_len: *mut usize) -> i32 {
+    if tree.is_null() || out_len.is_null() {
+        return MERKLE_ERR_NULL;
+    }
+    write_out(&(*tree).tree.root(), out, out_cap, out_len)
+}
+
+
+/// Writes the encoded proof for `data` into `out`. `out_len` receives the encoded
+/// length, even when the buffer is too small.
+///
+/// # Safety
+/// `tree` must be a live handle, `data` readable for `data_len` bytes, `out` writable
+/// for `out_cap` bytes and `out_len` writable.
+#[no_mangle]
+pub unsafe extern "C" fn merkle_prove(
+    tree: *const MerkleTreeHandle,
+    data: *const u8,
+    data_len: usize,
+    out: *mut u8,
+    out_cap: usize,
+    out_len: *mut usize,
+) -> i32 {
+    if tree.is_null() || out_len.is_null() || (data.is_null() && data_len > 0) {
+        return MERKLE_ERR_NULL;
+    }
+    match (*tree).tree.prove(&bytes(data, data_len).to_vec()) {
+        Some(proof) => write_out(&proof.encode(), out, out_cap, out_len),
+        None => MERKLE_ERR_NOT_FOUND,
+    }
+}
+
+
+/// Returns 1 if the encoded proof leads from `data` to `root`, 0 otherwise,
+/// including when the proof is malformed.
+///
+/// # Safety
+/// Each pointer must be readable for its length.
+#[no_mangle]
+pub unsafe extern "C" fn merkle_verify_proof(
+    data: *const u8,
+    data_len: usize,
+    proof: *const u8,
+    proof_len: usize,
+    root: *const u8,
+    root_len: usize,
+) -> i32 {
+    if (data.is_null() && data_len > 0) || proof.is_null() || root.is_null() {
+        return 0;
+    }
+    match Proof::decode(bytes(proof, proof_len)) {
+        Some(decoded) => {
+            MerkleTree::verify_proof(&bytes(data, data_len).to_vec(), &decoded, &bytes(root, root_len).to_vec()) as i32
+        }
+        None => 0,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    #[test]
+    fn test_ffi_roundtrip() {
+        let data: Vec<Data> = (0..5u8).map(|i| vec![i; 3]).collect();
+        let ptrs: Vec<*const u8> = data.iter().map(|leaf| leaf.as_ptr()).collect();
+        let lens: Vec<usize> = data.iter().map(|leaf| leaf.len()).collect();
+        unsafe {
+            let tree = merkle_construct(ptrs.as_ptr(), lens.as_ptr(), data.len());
+            assert!(!tree.is_null());
+
+            let mut root = [0u8; 32];
+            let mut root_len = 0;
+            assert_eq!(merkle_root(tree, root.as_mut_ptr(), root.len(), &mut root_len), MERKLE_OK);
+            assert_eq!(root.to_vec(), MerkleTree::construct(&data).root());
+
+            let mut proof = [0u8; 4];
+            let mut proof_len = 0;
+            let status = merkle_prove(tree, data[2].as_ptr(), 3, proof.as_mut_ptr(), proof.len(), &mut proof_len);
+            assert_eq!(status, MERKLE_ERR_BUFFER_TOO_SMALL);
+            let mut proof = vec![0u8; proof_len];
+            let status = merkle_prove(tree, data[2].as_ptr(), 3, proof.as_mut_ptr(), proof.len(), &mut proof_len);
+            assert_eq!(status, MERKLE_OK);
+
+            assert_eq!(merkle_verify_proof(data[2].as_ptr(), 3, proof.as_ptr(), proof_len, root.as_ptr(), 32), 1);
+            assert_eq!(merkle_verify_proof(data[1].as_ptr(), 3, proof.as_ptr(), proof_len, root.as_ptr(), 32), 0);
+            assert_eq!(merkle_prove(tree, [9u8].as_ptr(), 1, proof.as_mut_ptr(), proof.len(), &mut proof_len), MERKLE_ERR_NOT_FOUND);
+            merkle_free(tree);
+        }
+    }
+}
@@ -0,0 +1,141 @@
+// Proofs with the hash width and the maximum depth in the type, for
+// verifiers that can't allocate. Siblings sit inline in an array, and a
+// fixed-width hasher streams its input instead of building buffers, so
+// checking a proof touches only the stack.
+//
+// This sits beside the heap-based types rather than replacing them: `Hash`
+// stays a `Vec<u8>` everywhere else, and the crate still needs std, so a
+// no_std verifier would have to copy this module out.
+
+use crate::ct::hashes_equal;
+use crate::hasher::{LEAF_PREFIX, NODE_PREFIX};
+use crate::{HashDirection, Legacy, Proof, Sha256Hasher};
+use sha2::Digest;
+
+
+// A hash function with an `N`-byte output that hashes pieces of input as
+// one message, without joining them first. Leaves and nodes are hashed as
+// `Hasher::hash_leaf` and `hash_node` do by default; hashers that build
+// trees differently override them to match.
+pub trait FixedHasher<const N: usize> {
+    fn hash_parts(&self, parts: &[&[u8]]) -> [u8; N];
+
+    fn hash_leaf_fixed(&self, data: &[u8]) -> [u8; N] {
+        self.hash_parts(&[&[LEAF_PREFIX], data])
+    }
+
+    fn hash_node_fixed(&self, left: &[u8; N], right: &[u8; N]) -> [u8; N] {
+        self.hash_parts(&[&[NODE_PREFIX], left, right])
+    }
+}
+
+
+impl FixedHasher<32> for Sha256Hasher {
+    fn hash_parts(&self, parts: &[&[u8]]) -> [u8; 32] {
+        let mut hasher = sha2::Sha256::new();
+        for part in parts {
+            hasher.update(part);
+        }
+        hasher.finalize().into()
+    }
+}
+
+
+impl<H: FixedHasher<N>, const N: usize> FixedHasher<N> for Legacy<H> {
+    fn hash_parts(&self, parts: &[&[u8]]) -> [u8; N] {
+        self.0.hash_parts(parts)
+    }
+
+    fn hash_leaf_fixed(&self, data: &[u8]) -> [u8; N] {
+        self.0.hash_parts(&[data])
+    }
+
+    fn hash_node_fixed(&self, left: &[u8; N], right: &[u8; N]) -> [u8; N] {
+        self.0.hash_parts(&[left, right])
+    }
+}
+
+
+// Up to `D` sibling hashes of `N` bytes each
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FixedProof<const N: usize, const D: usize> {
+    len: usize,
+    steps: [(HashDirection, [u8; N]); D],
+}
+
+
+// Deep enough for any tree of up to 2^64 leaves
+pub type Sha256Proof = FixedProof<32, 64>;
+
+
+impl<const N: usize, const D: usize> FixedProof<N, D> {
+    // None if the proof has more than `D` hashes or one isn't `N` bytes
+    pub fn from_proof(proof: &Proof) -> Option<FixedProof<N, D>> {
+        if proof.len() > D {
+            return None;
+        }
+        let mut steps = [(HashDirection::Left, [0; N]); D];
+        for (step, (direction, hash)) in steps.iter_mut().zip(&proof.hashes) {
+            *step = (*direction, hash.as_slice().try_into().ok()?);
+        }
+        Some(FixedProof { len: proof.len(), steps })
+    }
+
+
+    pub fn steps(&self) -> &[(HashDirection, [u8; N])] {
+        &self.steps[..self.len]
+    }
+
+
+    pub fn compute_root<F: FixedHasher<N>>(&self, data: &[u8], hasher: &F) -> [u8; N] {
+        let mut current = hasher.hash_leaf_fixed(data);
+        for (direction, hash) in self.steps() {
+            current = match direction {
+                HashDirection::Left => hasher.hash_node_fixed(hash, &current),
+                HashDirection::Right => hasher.hash_node_fixed(&current, hash),
+            };
+        }
+        current
+    }
+
+
+    pub fn verify<F: FixedHasher<N>>(&self, data: &[u8], root: &[u8; N], hasher: &F) -> bool {
+        hashes_equal(&self.compute_root(data, hasher), root)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Data, MerkleTree};
+
+
+    #[test]
+    fn test_fixed_proofs_match() {
+        let data: Vec<Data> = (0..11u8).map(|i| vec![i]).collect();
+        let tree = MerkleTree::construct(&data);
+        let root: [u8; 32] = tree.root().try_into().unwrap();
+        for leaf in &data {
+            let proof = tree.prove(leaf).unwrap();
+            let fixed = Sha256Proof::from_proof(&proof).unwrap();
+            assert_eq!(fixed.steps().len(), proof.len());
+            assert!(fixed.verify(leaf, &root, &Sha256Hasher));
+            assert!(!fixed.verify(&[99], &root, &Sha256Hasher));
+        }
+        // Too deep for the bound, and too wide for the hash
+        let proof = tree.prove(&data[0]).unwrap();
+        assert!(FixedProof::<32, 3>::from_proof(&proof).is_none());
+        assert!(FixedProof::<20, 64>::from_proof(&proof).is_none());
+    }
+
+    #[test]
+    fn test_fixed_legacy_proofs() {
+        let data: Vec<Data> = (0..6u8).map(|i| vec![i]).collect();
+        let tree = MerkleTree::construct_with(&data, Legacy(Sha256Hasher));
+        let root: [u8; 32] = tree.root().try_into().unwrap();
+        let fixed = Sha256Proof::from_proof(&tree.prove(&data[4]).unwrap()).unwrap();
+        assert!(fixed.verify(&data[4], &root, &Legacy(Sha256Hasher)));
+        assert!(!fixed.verify(&data[4], &root, &Sha256Hasher));
+    }
+}
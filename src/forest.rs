@@ -0,0 +1,191 @@
+// One commitment over data spread across many shards. The top tree's
+// leaves are the shard roots, and shard trees are loaded from a store only
+// when a proof needs them, then kept. A proof is the shard proof followed
+// by the top proof, as `ComposedProof` checks them. Loaded shards are
+// checked against the root the top tree holds for them, so a store that
+// hands back the wrong shard is caught before anything is proven from it.
+
+use crate::ct::hashes_equal;
+use crate::{ComposedProof, Hash, Hashable, Hasher, MerkleTree, Sha256Hasher};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+
+// Where shard trees live, such as one file or node per shard
+pub trait ShardStore<H> {
+    type Error;
+
+    fn load_shard(&self, shard: usize) -> Result<MerkleTree<H>, Self::Error>;
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ForestError<E> {
+    NoShard { shard: usize },
+    Store { shard: usize, error: E },
+    // The store's tree for `shard` doesn't have the root the top tree holds
+    ShardMismatch { shard: usize },
+    // The data isn't a leaf of the shard
+    NotFound { shard: usize },
+}
+
+
+impl<E: fmt::Display> fmt::Display for ForestError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ForestError::NoShard { shard } => write!(f, "no shard {}", shard),
+            ForestError::Store { shard, error } => write!(f, "loading shard {} failed: {}", shard, error),
+            ForestError::ShardMismatch { shard } => write!(f, "stored shard {} doesn't match its root", shard),
+            ForestError::NotFound { shard } => write!(f, "data isn't in shard {}", shard),
+        }
+    }
+}
+
+
+impl<E: fmt::Debug + fmt::Display> std::error::Error for ForestError<E> {}
+
+
+pub struct ForestTree<S, H = Sha256Hasher> {
+    top: MerkleTree<H>,
+    shard_roots: Vec<Hash>,
+    store: S,
+    loaded: Mutex<HashMap<usize, Arc<MerkleTree<H>>>>,
+}
+
+
+impl<S: ShardStore<Sha256Hasher>> ForestTree<S> {
+    pub fn new(shard_roots: Vec<Hash>, store: S) -> ForestTree<S> {
+        Self::with_hasher(shard_roots, store, Sha256Hasher)
+    }
+}
+
+
+impl<S: ShardStore<H>, H: Hasher> ForestTree<S, H> {
+    // A forest over shards with the given roots, in order. Nothing is
+    // loaded until a proof asks for it.
+    pub fn with_hasher(shard_roots: Vec<Hash>, store: S, hasher: H) -> ForestTree<S, H> {
+        let top = MerkleTree::construct_with(&shard_roots, hasher);
+        ForestTree { top, shard_roots, store, loaded: Mutex::new(HashMap::new()) }
+    }
+
+
+    pub fn root(&self) -> Hash {
+        self.top.root()
+    }
+
+
+    pub fn shard_count(&self) -> usize {
+        self.shard_roots.len()
+    }
+
+
+    pub fn top(&self) -> &MerkleTree<H> {
+        &self.top
+    }
+
+
+    // Shards loaded so far
+    pub fn loaded(&self) -> usize {
+        self.lock().len()
+    }
+
+
+    // Drops a loaded shard, so the next proof from it loads it again
+    pub fn unload(&self, shard: usize) -> bool {
+        self.lock().remove(&shard).is_some()
+    }
+
+
+    // The tree for `shard`, loading and checking it on first use
+    pub fn shard(&self, shard: usize) -> Result<Arc<MerkleTree<H>>, ForestError<S::Error>> {
+        let root = self.shard_roots.get(shard).ok_or(ForestError::NoShard { shard })?;
+        if let Some(tree) = self.lock().get(&shard) {
+            return Ok(Arc::clone(tree));
+        }
+        // Loaded outside the lock so a slow store doesn't hold up other shards
+        let tree = self.store.load_shard(shard).map_err(|error| ForestError::Store { shard, error })?;
+        if !hashes_equal(&tree.root(), root) {
+            return Err(ForestError::ShardMismatch { shard });
+        }
+        let tree = Arc::new(tree);
+        self.lock().insert(shard, Arc::clone(&tree));
+        Ok(tree)
+    }
+
+
+    // Proves `data` is in `shard` and `shard` is in the forest
+    pub fn prove<T: Hashable + ?Sized>(&self, shard: usize, data: &T) -> Result<ComposedProof<'static>, ForestError<S::Error>> {
+        let tree = self.shard(shard)?;
+        let inner = tree.prove(data).ok_or(ForestError::NotFound { shard })?.into_owned();
+        let outer = self.top.prove_index(shard).ok_or(ForestError::NoShard { shard })?.into_owned();
+        Ok(ComposedProof { shard, inner, outer: outer.with_position(shard, self.shard_count()) })
+    }
+
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<usize, Arc<MerkleTree<H>>>> {
+        self.loaded.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Data;
+    use std::cell::Cell;
+
+
+    struct Shards {
+        data: Vec<Vec<Data>>,
+        loads: Cell<usize>,
+    }
+
+
+    impl ShardStore<Sha256Hasher> for Shards {
+        type Error = String;
+
+        fn load_shard(&self, shard: usize) -> Result<MerkleTree, String> {
+            self.loads.set(self.loads.get() + 1);
+            let data = self.data.get(shard).ok_or("missing")?;
+            Ok(MerkleTree::construct(data))
+        }
+    }
+
+
+    fn shards() -> Shards {
+        let data = (0..4u8).map(|s| (0..(3 + s)).map(|i| vec![s, i]).collect()).collect();
+        Shards { data, loads: Cell::new(0) }
+    }
+
+    #[test]
+    fn test_forest_proofs() {
+        let store = shards();
+        let roots: Vec<Hash> = store.data.iter().map(|d| MerkleTree::construct(d).root()).collect();
+        let forest = ForestTree::new(roots.clone(), store);
+        assert_eq!(forest.loaded(), 0);
+        let root = forest.root();
+        assert_eq!(root, MerkleTree::construct(&roots).root());
+
+        for shard in 0..4 {
+            let leaf = vec![shard as u8, 1];
+            let proof = forest.prove(shard, &leaf).unwrap();
+            assert!(proof.verify(&leaf, &root));
+            forest.prove(shard, &vec![shard as u8, 0]).unwrap();
+        }
+        // Each shard was loaded once
+        assert_eq!((forest.loaded(), forest.store.loads.get()), (4, 4));
+        assert_eq!(forest.prove(1, &vec![0, 1]).err(), Some(ForestError::NotFound { shard: 1 }));
+        assert_eq!(forest.prove(4, &vec![0, 1]).err(), Some(ForestError::NoShard { shard: 4 }));
+    }
+
+    #[test]
+    fn test_forest_rejects_wrong_shard() {
+        let store = shards();
+        let mut roots: Vec<Hash> = store.data.iter().map(|d| MerkleTree::construct(d).root()).collect();
+        roots.swap(0, 1);
+        let forest = ForestTree::new(roots, store);
+        assert_eq!(forest.prove(0, &vec![0, 1]).err(), Some(ForestError::ShardMismatch { shard: 0 }));
+        assert_eq!(forest.loaded(), 0);
+    }
+}
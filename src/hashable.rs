@@ -0,0 +1,128 @@
+// Values that can be leaves. A value's leaf bytes are what gets hashed, so
+// trees can be built over domain types without serializing them first.
+// Structs get an implementation from `#[derive(Hashable)]` with the `derive`
+// feature.
+
+use crate::encoding::put_bytes;
+use std::borrow::Cow;
+
+
+pub trait Hashable {
+    fn leaf_bytes(&self) -> Cow<'_, [u8]>;
+}
+
+
+impl Hashable for [u8] {
+    fn leaf_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self)
+    }
+}
+
+
+impl<const N: usize> Hashable for [u8; N] {
+    fn leaf_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self)
+    }
+}
+
+
+impl Hashable for Vec<u8> {
+    fn leaf_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self)
+    }
+}
+
+
+impl Hashable for str {
+    fn leaf_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self.as_bytes())
+    }
+}
+
+
+impl Hashable for String {
+    fn leaf_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self.as_bytes())
+    }
+}
+
+
+impl<T: Hashable + ?Sized> Hashable for &T {
+    fn leaf_bytes(&self) -> Cow<'_, [u8]> {
+        (**self).leaf_bytes()
+    }
+}
+
+
+// Integers are big-endian, so they sort the same as bytes as they do as numbers
+macro_rules! hashable_int {
+    ($($int:ty),*) => {
+        $(
+            impl Hashable for $int {
+                fn leaf_bytes(&self) -> Cow<'_, [u8]> {
+                    Cow::Owned(self.to_be_bytes().to_vec())
+                }
+            }
+        )*
+    };
+}
+
+hashable_int!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+
+impl Hashable for bool {
+    fn leaf_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(vec![*self as u8])
+    }
+}
+
+
+// Used by the derive: one length-prefixed field
+#[doc(hidden)]
+pub fn put_field<T: Hashable + ?Sized>(out: &mut Vec<u8>, value: &T) {
+    put_bytes(out, &value.leaf_bytes());
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::MerkleTree;
+
+
+    #[test]
+    fn test_hashable_leaves() {
+        let strings = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+        let bytes: Vec<Vec<u8>> = strings.iter().map(|s| s.as_bytes().to_vec()).collect();
+        let tree = MerkleTree::construct(&strings);
+        assert_eq!(tree.root(), MerkleTree::construct(&bytes).root());
+        assert_eq!(tree.root(), MerkleTree::construct(&["a", "b", "c"]).root());
+
+        let proof = tree.prove("b").unwrap();
+        assert!(MerkleTree::verify_proof("b", &proof, &tree.root()));
+        assert!(!MerkleTree::verify_proof("c", &proof, &tree.root()));
+    }
+
+    #[cfg(feature = "derive")]
+    #[test]
+    fn test_derive_hashable() {
+        use crate::Hashable;
+
+        #[derive(Hashable)]
+        struct Entry {
+            seq: u64,
+            body: String,
+        }
+
+        #[derive(Hashable)]
+        struct Pair(u8, Vec<u8>);
+
+        let entries = vec![Entry { seq: 1, body: "x".into() }, Entry { seq: 2, body: "y".into() }];
+        let tree = MerkleTree::construct(&entries);
+        assert!(MerkleTree::verify_proof(&entries[1], &tree.prove(&entries[1]).unwrap(), &tree.root()));
+
+        // Length prefixes keep field boundaries apart
+        let split_early = Pair(1, vec![2, 3]).leaf_bytes().into_owned();
+        assert_eq!(split_early, [0, 0, 0, 1, 1, 0, 0, 0, 2, 2, 3]);
+        assert_ne!(Entry { seq: 1, body: "x".into() }.leaf_bytes(), Entry { seq: 1, body: "xx".into() }.leaf_bytes());
+    }
+}
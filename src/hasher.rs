@@ -0,0 +1,169 @@
+use crate::Hash;
+use sha2::Digest;
+
+
+// Prefixes that keep leaf and node hashes apart (RFC 6962). Without them a
+// "leaf" holding two concatenated child hashes has the same hash as their
+// parent, so it could be proven as a member of the tree.
+pub const LEAF_PREFIX: u8 = 0x00;
+pub const NODE_PREFIX: u8 = 0x01;
+
+
+// A hash function the tree can be built with
+pub trait Hasher {
+    // Hashes raw bytes
+    fn hash(&self, data: &[u8]) -> Hash;
+
+    // Hashes two child hashes into their parent, without domain separation
+    fn hash_concat(&self, left: &Hash, right: &Hash) -> Hash {
+        let mut buf = Vec::with_capacity(left.len() + right.len());
+        buf.extend_from_slice(left);
+        buf.extend_from_slice(right);
+        self.hash(&buf)
+    }
+
+    // Hashes any number of child hashes into their parent, for wider trees
+    fn hash_many(&self, children: &[Hash]) -> Hash {
+        self.hash(&children.concat())
+    }
+
+    // Hashes leaf data, as the trees do
+    fn hash_leaf(&self, data: &[u8]) -> Hash {
+        let mut buf = Vec::with_capacity(1 + data.len());
+        buf.push(LEAF_PREFIX);
+        buf.extend_from_slice(data);
+        self.hash(&buf)
+    }
+
+    // Hashes two children into their parent, as the trees do
+    fn hash_node(&self, left: &Hash, right: &Hash) -> Hash {
+        let mut buf = Vec::with_capacity(1 + left.len() + right.len());
+        buf.push(NODE_PREFIX);
+        buf.extend_from_slice(left);
+        buf.extend_from_slice(right);
+        self.hash(&buf)
+    }
+
+    // Hashes any number of children into their parent, as the wider trees do
+    fn hash_children(&self, children: &[Hash]) -> Hash {
+        let mut buf = vec![NODE_PREFIX];
+        for child in children {
+            buf.extend_from_slice(child);
+        }
+        self.hash(&buf)
+    }
+
+    // Which implementation is doing the hashing, for reporting
+    fn backend(&self) -> &'static str {
+        "generic"
+    }
+
+    // Whether leaves and nodes are hashed apart, so no leaf hash can equal
+    // a node hash. False for hashers that drop the prefixes.
+    fn domain_separated(&self) -> bool {
+        true
+    }
+}
+
+
+// Identifies what a hasher builds: the parent of two empty leaves, which
+// changes with the hash function and with whether prefixes are used. Stored
+// next to saved trees and checked by policies, so nothing built with one
+// hasher is read back with another.
+pub fn hasher_id<H: Hasher + ?Sized>(hasher: &H) -> Hash {
+    let empty = hasher.hash_leaf(&[]);
+    hasher.hash_node(&empty, &empty)
+}
+
+
+// The default SHA-256 hasher
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Sha256Hasher;
+
+
+impl Hasher for Sha256Hasher {
+    fn hash(&self, data: &[u8]) -> Hash {
+        sha2::Sha256::digest(data).to_vec()
+    }
+
+    // sha2 picks SHA-NI or the ARMv8 instructions at runtime when present
+    fn backend(&self) -> &'static str {
+        crate::backend::Sha256Backend::selected().name()
+    }
+}
+
+
+impl<H: Hasher + ?Sized> Hasher for &H {
+    fn hash(&self, data: &[u8]) -> Hash {
+        (**self).hash(data)
+    }
+
+    fn hash_concat(&self, left: &Hash, right: &Hash) -> Hash {
+        (**self).hash_concat(left, right)
+    }
+
+    fn hash_many(&self, children: &[Hash]) -> Hash {
+        (**self).hash_many(children)
+    }
+
+    fn hash_leaf(&self, data: &[u8]) -> Hash {
+        (**self).hash_leaf(data)
+    }
+
+    fn hash_node(&self, left: &Hash, right: &Hash) -> Hash {
+        (**self).hash_node(left, right)
+    }
+
+    fn hash_children(&self, children: &[Hash]) -> Hash {
+        (**self).hash_children(children)
+    }
+
+    fn backend(&self) -> &'static str {
+        (**self).backend()
+    }
+
+    fn domain_separated(&self) -> bool {
+        (**self).domain_separated()
+    }
+}
+
+
+// Builds trees the way they were built before domain separation, hashing
+// leaves and nodes with no prefix. Only for checking roots made back then.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Legacy<H>(pub H);
+
+
+impl<H: Hasher> Hasher for Legacy<H> {
+    fn hash(&self, data: &[u8]) -> Hash {
+        self.0.hash(data)
+    }
+
+    fn hash_concat(&self, left: &Hash, right: &Hash) -> Hash {
+        self.0.hash_concat(left, right)
+    }
+
+    fn hash_many(&self, children: &[Hash]) -> Hash {
+        self.0.hash_many(children)
+    }
+
+    fn hash_leaf(&self, data: &[u8]) -> Hash {
+        self.0.hash(data)
+    }
+
+    fn hash_node(&self, left: &Hash, right: &Hash) -> Hash {
+        self.0.hash_concat(left, right)
+    }
+
+    fn hash_children(&self, children: &[Hash]) -> Hash {
+        self.0.hash_many(children)
+    }
+
+    fn backend(&self) -> &'static str {
+        self.0.backend()
+    }
+
+    fn domain_separated(&self) -> bool {
+        false
+    }
+}
@@ -0,0 +1,156 @@
+// Proofs that carry the leaf's index and the tree size instead of a
+// direction per hash. The index says which side each sibling is on, and the
+// size says where an odd node is promoted with no sibling at all, so the
+// directions can't be wrong and the hash count is fixed by the position.
+//
+//   index: u64 | tree_size: u64 | hash_len: u8 | hashes
+//
+// The number of hashes isn't sent; it follows from the index and size.
+
+use crate::address::proof_path;
+use crate::ct::hashes_equal;
+use crate::{Hash, HashDirection, Hashable, Hasher, MerkleTree, Position, Proof, Sha256Hasher};
+use std::borrow::Cow;
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexedProof {
+    pub index: usize,
+    pub tree_size: usize,
+    pub hashes: Vec<Hash>,
+}
+
+
+// Which side each sibling goes on, leaf first, for a leaf at `index` of
+// `tree_size`. Levels where the leaf's ancestor is promoted are skipped.
+pub(crate) fn sibling_directions(index: usize, tree_size: usize) -> Vec<HashDirection> {
+    proof_path(index as u64, tree_size as u64).into_iter().map(|(_, direction)| direction).collect()
+}
+
+
+impl IndexedProof {
+    // Drops the directions from a proof that claims its position. None if
+    // it claims none or its directions don't fit that position.
+    pub fn from_proof(proof: &Proof) -> Option<IndexedProof> {
+        let Position { index, tree_size } = proof.position()?;
+        if !proof.matches_position() {
+            return None;
+        }
+        let hashes = proof.hashes.iter().map(|(_, hash)| hash.to_vec()).collect();
+        Some(IndexedProof { index, tree_size, hashes })
+    }
+
+
+    // Puts the directions back. None if the hash count doesn't fit the
+    // position.
+    pub fn to_proof(&self) -> Option<Proof<'static>> {
+        let directions = sibling_directions(self.index, self.tree_size);
+        if self.index >= self.tree_size || directions.len() != self.hashes.len() {
+            return None;
+        }
+        let hashes = directions.into_iter().zip(self.hashes.iter().cloned().map(Cow::Owned)).collect();
+        Some(Proof { hashes, position: None }.with_position(self.index, self.tree_size))
+    }
+
+
+    pub fn compute_root_with<T: Hashable + ?Sized, H: Hasher>(&self, data: &T, hasher: &H) -> Option<Hash> {
+        Some(self.to_proof()?.compute_root_with(data, hasher))
+    }
+
+
+    pub fn verify<T: Hashable + ?Sized>(&self, data: &T, root: &Hash) -> bool {
+        self.verify_with(data, root, &Sha256Hasher)
+    }
+
+
+    pub fn verify_with<T: Hashable + ?Sized, H: Hasher>(&self, data: &T, root: &Hash, hasher: &H) -> bool {
+        self.compute_root_with(data, hasher).is_some_and(|computed| hashes_equal(&computed, root))
+    }
+
+
+    pub fn encode(&self) -> Vec<u8> {
+        let hash_len = self.hashes.first().map_or(0, Vec::len);
+        assert!(hash_len <= u8::MAX as usize, "hash too long to encode");
+        let mut out = Vec::with_capacity(17 + self.hashes.len() * hash_len);
+        out.extend_from_slice(&(self.index as u64).to_be_bytes());
+        out.extend_from_slice(&(self.tree_size as u64).to_be_bytes());
+        out.push(hash_len as u8);
+        for hash in &self.hashes {
+            assert_eq!(hash.len(), hash_len, "proof hashes must share a length");
+            out.extend_from_slice(hash);
+        }
+        out
+    }
+
+
+    // Decodes a proof, returning None unless `bytes` is exactly one proof
+    // with the hash count its position calls for
+    pub fn decode(bytes: &[u8]) -> Option<IndexedProof> {
+        let index = usize::try_from(u64::from_be_bytes(bytes.get(..8)?.try_into().ok()?)).ok()?;
+        let tree_size = usize::try_from(u64::from_be_bytes(bytes.get(8..16)?.try_into().ok()?)).ok()?;
+        let hash_len = *bytes.get(16)? as usize;
+        if index >= tree_size {
+            return None;
+        }
+        let count = sibling_directions(index, tree_size).len();
+        let body = &bytes[17..];
+        if body.len() != count * hash_len || (count > 0 && hash_len == 0) {
+            return None;
+        }
+        let hashes = body.chunks(hash_len.max(1)).map(<[u8]>::to_vec).collect();
+        Some(IndexedProof { index, tree_size, hashes })
+    }
+}
+
+
+impl<H: Hasher> MerkleTree<H> {
+    pub fn prove_indexed(&self, index: usize) -> Option<IndexedProof> {
+        let hashes = self.prove_index(index)?.hashes.into_iter().map(|(_, hash)| hash.into_owned()).collect();
+        Some(IndexedProof { index, tree_size: self.leaf_count, hashes })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Data;
+
+
+    #[test]
+    fn test_indexed_proofs() {
+        for n in 1..=13 {
+            let data: Vec<Data> = (0..n as u8).map(|i| vec![i]).collect();
+            let tree = MerkleTree::construct(&data);
+            let root = tree.root();
+            for (index, leaf) in data.iter().enumerate() {
+                let proof = tree.prove_indexed(index).unwrap();
+                assert!(proof.verify(leaf, &root));
+                let positioned = tree.prove_with_position(leaf).unwrap();
+                assert_eq!(IndexedProof::from_proof(&positioned), Some(proof.clone()));
+
+                let encoded = proof.encode();
+                assert_eq!(encoded.len(), 17 + 32 * positioned.len());
+                assert_eq!(IndexedProof::decode(&encoded), Some(proof));
+            }
+        }
+    }
+
+    #[test]
+    fn test_position_fixes_directions() {
+        let data: Vec<Data> = (0..6u8).map(|i| vec![i]).collect();
+        let tree = MerkleTree::construct(&data);
+        let proof = tree.prove_indexed(4).unwrap();
+        // The same hashes claimed for the neighbouring leaf go on the wrong sides
+        let moved = IndexedProof { index: 5, ..proof.clone() };
+        assert!(!moved.verify(&data[4], &tree.root()));
+        // Leaf 4 is promoted past one level, so it takes one hash fewer than leaf 0
+        let stretched = IndexedProof { index: 0, ..proof.clone() };
+        assert!(stretched.to_proof().is_none());
+
+        let encoded = proof.encode();
+        assert!(IndexedProof::decode(&encoded[..encoded.len() - 1]).is_none());
+        assert!(IndexedProof::decode(&[encoded.clone(), vec![0; 32]].concat()).is_none());
+        assert!(IndexedProof::from_proof(&tree.prove(&data[4]).unwrap()).is_none());
+    }
+}
@@ -0,0 +1,180 @@
+// Structural guarantees of the binary tree, and a checker for them. Every
+// build checks the tree's shape after construction and bounds every proof it
+// hands out, panicking on a violation since that can only be a bug here;
+// both checks are cheap next to the hashing. The full check rehashes
+// everything, so it only runs when asked.
+
+use crate::{reduce_level, Hash, HashDirection, Hasher, MerkleTree, Proof, StorageMode};
+use std::borrow::Cow;
+use std::fmt;
+
+
+// The most hashes a proof can hold in a tree of `tree_size` leaves:
+// ceil(log2(n)), since each level halves the width (rounding up)
+pub fn max_proof_len(tree_size: usize) -> usize {
+    if tree_size <= 1 {
+        0
+    } else {
+        (usize::BITS - (tree_size - 1).leading_zeros()) as usize
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InvariantViolation {
+    // The tree has a different number of levels than its leaf count implies
+    Height { levels: usize, expected: usize },
+    // A level isn't half the width of the one below, rounded up
+    LevelWidth { level: usize },
+    // A node isn't the hash of its children
+    NodeHash { level: usize, index: usize },
+    // `leaves_idx` points at a leaf with a different hash
+    LeafIndex { index: usize },
+    // A proof came out longer than `max_proof_len` allows
+    ProofTooLong { len: usize, max: usize },
+}
+
+
+impl fmt::Display for InvariantViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InvariantViolation::Height { levels, expected } => {
+                write!(f, "tree has {} levels, expected {}", levels, expected)
+            }
+            InvariantViolation::LevelWidth { level } => write!(f, "level {} has the wrong width", level),
+            InvariantViolation::NodeHash { level, index } => {
+                write!(f, "node {} on level {} doesn't match its children", index, level)
+            }
+            InvariantViolation::LeafIndex { index } => write!(f, "leaf index entry {} is stale", index),
+            InvariantViolation::ProofTooLong { len, max } => {
+                write!(f, "proof has {} hashes, at most {} allowed", len, max)
+            }
+        }
+    }
+}
+
+
+impl std::error::Error for InvariantViolation {}
+
+
+// The most steps a k-ary proof can take: one per level above the leaves,
+// each level dividing the width by the arity (rounding up)
+pub fn max_kary_proof_len(tree_size: usize, arity: usize) -> usize {
+    let mut width = tree_size;
+    let mut len = 0;
+    while width > 1 {
+        width = width.div_ceil(arity);
+        len += 1;
+    }
+    len
+}
+
+
+// Every inclusion proof generator builds its output here, so no proof
+// handed out is longer than `max_proof_len` allows for its tree size
+pub(crate) fn bounded_proof(hashes: Vec<(HashDirection, Cow<'_, Hash>)>, tree_size: usize) -> Proof<'_> {
+    let proof = Proof { hashes, position: None };
+    assert_eq!(check_proof_len(&proof, tree_size), Ok(()));
+    proof
+}
+
+
+pub(crate) fn check_proof_len(proof: &Proof, tree_size: usize) -> Result<(), InvariantViolation> {
+    let max = max_proof_len(tree_size);
+    match proof.hashes.len() {
+        len if len > max => Err(InvariantViolation::ProofTooLong { len, max }),
+        _ => Ok(()),
+    }
+}
+
+
+impl<H: Hasher> MerkleTree<H> {
+    // Checks every level against the one below and the leaf index against
+    // the leaves. Touches every node, so it costs as much as construction.
+    pub fn check_invariants(&self) -> Result<(), InvariantViolation> {
+        self.check_shape()?;
+        let Some(levels) = self.levels() else {
+            return Ok(());
+        };
+        for (level, pair) in levels.windows(2).enumerate() {
+            let rebuilt = reduce_level(&pair[0], &self.hasher);
+            if let Some(index) = rebuilt.iter().zip(&pair[1]).position(|(want, got)| want != got) {
+                return Err(InvariantViolation::NodeHash { level: level + 1, index });
+            }
+        }
+        for (leaf, &index) in self.leaves_idx.iter() {
+            if levels[0].get(index) != Some(leaf) {
+                return Err(InvariantViolation::LeafIndex { index });
+            }
+        }
+        Ok(())
+    }
+
+
+    // The checks that need no hashing: level count and widths. Every
+    // construction runs these, in release builds too.
+    pub(crate) fn check_shape(&self) -> Result<(), InvariantViolation> {
+        // Proofs hold at most one hash per level below the root
+        let expected = match self.mode {
+            StorageMode::Full => max_proof_len(self.leaf_count) + 1,
+            StorageMode::LeavesOnly => (max_proof_len(self.leaf_count) + 1).min(2),
+            StorageMode::RootOnly => 1,
+        };
+        if self.nodes.len() != expected {
+            return Err(InvariantViolation::Height { levels: self.nodes.len(), expected });
+        }
+        if self.mode != StorageMode::Full {
+            return Ok(());
+        }
+        match self.nodes.windows(2).position(|pair| pair[1].len() != pair[0].len().div_ceil(2)) {
+            Some(level) => Err(InvariantViolation::LevelWidth { level: level + 1 }),
+            None => Ok(()),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Data, Sha256Hasher};
+    use std::sync::Arc;
+
+
+    #[test]
+    fn test_max_proof_len() {
+        let expected = [0, 0, 1, 2, 2, 3, 3, 3, 3, 4];
+        for (n, &len) in expected.iter().enumerate() {
+            assert_eq!(max_proof_len(n), len);
+        }
+        for n in 1..=40 {
+            let data: Vec<Data> = (0..n as u8).map(|i| vec![i]).collect();
+            let tree = MerkleTree::construct(&data);
+            let longest = data.iter().map(|leaf| tree.prove(leaf).unwrap().hashes.len()).max().unwrap();
+            assert_eq!(longest, max_proof_len(n));
+        }
+    }
+
+    #[test]
+    fn test_check_invariants() {
+        let data: Vec<Data> = (0..9u8).map(|i| vec![i]).collect();
+        for mode in [StorageMode::Full, StorageMode::LeavesOnly, StorageMode::RootOnly] {
+            assert_eq!(MerkleTree::construct_with_mode(&data, Sha256Hasher, mode).check_invariants(), Ok(()));
+        }
+
+        let mut tree = MerkleTree::construct(&data);
+        Arc::make_mut(&mut tree.nodes)[1][2] = vec![0; 32];
+        assert_eq!(tree.check_invariants(), Err(InvariantViolation::NodeHash { level: 1, index: 2 }));
+        let mut tree = MerkleTree::construct(&data);
+        Arc::make_mut(&mut tree.nodes)[2].pop();
+        assert_eq!(tree.check_invariants(), Err(InvariantViolation::LevelWidth { level: 2 }));
+        let mut tree = MerkleTree::construct(&data);
+        Arc::make_mut(&mut tree.leaves_idx).insert(vec![1; 32], 3);
+        assert_eq!(tree.check_invariants(), Err(InvariantViolation::LeafIndex { index: 3 }));
+
+        let tree = MerkleTree::construct(&data);
+        let proof = tree.prove(&data[0]).unwrap();
+        assert_eq!(check_proof_len(&proof, 9), Ok(()));
+        assert_eq!(check_proof_len(&proof, 8), Err(InvariantViolation::ProofTooLong { len: 4, max: 3 }));
+    }
+}
@@ -0,0 +1,92 @@
+// Iterators over a tree's leaves, levels and proofs. Proofs are made one
+// at a time as the iterator is advanced, so writing out every proof of a
+// large tree streams rather than building them all first. Storage modes
+// that rebuild the inner levels rebuild them once per iterator.
+
+use crate::{levels_proof, Hash, Hasher, MerkleTree, Proof};
+use std::borrow::Cow;
+use std::ops::Range;
+use std::slice;
+
+
+// Every leaf's proof in order, each claiming its position
+pub struct Proofs<'a> {
+    levels: Option<Cow<'a, [Vec<Hash>]>>,
+    indices: Range<usize>,
+}
+
+
+impl<'a> Iterator for Proofs<'a> {
+    type Item = Proof<'a>;
+
+    fn next(&mut self) -> Option<Proof<'a>> {
+        let index = self.indices.next()?;
+        let tree_size = self.indices.end;
+        // The same path as `prove_index`, over levels rebuilt once
+        Some(levels_proof(self.levels.as_ref()?, index).with_position(index, tree_size))
+    }
+
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = if self.levels.is_some() { self.indices.len() } else { 0 };
+        (len, Some(len))
+    }
+}
+
+
+impl ExactSizeIterator for Proofs<'_> {}
+
+
+impl<H: Hasher> MerkleTree<H> {
+    // Leaf hashes in order; none if the storage mode dropped them
+    pub fn iter_leaves(&self) -> slice::Iter<'_, Hash> {
+        self.level(0).unwrap_or(&[]).iter()
+    }
+
+
+    // The hashes on level `i`, as `level` gives them
+    pub fn iter_level(&self, i: usize) -> Option<slice::Iter<'_, Hash>> {
+        Some(self.level(i)?.iter())
+    }
+
+
+    // Proofs for every leaf in order, made as they're asked for. Empty if
+    // the storage mode kept too little to prove from.
+    pub fn iter_proofs(&self) -> Proofs<'_> {
+        Proofs { levels: self.levels(), indices: 0..self.leaf_count }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Data, Sha256Hasher, StorageMode};
+
+
+    #[test]
+    fn test_iterators() {
+        let data: Vec<Data> = (0..7u8).map(|i| vec![i]).collect();
+        let tree = MerkleTree::construct(&data);
+        assert!(tree.iter_leaves().eq(tree.level(0).unwrap()));
+        assert_eq!(tree.iter_level(1).unwrap().len(), 4);
+        assert!(tree.iter_level(4).is_none());
+
+        let proofs = tree.iter_proofs();
+        assert_eq!(proofs.len(), 7);
+        for (leaf, proof) in data.iter().zip(proofs) {
+            assert_eq!(proof, tree.prove_with_position(leaf).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_iter_proofs_by_storage_mode() {
+        let data: Vec<Data> = (0..6u8).map(|i| vec![i]).collect();
+        let root = MerkleTree::construct(&data).root();
+        let leaves_only = MerkleTree::construct_with_mode(&data, Sha256Hasher, StorageMode::LeavesOnly);
+        assert!(leaves_only.iter_proofs().zip(&data).all(|(proof, leaf)| MerkleTree::verify_proof(leaf, &proof, &root)));
+        let root_only = MerkleTree::construct_with_mode(&data, Sha256Hasher, StorageMode::RootOnly);
+        assert_eq!(root_only.iter_proofs().count(), 0);
+        assert_eq!(root_only.iter_leaves().len(), 0);
+    }
+}
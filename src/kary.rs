@@ -0,0 +1,282 @@
+use crate::ct::hashes_equal;
+use crate::invariants::{max_kary_proof_len, InvariantViolation};
+use crate::{Data, Hash, Hasher, Sha256Hasher};
+use std::collections::HashMap;
+
+
+// A Merkle tree where each parent commits to up to `arity` children.
+// Wider trees are shallower, so fewer hash invocations are needed when
+// the hash itself is expensive. A trailing group with a single node is
+// promoted unchanged, like the odd node of the binary tree.
+pub struct KaryTree<H = Sha256Hasher> {
+    arity: usize,
+    nodes: Vec<Vec<Hash>>,
+    leaves_idx: HashMap<Hash, usize>,
+    hasher: H,
+}
+
+
+// One level of a k-ary proof: where the proven node sits in its group and
+// the other members of that group, in order
+#[derive(Debug, Clone, PartialEq)]
+pub struct KaryStep {
+    pub position: usize,
+    pub siblings: Vec<Hash>,
+}
+
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct KaryProof {
+    pub steps: Vec<KaryStep>,
+}
+
+
+impl KaryProof {
+    // Folds the proof over the given data, returning the root it leads to.
+    // None if a step places the node past the end of its group.
+    pub fn compute_root_with<H: Hasher>(&self, data: &Data, hasher: &H) -> Option<Hash> {
+        let mut current_hash = hasher.hash_leaf(data);
+        for step in self.steps.iter() {
+            if step.position > step.siblings.len() {
+                return None;
+            }
+            let mut children = step.siblings.clone();
+            children.insert(step.position, current_hash);
+            current_hash = hasher.hash_children(&children);
+        }
+        Some(current_hash)
+    }
+}
+
+
+impl KaryTree {
+    // Constructs a tree of the given arity (at least 2) from input data
+    pub fn construct(input: &[Data], arity: usize) -> KaryTree {
+        Self::construct_with(input, arity, Sha256Hasher)
+    }
+
+
+    // Verifies that the given data and proof produce the given root hash
+    pub fn verify_proof(data: &Data, proof: &KaryProof, root_hash: &Hash) -> bool {
+        proof.compute_root_with(data, &Sha256Hasher).is_some_and(|root| hashes_equal(&root, root_hash))
+    }
+}
+
+
+impl<H: Hasher> KaryTree<H> {
+    // Constructs a tree of the given arity using the given hasher
+    pub fn construct_with(input: &[Data], arity: usize, hasher: H) -> KaryTree<H> {
+        assert!(arity >= 2, "arity must be at least 2");
+        let mut nodes = Vec::new();
+        let mut leaves_idx = HashMap::with_capacity(input.len());
+
+        let mut new_nodes: Vec<Hash> = input.iter().enumerate().map(|(i, leaf)| {
+            let h = hasher.hash_leaf(leaf);
+            leaves_idx.insert(h.clone(), i);
+            h
+        }).collect();
+
+        while new_nodes.len() > 1 {
+            let next = new_nodes
+                .chunks(arity)
+                .map(|chunk| {
+                    if chunk.len() == 1 {
+                        chunk[0].clone()
+                    } else {
+                        hasher.hash_children(chunk)
+                    }
+                })
+                .collect();
+            nodes.push(new_nodes);
+            new_nodes = next;
+        }
+        nodes.push(new_nodes);
+
+        let tree = KaryTree {
+            arity,
+            nodes,
+            leaves_idx,
+            hasher,
+        };
+        assert_eq!(tree.check_shape(), Ok(()));
+        tree
+    }
+
+
+    // Checks every level against the one below and the leaf index against
+    // the leaves, as `MerkleTree::check_invariants` does
+    pub fn check_invariants(&self) -> Result<(), InvariantViolation> {
+        self.check_shape()?;
+        for (level, pair) in self.nodes.windows(2).enumerate() {
+            let rebuilt = pair[0].chunks(self.arity).map(|chunk| match chunk {
+                [single] => single.clone(),
+                _ => self.hasher.hash_children(chunk),
+            });
+            if let Some(index) = rebuilt.zip(&pair[1]).position(|(want, got)| &want != got) {
+                return Err(InvariantViolation::NodeHash { level: level + 1, index });
+            }
+        }
+        for (leaf, &index) in self.leaves_idx.iter() {
+            if self.nodes[0].get(index) != Some(leaf) {
+                return Err(InvariantViolation::LeafIndex { index });
+            }
+        }
+        Ok(())
+    }
+
+
+    // Level count and widths, checked after every construction
+    fn check_shape(&self) -> Result<(), InvariantViolation> {
+        let expected = max_kary_proof_len(self.leaf_count(), self.arity) + 1;
+        if self.nodes.len() != expected {
+            return Err(InvariantViolation::Height { levels: self.nodes.len(), expected });
+        }
+        match self.nodes.windows(2).position(|pair| pair[1].len() != pair[0].len().div_ceil(self.arity)) {
+            Some(level) => Err(InvariantViolation::LevelWidth { level: level + 1 }),
+            None => Ok(()),
+        }
+    }
+
+
+    // Gets root hash for this tree
+    pub fn root(&self) -> Hash {
+        self.nodes.last().unwrap().first().unwrap().clone()
+    }
+
+
+    // Children per parent
+    pub fn arity(&self) -> usize {
+        self.arity
+    }
+
+
+    // The hasher this tree was built with
+    pub fn hasher(&self) -> &H {
+        &self.hasher
+    }
+
+
+    pub fn leaf_count(&self) -> usize {
+        self.nodes[0].len()
+    }
+
+
+    // Levels above the leaves
+    pub fn depth(&self) -> usize {
+        self.nodes.len() - 1
+    }
+
+
+    // The hashes on level `i`, leaves being level 0. None past the root.
+    pub fn level(&self, i: usize) -> Option<&[Hash]> {
+        self.nodes.get(i).map(Vec::as_slice)
+    }
+
+
+    // Where the leaf with the given hash sits, if it is in the tree
+    pub fn leaf_index(&self, leaf_hash: &Hash) -> Option<usize> {
+        self.leaves_idx.get(leaf_hash).copied()
+    }
+
+
+    // Returns the sibling groups proving that the given data is in this tree
+    pub fn prove(&self, data: &Data) -> Option<KaryProof> {
+        let mut current_idx = *self.leaves_idx.get(&self.hasher.hash_leaf(data))?;
+        let mut steps = Vec::new();
+        for level in &self.nodes[..self.nodes.len() - 1] {
+            let start = current_idx - current_idx % self.arity;
+            let end = (start + self.arity).min(level.len());
+            // A lone trailing node is promoted, so there is nothing to prove
+            if end - start > 1 {
+                let siblings = (start..end)
+                    .filter(|&i| i != current_idx)
+                    .map(|i| level[i].clone())
+                    .collect();
+                steps.push(KaryStep { position: current_idx - start, siblings });
+            }
+            current_idx /= self.arity;
+        }
+        let max = max_kary_proof_len(self.leaf_count(), self.arity);
+        assert!(steps.len() <= max, "proof has {} steps, at most {} allowed", steps.len(), max);
+        Some(KaryProof { steps })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MerkleTree;
+
+
+    #[test]
+    fn test_binary_arity_matches_merkle_tree() {
+        for n in 1..=10 {
+            let data: Vec<Data> = (0..n as u8).map(|i| vec![i]).collect();
+            assert_eq!(KaryTree::construct(&data, 2).root(), MerkleTree::construct(&data).root());
+        }
+    }
+
+    #[test]
+    fn test_kary_prove() {
+        for arity in [3, 4, 16] {
+            for n in 1..=40 {
+                let data: Vec<Data> = (0..n as u8).map(|i| vec![i]).collect();
+                let tree = KaryTree::construct(&data, arity);
+                for leaf in &data {
+                    let proof = tree.prove(leaf).unwrap();
+                    assert!(KaryTree::verify_proof(leaf, &proof, &tree.root()));
+                    assert!(!KaryTree::verify_proof(&vec![200], &proof, &tree.root()));
+                }
+                assert!(tree.prove(&vec![200]).is_none());
+            }
+        }
+
+        // A position past the end of its group is rejected, not clamped
+        let data: Vec<Data> = (0..9u8).map(|i| vec![i]).collect();
+        let tree = KaryTree::construct(&data, 3);
+        let mut proof = tree.prove(&data[8]).unwrap();
+        assert_eq!(proof.steps[0].position, 2);
+        proof.steps[0].position = 3;
+        assert_eq!(proof.compute_root_with(&data[8], &Sha256Hasher), None);
+        assert!(!KaryTree::verify_proof(&data[8], &proof, &tree.root()));
+        let data: Vec<Data> = (0..64u8).map(|i| vec![i]).collect();
+        assert_eq!(KaryTree::construct(&data, 4).depth(), 3);
+    }
+
+    #[test]
+    fn test_kary_accessors() {
+        let data: Vec<Data> = (0..10u8).map(|i| vec![i]).collect();
+        let tree = KaryTree::construct(&data, 3);
+        assert_eq!(tree.arity(), 3);
+        assert_eq!(tree.leaf_count(), 10);
+        let widths: Vec<usize> = (0..=tree.depth()).map(|i| tree.level(i).unwrap().len()).collect();
+        assert_eq!(widths, vec![10, 4, 2, 1]);
+        assert!(tree.level(tree.depth() + 1).is_none());
+        assert_eq!(tree.leaf_index(&Sha256Hasher.hash_leaf(&data[7])), Some(7));
+        assert_eq!(tree.leaf_index(&Sha256Hasher.hash_leaf(&[200])), None);
+    }
+
+    #[test]
+    fn test_kary_invariants() {
+        for arity in [2, 3, 5] {
+            for n in 0..=30 {
+                let data: Vec<Data> = (0..n as u8).map(|i| vec![i]).collect();
+                let tree = KaryTree::construct(&data, arity);
+                assert_eq!(tree.check_invariants(), Ok(()));
+                for leaf in &data {
+                    assert!(tree.prove(leaf).unwrap().steps.len() <= max_kary_proof_len(n, arity));
+                }
+            }
+        }
+
+        let data: Vec<Data> = (0..10u8).map(|i| vec![i]).collect();
+        let mut tree = KaryTree::construct(&data, 3);
+        tree.nodes[1][2] = vec![0; 32];
+        assert_eq!(tree.check_invariants(), Err(InvariantViolation::NodeHash { level: 1, index: 2 }));
+        tree.nodes[2].pop();
+        assert_eq!(tree.check_invariants(), Err(InvariantViolation::LevelWidth { level: 2 }));
+        tree.nodes.pop();
+        assert_eq!(tree.check_invariants(), Err(InvariantViolation::Height { levels: 3, expected: 4 }));
+    }
+}
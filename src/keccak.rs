@@ -0,0 +1,208 @@
+// Keccak-256 and SHA3-256, and trees that interoperate with Ethereum
+// contracts. `Keccak256Hasher` and `Sha3Hasher` are plain hash functions
+// and build this crate's usual domain-separated trees.
+//
+// `SortedKeccak` instead builds the trees OpenZeppelin's `MerkleProof.verify`
+// checks: a leaf hashes to keccak256 of its bytes, and a parent to keccak256
+// of its children in ascending order rather than left then right. Because
+// pairs are sorted, a proof is just the sibling hashes, with no sides, and
+// odd nodes are promoted as merkletreejs does with `sortPairs`. There are no
+// leaf and node prefixes, so as with any such tree a 64-byte leaf can pose
+// as a parent; contracts avoid this by hashing leaves twice, which here
+// means passing keccak256 of the ABI encoding as the leaf bytes.
+
+use crate::ct::hashes_equal;
+use crate::{FixedHasher, Hash, Hashable, Hasher, MerkleTree, Proof};
+use sha3::{Digest, Keccak256, Sha3_256};
+
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Keccak256Hasher;
+
+
+impl Hasher for Keccak256Hasher {
+    fn hash(&self, data: &[u8]) -> Hash {
+        Keccak256::digest(data).to_vec()
+    }
+
+    fn backend(&self) -> &'static str {
+        "keccak256"
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Sha3Hasher;
+
+
+impl Hasher for Sha3Hasher {
+    fn hash(&self, data: &[u8]) -> Hash {
+        Sha3_256::digest(data).to_vec()
+    }
+
+    fn backend(&self) -> &'static str {
+        "sha3-256"
+    }
+}
+
+
+// Keccak-256 with sorted pairs and no prefixes, as Solidity verifies
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SortedKeccak;
+
+
+impl Hasher for SortedKeccak {
+    fn hash(&self, data: &[u8]) -> Hash {
+        Keccak256::digest(data).to_vec()
+    }
+
+    fn hash_leaf(&self, data: &[u8]) -> Hash {
+        self.hash(data)
+    }
+
+    fn hash_node(&self, left: &Hash, right: &Hash) -> Hash {
+        let (low, high) = if left <= right { (left, right) } else { (right, left) };
+        self.hash_concat(low, high)
+    }
+
+    fn backend(&self) -> &'static str {
+        "keccak256"
+    }
+
+    fn domain_separated(&self) -> bool {
+        false
+    }
+}
+
+
+impl FixedHasher<32> for Keccak256Hasher {
+    fn hash_parts(&self, parts: &[&[u8]]) -> [u8; 32] {
+        digest_parts::<Keccak256>(parts)
+    }
+}
+
+
+impl FixedHasher<32> for Sha3Hasher {
+    fn hash_parts(&self, parts: &[&[u8]]) -> [u8; 32] {
+        digest_parts::<Sha3_256>(parts)
+    }
+}
+
+
+impl FixedHasher<32> for SortedKeccak {
+    fn hash_parts(&self, parts: &[&[u8]]) -> [u8; 32] {
+        digest_parts::<Keccak256>(parts)
+    }
+
+    fn hash_leaf_fixed(&self, data: &[u8]) -> [u8; 32] {
+        self.hash_parts(&[data])
+    }
+
+    fn hash_node_fixed(&self, left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+        let (low, high) = if left <= right { (left, right) } else { (right, left) };
+        self.hash_parts(&[low, high])
+    }
+}
+
+
+fn digest_parts<D: Digest>(parts: &[&[u8]]) -> [u8; 32] {
+    let mut digest = D::new();
+    for part in parts {
+        digest.update(part);
+    }
+    digest.finalize().as_slice().try_into().expect("a 32-byte digest")
+}
+
+
+impl MerkleTree<SortedKeccak> {
+    // A tree whose root a Solidity contract can hold and check proofs
+    // against with `MerkleProof.verify`
+    pub fn construct_ethereum<T: Hashable>(input: &[T]) -> MerkleTree<SortedKeccak> {
+        MerkleTree::construct_with(input, SortedKeccak)
+    }
+}
+
+
+impl Proof<'_> {
+    // The sibling hashes alone, bottom first: the `bytes32[]` proof that
+    // `MerkleProof.verify` takes. Only meaningful for `SortedKeccak` trees,
+    // where dropping the sides loses nothing.
+    pub fn to_sorted_proof(&self) -> Vec<Hash> {
+        self.hashes.iter().map(|(_, hash)| hash.to_vec()).collect()
+    }
+}
+
+
+// `MerkleProof.verify(proof, root, leaf)`, where `leaf` is the leaf's hash
+pub fn verify_sorted(proof: &[Hash], root: &Hash, leaf: &Hash) -> bool {
+    let computed = proof.iter().fold(leaf.clone(), |current, sibling| SortedKeccak.hash_node(&current, sibling));
+    hashes_equal(&computed, root)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Data;
+
+
+    #[test]
+    fn test_hashers() {
+        assert_eq!(hex::encode(Keccak256Hasher.hash(b"")), "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470");
+        assert_eq!(hex::encode(Sha3Hasher.hash(b"")), "a7ffc6f8bf1ed76651c14756a061d662f580ff4de43b49fa82d80a4b80f8434a");
+    }
+
+    #[test]
+    fn test_sorted_pair_proofs() {
+        let data: Vec<Data> = (0..5u8).map(|i| vec![i; 20]).collect();
+        let tree = MerkleTree::construct_ethereum(&data);
+        let leaves: Vec<Hash> = data.iter().map(|leaf| Keccak256::digest(leaf).to_vec()).collect();
+        // Two leaves hash in ascending order, whichever side they're on
+        let (low, high) = if leaves[0] < leaves[1] { (&leaves[0], &leaves[1]) } else { (&leaves[1], &leaves[0]) };
+        assert_eq!(tree.level(1).unwrap()[0], Keccak256::digest([low.as_slice(), high].concat()).to_vec());
+
+        let root = tree.root();
+        for (leaf, leaf_hash) in data.iter().zip(&leaves) {
+            let proof = tree.prove(leaf).unwrap().to_sorted_proof();
+            assert!(verify_sorted(&proof, &root, leaf_hash));
+            assert!(!verify_sorted(&proof, &root, &leaves[0]) || leaf_hash == &leaves[0]);
+        }
+    }
+
+    #[test]
+    fn test_openzeppelin_standard_tree() {
+        // The example from the @openzeppelin/merkle-tree README: leaves are
+        // keccak256(abi.encode(address, uint256)), which StandardMerkleTree
+        // hashes once more, as hash_leaf does here
+        let encode = |address: u8, amount: u128| {
+            let mut encoded = vec![0; 12];
+            encoded.extend_from_slice(&[address; 20]);
+            encoded.extend_from_slice(&[0; 16]);
+            encoded.extend_from_slice(&amount.to_be_bytes());
+            Keccak256::digest(&encoded).to_vec()
+        };
+        let leaves = [encode(0x11, 5_000_000_000_000_000_000), encode(0x22, 2_500_000_000_000_000_000)];
+        let tree = MerkleTree::construct_ethereum(&leaves);
+        assert_eq!(hex::encode(tree.root()), "d4dee0beab2d53f2cc83e567171bd2820e49898130a22622b10ead383e90bd77");
+        let proof = tree.prove(&leaves[0]).unwrap().to_sorted_proof();
+        assert_eq!(
+            proof.iter().map(hex::encode).collect::<Vec<_>>(),
+            ["b92c48e9d7abe27fd8dfd6b5dfdbfb1c9a463f80c712b66f3a5180a090cccafc"]
+        );
+        assert!(verify_sorted(&proof, &tree.root(), &SortedKeccak.hash_leaf(&leaves[0])));
+    }
+
+    #[test]
+    fn test_fixed_proofs() {
+        let data: Vec<Data> = (0..5u8).map(|i| vec![i; 20]).collect();
+        let keccak = MerkleTree::construct_with(&data, Keccak256Hasher);
+        let sha3 = MerkleTree::construct_with(&data, Sha3Hasher);
+        let sorted = MerkleTree::construct_ethereum(&data);
+        let leaf = &data[3];
+        let root = |tree_root: Hash| -> [u8; 32] { tree_root.try_into().unwrap() };
+        let fixed = |proof: Proof| crate::FixedProof::<32, 64>::from_proof(&proof).unwrap();
+        assert!(fixed(keccak.prove(leaf).unwrap()).verify(leaf, &root(keccak.root()), &Keccak256Hasher));
+        assert!(fixed(sha3.prove(leaf).unwrap()).verify(leaf, &root(sha3.root()), &Sha3Hasher));
+        assert!(fixed(sorted.prove(leaf).unwrap()).verify(leaf, &root(sorted.root()), &SortedKeccak));
+    }
+}
@@ -0,0 +1,153 @@
+// KZG commitments over BN254 as a `VectorCommitment` for `VerkleTree`. A
+// group of values is the polynomial taking value i at the i-th root of unity;
+// the commitment is that polynomial evaluated at a secret point in G1, and
+// an opening is one G1 point, checked with a pairing, whatever the width.
+//
+// Experimental. `insecure_setup` derives the secret from a seed, so anyone
+// with the seed can forge openings; real use needs the powers of a trusted
+// setup ceremony instead. Values are reduced into the scalar field, so two
+// hashes equal mod r are indistinguishable here.
+
+use crate::verkle::VectorCommitment;
+use crate::Hash;
+use ark_bn254::{Bn254, Fr, G1Affine, G1Projective, G2Affine, G2Projective};
+use ark_ec::pairing::Pairing;
+use ark_ec::{AffineRepr, CurveGroup, PrimeGroup, VariableBaseMSM};
+use ark_ff::{AdditiveGroup, Field, PrimeField};
+use ark_poly::{EvaluationDomain, Radix2EvaluationDomain};
+use ark_serialize::CanonicalSerialize;
+use sha2::Digest;
+
+
+pub struct Kzg {
+    domain: Radix2EvaluationDomain<Fr>,
+    // [s^i]G1 for every coefficient a group's polynomial can have
+    powers: Vec<G1Affine>,
+    // [s]G2
+    s_g2: G2Affine,
+}
+
+
+impl Kzg {
+    // Groups of `width` values, a power of two, with the secret derived
+    // from `seed`. For tests and experiments only.
+    pub fn insecure_setup(width: usize, seed: &[u8]) -> Kzg {
+        assert!(width.is_power_of_two() && width >= 2, "width must be a power of two of at least 2");
+        let domain = Radix2EvaluationDomain::new(width).expect("BN254 has roots of unity for any practical width");
+        let secret = Fr::from_be_bytes_mod_order(&sha2::Sha256::digest(seed));
+        let powers = (0..width)
+            .scan(Fr::ONE, |power, _| {
+                let point = G1Projective::generator() * *power;
+                *power *= secret;
+                Some(point)
+            })
+            .collect::<Vec<_>>();
+        let s_g2 = (G2Projective::generator() * secret).into_affine();
+        Kzg { domain, powers: G1Projective::normalize_batch(&powers), s_g2 }
+    }
+
+
+    // Coefficients of the polynomial through `values`, zero past the end
+    fn coefficients(&self, values: &[Hash]) -> Vec<Fr> {
+        assert!(values.len() <= self.powers.len(), "more values than the width");
+        let mut evaluations: Vec<Fr> = values.iter().map(to_field).collect();
+        evaluations.resize(self.powers.len(), Fr::ZERO);
+        self.domain.ifft(&evaluations)
+    }
+
+
+    fn commit_coefficients(&self, coefficients: &[Fr]) -> G1Affine {
+        G1Projective::msm(&self.powers[..coefficients.len()], coefficients)
+            .expect("as many bases as scalars")
+            .into_affine()
+    }
+}
+
+
+impl VectorCommitment for Kzg {
+    type Commitment = G1Affine;
+    type Opening = G1Affine;
+
+    fn width(&self) -> usize {
+        self.powers.len()
+    }
+
+    fn commit(&self, values: &[Hash]) -> G1Affine {
+        self.commit_coefficients(&self.coefficients(values))
+    }
+
+    // Commits to (p(X) - p(z)) / (X - z), found by synthetic division
+    fn open(&self, values: &[Hash], index: usize) -> G1Affine {
+        let coefficients = self.coefficients(values);
+        let z = self.domain.element(index);
+        let mut quotient = vec![Fr::ZERO; coefficients.len() - 1];
+        let mut carry = Fr::ZERO;
+        for i in (1..coefficients.len()).rev() {
+            carry = coefficients[i] + carry * z;
+            quotient[i - 1] = carry;
+        }
+        self.commit_coefficients(&quotient)
+    }
+
+    // e(C - [y]G1, G2) == e(proof, [s]G2 - [z]G2)
+    fn verify(&self, commitment: &G1Affine, index: usize, value: &Hash, opening: &G1Affine) -> bool {
+        if index >= self.width() {
+            return false;
+        }
+        let z = self.domain.element(index);
+        let lhs = *commitment - G1Affine::generator() * to_field(value);
+        let rhs = self.s_g2 - G2Affine::generator() * z;
+        Bn254::pairing(lhs, G2Affine::generator()) == Bn254::pairing(*opening, rhs)
+    }
+
+    // The compressed point, 32 bytes
+    fn to_hash(&self, commitment: &G1Affine) -> Hash {
+        let mut bytes = Vec::with_capacity(32);
+        commitment.serialize_compressed(&mut bytes).expect("writing to a vec can't fail");
+        bytes
+    }
+}
+
+
+fn to_field(value: &Hash) -> Fr {
+    Fr::from_be_bytes_mod_order(value)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::verkle::VerkleTree;
+    use crate::Data;
+
+
+    #[test]
+    fn test_kzg_openings() {
+        let kzg = Kzg::insecure_setup(8, b"test");
+        let values: Vec<Hash> = (0..5u8).map(|i| vec![i; 32]).collect();
+        let commitment = kzg.commit(&values);
+        for (index, value) in values.iter().enumerate() {
+            let opening = kzg.open(&values, index);
+            assert!(kzg.verify(&commitment, index, value, &opening));
+            assert!(!kzg.verify(&commitment, index, &vec![9; 32], &opening));
+        }
+        // Past the values the group holds zero
+        assert!(kzg.verify(&commitment, 6, &vec![0; 32], &kzg.open(&values, 6)));
+        assert!(!kzg.verify(&commitment, 8, &vec![0; 32], &kzg.open(&values, 6)));
+    }
+
+    #[test]
+    fn test_kzg_verkle_tree() {
+        let data: Vec<Data> = (0..20u8).map(|i| vec![i]).collect();
+        let tree = VerkleTree::construct(&data, Kzg::insecure_setup(4, b"test"));
+        let root = tree.root();
+        assert_eq!(root.len(), 32);
+        for i in [0, 7, 19] {
+            let proof = tree.prove(i).unwrap();
+            // Three levels of one commitment and one opening each
+            assert_eq!(proof.steps.len(), 3);
+            assert!(proof.verify(tree.scheme(), &data[i], &root));
+            assert!(!proof.verify(tree.scheme(), &data[(i + 1) % data.len()], &root));
+        }
+    }
+}
@@ -0,0 +1,161 @@
+// A tree for write-heavy workloads that rarely read the root. `update` and
+// `push` only replace a leaf and note it as dirty; the inner nodes above
+// dirty leaves are rehashed when `root` or `prove` next needs them, each
+// shared ancestor once. Many writes between reads then cost one rehash of
+// the paths they touched rather than one per write.
+
+use crate::invariants::check_proof_len;
+use crate::{path_proof, Data, Error, Hash, Hashable, Hasher, MerkleTree, Proof, Sha256Hasher};
+use std::collections::BTreeSet;
+
+
+pub struct LazyTree<H = Sha256Hasher> {
+    // Every level, leaves first. Levels above a dirty leaf may be stale,
+    // too short or missing until the next flush.
+    nodes: Vec<Vec<Hash>>,
+    // Leaves changed since the last flush
+    dirty: BTreeSet<usize>,
+    hasher: H,
+}
+
+
+impl LazyTree {
+    pub fn construct<T: Hashable>(input: &[T]) -> LazyTree {
+        Self::construct_with(input, Sha256Hasher)
+    }
+}
+
+
+impl<H: Hasher> LazyTree<H> {
+    // Hashes the leaves now; the levels above are built on first read
+    pub fn construct_with<T: Hashable>(input: &[T], hasher: H) -> LazyTree<H> {
+        let leaves: Vec<Hash> = input.iter().map(|leaf| hasher.hash_leaf(&leaf.leaf_bytes())).collect();
+        let dirty = (0..leaves.len()).collect();
+        LazyTree { nodes: vec![leaves], dirty, hasher }
+    }
+
+
+    pub fn leaf_count(&self) -> usize {
+        self.nodes[0].len()
+    }
+
+
+    // Whether a read would have to rehash first
+    pub fn is_dirty(&self) -> bool {
+        !self.dirty.is_empty()
+    }
+
+
+    pub fn update(&mut self, index: usize, data: &Data) -> Result<(), Error> {
+        let leaf_count = self.leaf_count();
+        let leaf = self.nodes[0].get_mut(index).ok_or(Error::IndexOutOfRange { index, leaf_count })?;
+        *leaf = self.hasher.hash_leaf(data);
+        self.dirty.insert(index);
+        Ok(())
+    }
+
+
+    // Appends a leaf, returning its index
+    pub fn push(&mut self, data: &Data) -> usize {
+        let index = self.leaf_count();
+        self.nodes[0].push(self.hasher.hash_leaf(data));
+        self.dirty.insert(index);
+        index
+    }
+
+
+    // Panics on an empty tree, as `MerkleTree::root` does
+    pub fn root(&mut self) -> Hash {
+        self.flush();
+        self.nodes.last().unwrap().first().unwrap().clone()
+    }
+
+
+    // Proves the leaf at `index`, claiming its position
+    pub fn prove(&mut self, index: usize) -> Option<Proof<'_>> {
+        if index >= self.leaf_count() {
+            return None;
+        }
+        self.flush();
+        let proof = path_proof(&self.nodes, index);
+        assert_eq!(check_proof_len(&proof, self.leaf_count()), Ok(()));
+        Some(proof.with_position(index, self.leaf_count()))
+    }
+
+
+    pub fn into_tree(mut self) -> MerkleTree<H> {
+        self.flush();
+        MerkleTree::from_levels(self.nodes, self.hasher)
+    }
+
+
+    // Rehashes the ancestors of every dirty leaf, growing levels that
+    // pushes widened and adding levels on top as needed
+    fn flush(&mut self) {
+        let mut indices: Vec<usize> = std::mem::take(&mut self.dirty).into_iter().collect();
+        let mut level = 0;
+        while self.nodes[level].len() > 1 {
+            let width = self.nodes[level].len().div_ceil(2);
+            if self.nodes.len() == level + 1 {
+                self.nodes.push(Vec::new());
+            }
+            self.nodes[level + 1].resize(width, Vec::new());
+            indices.dedup_by_key(|idx| *idx / 2);
+            for idx in indices.iter_mut() {
+                *idx /= 2;
+                let below = &self.nodes[level];
+                let parent = match below.get(*idx * 2 + 1) {
+                    Some(right) => self.hasher.hash_node(&below[*idx * 2], right),
+                    None => below[*idx * 2].clone(),
+                };
+                self.nodes[level + 1][*idx] = parent;
+            }
+            level += 1;
+        }
+        self.nodes.truncate(level + 1);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::middleware::{Counting, Layered};
+
+
+    #[test]
+    fn test_lazy_matches_eager() {
+        let mut data: Vec<Data> = (0..3u8).map(|i| vec![i]).collect();
+        let mut tree = LazyTree::construct(&data);
+        for i in 3..12u8 {
+            data.push(vec![i]);
+            tree.push(&data[i as usize]);
+            data[(i / 2) as usize] = vec![100 + i];
+            tree.update((i / 2) as usize, &data[(i / 2) as usize]).unwrap();
+            assert_eq!(tree.root(), MerkleTree::construct(&data).root());
+            let proof = tree.prove(i as usize).unwrap().into_owned();
+            assert_eq!(Some(proof), MerkleTree::construct(&data).prove_with_position(&data[i as usize]));
+        }
+        assert!(!tree.is_dirty());
+        assert_eq!(tree.update(12, &vec![0]), Err(Error::IndexOutOfRange { index: 12, leaf_count: 12 }));
+        assert_eq!(tree.into_tree().nodes, MerkleTree::construct(&data).nodes);
+    }
+
+    #[test]
+    fn test_writes_defer_hashing() {
+        let data: Vec<Data> = (0..8u8).map(|i| vec![i]).collect();
+        let counting = Counting::new();
+        let mut tree = LazyTree::construct_with(&data, Layered::new(Sha256Hasher, counting.clone()));
+        tree.root();
+        let before = counting.stats().ops;
+        for _ in 0..10 {
+            tree.update(0, &vec![9]).unwrap();
+            tree.update(1, &vec![9]).unwrap();
+        }
+        // Only the leaves were hashed
+        assert_eq!(counting.stats().ops, before + 20);
+        tree.root();
+        // Then one rehash of the shared path, however many writes
+        assert_eq!(counting.stats().ops, before + 23);
+    }
+}
@@ -1,19 +1,214 @@
 #![allow(dead_code)]
 #![allow(unused_variables)]
+// The crate still checks proofs through the untyped entry points it
+// deprecates for callers (see types.rs)
+#![allow(deprecated)]
 
-use sha2::Digest;
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+// Lets `#[derive(Hashable)]` name this crate from inside it
+#[cfg(feature = "derive")]
+extern crate self as merkle_tree;
+
+pub mod address;
+pub mod attestation;
+pub mod audit;
+pub mod backend;
+pub mod batch;
+pub mod builder;
+pub mod bundle;
+pub mod cache;
+pub mod canonical;
+pub mod checkpoints;
+pub mod compose;
+pub mod config;
+pub mod consistency;
+pub mod ct;
+pub mod deadline;
+pub mod deposit;
+pub mod diff;
+pub mod display;
+pub mod encoding;
+pub mod explain;
+pub mod export;
+pub mod fetch;
+pub mod fixed;
+pub mod forest;
+pub mod hashable;
+pub mod hasher;
+pub mod indexed;
+pub mod invariants;
+pub mod iter;
+pub mod kary;
+pub mod lazy;
+pub mod limits;
+pub mod merge;
+pub mod middleware;
+pub mod multiproof;
+pub mod mutate;
+pub mod nmt;
+pub mod partial;
+pub mod policy;
+pub mod progress;
+pub mod range;
+pub mod render;
+pub mod report;
+pub mod rolling;
+pub mod sampling;
+pub mod schema;
+pub mod snapshot;
+pub mod ssz;
+pub mod storage;
+pub mod strict;
+pub mod store;
+pub mod stream;
+pub mod tombstone;
+pub mod transparency;
+pub mod types;
+pub mod verkle;
+pub mod versioned;
+pub mod writer;
+pub mod zero;
+#[cfg(feature = "tokio")]
+pub mod async_io;
+#[cfg(any(feature = "borsh", feature = "bincode"))]
+mod codec;
+#[cfg(feature = "ics23")]
+pub mod cosmos;
+#[cfg(feature = "difftest")]
+pub mod difftest;
+#[cfg(feature = "envelope")]
+pub mod envelope;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "keccak")]
+pub mod keccak;
+#[cfg(feature = "kzg")]
+pub mod kzg;
+#[cfg(feature = "mmap")]
+pub mod mmap;
+#[cfg(feature = "mpt")]
+pub mod mpt;
+#[cfg(feature = "poseidon")]
+pub mod poseidon;
+#[cfg(feature = "prost")]
+pub mod proto;
+#[cfg(feature = "testvectors")]
+pub mod testvectors;
+#[cfg(feature = "thex")]
+pub mod thex;
+#[cfg(feature = "wasm-bindgen")]
+pub mod wasm;
+
+pub use address::NodeAddress;
+pub use attestation::{Attestation, AttestationError, HasherId, PolicyFlags};
+pub use audit::{AuditError, AuditResponse, Auditor, Challenge};
+pub use backend::{CpuFeatures, Sha256Backend};
+pub use builder::{CheckpointError, TreeBuilder};
+pub use bundle::ProofBundle;
+pub use cache::CachedProver;
+pub use canonical::CanonicalError;
+pub use checkpoints::Checkpoints;
+pub use compose::{ChainedProof, ComposedProof};
+pub use config::{ConfigError, TreeConfig};
+pub use consistency::ConsistencyProof;
+pub use deadline::DeadlineExceeded;
+pub use deposit::{DepositTree, TreeFull};
+pub use display::HexHash;
+pub use encoding::DecodeError;
+pub use explain::{ExplainStep, Explanation};
+pub use export::ImportError;
+pub use fetch::{ChunkFetcher, ChunkSource, FetchError};
+pub use fixed::{FixedHasher, FixedProof, Sha256Proof};
+pub use forest::{ForestError, ForestTree, ShardStore};
+pub use hashable::Hashable;
+pub use hasher::{hasher_id, Hasher, Legacy, Sha256Hasher};
+pub use indexed::IndexedProof;
+#[cfg(feature = "derive")]
+pub use merkle_tree_derive::Hashable;
+pub use invariants::{max_kary_proof_len, max_proof_len, InvariantViolation};
+pub use kary::{KaryProof, KaryStep, KaryTree};
+pub use lazy::LazyTree;
+pub use limits::{BuildLimits, LimitError};
+pub use middleware::{Counting, HashStats, Layer, Layered, Tee, Throttle};
+pub use multiproof::MultiProof;
+pub use nmt::{NamespaceProof, NamespaceTree, NmtError, NmtNode};
+pub use partial::PartialTree;
+pub use policy::{verify_receipt, verify_receipt_with, PolicyError, Receipt, VerificationPolicy};
+pub use progress::{Cancelled, ProgressHandle};
+pub use range::RangeProof;
+pub use report::{BuildReport, LeafOrdering, LevelReport, OddPolicy, Padding};
+pub use rolling::RollingTree;
+pub use sampling::SampleChallenge;
+pub use schema::{SchemaRegistry, VersionedLeaf, VersionedProof};
+pub use snapshot::{Snapshot, SnapshotError};
+pub use ssz::{SszError, SszProof, SszTree};
+pub use storage::StorageMode;
+pub use strict::StrictError;
+pub use store::{MemoryStore, NodeStore, StoreError, StoredTree};
+pub use stream::VerifiedReader;
+pub use tombstone::{LeafStatus, StatusProof};
+pub use transparency::{KeyHistory, KeyRotation, SignedTreeHead, TransparencyLog, TreeHeadSigner, TreeHeadVerifier};
+pub use types::{Leaf, NodeHash};
+pub use verkle::{HashCommitment, VectorCommitment, VerkleProof, VerkleStep, VerkleTree};
+pub use versioned::VersionedTree;
+pub use writer::MerkleWriter;
+pub use zero::ZeroHashes;
 
 pub type Data = Vec<u8>;
 pub type Hash = Vec<u8>;
 
 
-pub struct MerkleTree {
-    pub nodes: Vec<Vec<Data>>,
-    pub leaves_idx: HashMap<Hash, usize>,
+// Clones share the levels and leaf index, so handing a copy of a large tree
+// to every thread or request costs two reference counts. Mutations copy
+// whatever is still shared before writing.
+#[derive(Clone)]
+pub struct MerkleTree<H = Sha256Hasher> {
+    // Levels kept by the storage mode, leaves first
+    nodes: Arc<Vec<Vec<Hash>>>,
+    leaves_idx: Arc<HashMap<Hash, usize>>,
+    hasher: H,
+    // Leaf hashes that mutations must preserve, by leaf index
+    pins: HashMap<usize, Hash>,
+    mode: StorageMode,
+    leaf_count: usize,
 }
 
 
+// Errors from operations that change a tree
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    IndexOutOfRange { index: usize, leaf_count: usize },
+    // The mutation would change a pinned leaf to a different hash
+    PinViolation { index: usize },
+    // The operation needs levels the tree's storage mode discarded
+    NotStored,
+    // The operation relies on leaves and nodes being hashed apart, which
+    // the tree's hasher doesn't do
+    NotDomainSeparated,
+}
+
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::IndexOutOfRange { index, leaf_count } => {
+                write!(f, "leaf index {} out of range for {} leaves", index, leaf_count)
+            }
+            Error::PinViolation { index } => write!(f, "leaf {} is pinned to a different hash", index),
+            Error::NotStored => write!(f, "the tree's storage mode doesn't keep the nodes needed"),
+            Error::NotDomainSeparated => write!(f, "the tree's hasher doesn't separate leaves from nodes"),
+        }
+    }
+}
+
+
+impl std::error::Error for Error {}
+
+
 // Which side to put Hash on when concatinating proof hashes
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum HashDirection {
@@ -22,134 +217,420 @@ pub enum HashDirection {
 }
 
 
-#[derive(Debug, Default)]
+#[derive(Clone, Default, PartialEq)]
 pub struct Proof<'a> {
     // The hashes to use when verifying the proof
     // The first element of the tuple is which side the hash should be on when concatinating
-    hashes: Vec<(HashDirection, &'a Hash)>,
+    // Hashes borrow from the tree when proving and are owned when decoded
+    hashes: Vec<(HashDirection, Cow<'a, Hash>)>,
+    // Where the leaf sits, when the proof also vouches for that
+    position: Option<Position>,
+}
+
+
+// A leaf's index within a tree of `tree_size` leaves
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Position {
+    pub index: usize,
+    pub tree_size: usize,
+}
+
+
+impl Proof<'_> {
+    // Folds the proof over the given data, returning the root it leads to
+    pub fn compute_root<T: Hashable + ?Sized>(&self, data: &T) -> Hash {
+        self.compute_root_with(data, &Sha256Hasher)
+    }
+
+
+    // Folds the proof over the given data using the given hasher
+    pub fn compute_root_with<T: Hashable + ?Sized, H: Hasher>(&self, data: &T, hasher: &H) -> Hash {
+        self.compute_root_from_hash(hasher.hash_leaf(&data.leaf_bytes()), hasher)
+    }
+
+
+    // Folds the proof over an already hashed leaf
+    pub(crate) fn compute_root_from_hash<H: Hasher>(&self, leaf_hash: Hash, hasher: &H) -> Hash {
+        let mut current_hash = leaf_hash;
+        for (hash_direction, hash) in self.hashes.iter() {
+            current_hash = match hash_direction {
+                HashDirection::Left => hasher.hash_node(hash, &current_hash),
+                HashDirection::Right => hasher.hash_node(&current_hash, hash),
+            };
+        }
+        current_hash
+    }
+
+
+    // Sibling hashes in the proof, one hash each to verify
+    pub fn len(&self) -> usize {
+        self.hashes.len()
+    }
+
+
+    pub fn is_empty(&self) -> bool {
+        self.hashes.is_empty()
+    }
+
+
+    // The height of a tree of the claimed size, which is the most hashes a
+    // proof in it can need. None for proofs that don't claim a position.
+    pub fn expected_root_depth(&self) -> Option<usize> {
+        self.position.map(|position| max_proof_len(position.tree_size))
+    }
+
+
+    pub fn position(&self) -> Option<Position> {
+        self.position
+    }
+
+
+    // Makes the proof also claim the leaf is at `index` of `tree_size` leaves
+    pub fn with_position(self, index: usize, tree_size: usize) -> Self {
+        Proof { position: Some(Position { index, tree_size }), ..self }
+    }
+
+
+    // Whether the hashes follow the exact path from the claimed position:
+    // one sibling per level, on the side the index implies, and none where
+    // the leaf's ancestor is promoted off the right edge. True for proofs
+    // that don't claim a position.
+    pub fn matches_position(&self) -> bool {
+        let Some(Position { index, tree_size }) = self.position else {
+            return true;
+        };
+        if index >= tree_size {
+            return false;
+        }
+        let expected = indexed::sibling_directions(index, tree_size);
+        self.hashes.iter().map(|(direction, _)| *direction).eq(expected)
+    }
 }
 
 
 impl MerkleTree {
+    // Constructs a Merkle tree from given input data
+    pub fn construct<T: Hashable>(input: &[T]) -> MerkleTree {
+        Self::construct_with(input, Sha256Hasher)
+    }
+
+    // Constructs a Merkle tree from leaf hashes made by `Sha256Hasher::hash_leaf`
+    pub fn from_leaf_hashes(leaf_hashes: Vec<Hash>) -> MerkleTree {
+        Self::from_leaf_hashes_with(leaf_hashes, Sha256Hasher)
+    }
+
+    // Constructs a Merkle tree from leaves produced one at a time
+    pub fn construct_iter<I>(input: I) -> MerkleTree
+    where
+        I: IntoIterator,
+        I::Item: Hashable,
+    {
+        Self::construct_iter_with(input, Sha256Hasher)
+    }
+
+    // Verifies that the given input data produces the given root hash
+    pub fn verify(input: &[Data], root_hash: &Hash) -> bool {
+        Self::verify_with(input, root_hash, &Sha256Hasher)
+    }
+
+
+    // Verifies that the given data and proof_path correctly produce the given root_hash
+    #[deprecated(note = "takes the root as untyped bytes; use `verify_leaf` with a `NodeHash`")]
+    pub fn verify_proof<T: Hashable + ?Sized>(data: &T, proof: &Proof, root_hash: &Hash) -> bool {
+        Self::verify_proof_with(data, proof, root_hash, &Sha256Hasher)
+    }
+
+
+    // Verifies a proof for a leaf known only by its leaf hash
+    pub fn verify_proof_prehashed(leaf_hash: &Hash, proof: &Proof, root_hash: &Hash) -> bool {
+        Self::verify_proof_prehashed_with(leaf_hash, proof, root_hash, &Sha256Hasher)
+    }
+}
+
+
+impl<H: Hasher> MerkleTree<H> {
     // Gets root hash for this tree
     pub fn root(&self) -> Hash {
         // todo!("For tests to work")
-        self.nodes.last().unwrap().get(0).unwrap().clone()
+        self.nodes.last().unwrap().first().unwrap().clone()
     }
 
 
-    // Constructs a Merkle tree from given input data
-    pub fn construct(input: &[Data]) -> MerkleTree {
-        // Store nodes at each level
-        let mut nodes = Vec::new();
+    // The hasher this tree was built with
+    pub fn hasher(&self) -> &H {
+        &self.hasher
+    }
 
-        // Fast access to leaves
-        let mut leaves_idx = HashMap::with_capacity(input.len());
-        
+
+    pub fn leaf_count(&self) -> usize {
+        self.leaf_count
+    }
+
+
+    // Levels above the leaves, whatever the storage mode kept
+    pub fn depth(&self) -> usize {
+        max_proof_len(self.leaf_count)
+    }
+
+
+    // Nodes in the whole tree, leaves and root included. Promoted nodes
+    // count once per level they appear on.
+    pub fn node_count(&self) -> usize {
+        let mut width = self.leaf_count;
+        let mut count = width;
+        while width > 1 {
+            width = width.div_ceil(2);
+            count += width;
+        }
+        count
+    }
+
+
+    // The hashes on level `i`, leaves being level 0. None past the root or
+    // for levels the storage mode dropped.
+    pub fn level(&self, i: usize) -> Option<&[Hash]> {
+        let stored = match self.mode {
+            StorageMode::Full => self.nodes.get(i),
+            StorageMode::LeavesOnly if i == 0 => self.nodes.first(),
+            StorageMode::LeavesOnly | StorageMode::RootOnly if i == self.depth() => self.nodes.last(),
+            StorageMode::LeavesOnly | StorageMode::RootOnly => None,
+        };
+        stored.map(Vec::as_slice)
+    }
+
+
+    pub fn leaf_hash(&self, i: usize) -> Option<&Hash> {
+        self.level(0)?.get(i)
+    }
+
+
+    // Constructs a Merkle tree from given input data using the given hasher
+    pub fn construct_with<T: Hashable>(input: &[T], hasher: H) -> MerkleTree<H> {
+        Self::construct_with_mode(input, hasher, StorageMode::Full)
+    }
+
+
+    // Constructs a Merkle tree from leaves produced one at a time, such as
+    // borrowed slices of a larger buffer, without collecting them first
+    pub fn construct_iter_with<I>(input: I, hasher: H) -> MerkleTree<H>
+    where
+        I: IntoIterator,
+        I::Item: Hashable,
+    {
+        Self::construct_leaves(input, hasher, StorageMode::Full)
+    }
+
+
+    // Constructs a Merkle tree keeping only the parts `mode` asks for
+    pub fn construct_with_mode<T: Hashable>(input: &[T], hasher: H, mode: StorageMode) -> MerkleTree<H> {
+        Self::construct_leaves(input, hasher, mode)
+    }
+
+
+    fn construct_leaves<I>(input: I, hasher: H, mode: StorageMode) -> MerkleTree<H>
+    where
+        I: IntoIterator,
+        I::Item: Hashable,
+    {
         // Preprocess the input to hashes
-        let mut new_nodes: Vec<Hash> = input.iter().enumerate().map(|(i, leaf)| {
-            let h = hash_data(leaf);
-            leaves_idx.insert(h.clone(), i);
-            h
-        }).collect();
-
-        // Keep reducing the nodes util only root left 
-        while new_nodes.len() > 1 {
-            nodes.push(new_nodes.clone());
-            new_nodes = new_nodes
-                .chunks(2)
-                .map(|chunk| {
-                    if chunk.len() == 1 {
-                        chunk[0].clone()
-                    } else {
-                        hash_concat(&chunk[0], &chunk[1])
-                    }
-                })
-                .collect();
+        let leaves: Vec<Hash> = input.into_iter().map(|leaf| hasher.hash_leaf(&leaf.leaf_bytes())).collect();
+        Self::construct_hashed(leaves, hasher, mode)
+    }
+
+
+    // Constructs a Merkle tree over leaves already hashed with `hash_leaf`,
+    // such as digests of large files kept elsewhere
+    pub fn from_leaf_hashes_with(leaf_hashes: Vec<Hash>, hasher: H) -> MerkleTree<H> {
+        Self::construct_hashed(leaf_hashes, hasher, StorageMode::Full)
+    }
+
+
+    fn construct_hashed(leaves: Vec<Hash>, hasher: H, mode: StorageMode) -> MerkleTree<H> {
+        // Fast access to leaves
+        let mut leaves_idx: HashMap<Hash, usize> = leaves.iter().enumerate().map(|(i, h)| (h.clone(), i)).collect();
+        let leaf_count = leaves.len();
+
+        // Keep reducing the top level until only the root is left. Each
+        // level is built from a borrow of the one below, so nothing is copied.
+        let mut nodes = vec![leaves];
+        while let Some(top) = nodes.last().filter(|top| top.len() > 1) {
+            let next = reduce_level(top, &hasher);
+            nodes.push(next);
         }
-        // Push the root
-        nodes.push(new_nodes);
 
-        MerkleTree {
-            nodes,
-            leaves_idx,
+        // Drop the levels the storage mode doesn't keep
+        match mode {
+            StorageMode::Full => {}
+            StorageMode::LeavesOnly => {
+                if nodes.len() > 2 {
+                    nodes.drain(1..nodes.len() - 1);
+                }
+            }
+            StorageMode::RootOnly => {
+                nodes.drain(..nodes.len() - 1);
+                leaves_idx = HashMap::new();
+            }
         }
+
+        let tree = MerkleTree {
+            nodes: Arc::new(nodes),
+            leaves_idx: Arc::new(leaves_idx),
+            hasher,
+            pins: HashMap::new(),
+            mode,
+            leaf_count,
+        };
+        assert_eq!(tree.check_shape(), Ok(()));
+        tree
     }
 
-    // Verifies that the given input data produces the given root hash
-    pub fn verify(input: &[Data], root_hash: &Hash) -> bool {
+    // Wraps fully built levels (leaves first, root last) in a tree
+    pub(crate) fn from_levels(nodes: Vec<Vec<Hash>>, hasher: H) -> MerkleTree<H> {
+        let leaves_idx = nodes[0].iter().enumerate().map(|(i, h)| (h.clone(), i)).collect();
+        let leaf_count = nodes[0].len();
+        let tree = MerkleTree {
+            nodes: Arc::new(nodes),
+            leaves_idx: Arc::new(leaves_idx),
+            hasher,
+            pins: HashMap::new(),
+            mode: StorageMode::Full,
+            leaf_count,
+        };
+        assert_eq!(tree.check_shape(), Ok(()));
+        tree
+    }
+
+    // Verifies that the given input data produces the given root hash using the given hasher
+    pub fn verify_with(input: &[Data], root_hash: &Hash, hasher: &H) -> bool {
 
         if input.is_empty() {
             root_hash.is_empty()
         } else {
             // Just calculate the root_hash, don't need to store nodes
-            let mut nodes: Vec<Hash> = input.iter().map(|data| hash_data(data)).collect();
+            let mut nodes: Vec<Hash> = input.iter().map(|data| hasher.hash_leaf(data)).collect();
             while nodes.len() > 1 {
-                nodes = nodes
-                    .chunks(2)
-                    .map(|chunk| {
-                        if chunk.len() == 1 {
-                            chunk[0].clone()
-                        } else {
-                            hash_concat(&chunk[0], &chunk[1])
-                        }
-                    }).collect();
+                nodes = reduce_level(&nodes, hasher);
             }
-            nodes[0] == *root_hash
+            ct::hashes_equal(&nodes[0], root_hash)
         }
     }
 
 
-    // Verifies that the given data and proof_path correctly produce the given root_hash
-    pub fn verify_proof(data: &Data, proof: &Proof, root_hash: &Hash) -> bool {
-        let mut current_hash = hash_data(data);
-        for (hash_direction, hash) in proof.hashes.iter() {
-            current_hash = match hash_direction {
-                HashDirection::Left => hash_concat(*hash, &current_hash),
-                HashDirection::Right => hash_concat(&current_hash, *hash),
-            };
-        }
-        current_hash == *root_hash
+    // Verifies a proof using the given hasher
+    #[deprecated(note = "takes the root as untyped bytes; use `verify_leaf_with` with a `NodeHash`")]
+    pub fn verify_proof_with<T: Hashable + ?Sized>(data: &T, proof: &Proof, root_hash: &Hash, hasher: &H) -> bool {
+        proof.matches_position() && ct::hashes_equal(&proof.compute_root_with(data, hasher), root_hash)
+    }
+
+
+    // Like `verify_proof_with`, but starts from the leaf hash (as `hash_leaf`
+    // makes it) for callers that keep digests and not the data
+    pub fn verify_proof_prehashed_with(leaf_hash: &Hash, proof: &Proof, root_hash: &Hash, hasher: &H) -> bool {
+        proof.matches_position() && ct::hashes_equal(&proof.compute_root_from_hash(leaf_hash.clone(), hasher), root_hash)
+    }
+
+
+    // Like `verify_proof_with`, but rejects proofs longer than `max_depth`
+    // before hashing anything, so a forged path can't cost much to check
+    pub fn verify_proof_bounded<T: Hashable + ?Sized>(
+        data: &T,
+        proof: &Proof,
+        root_hash: &Hash,
+        hasher: &H,
+        max_depth: usize,
+    ) -> bool {
+        proof.len() <= max_depth && Self::verify_proof_with(data, proof, root_hash, hasher)
     }
 
 
     // Returns a list of hashes that can be used to prove that the given data is in this tree
-    pub fn prove(&self, data: &Data) -> Option<Proof> {
-        if let Some(mut current_idx) = self.leaves_idx.get(&hash_data(data)).copied() {
-            let mut hashes = Vec::new();
-
-            for level in 0..self.nodes.len()-1 {
-                let parent_idx = current_idx / 2;
-                if current_idx % 2 == 0 {
-                    // Only push node if exist
-                    if let Some(neighbor_hash) = self.nodes[level].get(current_idx+1) {
-                        hashes.push((HashDirection::Right, neighbor_hash));
-                    }
-                } else {
-                    hashes.push((HashDirection::Left, &self.nodes[level][current_idx-1]));
-                }
-                current_idx = parent_idx;
+    pub fn prove<T: Hashable + ?Sized>(&self, data: &T) -> Option<Proof<'_>> {
+        let current_idx = self.leaves_idx.get(&self.hasher.hash_leaf(&data.leaf_bytes())).copied()?;
+        self.prove_index(current_idx)
+    }
+
+
+    // Like `prove`, for the leaf whose leaf hash is `leaf_hash`
+    pub fn prove_by_leaf_hash(&self, leaf_hash: &Hash) -> Option<Proof<'_>> {
+        self.prove_index(*self.leaves_idx.get(leaf_hash)?)
+    }
+
+
+    // Proves whatever leaf is at `index`, by position rather than content
+    pub(crate) fn prove_index(&self, index: usize) -> Option<Proof<'_>> {
+        if index >= self.leaf_count {
+            return None;
+        }
+        Some(levels_proof(&self.levels()?, index))
+    }
+
+
+    // Like `prove`, but the proof also commits to the leaf's index and the
+    // tree size, so verifying it confirms where the leaf is
+    pub fn prove_with_position<T: Hashable + ?Sized>(&self, data: &T) -> Option<Proof<'_>> {
+        let index = *self.leaves_idx.get(&self.hasher.hash_leaf(&data.leaf_bytes()))?;
+        Some(self.prove(data)?.with_position(index, self.leaf_count))
+    }
+}
+
+
+// The proof for `index` from levels as `levels()` returns them. Rebuilt
+// levels don't outlive their caller, so proofs from them are copied.
+fn levels_proof<'a>(levels: &Cow<'a, [Vec<Hash>]>, index: usize) -> Proof<'a> {
+    match levels {
+        Cow::Borrowed(levels) => path_proof(levels, index),
+        Cow::Owned(levels) => path_proof(levels, index).into_owned(),
+    }
+}
+
+
+// Collects the siblings on the path from the leaf at `current_idx` to the root
+fn path_proof(levels: &[Vec<Hash>], mut current_idx: usize) -> Proof<'_> {
+    let mut hashes = Vec::new();
+
+    for level in &levels[..levels.len()-1] {
+        let parent_idx = current_idx / 2;
+        if current_idx.is_multiple_of(2) {
+            // Only push node if exist
+            if let Some(neighbor_hash) = level.get(current_idx+1) {
+                hashes.push((HashDirection::Right, Cow::Borrowed(neighbor_hash)));
             }
-            Some(Proof { hashes })
         } else {
-            None
+            hashes.push((HashDirection::Left, Cow::Borrowed(&level[current_idx-1])));
         }
+        current_idx = parent_idx;
     }
+    invariants::bounded_proof(hashes, levels[0].len())
+}
+
+
+// Hashes pairs of nodes into the next level up, promoting an odd last node unchanged
+fn reduce_level<H: Hasher>(nodes: &[Hash], hasher: &H) -> Vec<Hash> {
+    nodes
+        .chunks(2)
+        .map(|chunk| {
+            if chunk.len() == 1 {
+                chunk[0].clone()
+            } else {
+                hasher.hash_node(&chunk[0], &chunk[1])
+            }
+        })
+        .collect()
 }
 
 
 fn hash_data(data: &Data) -> Hash {
-    sha2::Sha256::digest(data).to_vec()
+    Sha256Hasher.hash_leaf(data)
 }
 
 
 fn hash_concat(h1: &Hash, h2: &Hash) -> Hash {
-    let h3 = h1.iter().chain(h2).copied().collect();
-    hash_data(&h3)
+    Sha256Hasher.hash_node(h1, h2)
 }
 
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison, clippy::useless_vec, clippy::needless_range_loop)]
 mod tests {
     use super::*;
 
@@ -166,21 +647,127 @@ mod tests {
     fn test_construct_root() {
         let data = example_data(4);
         let tree = MerkleTree::construct(&data);
+        let expected_root = "9bcd51240af4005168f033121ba85be5a6ed4f0e6a5fac262066729b8fbfdecb";
+        assert_eq!(hex::encode(tree.root()), expected_root);
+
+        let data = example_data(3);
+        let tree = MerkleTree::construct(&data);
+        let expected_root = "3b6cccd7e3e023ff393006f030315ee7ad9eb111b022b41fba7e5b7a3973f688";
+        assert_eq!(hex::encode(tree.root()), expected_root);
+
+        let data = example_data(8);
+        let tree = MerkleTree::construct(&data);
+        let expected_root = "ef7f49b620f6c7ea9b963a214da34b5021c6ded8ed57734380a311ab726aa907";
+        assert_eq!(hex::encode(tree.root()), expected_root);
+    }
+
+    #[test]
+    fn test_construct_root_legacy() {
+        let data = example_data(4);
+        let tree = MerkleTree::construct_with(&data, Legacy(Sha256Hasher));
         let expected_root = "9675e04b4ba9dc81b06e81731e2d21caa2c95557a85dcfa3fff70c9ff0f30b2e";
         assert_eq!(hex::encode(tree.root()), expected_root);
 
         // Uncomment if your implementation allows for unbalanced trees
         let data = example_data(3);
-        let tree = MerkleTree::construct(&data);
+        let tree = MerkleTree::construct_with(&data, Legacy(Sha256Hasher));
         let expected_root = "773a93ac37ea78b3f14ac31872c83886b0a0f1fec562c4e848e023c889c2ce9f";
         assert_eq!(hex::encode(tree.root()), expected_root);
 
         let data = example_data(8);
-        let tree = MerkleTree::construct(&data);
+        let tree = MerkleTree::construct_with(&data, Legacy(Sha256Hasher));
         let expected_root = "0727b310f87099c1ba2ec0ba408def82c308237c8577f0bdfd2643e9cc6b7578";
         assert_eq!(hex::encode(tree.root()), expected_root);
     }
 
+    #[test]
+    fn test_construct_borrowed_leaves() {
+        let buffer: Vec<u8> = (0..64).collect();
+        let owned: Vec<Data> = buffer.chunks(8).map(<[u8]>::to_vec).collect();
+        let root = MerkleTree::construct(&owned).root();
+
+        let slices: Vec<&[u8]> = buffer.chunks(8).collect();
+        assert_eq!(MerkleTree::construct(&slices).root(), root);
+        let tree = MerkleTree::construct_iter(buffer.chunks(8));
+        assert_eq!(tree.root(), root);
+        assert!(MerkleTree::verify_proof(&buffer[8..16], &tree.prove(&buffer[8..16]).unwrap(), &root));
+    }
+
+    #[test]
+    fn test_leaf_cannot_pose_as_node() {
+        let data = example_data(4);
+        let tree = MerkleTree::construct(&data);
+        // A leaf made of two child hashes used to hash to their parent
+        let forged = [tree.nodes[0][0].clone(), tree.nodes[0][1].clone()].concat();
+        let proof = tree.prove(&data[0]).unwrap();
+        let sibling = Proof { hashes: proof.hashes[1..].to_vec(), position: None };
+        assert_ne!(sibling.compute_root(&forged), tree.root());
+
+        let legacy = MerkleTree::construct_with(&data, Legacy(Sha256Hasher));
+        let forged = [legacy.nodes[0][0].clone(), legacy.nodes[0][1].clone()].concat();
+        let proof = legacy.prove(&data[0]).unwrap();
+        let sibling = Proof { hashes: proof.hashes[1..].to_vec(), position: None };
+        assert_eq!(sibling.compute_root_with(&forged, &Legacy(Sha256Hasher)), legacy.root());
+    }
+
+    #[test]
+    fn test_clones_share_levels() {
+        let data = example_data(9);
+        let tree = MerkleTree::construct(&data);
+        let mut copy = tree.clone();
+        assert!(Arc::ptr_eq(&tree.nodes, &copy.nodes) && Arc::ptr_eq(&tree.leaves_idx, &copy.leaves_idx));
+
+        // Writing to a clone copies first and leaves the original alone
+        copy.update(4, &vec![99]).unwrap();
+        assert!(!Arc::ptr_eq(&tree.nodes, &copy.nodes));
+        assert_eq!(tree.root(), MerkleTree::construct(&data).root());
+        assert!(tree.prove(&data[4]).is_some() && copy.prove(&data[4]).is_none());
+    }
+
+    #[test]
+    fn test_concurrent_proving() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<MerkleTree>();
+
+        let data = example_data(1000);
+        let tree = Arc::new(MerkleTree::construct(&data));
+        let root = tree.root();
+        let handles: Vec<_> = (0..8)
+            .map(|t| {
+                let (tree, data, root) = (Arc::clone(&tree), data.clone(), root.clone());
+                std::thread::spawn(move || {
+                    for leaf in data.iter().skip(t).step_by(3) {
+                        assert_eq!(tree.root(), root);
+                        let proof = tree.prove(leaf).unwrap();
+                        assert!(MerkleTree::verify_proof(leaf, &proof, &root));
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_accessors() {
+        let data = example_data(5);
+        let tree = MerkleTree::construct(&data);
+        assert_eq!((tree.leaf_count(), tree.depth(), tree.node_count()), (5, 3, 11));
+        assert_eq!(tree.level(1).unwrap().len(), 3);
+        assert_eq!(tree.level(3).unwrap(), [tree.root()]);
+        assert!(tree.level(4).is_none());
+        assert_eq!(tree.leaf_hash(2), Some(&hash_data(&data[2])));
+
+        let leaves_only = MerkleTree::construct_with_mode(&data, Sha256Hasher, StorageMode::LeavesOnly);
+        assert_eq!(leaves_only.level(0), tree.level(0));
+        assert!(leaves_only.level(1).is_none());
+        assert_eq!(leaves_only.level(3), tree.level(3));
+        let root_only = MerkleTree::construct_with_mode(&data, Sha256Hasher, StorageMode::RootOnly);
+        assert!(root_only.leaf_hash(0).is_none());
+        assert_eq!(root_only.level(3), tree.level(3));
+    }
+
     #[test]
     fn test_verify() {
         for n in 1..=10 {
@@ -204,8 +791,64 @@ mod tests {
                 assert_eq!(MerkleTree::verify_proof(&data[m], &proof.unwrap(), &tree.root()), true);
             }
             let fake_data = vec![(n+1) as u8];
+            let proof = tree.prove(&data[0]).unwrap();
+            assert_eq!(proof.compute_root(&data[0]), tree.root());
+            assert_ne!(proof.compute_root(&fake_data), tree.root());
             let proof = tree.prove(&fake_data);
             assert!(proof.is_none());
         }
     }
+
+    #[test]
+    fn test_proof_len_and_bounded_verify() {
+        let data = example_data(5);
+        let tree = MerkleTree::construct(&data);
+        let proof = tree.prove_with_position(&data[0]).unwrap();
+        assert_eq!((proof.len(), proof.expected_root_depth()), (3, Some(3)));
+        assert_eq!(tree.prove(&data[4]).unwrap().len(), 1);
+        assert_eq!(tree.prove(&data[4]).unwrap().expected_root_depth(), None);
+
+        assert!(MerkleTree::verify_proof_bounded(&data[0], &proof, &tree.root(), &Sha256Hasher, 3));
+        assert!(!MerkleTree::verify_proof_bounded(&data[0], &proof, &tree.root(), &Sha256Hasher, 2));
+    }
+
+    #[test]
+    fn test_verify_proof_with_position() {
+        let data = example_data(7);
+        let tree = MerkleTree::construct(&data);
+        for m in 0..7 {
+            let proof = tree.prove_with_position(&data[m]).unwrap();
+            assert_eq!(proof.position(), Some(Position { index: m, tree_size: 7 }));
+            assert!(MerkleTree::verify_proof(&data[m], &proof, &tree.root()));
+        }
+        // The path itself is right; only the claimed position is wrong
+        let proof = tree.prove(&data[4]).unwrap();
+        assert!(!MerkleTree::verify_proof(&data[4], &proof.with_position(5, 7), &tree.root()));
+        let proof = tree.prove(&data[6]).unwrap();
+        assert!(!MerkleTree::verify_proof(&data[6], &proof.with_position(6, 8), &tree.root()));
+    }
+
+    #[test]
+    fn test_prehashed_proofs() {
+        let data = example_data(6);
+        let tree = MerkleTree::construct(&data);
+        for leaf in &data {
+            let leaf_hash = Sha256Hasher.hash_leaf(leaf);
+            let proof = tree.prove_by_leaf_hash(&leaf_hash).unwrap();
+            assert!(MerkleTree::verify_proof_prehashed(&leaf_hash, &proof, &tree.root()));
+            // The digest is the leaf hash, not the data
+            assert!(!MerkleTree::verify_proof(&leaf_hash, &proof, &tree.root()));
+        }
+        assert!(tree.prove_by_leaf_hash(&Sha256Hasher.hash(&data[0])).is_none());
+    }
+
+    #[test]
+    fn test_from_leaf_hashes() {
+        let data = example_data(7);
+        let leaf_hashes: Vec<Hash> = data.iter().map(|leaf| Sha256Hasher.hash_leaf(leaf)).collect();
+        let tree = MerkleTree::from_leaf_hashes(leaf_hashes);
+        assert_eq!(tree.root(), MerkleTree::construct(&data).root());
+        let proof = tree.prove(&data[3]).unwrap();
+        assert!(MerkleTree::verify_proof(&data[3], &proof, &tree.root()));
+    }
 }
@@ -0,0 +1,130 @@
+// Construction bounded by caller-chosen limits, for services that build
+// trees from untrusted uploads. Every limit is checked before any hashing,
+// so an oversized upload costs a pass over its lengths and nothing more.
+
+use crate::{Hashable, Hasher, MerkleTree, Sha256Hasher, StorageMode};
+use std::fmt;
+use std::mem::size_of;
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BuildLimits {
+    pub max_leaves: usize,
+    // Bytes in any one leaf
+    pub max_leaf_size: usize,
+    // Estimated bytes the built tree holds: every node plus the leaf index
+    pub max_memory: usize,
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LimitError {
+    TooManyLeaves { count: usize, max: usize },
+    LeafTooLarge { index: usize, len: usize, max: usize },
+    TooMuchMemory { needed: usize, max: usize },
+}
+
+
+impl fmt::Display for LimitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LimitError::TooManyLeaves { count, max } => write!(f, "{} leaves is over the limit of {}", count, max),
+            LimitError::LeafTooLarge { index, len, max } => {
+                write!(f, "leaf {} is {} bytes, over the limit of {}", index, len, max)
+            }
+            LimitError::TooMuchMemory { needed, max } => {
+                write!(f, "tree would need about {} bytes, over the limit of {}", needed, max)
+            }
+        }
+    }
+}
+
+
+impl std::error::Error for LimitError {}
+
+
+impl BuildLimits {
+    pub fn new(max_leaves: usize, max_leaf_size: usize, max_memory: usize) -> BuildLimits {
+        BuildLimits { max_leaves, max_leaf_size, max_memory }
+    }
+
+
+    // Bytes a full tree of `leaf_count` leaves with `hash_len`-byte hashes
+    // holds, counting each hash's allocation and the leaf index. None if
+    // that overflows.
+    pub fn memory_estimate(leaf_count: usize, hash_len: usize) -> Option<usize> {
+        let hash = size_of::<Vec<u8>>().checked_add(hash_len)?;
+        let mut width = leaf_count;
+        let mut nodes = width;
+        while width > 1 {
+            width = width.div_ceil(2);
+            nodes = nodes.checked_add(width)?;
+        }
+        let index_entry = hash.checked_add(size_of::<usize>())?;
+        nodes.checked_mul(hash)?.checked_add(leaf_count.checked_mul(index_entry)?)
+    }
+
+
+    fn check<T: Hashable, H: Hasher>(&self, input: &[T], hasher: &H) -> Result<(), LimitError> {
+        if input.len() > self.max_leaves {
+            return Err(LimitError::TooManyLeaves { count: input.len(), max: self.max_leaves });
+        }
+        for (index, leaf) in input.iter().enumerate() {
+            let len = leaf.leaf_bytes().len();
+            if len > self.max_leaf_size {
+                return Err(LimitError::LeafTooLarge { index, len, max: self.max_leaf_size });
+            }
+        }
+        let hash_len = hasher.hash_leaf(&[]).len();
+        let needed = Self::memory_estimate(input.len(), hash_len).unwrap_or(usize::MAX);
+        if needed > self.max_memory {
+            return Err(LimitError::TooMuchMemory { needed, max: self.max_memory });
+        }
+        Ok(())
+    }
+}
+
+
+impl MerkleTree {
+    pub fn construct_checked<T: Hashable>(input: &[T], limits: &BuildLimits) -> Result<MerkleTree, LimitError> {
+        Self::construct_checked_with(input, Sha256Hasher, limits)
+    }
+}
+
+
+impl<H: Hasher> MerkleTree<H> {
+    // Like `construct_with`, but fails instead of building a tree over
+    // more than `limits` allow
+    pub fn construct_checked_with<T: Hashable>(input: &[T], hasher: H, limits: &BuildLimits) -> Result<MerkleTree<H>, LimitError> {
+        limits.check(input, &hasher)?;
+        Ok(Self::construct_with_mode(input, hasher, StorageMode::Full))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Data;
+
+
+    #[test]
+    fn test_construct_checked() {
+        let data: Vec<Data> = (0..10u8).map(|i| vec![i; i as usize]).collect();
+        let needed = BuildLimits::memory_estimate(10, 32).unwrap();
+        let limits = BuildLimits::new(10, 9, needed);
+        let tree = MerkleTree::construct_checked(&data, &limits).unwrap();
+        assert_eq!(tree.root(), MerkleTree::construct(&data).root());
+
+        let check = |limits: BuildLimits| MerkleTree::construct_checked(&data, &limits).err();
+        assert_eq!(check(BuildLimits { max_leaves: 9, ..limits }), Some(LimitError::TooManyLeaves { count: 10, max: 9 }));
+        assert_eq!(check(BuildLimits { max_leaf_size: 7, ..limits }), Some(LimitError::LeafTooLarge { index: 8, len: 8, max: 7 }));
+        assert_eq!(check(BuildLimits { max_memory: needed - 1, ..limits }), Some(LimitError::TooMuchMemory { needed, max: needed - 1 }));
+    }
+
+    #[test]
+    fn test_memory_estimate_overflow() {
+        assert_eq!(BuildLimits::memory_estimate(usize::MAX, 32), None);
+        assert_eq!(BuildLimits::memory_estimate(0, 32), Some(0));
+    }
+}
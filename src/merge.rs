@@ -0,0 +1,83 @@
+// Joining two trees into one over the left leaves followed by the right.
+// Complete nodes of the left tree keep their place, and when the left leaf
+// count is a multiple of 2^level every node of the right tree on that level
+// lands on a node boundary too, so it's reused as is. Only the nodes that
+// straddle the seam are hashed: at most one per level when the left count
+// is a power of two, and O(right / 2^level) per level otherwise.
+
+use crate::{Hash, Hasher, MerkleTree};
+
+
+impl<H: Hasher + Clone> MerkleTree<H> {
+    // A tree over the leaves of `left` then `right`, with `left`'s hasher.
+    // None if either tree's storage mode kept too little to rebuild from.
+    pub fn merge(left: &MerkleTree<H>, right: &MerkleTree<H>) -> Option<MerkleTree<H>> {
+        let (left_levels, right_levels) = (left.levels()?, right.levels()?);
+        let left_count = left.leaf_count;
+        let hasher = left.hasher.clone();
+
+        let mut leaves = left_levels[0].clone();
+        leaves.extend_from_slice(&right_levels[0]);
+        let mut nodes = vec![leaves];
+        let mut level = 0;
+        while nodes[level].len() > 1 {
+            level += 1;
+            let width = nodes[level - 1].len().div_ceil(2);
+            let below = &nodes[level - 1];
+            // Left nodes covering only left leaves are unchanged
+            let mut next: Vec<Hash> = left_levels.get(level).map_or(&[][..], |nodes| &nodes[..left_count >> level]).to_vec();
+            if left_count.trailing_zeros() as usize >= level {
+                next.extend_from_slice(right_levels.get(level).map_or(&[][..], |nodes| &nodes[..]));
+            }
+            // Whatever's left straddles the seam or sits on misaligned right
+            // nodes, so it's hashed from the level below
+            for index in next.len()..width {
+                next.push(match below.get(2 * index + 1) {
+                    Some(right) => hasher.hash_node(&below[2 * index], right),
+                    None => below[2 * index].clone(),
+                });
+            }
+            nodes.push(next);
+        }
+        Some(MerkleTree::from_levels(nodes, hasher))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::middleware::{Counting, Layered};
+    use crate::{Data, Sha256Hasher};
+
+
+    #[test]
+    fn test_merge_matches_fresh_tree() {
+        let data: Vec<Data> = (0..20u8).map(|i| vec![i]).collect();
+        for split in 0..=data.len() {
+            for end in split..=data.len() {
+                if end == 0 {
+                    continue;
+                }
+                let left = MerkleTree::construct(&data[..split]);
+                let right = MerkleTree::construct(&data[split..end]);
+                let merged = MerkleTree::merge(&left, &right).unwrap();
+                assert_eq!(merged.root(), MerkleTree::construct(&data[..end]).root(), "{} + {}", split, end - split);
+                assert_eq!(merged.check_invariants(), Ok(()));
+            }
+        }
+    }
+
+    #[test]
+    fn test_merge_reuses_aligned_subtrees() {
+        let data: Vec<Data> = (0..16u8).map(|i| vec![i]).collect();
+        let counting = Counting::new();
+        let hasher = Layered::new(Sha256Hasher, counting.clone());
+        let left = MerkleTree::construct_with(&data[..8], hasher.clone());
+        let right = MerkleTree::construct_with(&data[8..], hasher);
+        let before = counting.stats().ops;
+        let merged = MerkleTree::merge(&left, &right).unwrap();
+        assert_eq!(counting.stats().ops - before, 1);
+        assert_eq!(merged.root(), MerkleTree::construct(&data).root());
+    }
+}
@@ -0,0 +1,260 @@
+// Hasher middleware: a `Layer` observes every input handed to the wrapped
+// hasher, so counting, teeing or throttling can be stacked on any backend
+// without touching its implementation.
+
+use crate::{Hash, Hasher};
+use std::io::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+
+// Sees the input of each hash invocation before the inner hasher runs.
+// Parent hashes are reported as their two halves.
+pub trait Layer {
+    fn on_hash(&self, parts: &[&[u8]]);
+}
+
+
+// A hasher wrapped in a layer
+#[derive(Debug, Clone, Default)]
+pub struct Layered<H, L> {
+    inner: H,
+    layer: L,
+}
+
+
+impl<H: Hasher, L: Layer> Layered<H, L> {
+    pub fn new(inner: H, layer: L) -> Layered<H, L> {
+        Layered { inner, layer }
+    }
+
+    pub fn inner(&self) -> &H {
+        &self.inner
+    }
+
+    pub fn layer(&self) -> &L {
+        &self.layer
+    }
+}
+
+
+impl<H: Hasher, L: Layer> Hasher for Layered<H, L> {
+    fn hash(&self, data: &[u8]) -> Hash {
+        self.layer.on_hash(&[data]);
+        self.inner.hash(data)
+    }
+
+    fn hash_concat(&self, left: &Hash, right: &Hash) -> Hash {
+        self.layer.on_hash(&[left, right]);
+        self.inner.hash_concat(left, right)
+    }
+
+    fn hash_many(&self, children: &[Hash]) -> Hash {
+        let parts: Vec<&[u8]> = children.iter().map(|child| child.as_slice()).collect();
+        self.layer.on_hash(&parts);
+        self.inner.hash_many(children)
+    }
+
+    fn hash_leaf(&self, data: &[u8]) -> Hash {
+        self.layer.on_hash(&[data]);
+        self.inner.hash_leaf(data)
+    }
+
+    fn hash_node(&self, left: &Hash, right: &Hash) -> Hash {
+        self.layer.on_hash(&[left, right]);
+        self.inner.hash_node(left, right)
+    }
+
+    fn hash_children(&self, children: &[Hash]) -> Hash {
+        let parts: Vec<&[u8]> = children.iter().map(|child| child.as_slice()).collect();
+        self.layer.on_hash(&parts);
+        self.inner.hash_children(children)
+    }
+
+    fn backend(&self) -> &'static str {
+        self.inner.backend()
+    }
+
+    fn domain_separated(&self) -> bool {
+        self.inner.domain_separated()
+    }
+}
+
+
+// Snapshot of the work seen by a `Counting` layer
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HashStats {
+    pub bytes: u64,
+    pub ops: u64,
+    // Time since the counters were created
+    pub elapsed: Duration,
+    // Backend of the wrapped hasher, when the counters are read through one
+    pub backend: Option<&'static str>,
+}
+
+
+impl HashStats {
+    pub fn bytes_per_sec(&self) -> f64 {
+        self.bytes as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+
+// Counts bytes and hash invocations. Clones share the same counters, so one
+// `Counting` per tenant can be handed to every tree built on their behalf.
+#[derive(Debug, Clone)]
+pub struct Counting {
+    bytes: Arc<AtomicU64>,
+    ops: Arc<AtomicU64>,
+    started: Instant,
+}
+
+
+impl Counting {
+    pub fn new() -> Counting {
+        Counting {
+            bytes: Arc::new(AtomicU64::new(0)),
+            ops: Arc::new(AtomicU64::new(0)),
+            started: Instant::now(),
+        }
+    }
+
+    pub fn stats(&self) -> HashStats {
+        HashStats {
+            bytes: self.bytes.load(Ordering::Relaxed),
+            ops: self.ops.load(Ordering::Relaxed),
+            elapsed: self.started.elapsed(),
+            backend: None,
+        }
+    }
+}
+
+
+impl Default for Counting {
+    fn default() -> Counting {
+        Counting::new()
+    }
+}
+
+
+impl Layer for Counting {
+    fn on_hash(&self, parts: &[&[u8]]) {
+        let bytes: usize = parts.iter().map(|part| part.len()).sum();
+        self.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+        self.ops.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+
+impl<H: Hasher> Layered<H, Counting> {
+    pub fn stats(&self) -> HashStats {
+        HashStats { backend: Some(self.inner.backend()), ..self.layer.stats() }
+    }
+}
+
+
+// Copies every hashed input to a writer, for debugging. Write errors are ignored
+// so a broken debug sink can't change hashing results.
+#[derive(Debug)]
+pub struct Tee<W> {
+    sink: Mutex<W>,
+}
+
+
+impl<W: Write> Tee<W> {
+    pub fn new(sink: W) -> Tee<W> {
+        Tee { sink: Mutex::new(sink) }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.sink.into_inner().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+
+impl<W: Write> Layer for Tee<W> {
+    fn on_hash(&self, parts: &[&[u8]]) {
+        let mut sink = self.sink.lock().unwrap_or_else(|e| e.into_inner());
+        for part in parts {
+            let _ = sink.write_all(part);
+        }
+    }
+}
+
+
+// Limits hashing to a number of bytes per second by sleeping the calling thread
+#[derive(Debug)]
+pub struct Throttle {
+    bytes_per_sec: u64,
+    state: Mutex<(Instant, u64)>,
+}
+
+
+impl Throttle {
+    pub fn new(bytes_per_sec: u64) -> Throttle {
+        Throttle {
+            bytes_per_sec: bytes_per_sec.max(1),
+            state: Mutex::new((Instant::now(), 0)),
+        }
+    }
+}
+
+
+impl Layer for Throttle {
+    fn on_hash(&self, parts: &[&[u8]]) {
+        let bytes: usize = parts.iter().map(|part| part.len()).sum();
+        let wait = {
+            let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+            state.1 += bytes as u64;
+            let allowed = Duration::from_secs_f64(state.1 as f64 / self.bytes_per_sec as f64);
+            allowed.saturating_sub(state.0.elapsed())
+        };
+        if !wait.is_zero() {
+            std::thread::sleep(wait);
+        }
+    }
+}
+
+
+// Runs two layers in order, so layers can be stacked without nesting hashers
+impl<A: Layer, B: Layer> Layer for (A, B) {
+    fn on_hash(&self, parts: &[&[u8]]) {
+        self.0.on_hash(parts);
+        self.1.on_hash(parts);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Data, MerkleTree, Sha256Hasher};
+
+
+    #[test]
+    fn test_counting_layer() {
+        let data: Vec<Data> = (0..4u8).map(|i| vec![i]).collect();
+        let counting = Counting::new();
+        let tree = MerkleTree::construct_with(&data, Layered::new(Sha256Hasher, counting.clone()));
+        assert_eq!(tree.root(), MerkleTree::construct(&data).root());
+
+        // 4 one-byte leaves, then 3 parents over two 32-byte children
+        let stats = tree.hasher().stats();
+        assert_eq!((stats.ops, stats.bytes), (7, 4 + 3 * 64));
+
+        // Clones share counters, so a second tree adds to the same tenant
+        let _ = MerkleTree::construct_with(&data[..1], Layered::new(Sha256Hasher, counting.clone()));
+        assert_eq!(counting.stats().ops, 8);
+    }
+
+    #[test]
+    fn test_tee_layer() {
+        let hasher = Layered::new(Sha256Hasher, (Counting::new(), Tee::new(Vec::new())));
+        let _ = MerkleTree::construct_with(&[vec![1u8], vec![2u8]], &hasher);
+        assert_eq!(hasher.layer().0.stats().ops, 3);
+        let teed = hasher.layer.1.into_inner();
+        assert_eq!(&teed[..2], &[1, 2]);
+        assert_eq!(teed.len(), 2 + 64);
+    }
+}
@@ -0,0 +1,93 @@
+// Leaves read straight out of a memory-mapped file of fixed-size records.
+// Records are borrowed from the mapping and hashed one at a time, so the
+// OS pages the file in as construction walks it and nothing but the hashes
+// stays resident, even for files larger than RAM.
+
+use crate::{Hasher, MerkleTree, Sha256Hasher};
+use memmap2::Mmap;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::slice::Chunks;
+
+
+pub struct MmapLeaves {
+    map: Mmap,
+    record_size: usize,
+}
+
+
+impl MmapLeaves {
+    // Maps the file at `path`. The file must not be changed while mapped,
+    // or records may change under the tree being built.
+    pub fn open<P: AsRef<Path>>(path: P, record_size: usize) -> io::Result<MmapLeaves> {
+        assert!(record_size > 0, "record size must be positive");
+        let file = File::open(path)?;
+        // Safety: the mapping is read-only and the caller keeps the file unchanged
+        let map = unsafe { Mmap::map(&file)? };
+        Ok(MmapLeaves { map, record_size })
+    }
+
+
+    // Number of records, counting a short one at the end
+    pub fn len(&self) -> usize {
+        self.map.len().div_ceil(self.record_size)
+    }
+
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+
+    // The records in file order. The last one is short if the file size
+    // isn't a multiple of the record size.
+    pub fn records(&self) -> Chunks<'_, u8> {
+        self.map.chunks(self.record_size)
+    }
+
+
+    pub fn construct(&self) -> MerkleTree {
+        self.construct_with(Sha256Hasher)
+    }
+
+
+    pub fn construct_with<H: Hasher>(&self, hasher: H) -> MerkleTree<H> {
+        MerkleTree::construct_iter_with(self.records(), hasher)
+    }
+}
+
+
+impl<'a> IntoIterator for &'a MmapLeaves {
+    type Item = &'a [u8];
+    type IntoIter = Chunks<'a, u8>;
+
+    fn into_iter(self) -> Chunks<'a, u8> {
+        self.records()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Data;
+
+
+    #[test]
+    fn test_mmap_leaves() {
+        let path = std::env::temp_dir().join(format!("merkle_tree_mmap_{}", std::process::id()));
+        let contents: Vec<u8> = (0..1000u32).map(|i| (i % 256) as u8).collect();
+        std::fs::write(&path, &contents).unwrap();
+
+        let leaves = MmapLeaves::open(&path, 48).unwrap();
+        assert_eq!(leaves.len(), 21);
+        let records: Vec<Data> = contents.chunks(48).map(<[u8]>::to_vec).collect();
+        let tree = leaves.construct();
+        assert_eq!(tree.root(), MerkleTree::construct(&records).root());
+        assert!(MerkleTree::verify_proof(&records[20], &tree.prove(&records[20]).unwrap(), &tree.root()));
+
+        drop(leaves);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,554 @@
+// Ethereum's Merkle Patricia Trie, so account and storage proofs from an
+// Ethereum node (`eth_getProof`) can be checked against a state root, and
+// tries built here match the roots Ethereum computes.
+//
+// Keys are walked a nibble at a time. Leaves and extensions hold a run of
+// nibbles in hex-prefix form, branches have 16 children and an optional
+// value, and every node is RLP encoded. A parent refers to a child by the
+// keccak256 of its encoding, or holds the encoding itself when that is
+// shorter than 32 bytes. Ethereum's state and storage tries are keyed by
+// keccak256 of the address or slot; callers hash keys themselves.
+
+use crate::Hash;
+use sha3::{Digest, Keccak256};
+use std::collections::HashMap;
+use std::fmt;
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MptError {
+    // The proof doesn't include the node with this hash
+    MissingNode(Hash),
+    BadRlp,
+    // Well-formed RLP that isn't a trie node
+    BadNode,
+}
+
+
+impl fmt::Display for MptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MptError::MissingNode(hash) => write!(f, "proof is missing node {}", hex::encode(hash)),
+            MptError::BadRlp => write!(f, "malformed rlp"),
+            MptError::BadNode => write!(f, "not a trie node"),
+        }
+    }
+}
+
+
+impl std::error::Error for MptError {}
+
+
+#[derive(Debug, Clone, Default, PartialEq)]
+enum Node {
+    #[default]
+    Empty,
+    Leaf { path: Vec<u8>, value: Vec<u8> },
+    Extension { path: Vec<u8>, child: Box<Node> },
+    Branch { children: Box<[Node; 16]>, value: Option<Vec<u8>> },
+}
+
+
+impl Node {
+    fn branch() -> Node {
+        Node::Branch { children: Box::new(std::array::from_fn(|_| Node::Empty)), value: None }
+    }
+
+
+    fn encode(&self) -> Vec<u8> {
+        match self {
+            Node::Empty => rlp_bytes(&[]),
+            Node::Leaf { path, value } => rlp_list(&[rlp_bytes(&hex_prefix(path, true)), rlp_bytes(value)]),
+            Node::Extension { path, child } => rlp_list(&[rlp_bytes(&hex_prefix(path, false)), child.reference()]),
+            Node::Branch { children, value } => {
+                let mut items: Vec<Vec<u8>> = children.iter().map(Node::reference).collect();
+                items.push(rlp_bytes(value.as_deref().unwrap_or(&[])));
+                rlp_list(&items)
+            }
+        }
+    }
+
+
+    // How a parent holds this node: inline if shorter than a hash
+    fn reference(&self) -> Vec<u8> {
+        let encoded = self.encode();
+        if encoded.len() < 32 {
+            encoded
+        } else {
+            rlp_bytes(&keccak(&encoded))
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MptTrie {
+    root: Node,
+}
+
+
+impl MptTrie {
+    pub fn new() -> MptTrie {
+        MptTrie::default()
+    }
+
+
+    pub fn root_hash(&self) -> Hash {
+        keccak(&self.root.encode())
+    }
+
+
+    pub fn get(&self, key: &[u8]) -> Option<&[u8]> {
+        let path = nibbles(key);
+        let mut path = path.as_slice();
+        let mut node = &self.root;
+        loop {
+            match node {
+                Node::Empty => return None,
+                Node::Leaf { path: rest, value } => return (rest == path).then_some(value.as_slice()),
+                Node::Extension { path: prefix, child } => {
+                    path = path.strip_prefix(prefix.as_slice())?;
+                    node = child;
+                }
+                Node::Branch { children, value } => match path.split_first() {
+                    None => return value.as_deref(),
+                    Some((&nibble, rest)) => {
+                        path = rest;
+                        node = &children[nibble as usize];
+                    }
+                },
+            }
+        }
+    }
+
+
+    // Sets `key` to `value`. As in Ethereum, an empty value deletes the key.
+    pub fn insert(&mut self, key: &[u8], value: Vec<u8>) {
+        if value.is_empty() {
+            self.delete(key);
+            return;
+        }
+        let root = std::mem::take(&mut self.root);
+        self.root = insert(root, &nibbles(key), value);
+    }
+
+
+    pub fn delete(&mut self, key: &[u8]) -> Option<Vec<u8>> {
+        let root = std::mem::take(&mut self.root);
+        let (root, removed) = delete(root, &nibbles(key));
+        self.root = root;
+        removed
+    }
+
+
+    // The encoded nodes from the root towards `key`, as `eth_getProof`
+    // returns them. Nodes held inline by their parent aren't repeated. Also
+    // proves a key is absent when it isn't in the trie.
+    pub fn prove(&self, key: &[u8]) -> Vec<Vec<u8>> {
+        let path = nibbles(key);
+        let mut path = path.as_slice();
+        let mut node = &self.root;
+        let mut proof = Vec::new();
+        loop {
+            let encoded = node.encode();
+            if proof.is_empty() || encoded.len() >= 32 {
+                proof.push(encoded);
+            }
+            match node {
+                Node::Empty | Node::Leaf { .. } => return proof,
+                Node::Extension { path: prefix, child } => match path.strip_prefix(prefix.as_slice()) {
+                    Some(rest) => {
+                        path = rest;
+                        node = child;
+                    }
+                    None => return proof,
+                },
+                Node::Branch { children, .. } => match path.split_first() {
+                    Some((&nibble, rest)) => {
+                        path = rest;
+                        node = &children[nibble as usize];
+                    }
+                    None => return proof,
+                },
+            }
+        }
+    }
+}
+
+
+// The value `proof` shows `key` has under `root`, or None if it shows the
+// key is absent. Fails if the proof doesn't reach far enough to say.
+pub fn verify_proof(root: &Hash, key: &[u8], proof: &[Vec<u8>]) -> Result<Option<Vec<u8>>, MptError> {
+    let nodes: HashMap<Hash, &[u8]> = proof.iter().map(|node| (keccak(node), node.as_slice())).collect();
+    let path = nibbles(key);
+    let mut path = path.as_slice();
+    let mut item = Item::Bytes(root);
+    loop {
+        let Some(mut items) = resolve(item, &nodes)? else {
+            return Ok(None);
+        };
+        match items.len() {
+            17 => {
+                let Some((&nibble, rest)) = path.split_first() else {
+                    return match items.pop() {
+                        Some(Item::Bytes([])) => Ok(None),
+                        Some(Item::Bytes(value)) => Ok(Some(value.to_vec())),
+                        _ => Err(MptError::BadNode),
+                    };
+                };
+                path = rest;
+                item = items.swap_remove(nibble as usize);
+            }
+            2 => {
+                let child = items.pop().unwrap();
+                let Some(Item::Bytes(encoded_path)) = items.pop() else {
+                    return Err(MptError::BadNode);
+                };
+                let (node_path, is_leaf) = decode_hex_prefix(encoded_path).ok_or(MptError::BadNode)?;
+                if is_leaf {
+                    let Item::Bytes(value) = child else {
+                        return Err(MptError::BadNode);
+                    };
+                    return Ok((node_path == path).then(|| value.to_vec()));
+                }
+                match path.strip_prefix(node_path.as_slice()) {
+                    Some(rest) => {
+                        path = rest;
+                        item = child;
+                    }
+                    None => return Ok(None),
+                }
+            }
+            _ => return Err(MptError::BadNode),
+        }
+    }
+}
+
+
+fn insert(node: Node, path: &[u8], value: Vec<u8>) -> Node {
+    match node {
+        Node::Empty => Node::Leaf { path: path.to_vec(), value },
+        Node::Leaf { path: existing, value: existing_value } => {
+            if existing == path {
+                return Node::Leaf { path: existing, value };
+            }
+            let common = common_prefix(&existing, path);
+            let branch = place(Node::branch(), &existing[common..], existing_value);
+            let branch = place(branch, &path[common..], value);
+            with_prefix(&path[..common], branch)
+        }
+        Node::Extension { path: prefix, child } => {
+            let common = common_prefix(&prefix, path);
+            if common == prefix.len() {
+                return Node::Extension { path: prefix, child: Box::new(insert(*child, &path[common..], value)) };
+            }
+            // Split the extension at the first nibble the paths differ on
+            let mut branch = Node::branch();
+            if let Node::Branch { children, .. } = &mut branch {
+                children[prefix[common] as usize] = with_prefix(&prefix[common + 1..], *child);
+            }
+            with_prefix(&path[..common], place(branch, &path[common..], value))
+        }
+        Node::Branch { mut children, value: branch_value } => match path.split_first() {
+            None => Node::Branch { children, value: Some(value) },
+            Some((&nibble, rest)) => {
+                let child = std::mem::take(&mut children[nibble as usize]);
+                children[nibble as usize] = insert(child, rest, value);
+                Node::Branch { children, value: branch_value }
+            }
+        },
+    }
+}
+
+
+// Puts `value` at `path` below a new branch: in the branch itself if the
+// path ends there, else in a leaf under the path's first nibble
+fn place(branch: Node, path: &[u8], value: Vec<u8>) -> Node {
+    let Node::Branch { mut children, value: branch_value } = branch else {
+        unreachable!("only branches are placed into");
+    };
+    match path.split_first() {
+        None => Node::Branch { children, value: Some(value) },
+        Some((&nibble, rest)) => {
+            children[nibble as usize] = Node::Leaf { path: rest.to_vec(), value };
+            Node::Branch { children, value: branch_value }
+        }
+    }
+}
+
+
+// `node` reached through `prefix`, merged into it where the shapes allow
+fn with_prefix(prefix: &[u8], node: Node) -> Node {
+    if prefix.is_empty() {
+        return node;
+    }
+    match node {
+        Node::Empty => Node::Empty,
+        Node::Leaf { path, value } => Node::Leaf { path: [prefix, &path].concat(), value },
+        Node::Extension { path, child } => Node::Extension { path: [prefix, &path].concat(), child },
+        branch => Node::Extension { path: prefix.to_vec(), child: Box::new(branch) },
+    }
+}
+
+
+fn delete(node: Node, path: &[u8]) -> (Node, Option<Vec<u8>>) {
+    match node {
+        Node::Empty => (Node::Empty, None),
+        Node::Leaf { path: existing, value } if existing == path => (Node::Empty, Some(value)),
+        leaf @ Node::Leaf { .. } => (leaf, None),
+        Node::Extension { path: prefix, child } => match path.strip_prefix(prefix.as_slice()) {
+            Some(rest) => {
+                let (child, removed) = delete(*child, rest);
+                (with_prefix(&prefix, child), removed)
+            }
+            None => (Node::Extension { path: prefix, child }, None),
+        },
+        Node::Branch { mut children, mut value } => {
+            let removed = match path.split_first() {
+                None => value.take(),
+                Some((&nibble, rest)) => {
+                    let (child, removed) = delete(std::mem::take(&mut children[nibble as usize]), rest);
+                    children[nibble as usize] = child;
+                    removed
+                }
+            };
+            (collapse(children, value), removed)
+        }
+    }
+}
+
+
+// A branch left with fewer than two entries becomes the smaller node
+// Ethereum would have built instead
+fn collapse(mut children: Box<[Node; 16]>, value: Option<Vec<u8>>) -> Node {
+    let mut occupied = children.iter().enumerate().filter(|(_, child)| **child != Node::Empty);
+    match (occupied.next(), occupied.next(), value) {
+        (None, _, Some(value)) => Node::Leaf { path: Vec::new(), value },
+        (Some((nibble, _)), None, None) => {
+            let child = std::mem::take(&mut children[nibble]);
+            with_prefix(&[nibble as u8], child)
+        }
+        (_, _, value) => Node::Branch { children, value },
+    }
+}
+
+
+fn common_prefix(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).take_while(|(x, y)| x == y).count()
+}
+
+
+fn keccak(data: &[u8]) -> Hash {
+    Keccak256::digest(data).to_vec()
+}
+
+
+fn nibbles(key: &[u8]) -> Vec<u8> {
+    key.iter().flat_map(|byte| [byte >> 4, byte & 0x0f]).collect()
+}
+
+
+// The flag nibble says whether this is a leaf and whether the path has an
+// odd length; an odd path's first nibble shares the flag's byte
+fn hex_prefix(path: &[u8], leaf: bool) -> Vec<u8> {
+    let flag = if leaf { 2 } else { 0 } + (path.len() % 2) as u8;
+    let mut out = Vec::with_capacity(path.len() / 2 + 1);
+    let rest = if path.len() % 2 == 1 {
+        out.push(flag << 4 | path[0]);
+        &path[1..]
+    } else {
+        out.push(flag << 4);
+        path
+    };
+    out.extend(rest.chunks(2).map(|pair| pair[0] << 4 | pair[1]));
+    out
+}
+
+
+// (path, is a leaf)
+fn decode_hex_prefix(bytes: &[u8]) -> Option<(Vec<u8>, bool)> {
+    let (&first, rest) = bytes.split_first()?;
+    let flag = first >> 4;
+    let odd = flag & 1 == 1;
+    if flag > 3 || (!odd && first & 0x0f != 0) {
+        return None;
+    }
+    let mut path = if odd { vec![first & 0x0f] } else { Vec::new() };
+    path.extend(nibbles(rest));
+    Some((path, flag & 2 == 2))
+}
+
+
+enum Item<'a> {
+    Bytes(&'a [u8]),
+    List(Vec<Item<'a>>),
+}
+
+
+// The node a child reference points to: its items, or None for no node
+fn resolve<'a>(item: Item<'a>, nodes: &HashMap<Hash, &'a [u8]>) -> Result<Option<Vec<Item<'a>>>, MptError> {
+    match item {
+        Item::List(items) => Ok(Some(items)),
+        Item::Bytes([]) => Ok(None),
+        Item::Bytes(hash) if hash.len() == 32 => {
+            let encoded = nodes.get(hash).ok_or_else(|| MptError::MissingNode(hash.to_vec()))?;
+            match rlp_decode_all(encoded)? {
+                Item::List(items) => Ok(Some(items)),
+                Item::Bytes([]) => Ok(None),
+                Item::Bytes(_) => Err(MptError::BadNode),
+            }
+        }
+        Item::Bytes(_) => Err(MptError::BadNode),
+    }
+}
+
+
+fn rlp_length(len: usize, offset: u8) -> Vec<u8> {
+    if len < 56 {
+        return vec![offset + len as u8];
+    }
+    let bytes = (len as u64).to_be_bytes();
+    let skip = bytes.iter().take_while(|&&byte| byte == 0).count();
+    let mut out = vec![offset + 55 + (bytes.len() - skip) as u8];
+    out.extend_from_slice(&bytes[skip..]);
+    out
+}
+
+
+fn rlp_bytes(bytes: &[u8]) -> Vec<u8> {
+    if bytes.len() == 1 && bytes[0] < 0x80 {
+        return bytes.to_vec();
+    }
+    let mut out = rlp_length(bytes.len(), 0x80);
+    out.extend_from_slice(bytes);
+    out
+}
+
+
+// A list of items already encoded
+fn rlp_list(items: &[Vec<u8>]) -> Vec<u8> {
+    let mut out = rlp_length(items.iter().map(Vec::len).sum(), 0xc0);
+    for item in items {
+        out.extend_from_slice(item);
+    }
+    out
+}
+
+
+fn rlp_decode_all(bytes: &[u8]) -> Result<Item<'_>, MptError> {
+    match rlp_decode(bytes)? {
+        (item, []) => Ok(item),
+        _ => Err(MptError::BadRlp),
+    }
+}
+
+
+// Decodes the item at the front of `bytes`, returning it and the rest.
+// Only canonical encodings are accepted.
+fn rlp_decode(bytes: &[u8]) -> Result<(Item<'_>, &[u8]), MptError> {
+    let (&first, rest) = bytes.split_first().ok_or(MptError::BadRlp)?;
+    let (is_list, len, rest) = match first {
+        0x00..=0x7f => return Ok((Item::Bytes(&bytes[..1]), rest)),
+        0x80..=0xb7 => (false, (first - 0x80) as usize, rest),
+        0xb8..=0xbf => {
+            let (len, rest) = long_length(rest, first - 0xb7)?;
+            (false, len, rest)
+        }
+        0xc0..=0xf7 => (true, (first - 0xc0) as usize, rest),
+        0xf8..=0xff => {
+            let (len, rest) = long_length(rest, first - 0xf7)?;
+            (true, len, rest)
+        }
+    };
+    if rest.len() < len {
+        return Err(MptError::BadRlp);
+    }
+    let (mut payload, rest) = rest.split_at(len);
+    if !is_list {
+        // A single low byte is its own encoding
+        if len == 1 && payload[0] < 0x80 {
+            return Err(MptError::BadRlp);
+        }
+        return Ok((Item::Bytes(payload), rest));
+    }
+    let mut items = Vec::new();
+    while !payload.is_empty() {
+        let (item, more) = rlp_decode(payload)?;
+        items.push(item);
+        payload = more;
+    }
+    Ok((Item::List(items), rest))
+}
+
+
+fn long_length(bytes: &[u8], width: u8) -> Result<(usize, &[u8]), MptError> {
+    let width = width as usize;
+    if bytes.len() < width || bytes[0] == 0 {
+        return Err(MptError::BadRlp);
+    }
+    let len = bytes[..width].iter().fold(0u64, |len, &byte| len << 8 | byte as u64);
+    let len = usize::try_from(len).map_err(|_| MptError::BadRlp)?;
+    if len < 56 {
+        return Err(MptError::BadRlp);
+    }
+    Ok((len, &bytes[width..]))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    #[test]
+    fn test_ethereum_roots() {
+        let mut trie = MptTrie::new();
+        assert_eq!(hex::encode(trie.root_hash()), "56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421");
+        // The "puppy" case from Ethereum's trie tests
+        for (key, value) in [("doe", "reindeer"), ("dog", "puppy"), ("dogglesworth", "cat")] {
+            trie.insert(key.as_bytes(), value.as_bytes().to_vec());
+        }
+        assert_eq!(hex::encode(trie.root_hash()), "8aad789dff2f538bca5d8ea56e8abe10f4c7ba3a5dea95fea4cd6e7c3a1168d3");
+        assert_eq!(trie.get(b"dog"), Some(&b"puppy"[..]));
+        assert_eq!(trie.get(b"do"), None);
+
+        let mut smaller = MptTrie::new();
+        smaller.insert(b"doe", b"reindeer".to_vec());
+        smaller.insert(b"dog", b"puppy".to_vec());
+        assert_eq!(trie.delete(b"dogglesworth"), Some(b"cat".to_vec()));
+        assert_eq!(trie.root_hash(), smaller.root_hash());
+        trie.insert(b"dog", Vec::new());
+        trie.insert(b"doe", Vec::new());
+        assert_eq!(trie, MptTrie::new());
+    }
+
+    #[test]
+    fn test_proofs() {
+        let mut trie = MptTrie::new();
+        let keys: Vec<Hash> = (0..200u32).map(|i| keccak(&i.to_be_bytes())).collect();
+        for (i, key) in keys.iter().enumerate() {
+            // Short values leave some leaves inline in their parents
+            trie.insert(key, vec![i as u8 + 1; 1 + i % 40]);
+        }
+        // Deleting half leaves the trie as if they were never inserted
+        let mut half = MptTrie::new();
+        for (i, key) in keys.iter().enumerate() {
+            if i % 2 == 0 {
+                trie.delete(key);
+            } else {
+                half.insert(key, vec![i as u8 + 1; 1 + i % 40]);
+            }
+        }
+        assert_eq!(trie.root_hash(), half.root_hash());
+
+        let root = trie.root_hash();
+        for (i, key) in keys.iter().enumerate() {
+            let expected = (i % 2 == 1).then(|| vec![i as u8 + 1; 1 + i % 40]);
+            let proof = trie.prove(key);
+            assert_eq!(verify_proof(&root, key, &proof), Ok(expected));
+        }
+        let proof = trie.prove(&keys[1]);
+        assert!(matches!(verify_proof(&root, &keys[1], &proof[..proof.len() - 1]), Err(MptError::MissingNode(_))));
+        assert!(matches!(verify_proof(&keys[0], &keys[1], &proof), Err(MptError::MissingNode(_))));
+    }
+}
@@ -0,0 +1,189 @@
+// One proof for several leaves of the same tree. Siblings shared between
+// the leaves' paths are sent once, and siblings that are themselves on a
+// proven path are left out entirely since the verifier derives them, so k
+// leaves cost far fewer than k * log(n) hashes when they sit close together.
+
+use crate::ct::hashes_equal;
+use crate::{Hash, Hashable, Hasher, Proof, Sha256Hasher};
+use std::collections::{BTreeMap, BTreeSet};
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MultiProof {
+    leaf_count: usize,
+    // Proven leaf indices, ascending
+    indices: Vec<usize>,
+    // Hashes the verifier can't derive, ordered by (level, index)
+    hashes: Vec<Hash>,
+}
+
+
+impl MultiProof {
+    // Merges single-leaf proofs, each made with `prove_with_position` for
+    // the same tree. None if a proof has no position, the proofs disagree on
+    // the tree size or on a shared sibling, or a proof doesn't fit its path.
+    pub fn aggregate(proofs: &[Proof]) -> Option<MultiProof> {
+        let leaf_count = proofs.first()?.position()?.tree_size;
+        let mut siblings: BTreeMap<(usize, usize), &Hash> = BTreeMap::new();
+        let mut on_path: BTreeSet<(usize, usize)> = BTreeSet::new();
+        let mut indices = Vec::with_capacity(proofs.len());
+
+        for proof in proofs {
+            let position = proof.position()?;
+            if position.tree_size != leaf_count || !proof.matches_position() {
+                return None;
+            }
+            indices.push(position.index);
+            let mut hashes = proof.hashes.iter();
+            let (mut idx, mut width, mut level) = (position.index, leaf_count, 0);
+            while width > 1 {
+                on_path.insert((level, idx));
+                if idx ^ 1 < width {
+                    let (_, hash) = hashes.next()?;
+                    match siblings.insert((level, idx ^ 1), hash) {
+                        Some(previous) if !hashes_equal(previous, hash) => return None,
+                        _ => {}
+                    }
+                }
+                (idx, width, level) = (idx / 2, width.div_ceil(2), level + 1);
+            }
+        }
+
+        indices.sort_unstable();
+        indices.dedup();
+        // BTreeMap order is (level, index), the order the verifier asks in
+        let hashes = siblings
+            .into_iter()
+            .filter(|(position, _)| !on_path.contains(position))
+            .map(|(_, hash)| hash.clone())
+            .collect();
+        Some(MultiProof { leaf_count, indices, hashes })
+    }
+
+
+    // Reassembles a decoded proof, None unless the indices ascend strictly
+    // and fall within the tree
+    pub(crate) fn from_parts(leaf_count: usize, indices: Vec<usize>, hashes: Vec<Hash>) -> Option<MultiProof> {
+        let ascending = indices.windows(2).all(|pair| pair[0] < pair[1]);
+        let in_range = indices.last().is_none_or(|&last| last < leaf_count);
+        (ascending && in_range).then_some(MultiProof { leaf_count, indices, hashes })
+    }
+
+
+    pub(crate) fn hashes(&self) -> &[Hash] {
+        &self.hashes
+    }
+
+
+    pub fn indices(&self) -> &[usize] {
+        &self.indices
+    }
+
+
+    pub fn leaf_count(&self) -> usize {
+        self.leaf_count
+    }
+
+
+    // Hashes carried, besides the leaves themselves
+    pub fn len(&self) -> usize {
+        self.hashes.len()
+    }
+
+
+    pub fn is_empty(&self) -> bool {
+        self.hashes.is_empty()
+    }
+
+
+    // The root implied by `leaves`, given in the order of `indices`. None if
+    // the counts don't match the proof.
+    pub fn compute_root_with<T: Hashable, H: Hasher>(&self, leaves: &[T], hasher: &H) -> Option<Hash> {
+        if leaves.len() != self.indices.len() || self.indices.is_empty() {
+            return None;
+        }
+        let mut current: BTreeMap<usize, Hash> = self
+            .indices
+            .iter()
+            .zip(leaves)
+            .map(|(&index, leaf)| (index, hasher.hash_leaf(&leaf.leaf_bytes())))
+            .collect();
+        let mut hashes = self.hashes.iter();
+        let mut width = self.leaf_count;
+        while width > 1 {
+            let mut next = BTreeMap::new();
+            while let Some((idx, hash)) = current.pop_first() {
+                let parent = if idx ^ 1 >= width {
+                    hash
+                } else if idx % 2 == 0 {
+                    let right = match current.first_key_value() {
+                        Some((&right_idx, _)) if right_idx == idx + 1 => current.pop_first()?.1,
+                        _ => hashes.next()?.clone(),
+                    };
+                    hasher.hash_node(&hash, &right)
+                } else {
+                    hasher.hash_node(hashes.next()?, &hash)
+                };
+                next.insert(idx / 2, parent);
+            }
+            (current, width) = (next, width.div_ceil(2));
+        }
+        // Unused hashes mean the proof was made for other leaves
+        hashes.next().is_none().then(|| current.pop_first().map(|(_, root)| root)).flatten()
+    }
+
+
+    pub fn verify<T: Hashable>(&self, leaves: &[T], root: &Hash) -> bool {
+        self.verify_with(leaves, root, &Sha256Hasher)
+    }
+
+
+    pub fn verify_with<T: Hashable, H: Hasher>(&self, leaves: &[T], root: &Hash, hasher: &H) -> bool {
+        self.compute_root_with(leaves, hasher).is_some_and(|computed| hashes_equal(&computed, root))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Data, MerkleTree};
+
+
+    #[test]
+    fn test_aggregate() {
+        let data: Vec<Data> = (0..13u8).map(|i| vec![i]).collect();
+        let tree = MerkleTree::construct(&data);
+        let root = tree.root();
+        for indices in [vec![0], vec![1, 2], vec![12, 7, 2, 1], (0..13).collect()] {
+            let proofs: Vec<Proof> = indices.iter().map(|&i| tree.prove_with_position(&data[i]).unwrap()).collect();
+            let multi = MultiProof::aggregate(&proofs).unwrap();
+            let separate: usize = proofs.iter().map(Proof::len).sum();
+            assert!(multi.len() <= separate);
+
+            let leaves: Vec<&Data> = multi.indices().iter().map(|&i| &data[i]).collect();
+            assert!(multi.verify(&leaves, &root));
+            let mut wrong = leaves.clone();
+            wrong.reverse();
+            assert_eq!(multi.verify(&wrong, &root), wrong == leaves);
+        }
+        // Everything is derivable once every leaf is proven
+        let proofs: Vec<Proof> = data.iter().map(|leaf| tree.prove_with_position(leaf).unwrap()).collect();
+        assert!(MultiProof::aggregate(&proofs).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_aggregate_rejects_mismatched_proofs() {
+        let data: Vec<Data> = (0..6u8).map(|i| vec![i]).collect();
+        let tree = MerkleTree::construct(&data);
+        let other = MerkleTree::construct(&data[..5]);
+
+        assert!(MultiProof::aggregate(&[tree.prove(&data[0]).unwrap()]).is_none());
+        let mixed = [tree.prove_with_position(&data[0]).unwrap(), other.prove_with_position(&data[1]).unwrap()];
+        assert!(MultiProof::aggregate(&mixed).is_none());
+        // Same size but a different tree: the shared sibling disagrees
+        let forged = MerkleTree::construct(&[vec![9], vec![1], vec![2], vec![3], vec![4], vec![5]]);
+        let conflicting = [tree.prove_with_position(&data[2]).unwrap(), forged.prove_with_position(&data[3]).unwrap()];
+        assert!(MultiProof::aggregate(&conflicting).is_none());
+    }
+}
@@ -0,0 +1,287 @@
+use crate::storage::build_levels;
+use crate::{Data, Error, Hash, Hasher, MerkleTree, StorageMode};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+
+impl<H: Hasher> MerkleTree<H> {
+    // Replaces the leaf at `index` and recomputes its path to the root.
+    // Fails without changing the tree if the leaf is pinned to another hash.
+    pub fn update(&mut self, index: usize, data: &Data) -> Result<(), Error> {
+        self.check_index(index)?;
+        if self.mode == StorageMode::RootOnly {
+            return Err(Error::NotStored);
+        }
+        let leaf_hash = self.hasher.hash_leaf(data);
+        self.check_pin(index, &leaf_hash)?;
+
+        let old_hash = std::mem::replace(&mut Arc::make_mut(&mut self.nodes)[0][index], leaf_hash.clone());
+        let leaves_idx = Arc::make_mut(&mut self.leaves_idx);
+        if leaves_idx.get(&old_hash) == Some(&index) {
+            leaves_idx.remove(&old_hash);
+        }
+        leaves_idx.insert(leaf_hash, index);
+        self.recompute_path(index);
+        Ok(())
+    }
+
+
+    // Replaces several leaves, later changes to the same index winning, then
+    // rehashes each affected inner node once however many changed leaves
+    // sit under it. Fails without changing the tree if any change would.
+    pub fn update_many(&mut self, changes: &[(usize, Data)]) -> Result<(), Error> {
+        if let Some(&(index, _)) = changes.iter().find(|(index, _)| *index >= self.leaf_count) {
+            return Err(Error::IndexOutOfRange { index, leaf_count: self.leaf_count });
+        }
+        if self.mode == StorageMode::RootOnly {
+            return Err(Error::NotStored);
+        }
+        // Only the last write to each index counts, for pins as for the tree
+        let latest: BTreeMap<usize, &Data> = changes.iter().map(|(index, data)| (*index, data)).collect();
+        let hashed: Vec<(usize, Hash)> = latest.into_iter().map(|(index, data)| (index, self.hasher.hash_leaf(data))).collect();
+        for (index, leaf_hash) in &hashed {
+            self.check_pin(*index, leaf_hash)?;
+        }
+        if hashed.is_empty() {
+            return Ok(());
+        }
+
+        let leaves = &mut Arc::make_mut(&mut self.nodes)[0];
+        let leaves_idx = Arc::make_mut(&mut self.leaves_idx);
+        for (index, leaf_hash) in hashed.iter().cloned() {
+            let old_hash = std::mem::replace(&mut leaves[index], leaf_hash.clone());
+            if leaves_idx.get(&old_hash) == Some(&index) {
+                leaves_idx.remove(&old_hash);
+            }
+            leaves_idx.insert(leaf_hash, index);
+        }
+
+        // Already sorted and distinct
+        let dirty: Vec<usize> = hashed.iter().map(|(index, _)| *index).collect();
+        self.recompute_paths(dirty);
+        Ok(())
+    }
+
+
+    // Appends a leaf, returning its index. Only the right edge of each level
+    // changes, so this hashes at most once per level.
+    pub fn push(&mut self, data: &Data) -> Result<usize, Error> {
+        if self.mode != StorageMode::Full {
+            return Err(Error::NotStored);
+        }
+        let index = self.leaf_count;
+        let leaf_hash = self.hasher.hash_leaf(data);
+        Arc::make_mut(&mut self.leaves_idx).insert(leaf_hash.clone(), index);
+        let nodes = Arc::make_mut(&mut self.nodes);
+        nodes[0].push(leaf_hash);
+        self.leaf_count += 1;
+
+        let mut level = 0;
+        while nodes[level].len() > 1 {
+            if nodes.len() == level + 1 {
+                nodes.push(Vec::new());
+            }
+            let last = nodes[level].len() - 1;
+            let parent = if last % 2 == 1 {
+                self.hasher.hash_node(&nodes[level][last - 1], &nodes[level][last])
+            } else {
+                nodes[level][last].clone()
+            };
+            let above = &mut nodes[level + 1];
+            if above.len() == last / 2 {
+                above.push(parent);
+            } else {
+                above[last / 2] = parent;
+            }
+            level += 1;
+        }
+        Ok(index)
+    }
+
+
+    // Pins the leaf at `index` to `expected_hash`; every later mutation touching
+    // the leaf must keep that hash. The leaf must already match.
+    pub fn pin_leaf(&mut self, index: usize, expected_hash: Hash) -> Result<(), Error> {
+        self.check_index(index)?;
+        if self.mode == StorageMode::RootOnly {
+            return Err(Error::NotStored);
+        }
+        if self.nodes[0][index] != expected_hash {
+            return Err(Error::PinViolation { index });
+        }
+        self.pins.insert(index, expected_hash);
+        Ok(())
+    }
+
+
+    // Removes a pin, returning the hash it held
+    pub fn unpin_leaf(&mut self, index: usize) -> Option<Hash> {
+        self.pins.remove(&index)
+    }
+
+
+    pub fn is_pinned(&self, index: usize) -> bool {
+        self.pins.contains_key(&index)
+    }
+
+
+    pub(crate) fn check_index(&self, index: usize) -> Result<(), Error> {
+        let leaf_count = self.leaf_count;
+        if index >= leaf_count {
+            return Err(Error::IndexOutOfRange { index, leaf_count });
+        }
+        Ok(())
+    }
+
+
+    pub(crate) fn check_pin(&self, index: usize, new_hash: &Hash) -> Result<(), Error> {
+        match self.pins.get(&index) {
+            Some(pinned) if pinned != new_hash => Err(Error::PinViolation { index }),
+            _ => Ok(()),
+        }
+    }
+
+
+    // Rehashes every ancestor of the leaf at `index`
+    pub(crate) fn recompute_path(&mut self, index: usize) {
+        let nodes = Arc::make_mut(&mut self.nodes);
+        if self.mode == StorageMode::LeavesOnly {
+            // No inner levels to patch, so only the root is refreshed
+            let root = build_levels(nodes[0].clone(), &self.hasher).pop().unwrap();
+            *nodes.last_mut().unwrap() = root;
+            return;
+        }
+        let mut current_idx = index;
+        for level in 0..nodes.len() - 1 {
+            let parent_idx = current_idx / 2;
+            let left = &nodes[level][parent_idx * 2];
+            let parent = match nodes[level].get(parent_idx * 2 + 1) {
+                Some(right) => self.hasher.hash_node(left, right),
+                None => left.clone(),
+            };
+            nodes[level + 1][parent_idx] = parent;
+            current_idx = parent_idx;
+        }
+    }
+
+
+    // Rehashes every ancestor of the sorted, distinct leaf `indices`, each
+    // shared ancestor once
+    fn recompute_paths(&mut self, mut indices: Vec<usize>) {
+        if self.mode == StorageMode::LeavesOnly {
+            return self.recompute_path(indices[0]);
+        }
+        let nodes = Arc::make_mut(&mut self.nodes);
+        for level in 0..nodes.len() - 1 {
+            indices.dedup_by_key(|idx| *idx / 2);
+            for idx in indices.iter_mut() {
+                *idx /= 2;
+                let left = &nodes[level][*idx * 2];
+                let parent = match nodes[level].get(*idx * 2 + 1) {
+                    Some(right) => self.hasher.hash_node(left, right),
+                    None => left.clone(),
+                };
+                nodes[level + 1][*idx] = parent;
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    #[test]
+    fn test_update() {
+        for n in 1..=9 {
+            let mut data: Vec<Data> = (0..n as u8).map(|i| vec![i]).collect();
+            let mut tree = MerkleTree::construct(&data);
+            for i in 0..n {
+                data[i] = vec![100 + i as u8];
+                tree.update(i, &data[i]).unwrap();
+                assert_eq!(tree.root(), MerkleTree::construct(&data).root());
+                let proof = tree.prove(&data[i]).unwrap();
+                assert!(MerkleTree::verify_proof(&data[i], &proof, &tree.root()));
+            }
+            assert!(tree.prove(&vec![0]).is_none());
+            assert_eq!(tree.update(n, &vec![0]), Err(Error::IndexOutOfRange { index: n, leaf_count: n }));
+        }
+    }
+
+    #[test]
+    fn test_update_many() {
+        for n in 1..=9 {
+            let mut data: Vec<Data> = (0..n as u8).map(|i| vec![i]).collect();
+            let mut tree = MerkleTree::construct(&data);
+            // Out of order, with index 0 changed twice
+            let mut changes: Vec<(usize, Data)> = (0..n).rev().step_by(2).map(|i| (i, vec![100 + i as u8])).collect();
+            changes.extend([(0, vec![50]), (0, vec![51])]);
+            for (index, leaf) in &changes {
+                data[*index] = leaf.clone();
+            }
+            tree.update_many(&changes).unwrap();
+            assert_eq!(tree.nodes, MerkleTree::construct(&data).nodes);
+        }
+
+        let data: Vec<Data> = (0..4u8).map(|i| vec![i]).collect();
+        let mut tree = MerkleTree::construct(&data);
+        let root = tree.root();
+        assert_eq!(tree.update_many(&[(1, vec![9]), (4, vec![9])]), Err(Error::IndexOutOfRange { index: 4, leaf_count: 4 }));
+        tree.pin_leaf(2, tree.nodes[0][2].clone()).unwrap();
+        assert_eq!(tree.update_many(&[(1, vec![9]), (2, vec![9])]), Err(Error::PinViolation { index: 2 }));
+        assert_eq!(tree.root(), root);
+    }
+
+    #[test]
+    fn test_update_many_duplicate_indices() {
+        let data: Vec<Data> = (0..4u8).map(|i| vec![i]).collect();
+        let mut tree = MerkleTree::construct(&data);
+        tree.pin_leaf(2, tree.nodes[0][2].clone()).unwrap();
+
+        // The pinned leaf ends up unchanged, so the batch is allowed
+        tree.update_many(&[(2, vec![9]), (1, vec![7]), (2, vec![2])]).unwrap();
+        let expected = MerkleTree::construct(&[vec![0], vec![7], vec![2], vec![3]]);
+        assert_eq!(tree.nodes, expected.nodes);
+        // The overwritten value never entered the leaf index
+        assert!(tree.prove(&vec![9]).is_none());
+
+        // The pinned leaf ends up changed, so the batch is refused
+        let root = tree.root();
+        assert_eq!(tree.update_many(&[(2, vec![2]), (2, vec![9])]), Err(Error::PinViolation { index: 2 }));
+        assert_eq!(tree.root(), root);
+    }
+
+    #[test]
+    fn test_push() {
+        let data: Vec<Data> = (0..11u8).map(|i| vec![i]).collect();
+        let mut tree = MerkleTree::construct(&data[..1]);
+        for n in 2..=data.len() {
+            assert_eq!(tree.push(&data[n - 1]), Ok(n - 1));
+            assert_eq!(tree.check_invariants(), Ok(()));
+            assert_eq!(tree.root(), MerkleTree::construct(&data[..n]).root());
+        }
+        let mut leaves_only = MerkleTree::construct_with_mode(&data, crate::Sha256Hasher, StorageMode::LeavesOnly);
+        assert_eq!(leaves_only.push(&vec![0]), Err(Error::NotStored));
+    }
+
+    #[test]
+    fn test_pinned_leaf_refuses_update() {
+        let data: Vec<Data> = (0..4u8).map(|i| vec![i]).collect();
+        let mut tree = MerkleTree::construct(&data);
+        let root = tree.root();
+        let pinned = tree.nodes[0][2].clone();
+
+        assert_eq!(tree.pin_leaf(2, vec![0; 32]), Err(Error::PinViolation { index: 2 }));
+        tree.pin_leaf(2, pinned).unwrap();
+        assert_eq!(tree.update(2, &vec![9]), Err(Error::PinViolation { index: 2 }));
+        assert_eq!(tree.root(), root);
+
+        // Rewriting the same value keeps the pin satisfied
+        tree.update(2, &data[2]).unwrap();
+        tree.update(1, &vec![9]).unwrap();
+        assert!(tree.unpin_leaf(2).is_some());
+        tree.update(2, &vec![9]).unwrap();
+    }
+}
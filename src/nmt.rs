@@ -0,0 +1,308 @@
+// Namespaced Merkle trees, as used by data availability layers. Every leaf
+// belongs to a namespace and leaves are sorted by it. Each node carries the
+// smallest and largest namespace beneath it along with its hash, and the
+// hash commits to both, so a proof for a namespace can show that the leaves
+// returned are all of that namespace's leaves: every sibling to the left
+// ends before it and every sibling to the right starts after it.
+//
+// Leaf hashes are hash_leaf(namespace | data) and inner hashes are
+// hash_node(left, right) over the serialized children (min | max | hash),
+// which with `Sha256Hasher` matches Celestia's NMT.
+
+use crate::ct::hashes_equal;
+use crate::{Data, Hash, Hasher, Sha256Hasher};
+use std::fmt;
+use std::ops::Range;
+
+
+pub type Namespace = Vec<u8>;
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NmtNode {
+    pub min: Namespace,
+    pub max: Namespace,
+    pub hash: Hash,
+}
+
+
+impl NmtNode {
+    fn leaf<H: Hasher>(namespace: &[u8], data: &[u8], hasher: &H) -> NmtNode {
+        let hash = hasher.hash_leaf(&[namespace, data].concat());
+        NmtNode { min: namespace.to_vec(), max: namespace.to_vec(), hash }
+    }
+
+
+    // None if the children are out of namespace order
+    fn parent<H: Hasher>(left: &NmtNode, right: &NmtNode, hasher: &H) -> Option<NmtNode> {
+        if left.max > right.min {
+            return None;
+        }
+        let hash = hasher.hash_node(&left.to_bytes(), &right.to_bytes());
+        Some(NmtNode { min: left.min.clone(), max: right.max.clone(), hash })
+    }
+
+
+    // min | max | hash, the form a parent hashes
+    pub fn to_bytes(&self) -> Vec<u8> {
+        [&self.min[..], &self.max[..], &self.hash[..]].concat()
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NmtError {
+    // A leaf's namespace isn't the tree's namespace size
+    NamespaceSize { index: usize },
+    // A leaf's namespace is smaller than the one before it
+    Unsorted { index: usize },
+}
+
+
+impl fmt::Display for NmtError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NmtError::NamespaceSize { index } => write!(f, "leaf {} has the wrong namespace size", index),
+            NmtError::Unsorted { index } => write!(f, "leaf {} is out of namespace order", index),
+        }
+    }
+}
+
+
+impl std::error::Error for NmtError {}
+
+
+pub struct NamespaceTree<H = Sha256Hasher> {
+    namespace_size: usize,
+    levels: Vec<Vec<NmtNode>>,
+    hasher: H,
+}
+
+
+// Shows that a run of leaves is everything the tree holds for a namespace.
+// When the namespace is absent the run is empty and the proof carries the
+// leaf where it would have been, which belongs to some other namespace.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NamespaceProof {
+    start: usize,
+    leaf_count: usize,
+    // Per level, bottom up: the left edge sibling if needed, then the right one
+    siblings: Vec<NmtNode>,
+    absent_leaf: Option<NmtNode>,
+}
+
+
+impl NamespaceProof {
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+
+    pub fn is_absence(&self) -> bool {
+        self.absent_leaf.is_some()
+    }
+
+
+    // The root implied by `leaves` being all of `namespace`'s leaves, or None
+    // if the proof doesn't show that
+    pub fn compute_root_with<H: Hasher>(&self, namespace: &[u8], leaves: &[Data], hasher: &H) -> Option<NmtNode> {
+        let mut nodes = match (&self.absent_leaf, leaves.is_empty()) {
+            (None, false) => leaves.iter().map(|data| NmtNode::leaf(namespace, data, hasher)).collect(),
+            (Some(leaf), true) if leaf.min != namespace => vec![leaf.clone()],
+            _ => return None,
+        };
+        let (mut lo, mut hi) = (self.start, self.start.checked_add(nodes.len())?);
+        if hi > self.leaf_count {
+            return None;
+        }
+        let mut siblings = self.siblings.iter();
+        let mut width = self.leaf_count;
+        while width > 1 {
+            if lo % 2 == 1 {
+                let sibling = siblings.next()?;
+                if sibling.max.as_slice() >= namespace {
+                    return None;
+                }
+                nodes.insert(0, sibling.clone());
+                lo -= 1;
+            }
+            if hi % 2 == 1 && hi < width {
+                let sibling = siblings.next()?;
+                if sibling.min.as_slice() <= namespace {
+                    return None;
+                }
+                nodes.push(sibling.clone());
+                hi += 1;
+            }
+            nodes = reduce_nodes(&nodes, hasher)?;
+            (lo, hi, width) = (lo / 2, hi.div_ceil(2), width.div_ceil(2));
+        }
+        // Unused siblings mean the proof was made for a different range
+        siblings.next().is_none().then(|| nodes.pop()).flatten()
+    }
+
+
+    pub fn verify(&self, namespace: &[u8], leaves: &[Data], root: &NmtNode) -> bool {
+        self.verify_with(namespace, leaves, root, &Sha256Hasher)
+    }
+
+
+    pub fn verify_with<H: Hasher>(&self, namespace: &[u8], leaves: &[Data], root: &NmtNode, hasher: &H) -> bool {
+        self.compute_root_with(namespace, leaves, hasher).is_some_and(|computed| hashes_equal(&computed.to_bytes(), &root.to_bytes()))
+    }
+}
+
+
+// Like `reduce_level`, for namespaced nodes
+fn reduce_nodes<H: Hasher>(nodes: &[NmtNode], hasher: &H) -> Option<Vec<NmtNode>> {
+    nodes
+        .chunks(2)
+        .map(|chunk| match chunk {
+            [left, right] => NmtNode::parent(left, right, hasher),
+            _ => Some(chunk[0].clone()),
+        })
+        .collect()
+}
+
+
+impl NamespaceTree {
+    // Leaves are (namespace, data) pairs sorted by namespace
+    pub fn construct(namespace_size: usize, leaves: &[(Namespace, Data)]) -> Result<NamespaceTree, NmtError> {
+        Self::construct_with(namespace_size, leaves, Sha256Hasher)
+    }
+}
+
+
+impl<H: Hasher> NamespaceTree<H> {
+    pub fn construct_with(namespace_size: usize, leaves: &[(Namespace, Data)], hasher: H) -> Result<NamespaceTree<H>, NmtError> {
+        for (index, (namespace, _)) in leaves.iter().enumerate() {
+            if namespace.len() != namespace_size {
+                return Err(NmtError::NamespaceSize { index });
+            }
+            if index > 0 && leaves[index - 1].0 > *namespace {
+                return Err(NmtError::Unsorted { index });
+            }
+        }
+        let mut levels = vec![leaves.iter().map(|(ns, data)| NmtNode::leaf(ns, data, &hasher)).collect::<Vec<_>>()];
+        while levels[levels.len() - 1].len() > 1 {
+            let next = reduce_nodes(&levels[levels.len() - 1], &hasher).expect("leaves are sorted");
+            levels.push(next);
+        }
+        Ok(NamespaceTree { namespace_size, levels, hasher })
+    }
+
+
+    pub fn leaf_count(&self) -> usize {
+        self.levels[0].len()
+    }
+
+
+    // An empty tree's root has zero namespaces and the hash of nothing
+    pub fn root(&self) -> NmtNode {
+        match self.levels[self.levels.len() - 1].first() {
+            Some(root) => root.clone(),
+            None => NmtNode {
+                min: vec![0; self.namespace_size],
+                max: vec![0; self.namespace_size],
+                hash: self.hasher.hash(&[]),
+            },
+        }
+    }
+
+
+    // The leaves of `namespace`, as a range of leaf indices
+    pub fn namespace_range(&self, namespace: &[u8]) -> Range<usize> {
+        let leaves = &self.levels[0];
+        let start = leaves.partition_point(|leaf| leaf.min.as_slice() < namespace);
+        let end = leaves.partition_point(|leaf| leaf.min.as_slice() <= namespace);
+        start..end
+    }
+
+
+    // Proves which leaves belong to `namespace`, or that none do. None for
+    // an empty tree.
+    pub fn prove_namespace(&self, namespace: &[u8]) -> Option<NamespaceProof> {
+        let mut range = self.namespace_range(namespace);
+        let absent_leaf = if range.is_empty() {
+            // Stand in the first leaf after the gap, or the last leaf if none
+            let index = range.start.min(self.leaf_count().checked_sub(1)?);
+            range = index..index + 1;
+            Some(self.levels[0][index].clone())
+        } else {
+            None
+        };
+        let mut siblings = Vec::new();
+        let (mut lo, mut hi) = (range.start, range.end);
+        for level in &self.levels[..self.levels.len() - 1] {
+            if lo % 2 == 1 {
+                siblings.push(level[lo - 1].clone());
+            }
+            if hi % 2 == 1 && hi < level.len() {
+                siblings.push(level[hi].clone());
+            }
+            (lo, hi) = (lo / 2, hi.div_ceil(2));
+        }
+        Some(NamespaceProof { start: range.start, leaf_count: self.leaf_count(), siblings, absent_leaf })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    fn example() -> (Vec<(Namespace, Data)>, NamespaceTree) {
+        let namespaces = [1u8, 1, 2, 4, 4, 4, 7];
+        let leaves: Vec<(Namespace, Data)> =
+            namespaces.iter().enumerate().map(|(i, &ns)| (vec![0, ns], vec![i as u8])).collect();
+        let tree = NamespaceTree::construct(2, &leaves).unwrap();
+        (leaves, tree)
+    }
+
+    fn data_for(leaves: &[(Namespace, Data)], namespace: &[u8]) -> Vec<Data> {
+        leaves.iter().filter(|(ns, _)| ns == namespace).map(|(_, data)| data.clone()).collect()
+    }
+
+    #[test]
+    fn test_namespace_proofs() {
+        let (leaves, tree) = example();
+        let root = tree.root();
+        assert_eq!((root.min.clone(), root.max.clone()), (vec![0, 1], vec![0, 7]));
+
+        for ns in 0..9u8 {
+            let namespace = [0, ns];
+            let data = data_for(&leaves, &namespace);
+            let proof = tree.prove_namespace(&namespace).unwrap();
+            assert_eq!(proof.is_absence(), data.is_empty());
+            assert!(proof.verify(&namespace, &data, &root));
+        }
+    }
+
+    #[test]
+    fn test_incomplete_or_wrong_namespace_rejected() {
+        let (leaves, tree) = example();
+        let root = tree.root();
+        let namespace = [0, 4];
+        let data = data_for(&leaves, &namespace);
+        let proof = tree.prove_namespace(&namespace).unwrap();
+
+        // Dropping a leaf from either end shifts the run off the proof
+        assert!(!proof.verify(&namespace, &data[1..], &root));
+        assert!(!proof.verify(&namespace, &data[..2], &root));
+        // The same proof can't claim the leaves belong to another namespace
+        assert!(!proof.verify(&[0, 5], &data, &root));
+        // Nor can an absence proof hide a namespace that's there
+        let absent = tree.prove_namespace(&[0, 3]).unwrap();
+        assert!(!absent.verify(&namespace, &[], &root));
+    }
+
+    #[test]
+    fn test_construct_checks_leaves() {
+        let unsorted = vec![(vec![2], vec![]), (vec![1], vec![])];
+        assert_eq!(NamespaceTree::construct(1, &unsorted).err(), Some(NmtError::Unsorted { index: 1 }));
+        let wrong_size = vec![(vec![1, 2], vec![])];
+        assert_eq!(NamespaceTree::construct(1, &wrong_size).err(), Some(NmtError::NamespaceSize { index: 0 }));
+        assert!(NamespaceTree::construct(1, &[]).unwrap().prove_namespace(&[0]).is_none());
+    }
+}
@@ -0,0 +1,206 @@
+use crate::ct::hashes_equal;
+use crate::{Data, Hash, Hasher, MerkleTree, Proof, Sha256Hasher};
+use std::collections::HashMap;
+
+
+// A tree that only holds the nodes needed to verify some of its leaves.
+// It knows the full tree's shape from the leaf count, so every proof hash
+// can be placed at its (level, index) position, and the root is recomputed
+// from whatever has been collected so far.
+#[derive(Debug, Clone)]
+pub struct PartialTree<H = Sha256Hasher> {
+    // Number of nodes at each level, leaves first
+    level_sizes: Vec<usize>,
+    nodes: HashMap<(usize, usize), Hash>,
+    hasher: H,
+}
+
+
+impl PartialTree {
+    // Creates an empty partial view of a tree with `leaf_count` leaves
+    pub fn new(leaf_count: usize) -> PartialTree {
+        Self::new_with(leaf_count, Sha256Hasher)
+    }
+}
+
+
+impl<H: Hasher + Clone> PartialTree<H> {
+    // Creates an empty partial view of a tree built with `hasher`
+    pub fn new_with(leaf_count: usize, hasher: H) -> PartialTree<H> {
+        let mut level_sizes = vec![leaf_count];
+        while *level_sizes.last().unwrap() > 1 {
+            level_sizes.push(level_sizes.last().unwrap().div_ceil(2));
+        }
+        PartialTree {
+            level_sizes,
+            nodes: HashMap::new(),
+            hasher,
+        }
+    }
+
+
+    // Adds the proof for the leaf at `index`. Returns false, leaving the tree
+    // unchanged, if the proof doesn't fit the tree shape or disagrees with
+    // the nodes already collected.
+    pub fn insert(&mut self, index: usize, data: &Data, proof: &Proof) -> bool {
+        if index >= self.level_sizes[0] {
+            return false;
+        }
+        let mut current = self.hasher.hash_leaf(data);
+        let mut placed = vec![((0, index), current.clone())];
+        let mut hashes = proof.hashes.iter();
+        let mut current_idx = index;
+        for level in 0..self.level_sizes.len() - 1 {
+            let sibling_idx = current_idx ^ 1;
+            if sibling_idx < self.level_sizes[level] {
+                let Some((_, sibling)) = hashes.next() else {
+                    return false;
+                };
+                current = if current_idx.is_multiple_of(2) {
+                    self.hasher.hash_node(&current, sibling)
+                } else {
+                    self.hasher.hash_node(sibling, &current)
+                };
+                placed.push(((level, sibling_idx), sibling.to_vec()));
+            }
+            current_idx /= 2;
+        }
+        if hashes.next().is_some() {
+            return false;
+        }
+
+        // The proof has to lead to the root already known, and can't
+        // contradict any node collected before
+        if self.root().is_some_and(|root| !hashes_equal(&root, &current)) {
+            return false;
+        }
+        let conflicts = placed
+            .iter()
+            .any(|(position, hash)| self.nodes.get(position).is_some_and(|known| !hashes_equal(known, hash)));
+        if conflicts {
+            return false;
+        }
+        self.nodes.extend(placed);
+        true
+    }
+
+
+    // Recomputes the root from the collected nodes, if enough are known
+    pub fn root(&self) -> Option<Hash> {
+        self.node(self.level_sizes.len() - 1, 0)
+    }
+
+
+    // Checks that `data` is the leaf at `index` in this partial tree
+    pub fn contains(&self, index: usize, data: &Data) -> bool {
+        self.nodes.get(&(0, index)).is_some_and(|leaf| hashes_equal(leaf, &self.hasher.hash_leaf(data)))
+    }
+
+
+    // Number of hashes held, which stays close to k * log(n) for k proven leaves
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+
+    // Uses a stored hash where there is one, and otherwise derives the node
+    // from its children. Collected nodes are never left unchecked: `insert`
+    // only keeps proofs that agree with the root already known.
+    fn node(&self, level: usize, index: usize) -> Option<Hash> {
+        if let Some(hash) = self.nodes.get(&(level, index)) {
+            return Some(hash.clone());
+        }
+        if level == 0 {
+            return None;
+        }
+        let left_idx = index * 2;
+        if left_idx + 1 < self.level_sizes[level - 1] {
+            let left = self.node(level - 1, left_idx)?;
+            let right = self.node(level - 1, left_idx + 1)?;
+            Some(self.hasher.hash_node(&left, &right))
+        } else {
+            // Odd node promoted unchanged
+            self.node(level - 1, left_idx)
+        }
+    }
+}
+
+
+impl<H: Hasher + Clone> MerkleTree<H> {
+    // Builds the partial tree a light client needs to verify the leaves at `indices`
+    pub fn partial(&self, indices: &[usize]) -> Option<PartialTree<H>> {
+        let levels = self.levels()?;
+        let mut partial = PartialTree::new_with(levels[0].len(), self.hasher.clone());
+        for &index in indices {
+            let leaf_hash = levels[0].get(index)?;
+            partial.nodes.insert((0, index), leaf_hash.clone());
+            let mut current_idx = index;
+            for level in 0..levels.len() - 1 {
+                if let Some(sibling) = levels[level].get(current_idx ^ 1) {
+                    partial.nodes.insert((level, current_idx ^ 1), sibling.clone());
+                }
+                current_idx /= 2;
+            }
+        }
+        Some(partial)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Legacy;
+
+
+    #[test]
+    fn test_partial_tree() {
+        for n in 1..=10 {
+            let data: Vec<Data> = (0..n as u8).map(|i| vec![i]).collect();
+            let tree = MerkleTree::construct(&data);
+            let mut partial = PartialTree::new(n);
+            assert_eq!(partial.root(), None);
+            for (i, leaf) in data.iter().enumerate() {
+                assert!(partial.insert(i, leaf, &tree.prove(leaf).unwrap()));
+                assert_eq!(partial.root(), Some(tree.root()));
+                assert!(partial.contains(i, leaf));
+            }
+            assert_eq!(tree.partial(&[0, n - 1]).unwrap().root(), Some(tree.root()));
+        }
+    }
+
+    #[test]
+    fn test_partial_tree_rejects_inconsistent_proof() {
+        let data: Vec<Data> = (0..6u8).map(|i| vec![i]).collect();
+        let tree = MerkleTree::construct(&data);
+        let mut partial = PartialTree::new(6);
+        assert!(partial.insert(1, &data[1], &tree.prove(&data[1]).unwrap()));
+
+        let other = MerkleTree::construct(&data[..5]);
+        assert!(!partial.insert(2, &data[2], &other.prove(&data[2]).unwrap()));
+        assert!(!partial.insert(2, &vec![9], &tree.prove(&data[2]).unwrap()));
+        assert!(!partial.insert(6, &data[2], &tree.prove(&data[2]).unwrap()));
+        assert_eq!(partial.root(), Some(tree.root()));
+
+        // Leaf 2 sits under a node the first proof stored, which mustn't
+        // vouch for a forged leaf beneath it
+        let mut forged = tree.prove(&data[2]).unwrap();
+        forged.hashes[0].1 = std::borrow::Cow::Owned(vec![0; 32]);
+        assert!(!partial.insert(2, &data[2], &forged));
+        assert!(!partial.contains(2, &data[2]));
+    }
+
+    #[test]
+    fn test_partial_tree_with_hasher() {
+        let data: Vec<Data> = (0..7u8).map(|i| vec![i]).collect();
+        let tree = MerkleTree::construct_with(&data, Legacy(Sha256Hasher));
+        let partial = tree.partial(&[2, 5]).unwrap();
+        assert_eq!(partial.root(), Some(tree.root()));
+        assert!(partial.contains(5, &data[5]));
+    }
+}
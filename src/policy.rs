@@ -0,0 +1,234 @@
+use crate::ct::hashes_equal;
+use crate::transparency::{system_clock, Clock};
+use crate::{hasher_id, max_proof_len, Data, Hash, Hasher, MerkleTree, Proof, Sha256Hasher, SignedTreeHead, TreeHeadVerifier};
+use std::fmt;
+use std::sync::Arc;
+
+
+// Everything a verifier needs to check one inclusion claim
+#[derive(Debug)]
+pub struct Receipt<'a> {
+    pub data: Data,
+    pub proof: Proof<'a>,
+    pub root: Hash,
+    // The log's signed commitment to `root`, when the tree is a log
+    pub head: Option<SignedTreeHead>,
+}
+
+
+// The checks applied to every receipt, kept in one place instead of at each call site
+#[derive(Clone, Default)]
+pub struct VerificationPolicy {
+    // Reject proofs carrying more hashes than this
+    pub max_proof_depth: Option<usize>,
+    // Only accept receipts whose root is one of these
+    pub trusted_roots: Option<Vec<Hash>>,
+    // Only accept receipts checked with one of these hashers, by `hasher_id`
+    pub allowed_hashers: Option<Vec<Hash>>,
+    // Require a signed head no older than this, in milliseconds
+    pub max_head_age: Option<u64>,
+    // Require a signed head these keys accept
+    pub accepted_signers: Option<Arc<dyn TreeHeadVerifier + Send + Sync>>,
+    // What "now" is for `max_head_age`; the system time when None
+    pub clock: Option<Clock>,
+}
+
+
+impl fmt::Debug for VerificationPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("VerificationPolicy")
+            .field("max_proof_depth", &self.max_proof_depth)
+            .field("trusted_roots", &self.trusted_roots)
+            .field("allowed_hashers", &self.allowed_hashers)
+            .field("max_head_age", &self.max_head_age)
+            .field("accepted_signers", &self.accepted_signers.is_some())
+            .field("clock", &self.clock)
+            .finish()
+    }
+}
+
+
+impl VerificationPolicy {
+    // Caps proof depth at the longest proof a tree of this size can produce
+    pub fn for_tree_size(tree_size: usize) -> VerificationPolicy {
+        VerificationPolicy { max_proof_depth: Some(max_proof_len(tree_size)), ..Default::default() }
+    }
+
+
+    // Only accepts receipts checked with `hasher`
+    pub fn allow_hasher<H: Hasher>(mut self, hasher: &H) -> VerificationPolicy {
+        self.allowed_hashers.get_or_insert_with(Vec::new).push(hasher_id(hasher));
+        self
+    }
+}
+
+
+// Why a receipt was rejected
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PolicyError {
+    ProofTooDeep,
+    UntrustedRoot,
+    RootMismatch,
+    HasherNotAllowed,
+    // The policy requires a signed head and the receipt has none
+    MissingHead,
+    // The head commits to a different root or tree size than the receipt
+    HeadMismatch,
+    StaleHead,
+    UntrustedSigner,
+}
+
+
+// Checks a receipt against the policy, then checks the proof itself
+pub fn verify_receipt(receipt: &Receipt, policy: &VerificationPolicy) -> Result<(), PolicyError> {
+    verify_receipt_with(receipt, policy, &Sha256Hasher)
+}
+
+
+// Like `verify_receipt`, for receipts from trees built with `hasher`
+pub fn verify_receipt_with<H: Hasher>(receipt: &Receipt, policy: &VerificationPolicy, hasher: &H) -> Result<(), PolicyError> {
+    if let Some(allowed) = &policy.allowed_hashers {
+        let id = hasher_id(hasher);
+        if !allowed.iter().any(|allowed| hashes_equal(allowed, &id)) {
+            return Err(PolicyError::HasherNotAllowed);
+        }
+    }
+    if let Some(max_depth) = policy.max_proof_depth {
+        if receipt.proof.len() > max_depth {
+            return Err(PolicyError::ProofTooDeep);
+        }
+    }
+    if let Some(trusted_roots) = &policy.trusted_roots {
+        if !trusted_roots.iter().any(|root| hashes_equal(root, &receipt.root)) {
+            return Err(PolicyError::UntrustedRoot);
+        }
+    }
+    check_head(receipt, policy)?;
+    if MerkleTree::verify_proof_with(&receipt.data, &receipt.proof, &receipt.root, hasher) {
+        Ok(())
+    } else {
+        Err(PolicyError::RootMismatch)
+    }
+}
+
+
+// A head, when present, must commit to the receipt's root; the policy may
+// also require one that is recent and signed by a key it accepts
+fn check_head(receipt: &Receipt, policy: &VerificationPolicy) -> Result<(), PolicyError> {
+    let required = policy.max_head_age.is_some() || policy.accepted_signers.is_some();
+    let head = match &receipt.head {
+        Some(head) => head,
+        None if required => return Err(PolicyError::MissingHead),
+        None => return Ok(()),
+    };
+    let size_matches = receipt.proof.position().is_none_or(|position| position.tree_size == head.tree_size);
+    if !hashes_equal(&head.root, &receipt.root) || !size_matches {
+        return Err(PolicyError::HeadMismatch);
+    }
+    if let Some(signers) = &policy.accepted_signers {
+        if !head.verify(signers.as_ref()) {
+            return Err(PolicyError::UntrustedSigner);
+        }
+    }
+    if let Some(max_age) = policy.max_head_age {
+        let now = policy.clock.unwrap_or(system_clock)();
+        if now.saturating_sub(head.timestamp) > max_age {
+            return Err(PolicyError::StaleHead);
+        }
+    }
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Legacy;
+
+
+    fn receipt(tree: &MerkleTree, data: Data) -> Receipt<'_> {
+        let proof = tree.prove(&data).unwrap();
+        Receipt { data, proof, root: tree.root(), head: None }
+    }
+
+    #[test]
+    fn test_verify_receipt() {
+        let data: Vec<Data> = (0..8u8).map(|i| vec![i]).collect();
+        let tree = MerkleTree::construct(&data);
+
+        let policy = VerificationPolicy::default();
+        assert_eq!(verify_receipt(&receipt(&tree, vec![3]), &policy), Ok(()));
+
+        assert_eq!(verify_receipt(&receipt(&tree, vec![3]), &VerificationPolicy::for_tree_size(8)), Ok(()));
+        let policy = VerificationPolicy::for_tree_size(4);
+        assert_eq!(verify_receipt(&receipt(&tree, vec![3]), &policy), Err(PolicyError::ProofTooDeep));
+
+        let policy = VerificationPolicy { trusted_roots: Some(vec![vec![0; 32]]), ..Default::default() };
+        assert_eq!(verify_receipt(&receipt(&tree, vec![3]), &policy), Err(PolicyError::UntrustedRoot));
+
+        let mut bad = receipt(&tree, vec![3]);
+        bad.data = vec![4];
+        assert_eq!(verify_receipt(&bad, &VerificationPolicy::default()), Err(PolicyError::RootMismatch));
+    }
+
+    #[test]
+    fn test_verify_receipt_with_hasher() {
+        let data: Vec<Data> = (0..5u8).map(|i| vec![i]).collect();
+        let tree = MerkleTree::construct_with(&data, Legacy(Sha256Hasher));
+        let receipt = Receipt { data: data[2].clone(), proof: tree.prove(&data[2]).unwrap(), root: tree.root(), head: None };
+        let policy = VerificationPolicy::for_tree_size(5);
+        assert_eq!(verify_receipt_with(&receipt, &policy, &Legacy(Sha256Hasher)), Ok(()));
+        assert_eq!(verify_receipt(&receipt, &policy), Err(PolicyError::RootMismatch));
+
+        // A policy naming its hashers turns the others away
+        let policy = VerificationPolicy::default().allow_hasher(&Sha256Hasher);
+        assert_eq!(verify_receipt_with(&receipt, &policy, &Legacy(Sha256Hasher)), Err(PolicyError::HasherNotAllowed));
+        let policy = policy.allow_hasher(&Legacy(Sha256Hasher));
+        assert_eq!(verify_receipt_with(&receipt, &policy, &Legacy(Sha256Hasher)), Ok(()));
+    }
+
+    // Accepts heads whose signature is the key id followed by the message's hash
+    fn signers(key_id: &[u8], message: &[u8], signature: &[u8]) -> bool {
+        key_id == b"log" && signature == [key_id, &Sha256Hasher.hash(message)].concat()
+    }
+
+    fn signed_head(tree_size: usize, timestamp: u64, root: Hash) -> SignedTreeHead {
+        let mut head = SignedTreeHead { tree_size, timestamp, key_id: b"log".to_vec(), root, signature: Vec::new() };
+        head.signature = [b"log".as_slice(), &Sha256Hasher.hash(&head.signed_bytes())].concat();
+        head
+    }
+
+    #[test]
+    fn test_verify_receipt_head() {
+        let data: Vec<Data> = (0..6u8).map(|i| vec![i]).collect();
+        let tree = MerkleTree::construct(&data);
+        let policy = VerificationPolicy {
+            max_head_age: Some(1_000),
+            accepted_signers: Some(Arc::new(signers)),
+            clock: Some(|| 10_000),
+            ..Default::default()
+        };
+        let with_head = |head| Receipt { head, ..receipt(&tree, vec![1]) };
+
+        assert_eq!(verify_receipt(&with_head(Some(signed_head(6, 9_500, tree.root()))), &policy), Ok(()));
+        assert_eq!(verify_receipt(&with_head(None), &policy), Err(PolicyError::MissingHead));
+        assert_eq!(verify_receipt(&with_head(Some(signed_head(6, 8_000, tree.root()))), &policy), Err(PolicyError::StaleHead));
+        let other_root = signed_head(6, 9_500, vec![0; 32]);
+        assert_eq!(verify_receipt(&with_head(Some(other_root)), &policy), Err(PolicyError::HeadMismatch));
+
+        let mut forged = signed_head(6, 9_500, tree.root());
+        forged.tree_size = 7;
+        assert_eq!(verify_receipt(&with_head(Some(forged)), &policy), Err(PolicyError::UntrustedSigner));
+        let mut other_key = signed_head(6, 9_500, tree.root());
+        other_key.key_id = b"other".to_vec();
+        assert_eq!(verify_receipt(&with_head(Some(other_key)), &policy), Err(PolicyError::UntrustedSigner));
+
+        // A positioned proof must be for the size the head signs
+        let positioned = Receipt {
+            proof: tree.prove_with_position(&data[1]).unwrap(),
+            head: Some(signed_head(5, 9_500, tree.root())),
+            ..receipt(&tree, vec![1])
+        };
+        assert_eq!(verify_receipt(&positioned, &policy), Err(PolicyError::HeadMismatch));
+    }
+}
@@ -0,0 +1,180 @@
+// Poseidon over the BN254 scalar field, with circom-compatible parameters,
+// so roots and proofs from this crate can be checked inside SNARK circuits.
+// Every hash is a field element, stored as 32 big-endian bytes.
+//
+// Leaves are arbitrary bytes, so they are absorbed as field elements:
+// the length first, then 31-byte big-endian chunks (always below the
+// modulus), chained two at a time, and finished with a one-input hash.
+// Parents are the two-input hash of their children.
+//
+// Nodes of up to 12 children are one circom permutation. Wider nodes, which
+// circom has no parameters for, chain them: the child count, then the
+// children 11 at a time, each permutation taking the previous output first.
+//
+// Child hashes must be canonical, 32 bytes below the modulus. A
+// non-canonical child hashes to an empty hash, which no root equals, so a
+// proof can't pass off another encoding of the same field element.
+
+use crate::{Hash, HashDirection, Hasher, Proof};
+use ark_bn254::Fr;
+use ark_ff::{AdditiveGroup, BigInteger, PrimeField};
+use light_poseidon::{Poseidon, PoseidonHasher as _};
+
+
+// Largest number of inputs the circom parameter sets support
+const MAX_INPUTS: usize = 12;
+
+// Bytes packed into each leaf field element
+const CHUNK_LEN: usize = 31;
+
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PoseidonHasher;
+
+
+impl PoseidonHasher {
+    // One permutation over 1 to MAX_INPUTS inputs, the only widths anything
+    // here passes. Outside them light-poseidon errors, which maps to zero
+    // rather than a panic.
+    fn poseidon(inputs: &[Fr]) -> Fr {
+        Poseidon::<Fr>::new_circom(inputs.len())
+            .and_then(|mut poseidon| poseidon.hash(inputs))
+            .unwrap_or(Fr::ZERO)
+    }
+
+
+    // Any number of inputs, as one permutation where circom has one
+    fn poseidon_any(inputs: &[Fr]) -> Fr {
+        if (1..=MAX_INPUTS).contains(&inputs.len()) {
+            return Self::poseidon(inputs);
+        }
+        let mut acc = Fr::from(inputs.len() as u64);
+        for chunk in inputs.chunks(MAX_INPUTS - 1) {
+            let mut chained = Vec::with_capacity(MAX_INPUTS);
+            chained.push(acc);
+            chained.extend_from_slice(chunk);
+            acc = Self::poseidon(&chained);
+        }
+        acc
+    }
+
+
+    fn hash_fields(children: &[&Hash]) -> Hash {
+        match children.iter().map(|child| to_field(child)).collect::<Option<Vec<Fr>>>() {
+            Some(inputs) => from_field(&Self::poseidon_any(&inputs)),
+            None => Hash::new(),
+        }
+    }
+}
+
+
+impl Hasher for PoseidonHasher {
+    fn hash(&self, data: &[u8]) -> Hash {
+        let mut acc = Fr::from(data.len() as u64);
+        for chunk in data.chunks(CHUNK_LEN) {
+            acc = Self::poseidon(&[acc, Fr::from_be_bytes_mod_order(chunk)]);
+        }
+        from_field(&Self::poseidon(&[acc]))
+    }
+
+    fn hash_concat(&self, left: &Hash, right: &Hash) -> Hash {
+        Self::hash_fields(&[left, right])
+    }
+
+    fn hash_many(&self, children: &[Hash]) -> Hash {
+        Self::hash_fields(&children.iter().collect::<Vec<_>>())
+    }
+
+    // Leaves end in a one-input permutation and nodes use one per child
+    // count, so the two are already separated by the circom parameters.
+    // Prefix bytes would only make the leaves harder to hash in a circuit.
+    fn hash_leaf(&self, data: &[u8]) -> Hash {
+        self.hash(data)
+    }
+
+    fn hash_node(&self, left: &Hash, right: &Hash) -> Hash {
+        self.hash_concat(left, right)
+    }
+
+    fn hash_children(&self, children: &[Hash]) -> Hash {
+        self.hash_many(children)
+    }
+}
+
+
+// Reads a hash produced by `PoseidonHasher` back as a field element. None
+// unless it's 32 bytes holding a value below the modulus.
+pub fn to_field(hash: &Hash) -> Option<Fr> {
+    let element = Fr::from_be_bytes_mod_order(hash);
+    (hash.len() == 32 && from_field(&element) == *hash).then_some(element)
+}
+
+
+pub fn from_field(element: &Fr) -> Hash {
+    element.into_bigint().to_bytes_be()
+}
+
+
+impl Proof<'_> {
+    // The proof's hashes as field elements, for feeding a circuit witness.
+    // None if any of them isn't a canonical field element.
+    pub fn field_elements(&self) -> Option<Vec<(HashDirection, Fr)>> {
+        self.hashes.iter().map(|(direction, hash)| Some((*direction, to_field(hash)?))).collect()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Data, KaryTree, MerkleTree};
+
+
+    #[test]
+    fn test_poseidon_tree() {
+        let data: Vec<Data> = (0..5u8).map(|i| vec![i; 40 * i as usize]).collect();
+        let tree = MerkleTree::construct_with(&data, PoseidonHasher);
+        assert_eq!(tree.root().len(), 32);
+        for leaf in &data {
+            let proof = tree.prove(leaf).unwrap();
+            assert!(MerkleTree::verify_proof_with(leaf, &proof, &tree.root(), &PoseidonHasher));
+            assert_eq!(proof.field_elements().unwrap().len(), proof.hashes.len());
+        }
+        assert_ne!(PoseidonHasher.hash(&[]), PoseidonHasher.hash(&[0]));
+    }
+
+    #[test]
+    fn test_poseidon_matches_circom_vector() {
+        // Poseidon(1, 2) from the circomlibjs test suite
+        let expected = "115cc0f5e7d690413df64c6b9662e9cf2a3617f2743245519e19607a4417189a";
+        let one = from_field(&Fr::from(1u64));
+        let two = from_field(&Fr::from(2u64));
+        assert_eq!(hex::encode(PoseidonHasher.hash_concat(&one, &two)), expected);
+    }
+
+    #[test]
+    fn test_poseidon_wide_nodes() {
+        let data: Vec<Data> = (0..40u8).map(|i| vec![i]).collect();
+        for arity in [12, 13, 16, 30] {
+            let tree = KaryTree::construct_with(&data, arity, PoseidonHasher);
+            let proof = tree.prove(&data[25]).unwrap();
+            assert_eq!(proof.compute_root_with(&data[25], &PoseidonHasher), Some(tree.root()));
+        }
+        let children: Vec<Hash> = (0..13u64).map(|i| from_field(&Fr::from(i))).collect();
+        assert_ne!(PoseidonHasher.hash_many(&children), PoseidonHasher.hash_many(&children[..12]));
+    }
+
+    #[test]
+    fn test_poseidon_rejects_non_canonical() {
+        let one = from_field(&Fr::from(1u64));
+        // The modulus plus one reads as one when reduced
+        let mut alias = Fr::MODULUS;
+        alias.add_with_carry(&1u64.into());
+        let alias = alias.to_bytes_be();
+        assert_eq!(to_field(&one), Some(Fr::from(1u64)));
+        assert_eq!(to_field(&alias), None);
+        assert_eq!(to_field(&one[1..].to_vec()), None);
+        assert!(PoseidonHasher.hash_concat(&alias, &one).is_empty());
+        assert_ne!(PoseidonHasher.hash_concat(&alias, &one), PoseidonHasher.hash_concat(&one, &one));
+    }
+}
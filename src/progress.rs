@@ -0,0 +1,143 @@
+// Progress reporting and cooperative cancellation for long builds. A
+// `ProgressHandle` is shared between the build and whoever watches it, say a
+// progress bar on another thread: the build publishes how far it has got and
+// stops at its next check once the handle is cancelled.
+
+use crate::{reduce_level, Data, Hash, Hasher, MerkleTree};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+
+// Leaves hashed between publishing progress and checking for cancellation
+const REPORT_EVERY: usize = 64;
+
+
+#[derive(Debug, Default)]
+struct ProgressState {
+    leaves_hashed: AtomicUsize,
+    levels_reduced: AtomicUsize,
+    cancelled: AtomicBool,
+}
+
+
+// Cloning gives another handle on the same build
+#[derive(Debug, Clone, Default)]
+pub struct ProgressHandle {
+    state: Arc<ProgressState>,
+}
+
+
+impl ProgressHandle {
+    pub fn new() -> ProgressHandle {
+        ProgressHandle::default()
+    }
+
+
+    pub fn leaves_hashed(&self) -> usize {
+        self.state.leaves_hashed.load(Ordering::Relaxed)
+    }
+
+
+    // Levels above the leaves that are complete
+    pub fn levels_reduced(&self) -> usize {
+        self.state.levels_reduced.load(Ordering::Relaxed)
+    }
+
+
+    // Asks the build to stop. It notices at its next check, within
+    // `REPORT_EVERY` leaves or one level.
+    pub fn cancel(&self) {
+        self.state.cancelled.store(true, Ordering::Relaxed);
+    }
+
+
+    pub fn is_cancelled(&self) -> bool {
+        self.state.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cancelled {
+    pub leaves_hashed: usize,
+    pub levels_built: usize,
+}
+
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "build cancelled after {} leaves and {} levels", self.leaves_hashed, self.levels_built)
+    }
+}
+
+
+impl std::error::Error for Cancelled {}
+
+
+impl<H: Hasher> MerkleTree<H> {
+    // Like `construct_with`, reporting to `progress` and stopping if it's cancelled
+    pub fn construct_with_progress(input: &[Data], hasher: H, progress: &ProgressHandle) -> Result<MerkleTree<H>, Cancelled> {
+        let state = &progress.state;
+        let mut leaves = Vec::with_capacity(input.len());
+        for data in input {
+            if leaves.len().is_multiple_of(REPORT_EVERY) {
+                state.leaves_hashed.store(leaves.len(), Ordering::Relaxed);
+                if progress.is_cancelled() {
+                    return Err(Cancelled { leaves_hashed: leaves.len(), levels_built: 0 });
+                }
+            }
+            leaves.push(hasher.hash_leaf(data));
+        }
+        state.leaves_hashed.store(leaves.len(), Ordering::Relaxed);
+
+        let mut levels: Vec<Vec<Hash>> = vec![leaves];
+        while levels.last().unwrap().len() > 1 {
+            if progress.is_cancelled() {
+                return Err(Cancelled { leaves_hashed: input.len(), levels_built: levels.len() - 1 });
+            }
+            let next = reduce_level(levels.last().unwrap(), &hasher);
+            levels.push(next);
+            state.levels_reduced.store(levels.len() - 1, Ordering::Relaxed);
+        }
+        Ok(MerkleTree::from_levels(levels, hasher))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Sha256Hasher;
+
+
+    // Cancels the build when it reaches a given leaf
+    struct CancelAt {
+        leaf: Data,
+        progress: ProgressHandle,
+    }
+
+    impl Hasher for CancelAt {
+        fn hash(&self, data: &[u8]) -> Hash {
+            if data.ends_with(&self.leaf) {
+                self.progress.cancel();
+            }
+            Sha256Hasher.hash(data)
+        }
+    }
+
+    #[test]
+    fn test_construct_with_progress() {
+        let data: Vec<Data> = (0..200u8).map(|i| vec![i]).collect();
+        let progress = ProgressHandle::new();
+        let tree = MerkleTree::construct_with_progress(&data, Sha256Hasher, &progress).unwrap();
+        assert_eq!(tree.nodes, MerkleTree::construct(&data).nodes);
+        assert_eq!((progress.leaves_hashed(), progress.levels_reduced()), (200, 8));
+
+        let progress = ProgressHandle::new();
+        let hasher = CancelAt { leaf: vec![100], progress: progress.clone() };
+        let result = MerkleTree::construct_with_progress(&data, hasher, &progress);
+        assert_eq!(result.err(), Some(Cancelled { leaves_hashed: 128, levels_built: 0 }));
+        assert_eq!(progress.leaves_hashed(), 128);
+    }
+}
@@ -0,0 +1,238 @@
+// Protobuf encodings of proofs, multiproofs and signed tree heads, for
+// services in other languages. The messages follow proto/merkle_tree.proto,
+// which is also available as `SCHEMA` for generating code elsewhere.
+// Decoding gives None for anything that isn't a well-formed message or
+// doesn't make a valid value, such as an unknown side or a position past
+// what this platform can index.
+
+use crate::{HashDirection, MultiProof, Position, Proof, SignedTreeHead};
+use prost::Message;
+use std::borrow::Cow;
+
+
+pub const SCHEMA: &str = include_str!("../proto/merkle_tree.proto");
+
+
+// The messages of the schema, as prost types
+pub mod pb {
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, prost::Enumeration)]
+    #[repr(i32)]
+    pub enum Side {
+        Left = 0,
+        Right = 1,
+    }
+
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ProofStep {
+        #[prost(enumeration = "Side", tag = "1")]
+        pub side: i32,
+        #[prost(bytes = "vec", tag = "2")]
+        pub hash: Vec<u8>,
+    }
+
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Position {
+        #[prost(uint64, tag = "1")]
+        pub index: u64,
+        #[prost(uint64, tag = "2")]
+        pub tree_size: u64,
+    }
+
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Proof {
+        #[prost(message, repeated, tag = "1")]
+        pub steps: Vec<ProofStep>,
+        #[prost(message, optional, tag = "2")]
+        pub position: Option<Position>,
+    }
+
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct MultiProof {
+        #[prost(uint64, tag = "1")]
+        pub leaf_count: u64,
+        #[prost(uint64, repeated, tag = "2")]
+        pub indices: Vec<u64>,
+        #[prost(bytes = "vec", repeated, tag = "3")]
+        pub hashes: Vec<Vec<u8>>,
+    }
+
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct TreeHead {
+        #[prost(uint64, tag = "1")]
+        pub tree_size: u64,
+        #[prost(uint64, tag = "2")]
+        pub timestamp: u64,
+        #[prost(bytes = "vec", tag = "3")]
+        pub root: Vec<u8>,
+        #[prost(bytes = "vec", tag = "4")]
+        pub signature: Vec<u8>,
+        #[prost(bytes = "vec", tag = "5")]
+        pub key_id: Vec<u8>,
+    }
+}
+
+
+fn to_usize(value: u64) -> Option<usize> {
+    usize::try_from(value).ok()
+}
+
+
+impl Proof<'_> {
+    pub fn to_message(&self) -> pb::Proof {
+        let steps = self
+            .hashes
+            .iter()
+            .map(|(direction, hash)| {
+                let side = match direction {
+                    HashDirection::Left => pb::Side::Left,
+                    HashDirection::Right => pb::Side::Right,
+                };
+                pb::ProofStep { side: side.into(), hash: hash.to_vec() }
+            })
+            .collect();
+        let position = self.position.map(|p| pb::Position { index: p.index as u64, tree_size: p.tree_size as u64 });
+        pb::Proof { steps, position }
+    }
+
+
+    pub fn from_message(message: pb::Proof) -> Option<Proof<'static>> {
+        let hashes = message
+            .steps
+            .into_iter()
+            .map(|step| {
+                let direction = match pb::Side::try_from(step.side).ok()? {
+                    pb::Side::Left => HashDirection::Left,
+                    pb::Side::Right => HashDirection::Right,
+                };
+                Some((direction, Cow::Owned(step.hash)))
+            })
+            .collect::<Option<_>>()?;
+        let position = match message.position {
+            Some(p) => Some(Position { index: to_usize(p.index)?, tree_size: to_usize(p.tree_size)? }),
+            None => None,
+        };
+        Some(Proof { hashes, position })
+    }
+
+
+    pub fn to_protobuf(&self) -> Vec<u8> {
+        self.to_message().encode_to_vec()
+    }
+
+
+    pub fn from_protobuf(bytes: &[u8]) -> Option<Proof<'static>> {
+        Self::from_message(pb::Proof::decode(bytes).ok()?)
+    }
+}
+
+
+impl MultiProof {
+    pub fn to_message(&self) -> pb::MultiProof {
+        pb::MultiProof {
+            leaf_count: self.leaf_count() as u64,
+            indices: self.indices().iter().map(|&index| index as u64).collect(),
+            hashes: self.hashes().to_vec(),
+        }
+    }
+
+
+    pub fn from_message(message: pb::MultiProof) -> Option<MultiProof> {
+        let indices = message.indices.into_iter().map(to_usize).collect::<Option<_>>()?;
+        MultiProof::from_parts(to_usize(message.leaf_count)?, indices, message.hashes)
+    }
+
+
+    pub fn to_protobuf(&self) -> Vec<u8> {
+        self.to_message().encode_to_vec()
+    }
+
+
+    pub fn from_protobuf(bytes: &[u8]) -> Option<MultiProof> {
+        Self::from_message(pb::MultiProof::decode(bytes).ok()?)
+    }
+}
+
+
+impl SignedTreeHead {
+    pub fn to_message(&self) -> pb::TreeHead {
+        pb::TreeHead {
+            tree_size: self.tree_size as u64,
+            timestamp: self.timestamp,
+            root: self.root.clone(),
+            signature: self.signature.clone(),
+            key_id: self.key_id.clone(),
+        }
+    }
+
+
+    pub fn from_message(message: pb::TreeHead) -> Option<SignedTreeHead> {
+        Some(SignedTreeHead {
+            tree_size: to_usize(message.tree_size)?,
+            timestamp: message.timestamp,
+            key_id: message.key_id,
+            root: message.root,
+            signature: message.signature,
+        })
+    }
+
+
+    pub fn to_protobuf(&self) -> Vec<u8> {
+        self.to_message().encode_to_vec()
+    }
+
+
+    pub fn from_protobuf(bytes: &[u8]) -> Option<SignedTreeHead> {
+        Self::from_message(pb::TreeHead::decode(bytes).ok()?)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Data, MerkleTree};
+
+
+    #[test]
+    fn test_protobuf_roundtrips() {
+        let data: Vec<Data> = (0..7u8).map(|i| vec![i]).collect();
+        let tree = MerkleTree::construct(&data);
+        let proofs: Vec<Proof> = data.iter().map(|leaf| tree.prove_with_position(leaf).unwrap()).collect();
+        for (leaf, proof) in data.iter().zip(&proofs) {
+            let decoded = Proof::from_protobuf(&proof.to_protobuf()).unwrap();
+            assert_eq!(&decoded, proof);
+            assert!(MerkleTree::verify_proof(leaf, &decoded, &tree.root()));
+        }
+
+        let multi = MultiProof::aggregate(&proofs[2..5]).unwrap();
+        let decoded = MultiProof::from_protobuf(&multi.to_protobuf()).unwrap();
+        assert_eq!(decoded, multi);
+        assert!(decoded.verify(&data[2..5], &tree.root()));
+
+        let head = SignedTreeHead {
+            tree_size: 7,
+            timestamp: 1_700_000_000,
+            key_id: b"log-key".to_vec(),
+            root: tree.root(),
+            signature: vec![9; 64],
+        };
+        assert_eq!(SignedTreeHead::from_protobuf(&head.to_protobuf()), Some(head));
+    }
+
+    #[test]
+    fn test_protobuf_rejects_invalid_values() {
+        let unknown_side = pb::Proof { steps: vec![pb::ProofStep { side: 7, hash: vec![0; 32] }], position: None };
+        assert!(Proof::from_protobuf(&unknown_side.encode_to_vec()).is_none());
+        let descending = pb::MultiProof { leaf_count: 8, indices: vec![3, 1], hashes: vec![] };
+        assert!(MultiProof::from_protobuf(&descending.encode_to_vec()).is_none());
+        let past_end = pb::MultiProof { leaf_count: 4, indices: vec![4], hashes: vec![] };
+        assert!(MultiProof::from_protobuf(&past_end.encode_to_vec()).is_none());
+        assert!(Proof::from_protobuf(&[0xff]).is_none());
+        assert!(SCHEMA.contains("message TreeHead"));
+    }
+}
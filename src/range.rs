@@ -0,0 +1,133 @@
+// Proofs for a contiguous run of leaves. Only the siblings just outside the
+// run's two edges are included; everything between them is rebuilt from the
+// leaves themselves. The proof fixes where the run starts and how many leaves
+// the tree has, so it only verifies for those leaves at those positions, in
+// that order, with nothing missing in between.
+
+use crate::ct::hashes_equal;
+use crate::{Data, Hash, Hasher, MerkleTree, Sha256Hasher};
+use std::ops::Range;
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RangeProof {
+    start: usize,
+    leaf_count: usize,
+    // Per level, bottom up: the left edge sibling if needed, then the right one
+    hashes: Vec<Hash>,
+}
+
+
+impl RangeProof {
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+
+    pub fn leaf_count(&self) -> usize {
+        self.leaf_count
+    }
+
+
+    // Root implied by the proof for `leaves` placed from `start` on, or None
+    // if the proof doesn't fit that many leaves
+    pub fn compute_root_with<H: Hasher>(&self, leaves: &[Data], hasher: &H) -> Option<Hash> {
+        let (mut lo, mut hi) = (self.start, self.start.checked_add(leaves.len())?);
+        if leaves.is_empty() || hi > self.leaf_count {
+            return None;
+        }
+        let mut nodes: Vec<Hash> = leaves.iter().map(|leaf| hasher.hash_leaf(leaf)).collect();
+        let mut siblings = self.hashes.iter();
+        let mut width = self.leaf_count;
+        while width > 1 {
+            if lo % 2 == 1 {
+                nodes.insert(0, siblings.next()?.clone());
+                lo -= 1;
+            }
+            if hi % 2 == 1 && hi < width {
+                nodes.push(siblings.next()?.clone());
+                hi += 1;
+            }
+            nodes = crate::reduce_level(&nodes, hasher);
+            (lo, hi, width) = (lo / 2, hi.div_ceil(2), width.div_ceil(2));
+        }
+        // Unused siblings mean the proof was made for a different range
+        siblings.next().is_none().then(|| nodes.pop()).flatten()
+    }
+}
+
+
+impl MerkleTree {
+    pub fn verify_range(leaves: &[Data], proof: &RangeProof, root_hash: &Hash) -> bool {
+        Self::verify_range_with(leaves, proof, root_hash, &Sha256Hasher)
+    }
+}
+
+
+impl<H: Hasher> MerkleTree<H> {
+    // Proves the leaves in `range` together. None if the range is empty, out
+    // of bounds, or the tree doesn't keep its leaves.
+    pub fn prove_range(&self, range: Range<usize>) -> Option<RangeProof> {
+        if range.is_empty() || range.end > self.leaf_count {
+            return None;
+        }
+        let levels = self.levels()?;
+        let mut hashes = Vec::new();
+        let (mut lo, mut hi) = (range.start, range.end);
+        for level in &levels[..levels.len() - 1] {
+            if lo % 2 == 1 {
+                hashes.push(level[lo - 1].clone());
+            }
+            if hi % 2 == 1 && hi < level.len() {
+                hashes.push(level[hi].clone());
+            }
+            (lo, hi) = (lo / 2, hi.div_ceil(2));
+        }
+        Some(RangeProof { start: range.start, leaf_count: self.leaf_count, hashes })
+    }
+
+
+    pub fn verify_range_with(leaves: &[Data], proof: &RangeProof, root_hash: &Hash, hasher: &H) -> bool {
+        proof.compute_root_with(leaves, hasher).is_some_and(|root| hashes_equal(&root, root_hash))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    #[test]
+    fn test_range_proofs() {
+        for n in 1..=12 {
+            let data: Vec<Data> = (0..n as u8).map(|i| vec![i]).collect();
+            let tree = MerkleTree::construct(&data);
+            let root = tree.root();
+            for start in 0..n {
+                for end in start + 1..=n {
+                    let proof = tree.prove_range(start..end).unwrap();
+                    assert!(MerkleTree::verify_range(&data[start..end], &proof, &root));
+                }
+            }
+            assert!(tree.prove_range(0..n + 1).is_none());
+        }
+    }
+
+    #[test]
+    fn test_range_proof_rejects_reordered_or_shifted_leaves() {
+        let data: Vec<Data> = (0..10u8).map(|i| vec![i]).collect();
+        let tree = MerkleTree::construct(&data);
+        let root = tree.root();
+        let proof = tree.prove_range(3..7).unwrap();
+
+        let mut swapped = data[3..7].to_vec();
+        swapped.swap(1, 2);
+        assert!(!MerkleTree::verify_range(&swapped, &proof, &root));
+        assert!(!MerkleTree::verify_range(&data[4..8], &proof, &root));
+        // Dropping a leaf from the middle changes which siblings are needed
+        let gapped = [data[3].clone(), data[5].clone(), data[6].clone()];
+        assert!(!MerkleTree::verify_range(&gapped, &proof, &root));
+        assert!(!MerkleTree::verify_range(&data[3..6], &proof, &root));
+    }
+}
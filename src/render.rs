@@ -0,0 +1,167 @@
+// Text renderings of a tree for debugging, mostly for comparing against
+// another implementation that disagrees on the root. Nodes are labelled
+// `[level:index]` with the first bytes of their hash in hex. When a leaf
+// is given, the nodes on its path to the root and the siblings its proof
+// carries are marked, so a bad proof can be traced level by level. Levels
+// the storage mode dropped are left out.
+
+use crate::{Hash, Hasher, MerkleTree};
+use std::fmt::Write;
+
+
+// Bytes of each hash shown in labels
+pub const SHORT_HASH_BYTES: usize = 4;
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Role {
+    Path,
+    Sibling,
+    Other,
+}
+
+
+fn short_hash(hash: &Hash) -> String {
+    hex::encode(&hash[..hash.len().min(SHORT_HASH_BYTES)])
+}
+
+
+impl<H: Hasher> MerkleTree<H> {
+    // A Graphviz digraph of the tree, root at the top
+    pub fn to_dot(&self) -> String {
+        self.dot(None)
+    }
+
+
+    // Like `to_dot`, filling in the path from leaf `index` to the root and
+    // the siblings its proof carries
+    pub fn to_dot_with_path(&self, index: usize) -> String {
+        self.dot(Some(index))
+    }
+
+
+    // An indented tree, root first, one node per line
+    pub fn to_ascii(&self) -> String {
+        self.ascii(None)
+    }
+
+
+    // Like `to_ascii`, marking path nodes with `*` and proof siblings with `~`
+    pub fn to_ascii_with_path(&self, index: usize) -> String {
+        self.ascii(Some(index))
+    }
+
+
+    fn role(&self, level: usize, index: usize, path: Option<usize>) -> Role {
+        match path {
+            Some(leaf) if leaf >> level == index => Role::Path,
+            Some(leaf) if level < self.depth() && (leaf >> level) ^ 1 == index => Role::Sibling,
+            _ => Role::Other,
+        }
+    }
+
+
+    fn dot(&self, path: Option<usize>) -> String {
+        let path = path.filter(|&index| index < self.leaf_count);
+        let mut out = String::from("digraph merkle {\n    node [shape=box, fontname=monospace];\n");
+        for level in (0..=self.depth()).rev() {
+            let Some(hashes) = self.level(level) else {
+                continue;
+            };
+            for (index, hash) in hashes.iter().enumerate() {
+                let style = match self.role(level, index, path) {
+                    Role::Path => ", style=filled, fillcolor=lightblue",
+                    Role::Sibling => ", style=filled, fillcolor=khaki",
+                    Role::Other => "",
+                };
+                let _ = writeln!(out, "    n{}_{} [label=\"[{}:{}]\\n{}\"{}];", level, index, level, index, short_hash(hash), style);
+            }
+            // Edges only where both ends were kept
+            if let Some(below) = level.checked_sub(1).and_then(|below| self.level(below)) {
+                for index in 0..hashes.len() {
+                    for child in [2 * index, 2 * index + 1].into_iter().filter(|&child| child < below.len()) {
+                        let _ = writeln!(out, "    n{}_{} -> n{}_{};", level, index, level - 1, child);
+                    }
+                }
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+
+
+    fn ascii(&self, path: Option<usize>) -> String {
+        let path = path.filter(|&index| index < self.leaf_count);
+        let mut out = String::new();
+        if self.leaf_count > 0 {
+            self.ascii_node(&mut out, self.depth(), 0, "", "", path);
+        }
+        out
+    }
+
+
+    fn ascii_node(&self, out: &mut String, level: usize, index: usize, lead: &str, indent: &str, path: Option<usize>) {
+        let Some(hash) = self.level(level).and_then(|hashes| hashes.get(index)) else {
+            return;
+        };
+        let mark = match self.role(level, index, path) {
+            Role::Path => " *",
+            Role::Sibling => " ~",
+            Role::Other => "",
+        };
+        let _ = writeln!(out, "{}[{}:{}] {}{}", lead, level, index, short_hash(hash), mark);
+        if level == 0 {
+            return;
+        }
+        let below = self.level(level - 1).map_or(0, <[Hash]>::len);
+        let children: Vec<usize> = [2 * index, 2 * index + 1].into_iter().filter(|&child| child < below).collect();
+        for (i, &child) in children.iter().enumerate() {
+            let (branch, extend) = if i + 1 == children.len() { ("`-- ", "    ") } else { ("+-- ", "|   ") };
+            let lead = format!("{}{}", indent, branch);
+            let indent = format!("{}{}", indent, extend);
+            self.ascii_node(out, level - 1, child, &lead, &indent, path);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Data, StorageMode};
+
+
+    #[test]
+    fn test_ascii() {
+        let data: Vec<Data> = (0..3u8).map(|i| vec![i]).collect();
+        let tree = MerkleTree::construct(&data);
+        let label = |level: usize, index: usize| short_hash(&tree.level(level).unwrap()[index]);
+        let expected = format!(
+            "[2:0] {} *\n+-- [1:0] {} *\n|   +-- [0:0] {} ~\n|   `-- [0:1] {} *\n`-- [1:1] {} ~\n    `-- [0:2] {}\n",
+            label(2, 0), label(1, 0), label(0, 0), label(0, 1), label(1, 1), label(0, 2),
+        );
+        assert_eq!(tree.to_ascii_with_path(1), expected);
+        assert_eq!(tree.to_ascii(), expected.replace(" *", "").replace(" ~", ""));
+        assert_eq!(MerkleTree::construct(&Vec::<Data>::new()).to_ascii(), "");
+    }
+
+    #[test]
+    fn test_dot() {
+        let data: Vec<Data> = (0..5u8).map(|i| vec![i]).collect();
+        let tree = MerkleTree::construct(&data);
+        let dot = tree.to_dot_with_path(4);
+        assert!(dot.starts_with("digraph merkle {\n"));
+        assert_eq!(dot.matches(" -> ").count(), 5 + 3 + 2);
+        assert!(dot.contains("n3_0 -> n2_1;"));
+        assert!(dot.contains("n2_1 [label=\"[2:1]\\n") && dot.contains("n2_0 [label"));
+        assert_eq!(dot.matches("lightblue").count(), 4);
+        assert_eq!(dot.matches("khaki").count(), 1);
+        assert!(!tree.to_dot().contains("filled"));
+
+        // Only the leaves and root survive, with no edges between them
+        let leaves_only = MerkleTree::construct_with_mode(&data, crate::Sha256Hasher, StorageMode::LeavesOnly);
+        let dot = leaves_only.to_dot();
+        assert_eq!(dot.matches("[label").count(), 6);
+        assert!(!dot.contains(" -> "));
+    }
+}
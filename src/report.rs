@@ -0,0 +1,186 @@
+// A record of the construction decisions that affect a tree's root, so a
+// verifier holding the same leaves can reproduce the exact build.
+
+use crate::{Hasher, MerkleTree};
+use std::fmt;
+use std::str::FromStr;
+
+
+// What happens to the last node of a level with an odd number of nodes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OddPolicy {
+    // Carried up to the next level unchanged
+    Promote,
+}
+
+
+// How the leaf level is filled out before reduction
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Padding {
+    None,
+}
+
+
+// How leaves are ordered before hashing
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LeafOrdering {
+    // Exactly the order given to `construct`
+    Input,
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LevelReport {
+    pub width: usize,
+    // Index of the node the odd policy applied to, if any
+    pub odd_node: Option<usize>,
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BuildReport {
+    pub leaf_count: usize,
+    pub odd_policy: OddPolicy,
+    pub padding: Padding,
+    pub ordering: LeafOrdering,
+    // What did the hashing, as `Hasher::backend` names it
+    pub hasher: String,
+    // False for roots built without the leaf and node prefixes
+    pub domain_separated: bool,
+    // Every level below the root, leaves first
+    pub levels: Vec<LevelReport>,
+}
+
+
+impl<H: Hasher> MerkleTree<H> {
+    // Describes the decisions taken while building this tree
+    pub fn build_report(&self) -> BuildReport {
+        // Level widths follow from the leaf count, whatever the storage mode kept
+        let mut levels = Vec::new();
+        let mut width = self.leaf_count;
+        while width > 1 {
+            levels.push(LevelReport {
+                width,
+                odd_node: (width % 2 == 1).then(|| width - 1),
+            });
+            width = width.div_ceil(2);
+        }
+        BuildReport {
+            leaf_count: self.leaf_count,
+            odd_policy: OddPolicy::Promote,
+            padding: Padding::None,
+            ordering: LeafOrdering::Input,
+            hasher: self.hasher.backend().to_string(),
+            domain_separated: self.hasher.domain_separated(),
+            levels,
+        }
+    }
+}
+
+
+// One `key=value` line per field, then one line per level, so the report
+// can be stored as text next to the root
+impl fmt::Display for BuildReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "leaves={}", self.leaf_count)?;
+        writeln!(f, "odd=promote")?;
+        writeln!(f, "padding=none")?;
+        writeln!(f, "ordering=input")?;
+        writeln!(f, "hasher={}", self.hasher)?;
+        writeln!(f, "domain_separated={}", self.domain_separated)?;
+        for (i, level) in self.levels.iter().enumerate() {
+            match level.odd_node {
+                Some(node) => writeln!(f, "level={} width={} odd={}", i, level.width, node)?,
+                None => writeln!(f, "level={} width={}", i, level.width)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseReportError(pub String);
+
+
+impl FromStr for BuildReport {
+    type Err = ParseReportError;
+
+    fn from_str(s: &str) -> Result<BuildReport, ParseReportError> {
+        let err = |line: &str| ParseReportError(format!("unexpected line: {}", line));
+        let mut lines = s.lines();
+        let mut field = |name: &str| -> Result<String, ParseReportError> {
+            let line = lines.next().unwrap_or_default();
+            match line.split_once('=') {
+                Some((key, value)) if key == name => Ok(value.to_string()),
+                _ => Err(err(line)),
+            }
+        };
+        let leaf_count = field("leaves")?.parse().map_err(|_| err("leaves"))?;
+        let odd_policy = match field("odd")?.as_str() {
+            "promote" => OddPolicy::Promote,
+            other => return Err(err(other)),
+        };
+        let padding = match field("padding")?.as_str() {
+            "none" => Padding::None,
+            other => return Err(err(other)),
+        };
+        let ordering = match field("ordering")?.as_str() {
+            "input" => LeafOrdering::Input,
+            other => return Err(err(other)),
+        };
+        let hasher = field("hasher")?;
+        let domain_separated = field("domain_separated")?.parse().map_err(|_| err("domain_separated"))?;
+
+        let mut levels = Vec::new();
+        for line in lines {
+            let mut width = None;
+            let mut odd_node = None;
+            for part in line.split_whitespace() {
+                match part.split_once('=') {
+                    Some(("level", _)) => {}
+                    Some(("width", value)) => width = value.parse().ok(),
+                    Some(("odd", value)) => odd_node = Some(value.parse().map_err(|_| err(line))?),
+                    _ => return Err(err(line)),
+                }
+            }
+            levels.push(LevelReport { width: width.ok_or_else(|| err(line))?, odd_node });
+        }
+
+        Ok(BuildReport { leaf_count, odd_policy, padding, ordering, hasher, domain_separated, levels })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Data, Legacy, Sha256Hasher};
+
+
+    #[test]
+    fn test_build_report() {
+        let data: Vec<Data> = (0..5u8).map(|i| vec![i]).collect();
+        let report = MerkleTree::construct(&data).build_report();
+        let widths: Vec<(usize, Option<usize>)> = report.levels.iter().map(|l| (l.width, l.odd_node)).collect();
+        assert_eq!(widths, vec![(5, Some(4)), (3, Some(2)), (2, None)]);
+
+        let text = report.to_string();
+        assert_eq!(text.parse::<BuildReport>(), Ok(report));
+        assert!("leaves=5\nodd=duplicate\n".parse::<BuildReport>().is_err());
+    }
+
+
+    #[test]
+    fn test_build_report_hasher() {
+        let data: Vec<Data> = (0..3u8).map(|i| vec![i]).collect();
+        let report = MerkleTree::construct(&data).build_report();
+        assert_eq!(report.hasher, Sha256Hasher.backend());
+        assert!(report.domain_separated);
+
+        let legacy = MerkleTree::construct_with(&data, Legacy(Sha256Hasher)).build_report();
+        assert!(!legacy.domain_separated);
+        assert_eq!(legacy.to_string().parse::<BuildReport>(), Ok(legacy.clone()));
+        assert_ne!(legacy, report);
+    }
+}
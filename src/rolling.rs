@@ -0,0 +1,254 @@
+// A tree over the most recent leaves of a log, for commitments to sliding
+// windows such as the last day of events. Leaves sit in a ring of slots,
+// leaf `seq` in slot seq % capacity, with the capacity the window rounded
+// up to a power of two so the shape never changes. Appending or expiring a
+// leaf rewrites one slot and rehashes its path, O(log n) either way. Empty
+// slots hold hash(0x03), which no leaf hashed under the 0x00 prefix can
+// equal, so an empty slot can't be proven as a leaf of no bytes.
+//
+// The root also commits to the window: it's the root of the slots followed
+// by one more leaf holding the first sequence number and the length, both
+// as big-endian u64s. The same slots seen at different points of the log
+// then have different roots, and a proof is an ordinary proof at the
+// leaf's slot in a tree of capacity + 1 leaves.
+
+use crate::{path_proof, Hash, HashDirection, Hashable, Hasher, MerkleTree, Proof, Sha256Hasher};
+use std::borrow::Cow;
+
+
+pub const EMPTY_SLOT_PREFIX: u8 = 0x03;
+
+
+pub struct RollingTree<H = Sha256Hasher> {
+    window: usize,
+    // Every level of the full tree over the slots, leaves first
+    nodes: Vec<Vec<Hash>>,
+    // Sequence number of the oldest leaf still in the window
+    first: u64,
+    len: usize,
+    hasher: H,
+}
+
+
+impl RollingTree {
+    pub fn new(window: usize) -> RollingTree {
+        Self::with_hasher(window, Sha256Hasher)
+    }
+}
+
+
+impl<H: Hasher> RollingTree<H> {
+    pub fn with_hasher(window: usize, hasher: H) -> RollingTree<H> {
+        assert!(window > 0, "window must hold at least one leaf");
+        let capacity = window.next_power_of_two();
+        let depth = capacity.trailing_zeros() as usize;
+        let mut empty = hasher.hash(&[EMPTY_SLOT_PREFIX]);
+        let mut nodes = Vec::with_capacity(depth + 1);
+        for level in 0..=depth {
+            nodes.push(vec![empty.clone(); capacity >> level]);
+            empty = hasher.hash_node(&empty, &empty);
+        }
+        RollingTree { window, nodes, first: 0, len: 0, hasher }
+    }
+
+
+    // The root over the slots and the window
+    pub fn root(&self) -> Hash {
+        self.hasher.hash_node(self.slots_root(), &self.window_leaf())
+    }
+
+
+    // The root over the slots alone
+    pub fn slots_root(&self) -> &Hash {
+        &self.nodes.last().unwrap()[0]
+    }
+
+
+    // The leaf data committing to the window, last in the tree
+    pub fn window_bytes(&self) -> Vec<u8> {
+        [self.first.to_be_bytes(), (self.len as u64).to_be_bytes()].concat()
+    }
+
+
+    pub fn window(&self) -> usize {
+        self.window
+    }
+
+
+    // Slots in the tree, and so the tree size proofs claim
+    pub fn capacity(&self) -> usize {
+        self.nodes[0].len()
+    }
+
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+
+    // Sequence numbers of the leaves in the window
+    pub fn range(&self) -> std::ops::Range<u64> {
+        self.first..self.first + self.len as u64
+    }
+
+
+    // Appends a leaf, expiring the oldest first if the window is full, and
+    // returns the new leaf's sequence number
+    pub fn push<T: Hashable + ?Sized>(&mut self, data: &T) -> u64 {
+        if self.len == self.window {
+            self.expire();
+        }
+        let seq = self.first + self.len as u64;
+        let leaf = self.hasher.hash_leaf(&data.leaf_bytes());
+        self.set_slot(self.slot(seq), leaf);
+        self.len += 1;
+        seq
+    }
+
+
+    // Drops the oldest leaf, returning its sequence number
+    pub fn expire(&mut self) -> Option<u64> {
+        if self.len == 0 {
+            return None;
+        }
+        let seq = self.first;
+        let empty = self.hasher.hash(&[EMPTY_SLOT_PREFIX]);
+        self.set_slot(self.slot(seq), empty);
+        self.first += 1;
+        self.len -= 1;
+        Some(seq)
+    }
+
+
+    // Proves the leaf `seq`, claiming its slot as the position, with the
+    // window leaf as the last sibling. None once it has expired or before
+    // it's appended.
+    pub fn prove(&self, seq: u64) -> Option<Proof<'_>> {
+        if !self.range().contains(&seq) {
+            return None;
+        }
+        let slot = self.slot(seq);
+        let mut proof = path_proof(&self.nodes, slot);
+        proof.hashes.push((HashDirection::Right, Cow::Owned(self.window_leaf())));
+        Some(proof.with_position(slot, self.capacity() + 1))
+    }
+
+
+    // A tree of the slots and the window leaf as they are now
+    pub fn snapshot(&self) -> MerkleTree<H>
+    where
+        H: Clone,
+    {
+        // The window leaf is the odd one out on every level, so it's
+        // promoted until it pairs with the slots' root
+        let window_leaf = self.window_leaf();
+        let mut levels: Vec<Vec<Hash>> = self
+            .nodes
+            .iter()
+            .map(|level| level.iter().cloned().chain([window_leaf.clone()]).collect())
+            .collect();
+        levels.push(vec![self.root()]);
+        MerkleTree::from_levels(levels, self.hasher.clone())
+    }
+
+
+    fn window_leaf(&self) -> Hash {
+        self.hasher.hash_leaf(&self.window_bytes())
+    }
+
+
+    fn slot(&self, seq: u64) -> usize {
+        (seq % self.capacity() as u64) as usize
+    }
+
+
+    fn set_slot(&mut self, slot: usize, leaf: Hash) {
+        self.nodes[0][slot] = leaf;
+        let mut idx = slot;
+        for level in 1..self.nodes.len() {
+            idx /= 2;
+            let below = &self.nodes[level - 1];
+            let parent = self.hasher.hash_node(&below[2 * idx], &below[2 * idx + 1]);
+            self.nodes[level][idx] = parent;
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Data;
+
+
+    #[test]
+    fn test_rolling_window() {
+        let data: Vec<Data> = (0..10u8).map(|i| vec![i]).collect();
+        let mut tree = RollingTree::new(4);
+        for leaf in &data {
+            tree.push(leaf);
+        }
+        assert_eq!(tree.range(), 6..10);
+        // Slots hold seq % 4: 8, 9, 6, 7, then the window
+        let window = tree.window_bytes();
+        let slots = [&data[8], &data[9], &data[6], &data[7], &window];
+        assert_eq!(tree.root(), MerkleTree::construct(&slots).root());
+        assert_eq!(tree.snapshot().root(), tree.root());
+        for seq in tree.range() {
+            let proof = tree.prove(seq).unwrap();
+            assert!(MerkleTree::verify_proof(&data[seq as usize], &proof, &tree.root()));
+        }
+        assert!(tree.prove(5).is_none());
+
+        assert_eq!(tree.expire(), Some(6));
+        assert_eq!(tree.snapshot().root(), tree.root());
+        assert_eq!(tree.len(), 3);
+    }
+
+    #[test]
+    fn test_root_binds_window() {
+        // Both hold a then b in their slots, but at different sequence numbers
+        let mut early = RollingTree::new(2);
+        early.push(b"a");
+        early.push(b"b");
+        let mut late = RollingTree::new(2);
+        for leaf in [b"x", b"y", b"a", b"b"] {
+            late.push(leaf);
+        }
+        assert_eq!(early.slots_root(), late.slots_root());
+        assert_ne!(early.root(), late.root());
+        let proof = early.prove(0).unwrap();
+        assert!(MerkleTree::verify_proof(b"a", &proof, &early.root()));
+        assert!(!MerkleTree::verify_proof(b"a", &proof, &late.root()));
+    }
+
+    #[test]
+    fn test_empty_slot_is_not_a_leaf() {
+        let mut tree = RollingTree::new(2);
+        tree.push(b"a");
+        assert_ne!(tree.nodes[0][1], Sha256Hasher.hash_leaf(&[]));
+
+        // A proof that slot 1 holds no bytes can't be made to fit
+        let mut forged = path_proof(&tree.nodes, 1);
+        forged.hashes.push((HashDirection::Right, Cow::Owned(tree.window_leaf())));
+        assert!(!MerkleTree::verify_proof(&Vec::<u8>::new(), &forged.with_position(1, 3), &tree.root()));
+    }
+
+    #[test]
+    fn test_window_not_power_of_two() {
+        let mut tree = RollingTree::new(3);
+        for i in 0..7u8 {
+            tree.push(&[i]);
+        }
+        assert_eq!((tree.range(), tree.capacity()), (4..7, 4));
+        while tree.expire().is_some() {}
+        // Empty slots again, but seven leaves further along the log
+        assert_eq!(tree.slots_root(), RollingTree::new(3).slots_root());
+        assert_ne!(tree.root(), RollingTree::new(3).root());
+    }
+}
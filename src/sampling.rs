@@ -0,0 +1,143 @@
+// Sampling for storage audits: an auditor asks for proofs of a few leaves
+// picked at random, and a holder missing a fraction f of the data fails
+// with probability 1 - (1 - f)^k for k samples. Picks come from SHA-256
+// over the root, the seed and a counter, so both sides derive the same
+// ones, and the holder can't know them until the seed is revealed.
+
+use crate::ct::hashes_equal;
+use crate::{Data, Hash, Hashable, Hasher, MerkleTree, Proof, Sha256Hasher};
+use sha2::Digest;
+use std::collections::HashSet;
+
+
+// `k` distinct leaf indices below `tree_size`, or all of them when k is
+// at least the tree size, in the order they were picked
+pub fn sample_indices(root: &Hash, tree_size: usize, seed: &[u8], k: usize) -> Vec<usize> {
+    let k = k.min(tree_size);
+    let mut picked = Vec::with_capacity(k);
+    let mut seen = HashSet::with_capacity(k);
+    // Draws past the largest multiple of tree_size are redrawn, so every
+    // index is equally likely
+    let limit = u64::MAX - u64::MAX % tree_size.max(1) as u64;
+    let mut counter = 0u64;
+    while picked.len() < k {
+        let mut hasher = sha2::Sha256::new();
+        hasher.update(root);
+        hasher.update((seed.len() as u64).to_be_bytes());
+        hasher.update(seed);
+        hasher.update(counter.to_be_bytes());
+        let draw = u64::from_be_bytes(hasher.finalize()[..8].try_into().unwrap());
+        counter += 1;
+        if draw >= limit {
+            continue;
+        }
+        let index = (draw % tree_size as u64) as usize;
+        if seen.insert(index) {
+            picked.push(index);
+        }
+    }
+    picked
+}
+
+
+// The chance that `k` samples catch a holder missing `missing_fraction` of
+// the leaves, for picks made with replacement (distinct picks do better)
+pub fn detection_probability(missing_fraction: f64, k: usize) -> f64 {
+    1.0 - (1.0 - missing_fraction.clamp(0.0, 1.0)).powi(k as i32)
+}
+
+
+// An audit of a tree with a trusted root and size
+#[derive(Debug, Clone, PartialEq)]
+pub struct SampleChallenge {
+    pub root: Hash,
+    pub tree_size: usize,
+    pub seed: Vec<u8>,
+    pub k: usize,
+}
+
+
+impl SampleChallenge {
+    pub fn indices(&self) -> Vec<usize> {
+        sample_indices(&self.root, self.tree_size, &self.seed, self.k)
+    }
+
+
+    pub fn verify<T: Hashable>(&self, responses: &[(T, Proof)]) -> bool {
+        self.verify_with(responses, &Sha256Hasher)
+    }
+
+
+    // True if there is one response per sampled index, in `indices` order,
+    // each proving its leaf at that position under the root
+    pub fn verify_with<T: Hashable, H: Hasher>(&self, responses: &[(T, Proof)], hasher: &H) -> bool {
+        let indices = self.indices();
+        responses.len() == indices.len()
+            && indices.iter().zip(responses).all(|(&index, (data, proof))| {
+                proof.position().is_some_and(|p| p.index == index && p.tree_size == self.tree_size)
+                    && MerkleTree::verify_proof_strict_with(data, proof, &self.root, hasher).is_ok()
+            })
+    }
+}
+
+
+impl<H: Hasher> MerkleTree<H> {
+    // Answers a challenge for this tree, given the leaf data. None if the
+    // challenge is for another tree.
+    pub fn respond_to_samples<'a>(&'a self, challenge: &SampleChallenge, data: &[Data]) -> Option<Vec<(Data, Proof<'a>)>> {
+        if challenge.tree_size != self.leaf_count || !hashes_equal(&challenge.root, &self.root()) {
+            return None;
+        }
+        challenge
+            .indices()
+            .into_iter()
+            .map(|index| {
+                let proof = self.prove_index(index)?.with_position(index, self.leaf_count);
+                Some((data.get(index)?.clone(), proof))
+            })
+            .collect()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    #[test]
+    fn test_sample_indices() {
+        let root = vec![7; 32];
+        let picked = sample_indices(&root, 1000, b"audit-1", 20);
+        assert_eq!(picked.len(), 20);
+        assert!(picked.iter().all(|&i| i < 1000));
+        assert_eq!(picked, sample_indices(&root, 1000, b"audit-1", 20));
+        assert_ne!(picked, sample_indices(&root, 1000, b"audit-2", 20));
+        let mut all = sample_indices(&root, 5, b"seed", 9);
+        all.sort();
+        assert_eq!(all, vec![0, 1, 2, 3, 4]);
+        assert!(sample_indices(&root, 0, b"seed", 3).is_empty());
+        assert_eq!(sample_indices(&root, 4000, b"seed", usize::MAX).len(), 4000);
+        assert!((detection_probability(0.1, 30) - 0.9576).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_sample_audit() {
+        let data: Vec<Data> = (0..50u8).map(|i| vec![i; 3]).collect();
+        let tree = MerkleTree::construct(&data);
+        let challenge = SampleChallenge { root: tree.root(), tree_size: 50, seed: b"round 1".to_vec(), k: 8 };
+        let responses = tree.respond_to_samples(&challenge, &data).unwrap();
+        assert!(challenge.verify(&responses));
+
+        // A holder that lost one of the sampled leaves can't answer for it
+        let mut lost = responses.clone();
+        lost[2].0 = vec![0; 3];
+        assert!(!challenge.verify(&lost));
+        // Nor answer with other leaves it still has
+        let other = (0..50).find(|i| !challenge.indices().contains(i)).unwrap();
+        let mut substituted = responses.clone();
+        substituted[0] = (data[other].clone(), tree.prove_index(other).unwrap().with_position(other, 50));
+        assert!(!challenge.verify(&substituted));
+        assert!(!challenge.verify(&responses[1..]));
+    }
+}
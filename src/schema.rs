@@ -0,0 +1,154 @@
+// Leaves whose encoding changes over time. Each leaf carries the version of
+// the encoding its payload uses, and the version is hashed with the payload,
+// so old leaves keep proving under their original encoding while a registry
+// of codecs reads any version and re-encodes to the latest one.
+
+use crate::{Data, Hash, Hashable, Hasher, MerkleTree, Proof};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VersionedLeaf {
+    pub version: u16,
+    pub payload: Data,
+}
+
+
+// Hashed as version: u16 | payload
+impl Hashable for VersionedLeaf {
+    fn leaf_bytes(&self) -> Cow<'_, [u8]> {
+        let mut out = Vec::with_capacity(2 + self.payload.len());
+        out.extend_from_slice(&self.version.to_be_bytes());
+        out.extend_from_slice(&self.payload);
+        Cow::Owned(out)
+    }
+}
+
+
+// A proof for a versioned leaf, carrying the version the payload was encoded with
+#[derive(Debug)]
+pub struct VersionedProof<'a> {
+    pub version: u16,
+    pub proof: Proof<'a>,
+}
+
+
+impl VersionedProof<'_> {
+    pub fn verify_with<H: Hasher>(&self, payload: &Data, root_hash: &Hash, hasher: &H) -> bool {
+        let leaf = VersionedLeaf { version: self.version, payload: payload.clone() };
+        MerkleTree::verify_proof_with(&leaf, &self.proof, root_hash, hasher)
+    }
+}
+
+
+impl<H: Hasher> MerkleTree<H> {
+    pub fn prove_versioned(&self, leaf: &VersionedLeaf) -> Option<VersionedProof<'_>> {
+        Some(VersionedProof { version: leaf.version, proof: self.prove(leaf)? })
+    }
+}
+
+
+struct Codec<T> {
+    encode: fn(&T) -> Data,
+    decode: fn(&[u8]) -> Option<T>,
+}
+
+
+// Encoder/decoder pairs by version. New leaves use the highest version.
+pub struct SchemaRegistry<T> {
+    codecs: BTreeMap<u16, Codec<T>>,
+}
+
+
+impl<T> Default for SchemaRegistry<T> {
+    fn default() -> SchemaRegistry<T> {
+        SchemaRegistry { codecs: BTreeMap::new() }
+    }
+}
+
+
+impl<T> SchemaRegistry<T> {
+    pub fn new() -> SchemaRegistry<T> {
+        SchemaRegistry::default()
+    }
+
+
+    // Adds or replaces the codec for `version`
+    pub fn register(&mut self, version: u16, encode: fn(&T) -> Data, decode: fn(&[u8]) -> Option<T>) {
+        self.codecs.insert(version, Codec { encode, decode });
+    }
+
+
+    pub fn latest(&self) -> Option<u16> {
+        self.codecs.keys().next_back().copied()
+    }
+
+
+    // Encodes with the latest version. None if nothing is registered.
+    pub fn encode(&self, value: &T) -> Option<VersionedLeaf> {
+        let (&version, codec) = self.codecs.iter().next_back()?;
+        Some(VersionedLeaf { version, payload: (codec.encode)(value) })
+    }
+
+
+    // Decodes with the codec for the leaf's version
+    pub fn decode(&self, leaf: &VersionedLeaf) -> Option<T> {
+        (self.codecs.get(&leaf.version)?.decode)(&leaf.payload)
+    }
+
+
+    // Decodes an old leaf and encodes it again with the latest version
+    pub fn reencode(&self, leaf: &VersionedLeaf) -> Option<VersionedLeaf> {
+        self.encode(&self.decode(leaf)?)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Sha256Hasher;
+
+
+    #[derive(Debug, PartialEq)]
+    struct Record {
+        id: u32,
+        note: String,
+    }
+
+
+    fn registry() -> SchemaRegistry<Record> {
+        let mut registry = SchemaRegistry::new();
+        // v1 had only the id
+        registry.register(1, |r: &Record| r.id.to_be_bytes().to_vec(), |b| {
+            Some(Record { id: u32::from_be_bytes(b.try_into().ok()?), note: String::new() })
+        });
+        registry.register(2, |r: &Record| [&r.id.to_be_bytes()[..], r.note.as_bytes()].concat(), |b| {
+            let (id, note) = b.split_at_checked(4)?;
+            Some(Record { id: u32::from_be_bytes(id.try_into().ok()?), note: String::from_utf8(note.to_vec()).ok()? })
+        });
+        registry
+    }
+
+    #[test]
+    fn test_versioned_leaves() {
+        let registry = registry();
+        let old = VersionedLeaf { version: 1, payload: 7u32.to_be_bytes().to_vec() };
+        let new = registry.encode(&Record { id: 8, note: "hi".into() }).unwrap();
+        assert_eq!(new.version, 2);
+
+        let tree = MerkleTree::construct(&[old.clone(), new.clone()]);
+        let proof = tree.prove_versioned(&old).unwrap();
+        assert_eq!(proof.version, 1);
+        assert!(proof.verify_with(&old.payload, &tree.root(), &Sha256Hasher));
+        // The same payload under another version is a different leaf
+        let relabeled = VersionedProof { version: 2, proof: tree.prove(&old).unwrap() };
+        assert!(!relabeled.verify_with(&old.payload, &tree.root(), &Sha256Hasher));
+
+        assert_eq!(registry.decode(&old), Some(Record { id: 7, note: String::new() }));
+        let upgraded = registry.reencode(&old).unwrap();
+        assert_eq!(upgraded.version, 2);
+        assert_eq!(registry.decode(&upgraded), Some(Record { id: 7, note: String::new() }));
+    }
+}
@@ -0,0 +1,308 @@
+// A finalized, read-only tree laid out so it can be used straight from the
+// bytes it was written as. `open` looks only at the fixed-size header; nodes
+// and the leaf index are read in place when a proof asks for them, so open
+// time doesn't grow with the tree.
+//
+//   header:  magic "MTSS" | version: u8 | hash_len: u8 | leaf count: u64 |
+//            level count: u32 | hasher id with a u8 length prefix |
+//            offset of each level: u64 | index offset: u64 |
+//            SHA-256 of the header so far
+//   nodes:   every level, leaves first, hash_len bytes per node
+//   index:   (leaf hash, leaf index: u64) sorted by hash, one per distinct hash
+//   trailer: SHA-256 of the nodes and index, checked by `verify`
+//
+// The hasher id is `hasher_id` of the writing tree's hasher, as checkpoints
+// store it, so a snapshot opened with another hasher is refused rather
+// than giving proofs that don't verify.
+
+use crate::ct::hashes_equal;
+use crate::invariants::bounded_proof;
+use crate::{hasher_id, Data, Hash, HashDirection, Hasher, MerkleTree, Proof, Sha256Hasher};
+use sha2::Digest;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Write};
+
+
+const MAGIC: &[u8; 4] = b"MTSS";
+const VERSION: u8 = 2;
+const FIXED_HEADER: usize = 4 + 1 + 1 + 8 + 4 + 1;
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SnapshotError(pub &'static str);
+
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid snapshot: {}", self.0)
+    }
+}
+
+
+impl std::error::Error for SnapshotError {}
+
+
+impl<H: Hasher> MerkleTree<H> {
+    // Writes the tree in the snapshot format. Needs every level, so fails
+    // with `Error::NotStored` for root-only trees.
+    pub fn write_snapshot<W: Write>(&self, mut w: W) -> io::Result<()> {
+        let levels = self.levels().ok_or_else(|| io::Error::other(crate::Error::NotStored))?;
+        let hash_len = levels[0].first().map_or(0, Vec::len);
+        let id = hasher_id(&self.hasher);
+        if hash_len > u8::MAX as usize || id.len() > u8::MAX as usize {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "hash too long for a snapshot"));
+        }
+
+        let header_len = FIXED_HEADER + id.len() + 8 * (levels.len() + 1) + 32;
+        let mut header = MAGIC.to_vec();
+        header.extend_from_slice(&[VERSION, hash_len as u8]);
+        header.extend_from_slice(&(self.leaf_count as u64).to_be_bytes());
+        header.extend_from_slice(&(levels.len() as u32).to_be_bytes());
+        header.push(id.len() as u8);
+        header.extend_from_slice(&id);
+        let mut offset = header_len;
+        for level in levels.iter() {
+            header.extend_from_slice(&(offset as u64).to_be_bytes());
+            offset += level.len() * hash_len;
+        }
+        header.extend_from_slice(&(offset as u64).to_be_bytes());
+        let digest = sha2::Sha256::digest(&header);
+        header.extend_from_slice(&digest);
+
+        let mut body = Vec::with_capacity(offset - header_len);
+        for hash in levels.iter().flatten() {
+            body.extend_from_slice(hash);
+        }
+        // Later duplicates win, as in `leaves_idx`
+        let mut index: Vec<(&Hash, usize)> = levels[0]
+            .iter()
+            .enumerate()
+            .map(|(i, hash)| (hash, i))
+            .collect::<HashMap<_, _>>()
+            .into_iter()
+            .collect();
+        index.sort();
+        for (hash, i) in index {
+            body.extend_from_slice(hash);
+            body.extend_from_slice(&(i as u64).to_be_bytes());
+        }
+        let digest = sha2::Sha256::digest(&body);
+
+        w.write_all(&header)?;
+        w.write_all(&body)?;
+        w.write_all(&digest)
+    }
+}
+
+
+pub struct Snapshot<'a, H = Sha256Hasher> {
+    bytes: &'a [u8],
+    hash_len: usize,
+    leaf_count: usize,
+    // Byte offset of each level, then of the leaf index
+    offsets: Vec<usize>,
+    hasher: H,
+}
+
+
+impl<'a> Snapshot<'a> {
+    pub fn open(bytes: &'a [u8]) -> Result<Snapshot<'a>, SnapshotError> {
+        Self::open_with(bytes, Sha256Hasher)
+    }
+}
+
+
+impl<'a, H: Hasher> Snapshot<'a, H> {
+    // Validates the header and its checksum; nothing past it is read
+    pub fn open_with(bytes: &'a [u8], hasher: H) -> Result<Snapshot<'a, H>, SnapshotError> {
+        if bytes.len() < FIXED_HEADER || &bytes[..4] != MAGIC {
+            return Err(SnapshotError("bad magic"));
+        }
+        if bytes[4] != VERSION {
+            return Err(SnapshotError("unsupported version"));
+        }
+        let hash_len = bytes[5] as usize;
+        let too_large = |_| SnapshotError("too large for this platform");
+        let leaf_count = usize::try_from(read_u64(&bytes[6..])).map_err(too_large)?;
+        let level_count = u32::from_be_bytes(bytes[14..18].try_into().unwrap()) as usize;
+        let offsets_start = FIXED_HEADER + bytes[18] as usize;
+        let header_len = level_count
+            .checked_add(1)
+            .and_then(|n| n.checked_mul(8))
+            .and_then(|n| n.checked_add(offsets_start + 32))
+            .filter(|&len| len <= bytes.len())
+            .ok_or(SnapshotError("truncated header"))?;
+        let (header, digest) = bytes[..header_len].split_at(header_len - 32);
+        if !hashes_equal(&sha2::Sha256::digest(header), digest) {
+            return Err(SnapshotError("header checksum mismatch"));
+        }
+        if !hashes_equal(&header[FIXED_HEADER..offsets_start], &hasher_id(&hasher)) {
+            return Err(SnapshotError("written with a different hasher"));
+        }
+
+        let offsets = header[offsets_start..]
+            .chunks(8)
+            .map(|o| usize::try_from(read_u64(o)).map_err(too_large))
+            .collect::<Result<Vec<usize>, _>>()?;
+        // Offsets must run in order from the end of the header to the trailer
+        if offsets[0] != header_len
+            || offsets.windows(2).any(|pair| pair[0] > pair[1])
+            || offsets.last().unwrap().saturating_add(32) > bytes.len()
+        {
+            return Err(SnapshotError("bad offsets"));
+        }
+        if level_count == 0 || (hash_len == 0 && leaf_count > 0) || Some(offsets[1] - offsets[0]) != leaf_count.checked_mul(hash_len) {
+            return Err(SnapshotError("leaf level doesn't match leaf count"));
+        }
+        // One check per level, so still independent of the leaf count
+        let widths: Vec<usize> = offsets.windows(2).map(|pair| (pair[1] - pair[0]) / hash_len.max(1)).collect();
+        if widths[..level_count].windows(2).any(|pair| pair[1] != pair[0].div_ceil(2)) || widths[level_count - 1] > 1 {
+            return Err(SnapshotError("level sizes don't form a tree"));
+        }
+        Ok(Snapshot { bytes, hash_len, leaf_count, offsets, hasher })
+    }
+
+
+    // Checks the trailing checksum over every node and the leaf index.
+    // Reads the whole snapshot, so call it once after writing or copying.
+    pub fn verify(&self) -> bool {
+        let body = &self.bytes[self.offsets[0]..self.bytes.len() - 32];
+        hashes_equal(&sha2::Sha256::digest(body), &self.bytes[self.bytes.len() - 32..])
+    }
+
+
+    pub fn leaf_count(&self) -> usize {
+        self.leaf_count
+    }
+
+
+    pub fn root(&self) -> Option<Hash> {
+        let top = self.offsets.len() - 2;
+        (self.leaf_count > 0).then(|| self.node(top, 0).to_vec())
+    }
+
+
+    // Finds the leaf by binary search over the stored index
+    pub fn index_of(&self, data: &Data) -> Option<usize> {
+        let leaf_hash = self.hasher.hash_leaf(data);
+        let entry_len = self.hash_len + 8;
+        let start = *self.offsets.last().unwrap();
+        let index = &self.bytes[start..self.bytes.len() - 32];
+        let entries = index.len() / entry_len;
+        let (mut lo, mut hi) = (0, entries);
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            let entry = &index[mid * entry_len..(mid + 1) * entry_len];
+            match entry[..self.hash_len].cmp(&leaf_hash[..]) {
+                std::cmp::Ordering::Less => lo = mid + 1,
+                std::cmp::Ordering::Greater => hi = mid,
+                std::cmp::Ordering::Equal => return usize::try_from(read_u64(&entry[self.hash_len..])).ok(),
+            }
+        }
+        None
+    }
+
+
+    pub fn prove(&self, data: &Data) -> Option<Proof<'static>> {
+        self.prove_index(self.index_of(data)?)
+    }
+
+
+    pub fn prove_index(&self, index: usize) -> Option<Proof<'static>> {
+        if index >= self.leaf_count {
+            return None;
+        }
+        let mut hashes = Vec::new();
+        let mut current_idx = index;
+        for level in 0..self.offsets.len() - 2 {
+            let width = (self.offsets[level + 1] - self.offsets[level]) / self.hash_len;
+            if current_idx.is_multiple_of(2) {
+                if current_idx + 1 < width {
+                    hashes.push((HashDirection::Right, Cow::Owned(self.node(level, current_idx + 1).to_vec())));
+                }
+            } else {
+                hashes.push((HashDirection::Left, Cow::Owned(self.node(level, current_idx - 1).to_vec())));
+            }
+            current_idx /= 2;
+        }
+        Some(bounded_proof(hashes, self.leaf_count))
+    }
+
+
+    fn node(&self, level: usize, index: usize) -> &'a [u8] {
+        let start = self.offsets[level] + index * self.hash_len;
+        &self.bytes[start..start + self.hash_len]
+    }
+}
+
+
+fn read_u64(bytes: &[u8]) -> u64 {
+    u64::from_be_bytes(bytes[..8].try_into().unwrap())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Legacy;
+
+
+    #[test]
+    fn test_snapshot_roundtrip() {
+        for n in 1..=9 {
+            let data: Vec<Data> = (0..n as u8).map(|i| vec![i]).collect();
+            let tree = MerkleTree::construct(&data);
+            let mut bytes = Vec::new();
+            tree.write_snapshot(&mut bytes).unwrap();
+
+            let snapshot = Snapshot::open(&bytes).unwrap();
+            assert!(snapshot.verify());
+            assert_eq!(snapshot.root(), Some(tree.root()));
+            for (i, leaf) in data.iter().enumerate() {
+                assert_eq!(snapshot.index_of(leaf), Some(i));
+                let proof = snapshot.prove(leaf).unwrap();
+                assert_eq!(proof.hashes, tree.prove(leaf).unwrap().hashes);
+            }
+            assert!(snapshot.prove(&vec![99]).is_none());
+        }
+    }
+
+    #[test]
+    fn test_snapshot_rejects_corruption() {
+        let data: Vec<Data> = (0..6u8).map(|i| vec![i]).collect();
+        let mut bytes = Vec::new();
+        MerkleTree::construct(&data).write_snapshot(&mut bytes).unwrap();
+
+        let mut header = bytes.clone();
+        header[8] ^= 1;
+        assert_eq!(Snapshot::open(&header).err(), Some(SnapshotError("header checksum mismatch")));
+        assert!(Snapshot::open(&bytes[..40]).is_err());
+
+        // Body damage gets past `open` but not `verify`
+        let mut body = bytes.clone();
+        let last = body.len() - 40;
+        body[last] ^= 1;
+        assert!(!Snapshot::open(&body).unwrap().verify());
+    }
+
+    #[test]
+    fn test_snapshot_rejects_other_hasher() {
+        let data: Vec<Data> = (0..5u8).map(|i| vec![i]).collect();
+        let mut bytes = Vec::new();
+        MerkleTree::construct(&data).write_snapshot(&mut bytes).unwrap();
+        let opened = Snapshot::open_with(&bytes, Legacy(Sha256Hasher));
+        assert_eq!(opened.err(), Some(SnapshotError("written with a different hasher")));
+
+        let mut legacy = Vec::new();
+        MerkleTree::construct_with(&data, Legacy(Sha256Hasher)).write_snapshot(&mut legacy).unwrap();
+        assert!(Snapshot::open(&legacy).is_err());
+        let snapshot = Snapshot::open_with(&legacy, Legacy(Sha256Hasher)).unwrap();
+        assert_eq!(snapshot.index_of(&data[3]), Some(3));
+
+        let mut empty = Vec::new();
+        MerkleTree::construct(&Vec::<Data>::new()).write_snapshot(&mut empty).unwrap();
+        assert_eq!(Snapshot::open(&empty).unwrap().root(), None);
+    }
+}
@@ -0,0 +1,262 @@
+// Merkleization as in the Ethereum consensus specs, so roots match SSZ
+// `hash_tree_root`. Values are packed into 32-byte chunks, the chunk count
+// is padded to a power of two with zero chunks, and nodes are plain
+// hash(left | right) with no domain prefixes. Padding subtrees are never
+// built: their roots come from a table of zero hashes, one per height.
+// Proofs name nodes by generalized index: the root is 1 and the children
+// of node g are 2g and 2g + 1.
+
+use crate::ct::hashes_equal;
+use crate::{Hash, Hasher, Sha256Hasher, ZeroHashes};
+use std::fmt;
+
+
+pub const BYTES_PER_CHUNK: usize = 32;
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SszError {
+    // A chunk wasn't exactly 32 bytes
+    ChunkSize { index: usize },
+    // More chunks than the type's limit allows
+    TooManyChunks { chunks: usize, limit: usize },
+}
+
+
+impl fmt::Display for SszError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SszError::ChunkSize { index } => write!(f, "chunk {} isn't {} bytes", index, BYTES_PER_CHUNK),
+            SszError::TooManyChunks { chunks, limit } => write!(f, "{} chunks exceed the limit of {}", chunks, limit),
+        }
+    }
+}
+
+
+impl std::error::Error for SszError {}
+
+
+// Splits serialized bytes into chunks, zero-padding the last one
+pub fn pack(bytes: &[u8]) -> Vec<Hash> {
+    bytes
+        .chunks(BYTES_PER_CHUNK)
+        .map(|chunk| {
+            let mut chunk = chunk.to_vec();
+            chunk.resize(BYTES_PER_CHUNK, 0);
+            chunk
+        })
+        .collect()
+}
+
+
+// Commits a list's length alongside its contents, as SSZ lists and bitlists do
+pub fn mix_in_length<H: Hasher>(root: &Hash, length: usize, hasher: &H) -> Hash {
+    let mut length_chunk = (length as u64).to_le_bytes().to_vec();
+    length_chunk.resize(BYTES_PER_CHUNK, 0);
+    hasher.hash_concat(root, &length_chunk)
+}
+
+
+pub struct SszTree<H = Sha256Hasher> {
+    // Levels from the chunks up. Only nodes with at least one real chunk
+    // beneath them are stored; the rest are zero hashes.
+    nodes: Vec<Vec<Hash>>,
+    zeros: ZeroHashes,
+    hasher: H,
+}
+
+
+// The sibling hashes from a node up to the root, bottom first
+#[derive(Debug, Clone, PartialEq)]
+pub struct SszProof {
+    pub gindex: u64,
+    pub branch: Vec<Hash>,
+}
+
+
+impl SszProof {
+    // The root the branch leads to from `leaf`. None if the branch length
+    // doesn't match the depth of the generalized index.
+    pub fn compute_root_with<H: Hasher>(&self, leaf: &Hash, hasher: &H) -> Option<Hash> {
+        if self.gindex == 0 || self.branch.len() != self.gindex.ilog2() as usize {
+            return None;
+        }
+        let mut current = leaf.clone();
+        for (height, sibling) in self.branch.iter().enumerate() {
+            current = if (self.gindex >> height) & 1 == 1 {
+                hasher.hash_concat(sibling, &current)
+            } else {
+                hasher.hash_concat(&current, sibling)
+            };
+        }
+        Some(current)
+    }
+
+
+    pub fn verify(&self, leaf: &Hash, root: &Hash) -> bool {
+        self.verify_with(leaf, root, &Sha256Hasher)
+    }
+
+
+    pub fn verify_with<H: Hasher>(&self, leaf: &Hash, root: &Hash, hasher: &H) -> bool {
+        self.compute_root_with(leaf, hasher).is_some_and(|computed| hashes_equal(&computed, root))
+    }
+}
+
+
+impl SszTree {
+    // Merkleizes chunks padded to the next power of two of their count
+    pub fn merkleize(chunks: &[Hash]) -> Result<SszTree, SszError> {
+        Self::merkleize_with(chunks, None, Sha256Hasher)
+    }
+}
+
+
+impl<H: Hasher> SszTree<H> {
+    // Merkleizes chunks padded to the next power of two of `limit`, or of
+    // their count when there's no limit, matching the spec's `merkleize`
+    pub fn merkleize_with(chunks: &[Hash], limit: Option<usize>, hasher: H) -> Result<SszTree<H>, SszError> {
+        let zeros = ZeroHashes::ssz(0, &hasher);
+        Self::merkleize_with_zeros(chunks, limit, hasher, &zeros)
+    }
+
+
+    // Like `merkleize_with`, reusing `zeros` when it's deep enough, so
+    // trees built one after another share a single table
+    pub fn merkleize_with_zeros(chunks: &[Hash], limit: Option<usize>, hasher: H, zeros: &ZeroHashes) -> Result<SszTree<H>, SszError> {
+        if let Some(index) = chunks.iter().position(|chunk| chunk.len() != BYTES_PER_CHUNK) {
+            return Err(SszError::ChunkSize { index });
+        }
+        let width = match limit {
+            Some(limit) if chunks.len() > limit => {
+                return Err(SszError::TooManyChunks { chunks: chunks.len(), limit });
+            }
+            Some(limit) => limit,
+            None => chunks.len(),
+        };
+        let depth = width.max(1).next_power_of_two().trailing_zeros() as usize;
+        let zeros = if zeros.depth() >= depth { zeros.clone() } else { ZeroHashes::ssz(depth, &hasher) };
+
+        let mut nodes = vec![chunks.to_vec()];
+        for height in 0..depth {
+            let next = nodes[height]
+                .chunks(2)
+                .map(|pair| hasher.hash_concat(&pair[0], pair.get(1).unwrap_or(&zeros[height])))
+                .collect();
+            nodes.push(next);
+        }
+        Ok(SszTree { nodes, zeros, hasher })
+    }
+
+
+    // Levels below the root; chunk i has generalized index 2^depth + i
+    pub fn depth(&self) -> usize {
+        self.nodes.len() - 1
+    }
+
+
+    pub fn root(&self) -> Hash {
+        self.node(self.depth(), 0)
+    }
+
+
+    // The root with the list length mixed in, for SSZ lists
+    pub fn list_root(&self, length: usize) -> Hash {
+        mix_in_length(&self.root(), length, &self.hasher)
+    }
+
+
+    // The node at a generalized index, zero hashes included. None past the
+    // bottom of the tree.
+    pub fn node_at(&self, gindex: u64) -> Option<Hash> {
+        let (height, index) = self.locate(gindex)?;
+        Some(self.node(height, index))
+    }
+
+
+    pub fn prove(&self, index: usize) -> Option<SszProof> {
+        self.prove_gindex((1u64 << self.depth()) + index as u64)
+    }
+
+
+    // Proves any node, not just chunks: proving an inner node commits to
+    // the whole subtree beneath it
+    pub fn prove_gindex(&self, gindex: u64) -> Option<SszProof> {
+        let (height, index) = self.locate(gindex)?;
+        let branch = (height..self.depth())
+            .map(|h| self.node(h, (index >> (h - height)) ^ 1))
+            .collect();
+        Some(SszProof { gindex, branch })
+    }
+
+
+    // (height above the chunks, index within the level) of a generalized index
+    fn locate(&self, gindex: u64) -> Option<(usize, usize)> {
+        if gindex == 0 {
+            return None;
+        }
+        let level_from_root = gindex.ilog2() as usize;
+        let height = self.depth().checked_sub(level_from_root)?;
+        Some((height, (gindex - (1 << level_from_root)) as usize))
+    }
+
+
+    fn node(&self, height: usize, index: usize) -> Hash {
+        self.nodes[height].get(index).unwrap_or(&self.zeros[height]).clone()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    fn uint64(value: u64) -> Hash {
+        pack(&value.to_le_bytes()).remove(0)
+    }
+
+    #[test]
+    fn test_hash_tree_root() {
+        // Container { a: uint64, b: uint64, c: uint64 } = { 1, 2, 3 }
+        let tree = SszTree::merkleize(&[uint64(1), uint64(2), uint64(3)]).unwrap();
+        assert_eq!(hex::encode(tree.root()), "66c419026fee8793be7fd0011b9db46b98a79f9c9b640e25317865c358f442db");
+
+        // List[uint64, 100] = [1, 2, 3], limited to 25 chunks
+        let bytes: Vec<u8> = [1u64, 2, 3].iter().flat_map(|v| v.to_le_bytes()).collect();
+        let tree = SszTree::merkleize_with(&pack(&bytes), Some(25), Sha256Hasher).unwrap();
+        assert_eq!(tree.depth(), 5);
+        assert_eq!(hex::encode(tree.list_root(3)), "7fae6fdfa89a6996cb08047fc214d50987e74292f93920274b4b9f3a05bb9898");
+        let shared = ZeroHashes::ssz(40, &Sha256Hasher);
+        let reused = SszTree::merkleize_with_zeros(&pack(&bytes), Some(25), Sha256Hasher, &shared).unwrap();
+        assert_eq!(reused.list_root(3), tree.list_root(3));
+
+        assert_eq!(SszTree::merkleize(&[]).unwrap().root(), vec![0; 32]);
+        assert_eq!(SszTree::merkleize(&[vec![0; 31]]).err(), Some(SszError::ChunkSize { index: 0 }));
+        let too_many = SszTree::merkleize_with(&[uint64(1), uint64(2)], Some(1), Sha256Hasher);
+        assert_eq!(too_many.err(), Some(SszError::TooManyChunks { chunks: 2, limit: 1 }));
+    }
+
+    #[test]
+    fn test_gindex_proofs() {
+        let chunks: Vec<Hash> = (0..5).map(uint64).collect();
+        let tree = SszTree::merkleize(&chunks).unwrap();
+        let root = tree.root();
+        for (i, chunk) in chunks.iter().enumerate() {
+            let proof = tree.prove(i).unwrap();
+            assert_eq!(proof.gindex, 8 + i as u64);
+            assert!(proof.verify(chunk, &root));
+            assert!(!proof.verify(&uint64(9), &root));
+        }
+        // A padding chunk and an inner node over real and padding chunks
+        let proof = tree.prove(7).unwrap();
+        assert!(proof.verify(&vec![0; 32], &root));
+        let proof = tree.prove_gindex(3).unwrap();
+        assert!(proof.verify(&tree.node_at(3).unwrap(), &root));
+
+        let mut short = tree.prove(0).unwrap();
+        short.branch.pop();
+        assert!(short.compute_root_with(&chunks[0], &Sha256Hasher).is_none());
+        assert!(tree.prove_gindex(16).is_none());
+    }
+}
@@ -0,0 +1,151 @@
+use crate::invariants::bounded_proof;
+use crate::{reduce_level, Error, Hash, HashDirection, Hasher, MerkleTree, Proof};
+use std::borrow::Cow;
+
+
+// How much of the tree is kept in memory after construction
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum StorageMode {
+    // Every level, so proofs are plain lookups
+    #[default]
+    Full,
+    // Leaves and root only; proofs rebuild the inner levels on request
+    LeavesOnly,
+    // Root only; nothing can be proven or updated
+    RootOnly,
+}
+
+
+impl<H: Hasher> MerkleTree<H> {
+    pub fn storage_mode(&self) -> StorageMode {
+        self.mode
+    }
+
+
+    // All levels, leaves first, rebuilt from the leaves when only they are kept.
+    // None when the tree kept nothing to rebuild from.
+    pub(crate) fn levels(&self) -> Option<Cow<'_, [Vec<Hash>]>> {
+        match self.mode {
+            StorageMode::Full => Some(Cow::Borrowed(self.nodes.as_slice())),
+            StorageMode::LeavesOnly => Some(Cow::Owned(build_levels(self.nodes[0].clone(), &self.hasher))),
+            StorageMode::RootOnly => None,
+        }
+    }
+
+
+    // Proves the leaf at `index` from the leaves alone, hashing each sibling
+    // subtree in turn instead of rebuilding whole levels. Costs O(n) hashes
+    // but only O(log n) memory beyond the leaves.
+    pub fn prove_recompute(&self, index: usize) -> Result<Proof<'_>, Error> {
+        self.check_index(index)?;
+        if self.mode == StorageMode::RootOnly {
+            return Err(Error::NotStored);
+        }
+        let leaves = &self.nodes[0];
+        let mut hashes = Vec::new();
+        let mut current_idx = index;
+        let mut level = 0;
+        // The node at (level, j) covers leaves [j << level, (j + 1) << level)
+        while (leaves.len() - 1) >> level > 0 {
+            let sibling = current_idx ^ 1;
+            let start = sibling << level;
+            if start < leaves.len() {
+                let end = ((sibling + 1) << level).min(leaves.len());
+                let hash = if level == 0 {
+                    Cow::Borrowed(&leaves[start])
+                } else {
+                    Cow::Owned(subtree_root(&leaves[start..end], &self.hasher))
+                };
+                let direction = if sibling < current_idx { HashDirection::Left } else { HashDirection::Right };
+                hashes.push((direction, hash));
+            }
+            current_idx /= 2;
+            level += 1;
+        }
+        Ok(bounded_proof(hashes, self.leaf_count))
+    }
+}
+
+
+// Root of a run of leaves, keeping one pending node per height. Folding the
+// leftovers right to left promotes odd nodes the same way `reduce_level` does.
+fn subtree_root<H: Hasher>(leaves: &[Hash], hasher: &H) -> Hash {
+    let mut stack: Vec<(u32, Hash)> = Vec::new();
+    for leaf in leaves {
+        let mut node = (0, leaf.clone());
+        while let Some((height, left)) = stack.pop_if(|(height, _)| *height == node.0) {
+            node = (height + 1, hasher.hash_node(&left, &node.1));
+        }
+        stack.push(node);
+    }
+    let (_, mut root) = stack.pop().unwrap();
+    while let Some((_, left)) = stack.pop() {
+        root = hasher.hash_node(&left, &root);
+    }
+    root
+}
+
+
+// Reduces leaf hashes level by level, keeping every level
+pub(crate) fn build_levels<H: Hasher>(leaves: Vec<Hash>, hasher: &H) -> Vec<Vec<Hash>> {
+    let mut levels = vec![leaves];
+    while levels.last().unwrap().len() > 1 {
+        let next = reduce_level(levels.last().unwrap(), hasher);
+        levels.push(next);
+    }
+    levels
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Data, Error, Sha256Hasher};
+
+
+    #[test]
+    fn test_leaves_only_mode() {
+        for n in 1..=9 {
+            let mut data: Vec<Data> = (0..n as u8).map(|i| vec![i]).collect();
+            let full = MerkleTree::construct(&data);
+            let mut tree = MerkleTree::construct_with_mode(&data, Sha256Hasher, StorageMode::LeavesOnly);
+            assert!(tree.nodes.len() <= 2);
+            assert_eq!(tree.root(), full.root());
+            for leaf in &data {
+                let proof = tree.prove(leaf).unwrap();
+                assert!(MerkleTree::verify_proof(leaf, &proof, &tree.root()));
+            }
+
+            data[n - 1] = vec![99];
+            tree.update(n - 1, &data[n - 1]).unwrap();
+            assert_eq!(tree.root(), MerkleTree::construct(&data).root());
+        }
+    }
+
+    #[test]
+    fn test_root_only_mode() {
+        let data: Vec<Data> = (0..5u8).map(|i| vec![i]).collect();
+        let mut tree = MerkleTree::construct_with_mode(&data, Sha256Hasher, StorageMode::RootOnly);
+        assert_eq!(tree.nodes.len(), 1);
+        assert!(tree.leaves_idx.is_empty());
+        assert_eq!(tree.root(), MerkleTree::construct(&data).root());
+        assert!(tree.prove(&data[0]).is_none());
+        assert_eq!(tree.update(0, &vec![9]), Err(Error::NotStored));
+        assert!(matches!(tree.prove_recompute(0), Err(Error::NotStored)));
+    }
+
+    #[test]
+    fn test_prove_recompute() {
+        for n in 1..=17 {
+            let data: Vec<Data> = (0..n as u8).map(|i| vec![i]).collect();
+            let full = MerkleTree::construct(&data);
+            let tree = MerkleTree::construct_with_mode(&data, Sha256Hasher, StorageMode::LeavesOnly);
+            for (i, leaf) in data.iter().enumerate() {
+                let proof = tree.prove_recompute(i).unwrap();
+                assert_eq!(proof.hashes, full.prove(leaf).unwrap().hashes);
+                assert!(MerkleTree::verify_proof(leaf, &proof, &tree.root()));
+            }
+            assert!(matches!(tree.prove_recompute(n), Err(Error::IndexOutOfRange { .. })));
+        }
+    }
+}
@@ -0,0 +1,266 @@
+// Trees whose nodes live in a `NodeStore` rather than in memory. Only the
+// level widths are held by the tree itself, so a store backed by disk lets
+// the leaf set grow past what fits in RAM while proofs stay the same.
+
+use crate::deadline::{Deadline, DeadlineExceeded};
+use crate::invariants::bounded_proof;
+use crate::{Data, Error, Hash, HashDirection, Hasher, Proof, Sha256Hasher};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::fmt;
+use std::time::Instant;
+
+
+pub trait NodeStore {
+    type Error: std::error::Error;
+
+    fn get(&self, level: usize, index: usize) -> Result<Option<Hash>, Self::Error>;
+
+    fn put(&mut self, level: usize, index: usize, hash: Hash) -> Result<(), Self::Error>;
+}
+
+
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStore {
+    nodes: HashMap<(usize, usize), Hash>,
+}
+
+
+impl MemoryStore {
+    pub fn new() -> MemoryStore {
+        MemoryStore::default()
+    }
+}
+
+
+impl NodeStore for MemoryStore {
+    type Error = Infallible;
+
+    fn get(&self, level: usize, index: usize) -> Result<Option<Hash>, Infallible> {
+        Ok(self.nodes.get(&(level, index)).cloned())
+    }
+
+    fn put(&mut self, level: usize, index: usize, hash: Hash) -> Result<(), Infallible> {
+        self.nodes.insert((level, index), hash);
+        Ok(())
+    }
+}
+
+
+// Nodes keyed by big-endian (level, index), so each level is one contiguous range
+#[cfg(feature = "sled")]
+impl NodeStore for sled::Tree {
+    type Error = sled::Error;
+
+    fn get(&self, level: usize, index: usize) -> Result<Option<Hash>, sled::Error> {
+        Ok(sled::Tree::get(self, node_key(level, index))?.map(|value| value.to_vec()))
+    }
+
+    fn put(&mut self, level: usize, index: usize, hash: Hash) -> Result<(), sled::Error> {
+        self.insert(node_key(level, index), hash)?;
+        Ok(())
+    }
+}
+
+
+#[cfg(feature = "sled")]
+fn node_key(level: usize, index: usize) -> [u8; 12] {
+    let mut key = [0; 12];
+    key[..4].copy_from_slice(&(level as u32).to_be_bytes());
+    key[4..].copy_from_slice(&(index as u64).to_be_bytes());
+    key
+}
+
+
+#[derive(Debug)]
+pub enum StoreError<E> {
+    Store(E),
+    Tree(Error),
+    // A node the tree's shape says exists was not in the store
+    Missing { level: usize, index: usize },
+    Deadline(DeadlineExceeded),
+}
+
+
+impl<E: fmt::Display> fmt::Display for StoreError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StoreError::Store(e) => write!(f, "node store error: {}", e),
+            StoreError::Tree(e) => write!(f, "{}", e),
+            StoreError::Missing { level, index } => write!(f, "node ({}, {}) missing from store", level, index),
+            StoreError::Deadline(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+
+impl<E: fmt::Debug + fmt::Display> std::error::Error for StoreError<E> {}
+
+
+pub struct StoredTree<S, H = Sha256Hasher> {
+    store: S,
+    hasher: H,
+    // Node count of every level, leaves first
+    widths: Vec<usize>,
+}
+
+
+impl<S: NodeStore> StoredTree<S> {
+    pub fn construct(input: &[Data], store: S) -> Result<StoredTree<S>, StoreError<S::Error>> {
+        Self::construct_with(input, store, Sha256Hasher)
+    }
+}
+
+
+impl<S: NodeStore, H: Hasher> StoredTree<S, H> {
+    // Writes every node into `store`, one level at a time
+    pub fn construct_with(input: &[Data], store: S, hasher: H) -> Result<StoredTree<S, H>, StoreError<S::Error>> {
+        Self::build(input, store, hasher, None)
+    }
+
+
+    // Like `construct_with`, but gives up once `deadline` has passed. Nodes
+    // written before then stay in the store.
+    pub fn construct_with_deadline(input: &[Data], store: S, hasher: H, deadline: Instant) -> Result<StoredTree<S, H>, StoreError<S::Error>> {
+        Self::build(input, store, hasher, Some(Deadline::new(deadline)))
+    }
+
+
+    fn build(input: &[Data], store: S, hasher: H, mut clock: Option<Deadline>) -> Result<StoredTree<S, H>, StoreError<S::Error>> {
+        let mut tree = StoredTree { store, hasher, widths: level_widths(input.len()) };
+        let mut passed = |leaves_hashed, levels_built| {
+            if clock.as_mut().is_some_and(Deadline::passed) {
+                return Err(StoreError::Deadline(DeadlineExceeded { leaves_hashed, levels_built }));
+            }
+            Ok(())
+        };
+        for (i, data) in input.iter().enumerate() {
+            passed(i, 0)?;
+            let leaf_hash = tree.hasher.hash_leaf(data);
+            tree.store.put(0, i, leaf_hash).map_err(StoreError::Store)?;
+        }
+        for level in 1..tree.widths.len() {
+            for i in 0..tree.widths[level] {
+                passed(input.len(), level - 1)?;
+                let left = tree.node(level - 1, i * 2)?;
+                let parent = if i * 2 + 1 < tree.widths[level - 1] {
+                    tree.hasher.hash_node(&left, &tree.node(level - 1, i * 2 + 1)?)
+                } else {
+                    left
+                };
+                tree.store.put(level, i, parent).map_err(StoreError::Store)?;
+            }
+        }
+        Ok(tree)
+    }
+
+
+    // Reopens a tree previously written to `store`
+    pub fn open(store: S, hasher: H, leaf_count: usize) -> StoredTree<S, H> {
+        StoredTree { store, hasher, widths: level_widths(leaf_count) }
+    }
+
+
+    pub fn leaf_count(&self) -> usize {
+        self.widths[0]
+    }
+
+
+    pub fn root(&self) -> Result<Hash, StoreError<S::Error>> {
+        self.node(self.widths.len() - 1, 0)
+    }
+
+
+    // Same proof `MerkleTree::prove` gives for the leaf at `index`
+    pub fn prove(&self, index: usize) -> Result<Proof<'static>, StoreError<S::Error>> {
+        let leaf_count = self.leaf_count();
+        if index >= leaf_count {
+            return Err(StoreError::Tree(Error::IndexOutOfRange { index, leaf_count }));
+        }
+        let mut hashes = Vec::new();
+        let mut current_idx = index;
+        for (level, &width) in self.widths[..self.widths.len() - 1].iter().enumerate() {
+            if current_idx.is_multiple_of(2) {
+                if current_idx + 1 < width {
+                    hashes.push((HashDirection::Right, Cow::Owned(self.node(level, current_idx + 1)?)));
+                }
+            } else {
+                hashes.push((HashDirection::Left, Cow::Owned(self.node(level, current_idx - 1)?)));
+            }
+            current_idx /= 2;
+        }
+        Ok(bounded_proof(hashes, leaf_count))
+    }
+
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+
+    pub fn into_store(self) -> S {
+        self.store
+    }
+
+
+    fn node(&self, level: usize, index: usize) -> Result<Hash, StoreError<S::Error>> {
+        self.store
+            .get(level, index)
+            .map_err(StoreError::Store)?
+            .ok_or(StoreError::Missing { level, index })
+    }
+}
+
+
+fn level_widths(leaf_count: usize) -> Vec<usize> {
+    let mut widths = vec![leaf_count];
+    while *widths.last().unwrap() > 1 {
+        widths.push(widths.last().unwrap().div_ceil(2));
+    }
+    widths
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MerkleTree;
+
+
+    #[test]
+    fn test_memory_store() {
+        for n in 1..=9 {
+            let data: Vec<Data> = (0..n as u8).map(|i| vec![i]).collect();
+            let expected = MerkleTree::construct(&data);
+            let tree = StoredTree::construct(&data, MemoryStore::new()).unwrap();
+            assert_eq!(tree.root().unwrap(), expected.root());
+            for (i, leaf) in data.iter().enumerate() {
+                let proof = tree.prove(i).unwrap();
+                assert_eq!(proof.hashes, expected.prove(leaf).unwrap().hashes);
+            }
+            assert!(matches!(tree.prove(n), Err(StoreError::Tree(Error::IndexOutOfRange { .. }))));
+
+            let reopened = StoredTree::open(tree.into_store(), Sha256Hasher, n);
+            assert_eq!(reopened.root().unwrap(), expected.root());
+        }
+    }
+
+    #[test]
+    fn test_construct_with_deadline() {
+        let data: Vec<Data> = (0..100u8).map(|i| vec![i]).collect();
+        let result = StoredTree::construct_with_deadline(&data, MemoryStore::new(), Sha256Hasher, Instant::now());
+        assert!(matches!(result, Err(StoreError::Deadline(DeadlineExceeded { leaves_hashed: 63, levels_built: 0 }))));
+    }
+
+    #[cfg(feature = "sled")]
+    #[test]
+    fn test_sled_store() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let data: Vec<Data> = (0..11u8).map(|i| vec![i]).collect();
+        let tree = StoredTree::construct(&data, db.open_tree("nodes").unwrap()).unwrap();
+        let root = tree.root().unwrap();
+        assert_eq!(root, MerkleTree::construct(&data).root());
+        assert!(MerkleTree::verify_proof(&data[6], &tree.prove(6).unwrap(), &root));
+    }
+}
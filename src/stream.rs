@@ -0,0 +1,264 @@
+// Verified streaming in the style of Bao. A file is split into chunks and
+// encoded with its length up front, then the tree in pre-order: each parent
+// is written as its two children's hashes, followed by the left subtree and
+// then the right one, down to the chunk bytes. A reader that knows only the
+// root checks every pair of hashes against the parent it already trusts, so
+// each chunk is verified before it's handed out and corruption is caught at
+// the first bad chunk rather than after the whole download.
+
+use crate::ct::hashes_equal;
+use crate::{Data, Hash, Hasher, MerkleTree, Sha256Hasher};
+use std::io::{self, Read};
+
+
+pub const CHUNK_SIZE: usize = 1024;
+
+
+fn chunk_count(len: u64, chunk_size: usize) -> u64 {
+    len.div_ceil(chunk_size as u64).max(1)
+}
+
+
+// Largest power of two below `count`, which is how many chunks the left
+// subtree of `count` chunks holds
+fn left_count(count: u64) -> u64 {
+    1 << (count - 1).ilog2()
+}
+
+
+// Encodes `file` for streaming, returning the root and the encoding
+pub fn encode(file: &[u8]) -> (Hash, Vec<u8>) {
+    encode_with(file, CHUNK_SIZE, Sha256Hasher)
+}
+
+
+// Panics if `chunk_size` is zero
+pub fn encode_with<H: Hasher>(file: &[u8], chunk_size: usize, hasher: H) -> (Hash, Vec<u8>) {
+    assert!(chunk_size > 0, "chunk size must be at least one byte");
+    let chunks: Vec<Data> = if file.is_empty() {
+        vec![Vec::new()]
+    } else {
+        file.chunks(chunk_size).map(<[u8]>::to_vec).collect()
+    };
+    let tree = MerkleTree::construct_with(&chunks, hasher);
+    let mut out = (file.len() as u64).to_le_bytes().to_vec();
+    encode_subtree(&tree.nodes, &chunks, 0, chunks.len() as u64, &mut out);
+    (tree.root(), out)
+}
+
+
+fn encode_subtree(levels: &[Vec<Hash>], chunks: &[Data], start: u64, count: u64, out: &mut Vec<u8>) {
+    if count == 1 {
+        out.extend_from_slice(&chunks[start as usize]);
+        return;
+    }
+    let split = left_count(count);
+    for (child_start, child_count) in [(start, split), (start + split, count - split)] {
+        out.extend_from_slice(node(levels, child_start, child_count));
+    }
+    encode_subtree(levels, chunks, start, split, out);
+    encode_subtree(levels, chunks, start + split, count - split, out);
+}
+
+
+// The node over chunks [start, start + count). The subtree sits on the
+// level of the smallest power of two holding it, since smaller subtrees
+// are promoted up to there unchanged.
+fn node(levels: &[Vec<Hash>], start: u64, count: u64) -> &Hash {
+    let level = count.next_power_of_two().trailing_zeros();
+    &levels[level as usize][(start >> level) as usize]
+}
+
+
+// Reads an encoding and yields the file's bytes, each chunk only once it
+// verifies against the root. Bad hashes or chunks fail the read with
+// `InvalidData`, and a short encoding with `UnexpectedEof`. Once a read
+// fails the position in the encoding is lost, so every later read returns
+// the same error.
+pub struct VerifiedReader<R, H = Sha256Hasher> {
+    inner: R,
+    root: Hash,
+    chunk_size: usize,
+    hasher: H,
+    len: Option<u64>,
+    // Subtrees still to read, next on top: (expected hash, first chunk, chunk count)
+    pending: Vec<(Hash, u64, u64)>,
+    chunk: Data,
+    pos: usize,
+    // The error that stopped the read, repeated from then on
+    failed: Option<(io::ErrorKind, String)>,
+}
+
+
+impl<R: Read> VerifiedReader<R> {
+    pub fn new(inner: R, root: Hash) -> VerifiedReader<R> {
+        Self::new_with(inner, root, CHUNK_SIZE, Sha256Hasher)
+    }
+}
+
+
+impl<R: Read, H: Hasher> VerifiedReader<R, H> {
+    // Panics if `chunk_size` is zero
+    pub fn new_with(inner: R, root: Hash, chunk_size: usize, hasher: H) -> VerifiedReader<R, H> {
+        assert!(chunk_size > 0, "chunk size must be at least one byte");
+        VerifiedReader {
+            inner,
+            root,
+            chunk_size,
+            hasher,
+            len: None,
+            pending: Vec::new(),
+            chunk: Vec::new(),
+            pos: 0,
+            failed: None,
+        }
+    }
+
+
+    // The file length from the header, once the first read has taken it
+    pub fn file_len(&self) -> Option<u64> {
+        self.len
+    }
+
+
+    fn read_header(&mut self) -> io::Result<()> {
+        let mut header = [0; 8];
+        self.inner.read_exact(&mut header)?;
+        let len = u64::from_le_bytes(header);
+        self.pending.push((self.root.clone(), 0, chunk_count(len, self.chunk_size)));
+        self.len = Some(len);
+        Ok(())
+    }
+
+
+    // Walks down to the next chunk, checking each pair of hashes on the way.
+    // False once the file is done.
+    fn next_chunk(&mut self) -> io::Result<bool> {
+        let len = self.len.unwrap_or(0);
+        while let Some((expected, start, count)) = self.pending.pop() {
+            if count == 1 {
+                let offset = start * self.chunk_size as u64;
+                let size = (len - offset).min(self.chunk_size as u64) as usize;
+                let mut chunk = vec![0; size];
+                self.inner.read_exact(&mut chunk)?;
+                if !hashes_equal(&self.hasher.hash_leaf(&chunk), &expected) {
+                    return Err(corrupt(start));
+                }
+                (self.chunk, self.pos) = (chunk, 0);
+                return Ok(true);
+            }
+            let mut left = vec![0; self.root.len()];
+            let mut right = vec![0; self.root.len()];
+            self.inner.read_exact(&mut left)?;
+            self.inner.read_exact(&mut right)?;
+            if !hashes_equal(&self.hasher.hash_node(&left, &right), &expected) {
+                return Err(corrupt(start));
+            }
+            let split = left_count(count);
+            self.pending.push((right, start + split, count - split));
+            self.pending.push((left, start, split));
+        }
+        Ok(false)
+    }
+
+
+    // Makes sure there's unread data in `chunk`, unless the file is done
+    fn fill(&mut self) -> io::Result<()> {
+        if self.len.is_none() {
+            self.read_header()?;
+        }
+        while self.pos == self.chunk.len() {
+            if !self.next_chunk()? {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+
+fn corrupt(chunk: u64) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("encoding doesn't match the root at chunk {}", chunk))
+}
+
+
+impl<R: Read, H: Hasher> Read for VerifiedReader<R, H> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some((kind, message)) = &self.failed {
+            return Err(io::Error::new(*kind, message.clone()));
+        }
+        if let Err(err) = self.fill() {
+            self.failed = Some((err.kind(), err.to_string()));
+            return Err(err);
+        }
+        if self.pos == self.chunk.len() {
+            return Ok(0);
+        }
+        let n = buf.len().min(self.chunk.len() - self.pos);
+        buf[..n].copy_from_slice(&self.chunk[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    fn file(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 253) as u8).collect()
+    }
+
+    #[test]
+    fn test_stream_roundtrip() {
+        for len in [0, 1, 1023, 1024, 1025, 3000, 5 * 1024, 7 * 1024 + 5] {
+            let file = file(len);
+            let (root, encoded) = encode(&file);
+            let chunks: Vec<Data> = file.chunks(CHUNK_SIZE).map(<[u8]>::to_vec).collect();
+            if !chunks.is_empty() {
+                assert_eq!(root, MerkleTree::construct(&chunks).root());
+            }
+
+            let mut out = Vec::new();
+            let mut reader = VerifiedReader::new(&encoded[..], root);
+            reader.read_to_end(&mut out).unwrap();
+            assert_eq!(out, file);
+            assert_eq!(reader.file_len(), Some(len as u64));
+        }
+    }
+
+    #[test]
+    fn test_stream_fails_fast() {
+        let file = file(8 * 1024);
+        let (root, encoded) = encode(&file);
+
+        // Flip a byte in the last chunk: everything before it still comes out
+        let mut corrupted = encoded.clone();
+        *corrupted.last_mut().unwrap() ^= 1;
+        let mut reader = VerifiedReader::new(&corrupted[..], root.clone());
+        let mut out = Vec::new();
+        let err = reader.read_to_end(&mut out).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(out, file[..7 * 1024]);
+        // Reading on doesn't resume past the bad chunk
+        let again = reader.read(&mut [0; 16]).unwrap_err();
+        assert_eq!((again.kind(), again.to_string()), (err.kind(), err.to_string()));
+
+        // A bad hash near the top stops the read before any data
+        let mut corrupted = encoded.clone();
+        corrupted[8] ^= 1;
+        let mut reader = VerifiedReader::new(&corrupted[..], root.clone());
+        assert_eq!(reader.read(&mut [0; 16]).unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        // So does a header claiming a different length
+        let mut corrupted = encoded.clone();
+        corrupted[0] ^= 1;
+        let mut reader = VerifiedReader::new(&corrupted[..], root.clone());
+        assert!(reader.read_to_end(&mut Vec::new()).is_err());
+
+        let mut reader = VerifiedReader::new(&encoded[..encoded.len() - 1], root);
+        assert_eq!(reader.read_to_end(&mut Vec::new()).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
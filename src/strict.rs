@@ -0,0 +1,132 @@
+// Verification that refuses anything short of a proof exactly as this crate
+// would have made it for the claimed position, and says which check failed.
+// Plain verification only asks whether the hashes lead to the root; strict
+// verification also wants the position claimed, one hash per level the
+// leaf isn't promoted past, each on the side the index implies, and every
+// hash as long as the hasher's output.
+
+use crate::ct::hashes_equal;
+use crate::indexed::sibling_directions;
+use crate::{Hash, Hashable, Hasher, MerkleTree, Position, Proof, Sha256Hasher};
+use std::fmt;
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StrictError {
+    // The proof doesn't claim where the leaf is
+    NoPosition,
+    IndexOutOfRange { index: usize, tree_size: usize },
+    // The number of hashes isn't the one the position calls for
+    ProofLength { len: usize, expected: usize },
+    // Hash `index` isn't as long as the hasher's output
+    HashLength { index: usize, len: usize, expected: usize },
+    // Hash `index` is on the wrong side for the position
+    Direction { index: usize },
+    RootMismatch,
+}
+
+
+impl fmt::Display for StrictError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StrictError::NoPosition => write!(f, "proof doesn't claim a position"),
+            StrictError::IndexOutOfRange { index, tree_size } => {
+                write!(f, "leaf index {} out of range for {} leaves", index, tree_size)
+            }
+            StrictError::ProofLength { len, expected } => {
+                write!(f, "proof has {} hashes but its position needs {}", len, expected)
+            }
+            StrictError::HashLength { index, len, expected } => {
+                write!(f, "hash {} is {} bytes but the hasher makes {}", index, len, expected)
+            }
+            StrictError::Direction { index } => write!(f, "hash {} is on the wrong side for its position", index),
+            StrictError::RootMismatch => write!(f, "proof doesn't lead to the root"),
+        }
+    }
+}
+
+
+impl std::error::Error for StrictError {}
+
+
+impl MerkleTree {
+    pub fn verify_proof_strict<T: Hashable + ?Sized>(data: &T, proof: &Proof, root_hash: &Hash) -> Result<(), StrictError> {
+        Self::verify_proof_strict_with(data, proof, root_hash, &Sha256Hasher)
+    }
+}
+
+
+impl<H: Hasher> MerkleTree<H> {
+    pub fn verify_proof_strict_with<T: Hashable + ?Sized>(
+        data: &T,
+        proof: &Proof,
+        root_hash: &Hash,
+        hasher: &H,
+    ) -> Result<(), StrictError> {
+        let Position { index, tree_size } = proof.position().ok_or(StrictError::NoPosition)?;
+        if index >= tree_size {
+            return Err(StrictError::IndexOutOfRange { index, tree_size });
+        }
+        let expected = sibling_directions(index, tree_size);
+        if proof.len() != expected.len() {
+            return Err(StrictError::ProofLength { len: proof.len(), expected: expected.len() });
+        }
+        let hash_len = hasher.hash(&[]).len();
+        for (i, ((direction, hash), expected)) in proof.hashes.iter().zip(expected).enumerate() {
+            if hash.len() != hash_len {
+                return Err(StrictError::HashLength { index: i, len: hash.len(), expected: hash_len });
+            }
+            if *direction != expected {
+                return Err(StrictError::Direction { index: i });
+            }
+        }
+        if hashes_equal(&proof.compute_root_with(data, hasher), root_hash) {
+            Ok(())
+        } else {
+            Err(StrictError::RootMismatch)
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Data, HashDirection};
+    use std::borrow::Cow;
+
+
+    #[test]
+    fn test_verify_proof_strict() {
+        let data: Vec<Data> = (0..6u8).map(|i| vec![i]).collect();
+        let tree = MerkleTree::construct(&data);
+        let root = tree.root();
+        for leaf in &data {
+            let proof = tree.prove_with_position(leaf).unwrap();
+            assert_eq!(MerkleTree::verify_proof_strict(leaf, &proof, &root), Ok(()));
+        }
+        let bare = tree.prove(&data[4]).unwrap();
+        assert_eq!(MerkleTree::verify_proof_strict(&data[4], &bare, &root), Err(StrictError::NoPosition));
+
+        let proof = tree.prove_with_position(&data[4]).unwrap();
+        let claim = |index, tree_size| Proof { hashes: proof.hashes.clone(), position: Some(Position { index, tree_size }) };
+        assert_eq!(
+            MerkleTree::verify_proof_strict(&data[4], &claim(6, 6), &root),
+            Err(StrictError::IndexOutOfRange { index: 6, tree_size: 6 })
+        );
+        // Leaf 0 is never promoted, so it needs a hash on every level
+        assert_eq!(
+            MerkleTree::verify_proof_strict(&data[4], &claim(0, 6), &root),
+            Err(StrictError::ProofLength { len: 2, expected: 3 })
+        );
+        assert_eq!(MerkleTree::verify_proof_strict(&data[4], &claim(5, 6), &root), Err(StrictError::Direction { index: 0 }));
+        assert_eq!(MerkleTree::verify_proof_strict(&data[5], &proof, &root), Err(StrictError::RootMismatch));
+
+        let mut truncated = claim(4, 6);
+        truncated.hashes[1] = (HashDirection::Left, Cow::Owned(vec![0; 20]));
+        assert_eq!(
+            MerkleTree::verify_proof_strict(&data[4], &truncated, &root),
+            Err(StrictError::HashLength { index: 1, len: 20, expected: 32 })
+        );
+    }
+}
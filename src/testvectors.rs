@@ -0,0 +1,139 @@
+// Test vectors for implementations in other languages to check themselves
+// against this crate. Each vector names a mode and gives its `TreeConfig`
+// encoding, the leaves, the root and every leaf's proof, all as hex:
+//
+//   rfc6962  the default tree: RFC 6962 prefixes, odd nodes promoted
+//   legacy   `Legacy`: no prefixes, odd nodes promoted
+//   ssz      `SszTree`: 32-byte chunks padded to a power of two, with
+//            generalized indices
+//
+// The default tree is the RFC 6962 one, so a single mode covers both.
+// Bitcoin's tree, which duplicates odd nodes and double hashes, isn't
+// built by this crate and has no vectors.
+
+use crate::attestation::PolicyFlags;
+use crate::ssz::{pack, SszTree};
+use crate::{Data, Hash, HashDirection, Hasher, Legacy, MerkleTree, Sha256Hasher, TreeConfig};
+use std::io::{self, Write};
+
+
+pub const VERSION: u32 = 1;
+
+// Leaf counts covering single leaves, powers of two and promotion
+pub const LEAF_COUNTS: &[usize] = &[1, 2, 3, 4, 5, 7, 8, 9, 16, 17];
+
+
+pub fn leaves(count: usize) -> Vec<Data> {
+    (0..count).map(|i| format!("leaf {}", i).into_bytes()).collect()
+}
+
+
+// Every vector as one JSON document
+pub fn write_json<W: Write>(mut w: W) -> io::Result<()> {
+    writeln!(w, "{{\"version\": {}, \"vectors\": [", VERSION)?;
+    let mut vectors = Vec::new();
+    for &count in LEAF_COUNTS {
+        let data = leaves(count);
+        vectors.push(tree_vector("rfc6962", TreeConfig::sha256(), &data, Sha256Hasher));
+        let legacy = TreeConfig { flags: PolicyFlags::PROMOTE_ODD, ..TreeConfig::sha256() };
+        vectors.push(tree_vector("legacy", legacy, &data, Legacy(Sha256Hasher)));
+        vectors.push(ssz_vector(&data));
+    }
+    writeln!(w, "{}", vectors.join(",\n"))?;
+    writeln!(w, "]}}")
+}
+
+
+fn tree_vector<H: Hasher>(mode: &str, config: TreeConfig, data: &[Data], hasher: H) -> String {
+    let tree = MerkleTree::construct_with(data, hasher);
+    let proofs: Vec<String> = tree
+        .iter_proofs()
+        .enumerate()
+        .map(|(index, proof)| {
+            let path: Vec<String> = proof
+                .hashes
+                .iter()
+                .map(|(direction, hash)| {
+                    let side = match direction {
+                        HashDirection::Left => "left",
+                        HashDirection::Right => "right",
+                    };
+                    format!("{{\"side\": \"{}\", \"hash\": \"{}\"}}", side, hex::encode(hash.as_ref()))
+                })
+                .collect();
+            format!("{{\"index\": {}, \"path\": [{}]}}", index, path.join(", "))
+        })
+        .collect();
+    vector(mode, config, data, &tree.root(), &proofs)
+}
+
+
+// Each leaf is packed into one chunk, so leaves must be 32 bytes or less
+fn ssz_vector(data: &[Data]) -> String {
+    let chunks: Vec<Hash> = data.iter().map(|leaf| pack(leaf).remove(0)).collect();
+    let tree = SszTree::merkleize(&chunks).expect("no limit to exceed");
+    let proofs: Vec<String> = (0..chunks.len())
+        .map(|index| {
+            let proof = tree.prove(index).unwrap();
+            let branch: Vec<String> = proof.branch.iter().map(|hash| format!("\"{}\"", hex::encode(hash))).collect();
+            format!("{{\"index\": {}, \"gindex\": {}, \"branch\": [{}]}}", index, proof.gindex, branch.join(", "))
+        })
+        .collect();
+    let config = TreeConfig { flags: PolicyFlags::PADDED, ..TreeConfig::sha256() }.with_chunk_size(32);
+    vector("ssz", config, data, &tree.root(), &proofs)
+}
+
+
+fn vector(mode: &str, config: TreeConfig, data: &[Data], root: &Hash, proofs: &[String]) -> String {
+    let leaves: Vec<String> = data.iter().map(|leaf| format!("\"{}\"", hex::encode(leaf))).collect();
+    format!(
+        "{{\"mode\": \"{}\", \"config\": \"{}\", \"leaves\": [{}], \"root\": \"{}\", \"proofs\": [{}]}}",
+        mode,
+        hex::encode(config.encode()),
+        leaves.join(", "),
+        hex::encode(root),
+        proofs.join(", ")
+    )
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    #[test]
+    fn test_vectors_cover_every_mode() {
+        let mut json = Vec::new();
+        write_json(&mut json).unwrap();
+        let json = String::from_utf8(json).unwrap();
+        for mode in ["rfc6962", "legacy", "ssz"] {
+            assert_eq!(json.matches(&format!("\"mode\": \"{}\"", mode)).count(), LEAF_COUNTS.len());
+        }
+        assert_eq!(json.matches('[').count(), json.matches(']').count());
+    }
+
+    #[test]
+    fn test_rfc6962_matches_certificate_transparency() {
+        // The reference leaves and roots from certificate-transparency's
+        // Merkle tree tests, for the first one to eight leaves
+        let leaves: Vec<Data> = ["", "00", "10", "2021", "3031", "40414243", "5051525354555657", "606162636465666768696a6b6c6d6e6f"]
+            .iter()
+            .map(|leaf| hex::decode(leaf).unwrap())
+            .collect();
+        let roots = [
+            "6e340b9cffb37a989ca544e6bb780a2c78901d3fb33738768511a30617afa01d",
+            "fac54203e7cc696cf0dfcb42c92a1d9dbaf70ad9e621f4bd8d98662f00e3c125",
+            "aeb6bcfe274b70a14fb067a5e5578264db0fa9b51af5e0ba159158f329e06e77",
+            "d37ee418976dd95753c1c73862b9398fa2a2cf9b4ff0fdfe8b30cd95209614b7",
+            "4e3bbb1f7b478dcfe71fb631631519a3bca12c9aefca1612bfce4c13a86264d4",
+            "76e67dadbcdf1e10e1b74ddc608abd2f98dfb16fbce75277b5232a127f2087ef",
+            "ddb89be403809e325750d3d263cd78929c2942b7942a34b77e122c9594a74c8c",
+            "5dc9da79a70659a9ad559cb701ded9a2ab9d823aad2f4960cfe370eff4604328",
+        ];
+        for (count, root) in (1..=8).zip(roots) {
+            let vector = tree_vector("rfc6962", TreeConfig::sha256(), &leaves[..count], Sha256Hasher);
+            assert!(vector.contains(&format!("\"root\": \"{}\"", root)), "{} leaves", count);
+        }
+    }
+}
@@ -0,0 +1,76 @@
+// Tiger tree hashes as in the THEX spec, the tree hash P2P file-sharing
+// networks publish for files. Files are split into 1024-byte segments, and
+// the spec's 0x00 leaf and 0x01 node prefixes and odd-node promotion are
+// what this crate's tree already does, so a THEX tree is a plain tree over
+// the segments with Tiger as the hash. Segment proofs come from
+// `prove_recompute` by segment index, since equal segments share a hash.
+
+use crate::{Data, Hash, Hasher, MerkleTree};
+use tiger::{Digest, Tiger};
+
+
+pub const SEGMENT_SIZE: usize = 1024;
+
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TigerHasher;
+
+
+impl Hasher for TigerHasher {
+    fn hash(&self, data: &[u8]) -> Hash {
+        Tiger::digest(data).to_vec()
+    }
+
+    fn backend(&self) -> &'static str {
+        "tiger"
+    }
+}
+
+
+// The file's segments. An empty file is one empty segment.
+pub fn segments(file: &[u8]) -> Vec<Data> {
+    if file.is_empty() {
+        return vec![Vec::new()];
+    }
+    file.chunks(SEGMENT_SIZE).map(<[u8]>::to_vec).collect()
+}
+
+
+impl MerkleTree<TigerHasher> {
+    pub fn construct_thex(file: &[u8]) -> MerkleTree<TigerHasher> {
+        MerkleTree::construct_with(&segments(file), TigerHasher)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    // Vectors from the THEX spec, decoded from base32
+    #[test]
+    fn test_thex_roots() {
+        let cases = [
+            (0, "5d9ed00a030e638bdb753a6a24fb900e5a63b8e73e6c25b6"),
+            (1024, "5fbd0e62ad016d596b77d1d28883b94fed78ecbaf4640914"),
+            (1025, "7e591c1cd8f2e6121fdbcd8071ba279626b771642d10a3db"),
+        ];
+        for (len, expected) in cases {
+            let tree = MerkleTree::construct_thex(&vec![b'A'; len]);
+            assert_eq!(hex::encode(tree.root()), expected);
+        }
+    }
+
+    #[test]
+    fn test_segment_proofs() {
+        let file: Vec<u8> = (0..5000u32).map(|i| (i % 251) as u8).collect();
+        let tree = MerkleTree::construct_thex(&file);
+        let segments = segments(&file);
+        assert_eq!(segments.len(), 5);
+        for (i, segment) in segments.iter().enumerate() {
+            let proof = tree.prove_recompute(i).unwrap();
+            assert!(MerkleTree::verify_proof_with(segment, &proof, &tree.root(), &TigerHasher));
+        }
+    }
+}
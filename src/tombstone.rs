@@ -0,0 +1,200 @@
+// Redacting leaves without changing the tree's shape. A removed leaf is
+// replaced by a tombstone, hash(0x02), which no leaf or node can equal
+// since those are hashed under the 0x00 and 0x01 prefixes. Indices and
+// sizes stay put, so a proof about any index shows one of three things:
+// the leaf is present, it was deleted, or the tree never grew that far.
+//
+// Hashers without those prefixes, such as `Legacy`, hash a leaf of 0x02 to
+// the tombstone itself, so trees built with them can't remove leaves.
+
+use crate::ct::hashes_equal;
+use crate::{Error, Hash, Hashable, Hasher, MerkleTree, Proof, Sha256Hasher, StorageMode};
+use std::sync::Arc;
+
+
+pub const TOMBSTONE_PREFIX: u8 = 0x02;
+
+
+pub fn tombstone_hash<H: Hasher>(hasher: &H) -> Hash {
+    hasher.hash(&[TOMBSTONE_PREFIX])
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LeafStatus {
+    Present,
+    Deleted,
+    NeverExisted,
+}
+
+
+// What a tree says about one index. Present and deleted leaves are proven
+// at their own position; an index past the end is shown to be past the end
+// by proving the last leaf, whose position commits to the tree size.
+#[derive(Debug)]
+pub struct StatusProof<'a> {
+    pub index: usize,
+    pub status: LeafStatus,
+    // The hash at the proven position
+    pub leaf_hash: Hash,
+    proof: Proof<'a>,
+}
+
+
+impl StatusProof<'_> {
+    pub fn verify(&self, root: &Hash) -> bool {
+        self.verify_with(root, &Sha256Hasher)
+    }
+
+
+    // Checks the proof leads to `root` and backs up the claimed status. For
+    // a present leaf, also check `leaf_hash` against the data with `holds`.
+    pub fn verify_with<H: Hasher>(&self, root: &Hash, hasher: &H) -> bool {
+        let Some(position) = self.proof.position() else {
+            return false;
+        };
+        let is_tombstone = hashes_equal(&self.leaf_hash, &tombstone_hash(hasher));
+        let status_holds = match self.status {
+            LeafStatus::Present => position.index == self.index && !is_tombstone,
+            LeafStatus::Deleted => position.index == self.index && is_tombstone,
+            LeafStatus::NeverExisted => position.index + 1 == position.tree_size && self.index >= position.tree_size,
+        };
+        status_holds
+            && self.proof.matches_position()
+            && hashes_equal(&self.proof.compute_root_from_hash(self.leaf_hash.clone(), hasher), root)
+    }
+
+
+    // Whether the proven leaf is `data`
+    pub fn holds<T: Hashable + ?Sized, H: Hasher>(&self, data: &T, hasher: &H) -> bool {
+        self.status == LeafStatus::Present && hashes_equal(&hasher.hash_leaf(&data.leaf_bytes()), &self.leaf_hash)
+    }
+}
+
+
+impl<H: Hasher> MerkleTree<H> {
+    // Replaces the leaf at `index` with a tombstone and recomputes its path,
+    // returning the hash it had. Pinned leaves can't be removed, nor can
+    // any leaf of a tree whose hasher isn't domain separated.
+    pub fn remove(&mut self, index: usize) -> Result<Hash, Error> {
+        self.check_index(index)?;
+        if self.mode == StorageMode::RootOnly {
+            return Err(Error::NotStored);
+        }
+        if !self.hasher.domain_separated() {
+            return Err(Error::NotDomainSeparated);
+        }
+        if self.pins.contains_key(&index) {
+            return Err(Error::PinViolation { index });
+        }
+        let leaves = &mut Arc::make_mut(&mut self.nodes)[0];
+        let old_hash = std::mem::replace(&mut leaves[index], tombstone_hash(&self.hasher));
+        let leaves_idx = Arc::make_mut(&mut self.leaves_idx);
+        if leaves_idx.get(&old_hash) == Some(&index) {
+            // A duplicate of the removed leaf can still be proven, from the
+            // last copy as when the index was built
+            match leaves.iter().rposition(|leaf| hashes_equal(leaf, &old_hash)) {
+                Some(duplicate) => leaves_idx.insert(old_hash.clone(), duplicate),
+                None => leaves_idx.remove(&old_hash),
+            };
+        }
+        self.recompute_path(index);
+        Ok(old_hash)
+    }
+
+
+    pub fn is_removed(&self, index: usize) -> bool {
+        self.leaf_hash(index).is_some_and(|hash| hashes_equal(hash, &tombstone_hash(&self.hasher)))
+    }
+
+
+    pub fn status(&self, index: usize) -> LeafStatus {
+        if index >= self.leaf_count {
+            LeafStatus::NeverExisted
+        } else if self.is_removed(index) {
+            LeafStatus::Deleted
+        } else {
+            LeafStatus::Present
+        }
+    }
+
+
+    // Proves the status of `index`. None for an empty tree, which has no
+    // leaf to anchor a proof on, and for root-only trees.
+    pub fn prove_status(&self, index: usize) -> Option<StatusProof<'_>> {
+        let status = self.status(index);
+        let proven = if status == LeafStatus::NeverExisted { self.leaf_count.checked_sub(1)? } else { index };
+        let proof = self.prove_index(proven)?;
+        Some(StatusProof {
+            index,
+            status,
+            leaf_hash: self.nodes[0][proven].clone(),
+            proof: proof.with_position(proven, self.leaf_count),
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Data, Legacy};
+
+
+    #[test]
+    fn test_remove() {
+        let data: Vec<Data> = (0..7u8).map(|i| vec![i]).collect();
+        let mut tree = MerkleTree::construct(&data);
+        let removed = tree.leaf_hash(3).unwrap().clone();
+        assert_eq!(tree.remove(3), Ok(removed));
+        assert!(tree.is_removed(3));
+        assert!(tree.prove(&data[3]).is_none());
+        assert_eq!(tree.check_invariants(), Ok(()));
+        assert_eq!(tree.remove(7), Err(Error::IndexOutOfRange { index: 7, leaf_count: 7 }));
+
+        tree.pin_leaf(5, tree.leaf_hash(5).unwrap().clone()).unwrap();
+        assert_eq!(tree.remove(5), Err(Error::PinViolation { index: 5 }));
+
+        // Under `Legacy` a leaf of 0x02 would pass for a tombstone
+        let mut legacy = MerkleTree::construct_with(&[vec![TOMBSTONE_PREFIX], vec![1]], Legacy(Sha256Hasher));
+        assert_eq!(legacy.remove(1), Err(Error::NotDomainSeparated));
+    }
+
+    #[test]
+    fn test_remove_duplicate() {
+        let data: Vec<Data> = vec![vec![1], vec![2], vec![1], vec![1]];
+        let mut tree = MerkleTree::construct(&data);
+        tree.remove(3).unwrap();
+        assert_eq!(tree.prove_index(2).unwrap(), tree.prove(&data[0]).unwrap());
+        tree.remove(0).unwrap();
+        assert_eq!(tree.prove_index(2).unwrap(), tree.prove(&data[0]).unwrap());
+        tree.remove(2).unwrap();
+        assert!(tree.prove(&data[0]).is_none());
+        assert_eq!(tree.check_invariants(), Ok(()));
+    }
+
+    #[test]
+    fn test_status_proofs() {
+        let data: Vec<Data> = (0..6u8).map(|i| vec![i]).collect();
+        let mut tree = MerkleTree::construct(&data);
+        tree.remove(2).unwrap();
+        let root = tree.root();
+        for (index, status) in [(1, LeafStatus::Present), (2, LeafStatus::Deleted), (6, LeafStatus::NeverExisted), (40, LeafStatus::NeverExisted)] {
+            let proof = tree.prove_status(index).unwrap();
+            assert_eq!(proof.status, status);
+            assert!(proof.verify(&root));
+        }
+        assert!(tree.prove_status(1).unwrap().holds(&data[1], &Sha256Hasher));
+        assert!(!tree.prove_status(2).unwrap().holds(&data[2], &Sha256Hasher));
+
+        // Relabelling a proof's status doesn't get past verification
+        for (index, forged) in [(1, LeafStatus::Deleted), (2, LeafStatus::Present), (5, LeafStatus::NeverExisted)] {
+            let proof = StatusProof { status: forged, ..tree.prove_status(index).unwrap() };
+            assert!(!proof.verify(&root));
+        }
+        let mut past_end = tree.prove_status(9).unwrap();
+        past_end.index = 4;
+        assert!(!past_end.verify(&root));
+        assert!(MerkleTree::construct(&Vec::<Data>::new()).prove_status(0).is_none());
+    }
+}
@@ -0,0 +1,411 @@
+// A verifiable append-only log in the style of Certificate Transparency.
+// Every appended entry is kept, a signed tree head (STH) is issued every
+// `sign_every` entries or on demand, and the log serves inclusion proofs
+// against any earlier size and consistency proofs between any two sizes,
+// so clients holding an old STH can check the log never rewrote history.
+//
+// Signing and checking signatures are left to the caller through
+// `TreeHeadSigner` and `TreeHeadVerifier`. The signed message is:
+//
+//   tree_size: u64 | timestamp: u64 | key id with a u32 length prefix |
+//   root with a u32 length prefix
+//
+// all big endian, with the timestamp in milliseconds since the Unix epoch.
+// The key id names the key that signed, so verifiers can tell keys apart
+// once a log has rotated its key.
+//
+// Rotating the log's key issues a `KeyRotation`: the new key id and the
+// time it takes over, signed by the outgoing key. A `KeyHistory` built by
+// following those rotations from a trusted first key accepts each head only
+// under the key that was current at its timestamp. A rotation signs:
+//
+//   "MTKR" | valid_from: u64 | new key id with a u32 length prefix
+
+use crate::consistency::ConsistencyProof;
+use crate::encoding::put_bytes;
+use crate::{Data, Hash, Hasher, MerkleTree, Proof, Sha256Hasher};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+
+// Milliseconds since the Unix epoch, for timestamping heads
+pub type Clock = fn() -> u64;
+
+
+pub fn system_clock() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_millis() as u64)
+}
+
+
+pub trait TreeHeadSigner {
+    // Names the key `sign` signs with
+    fn key_id(&self) -> &[u8];
+
+    fn sign(&self, message: &[u8]) -> Vec<u8>;
+}
+
+
+pub trait TreeHeadVerifier {
+    // Whether `signature` is a signature over `message` by the key `key_id`
+    fn verify(&self, key_id: &[u8], message: &[u8], signature: &[u8]) -> bool;
+}
+
+
+impl<F: Fn(&[u8], &[u8], &[u8]) -> bool> TreeHeadVerifier for F {
+    fn verify(&self, key_id: &[u8], message: &[u8], signature: &[u8]) -> bool {
+        self(key_id, message, signature)
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SignedTreeHead {
+    pub tree_size: usize,
+    pub timestamp: u64,
+    pub key_id: Vec<u8>,
+    pub root: Hash,
+    pub signature: Vec<u8>,
+}
+
+
+impl SignedTreeHead {
+    // The bytes the signature covers
+    pub fn signed_bytes(&self) -> Vec<u8> {
+        head_message(self.tree_size, self.timestamp, &self.key_id, &self.root)
+    }
+
+
+    // Checks the signature over this head
+    pub fn verify<V: TreeHeadVerifier + ?Sized>(&self, verifier: &V) -> bool {
+        verifier.verify(&self.key_id, &self.signed_bytes(), &self.signature)
+    }
+}
+
+
+// Hands the log over to a new key, signed by the key it replaces
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyRotation {
+    pub key_id: Vec<u8>,
+    // Heads from this time on are signed with the new key
+    pub valid_from: u64,
+    pub signature: Vec<u8>,
+}
+
+
+impl KeyRotation {
+    // The bytes the outgoing key signs
+    pub fn signed_bytes(&self) -> Vec<u8> {
+        let mut out = b"MTKR".to_vec();
+        out.extend_from_slice(&self.valid_from.to_be_bytes());
+        put_bytes(&mut out, &self.key_id);
+        out
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RotationError {
+    // The outgoing key didn't sign the rotation
+    BadSignature,
+    // The new key would take over before the current one did
+    OutOfOrder,
+}
+
+
+struct LogKey<V> {
+    key_id: Vec<u8>,
+    valid_from: u64,
+    verifier: V,
+}
+
+
+// The keys a log has signed with, oldest first
+pub struct KeyHistory<V> {
+    keys: Vec<LogKey<V>>,
+}
+
+
+impl<V: TreeHeadVerifier> KeyHistory<V> {
+    // Starts from the log's first key, trusted out of band
+    pub fn new(key_id: &[u8], verifier: V) -> KeyHistory<V> {
+        KeyHistory { keys: vec![LogKey { key_id: key_id.to_vec(), valid_from: 0, verifier }] }
+    }
+
+
+    // Accepts the next key if the current one signed its rotation
+    pub fn rotate(&mut self, rotation: &KeyRotation, verifier: V) -> Result<(), RotationError> {
+        let current = self.keys.last().unwrap();
+        if rotation.valid_from < current.valid_from {
+            return Err(RotationError::OutOfOrder);
+        }
+        if !current.verifier.verify(&current.key_id, &rotation.signed_bytes(), &rotation.signature) {
+            return Err(RotationError::BadSignature);
+        }
+        self.keys.push(LogKey { key_id: rotation.key_id.clone(), valid_from: rotation.valid_from, verifier });
+        Ok(())
+    }
+
+
+    pub fn current_key_id(&self) -> &[u8] {
+        &self.keys.last().unwrap().key_id
+    }
+
+
+    // The key that was current at `timestamp`
+    fn key_at(&self, timestamp: u64) -> &LogKey<V> {
+        let later = self.keys.partition_point(|key| key.valid_from <= timestamp);
+        &self.keys[later.max(1) - 1]
+    }
+}
+
+
+// Checks a head under the key that was current at its timestamp, which is
+// read back out of the signed message
+impl<V: TreeHeadVerifier> TreeHeadVerifier for KeyHistory<V> {
+    fn verify(&self, key_id: &[u8], message: &[u8], signature: &[u8]) -> bool {
+        let Some(timestamp) = message.get(8..16) else {
+            return false;
+        };
+        let key = self.key_at(u64::from_be_bytes(timestamp.try_into().unwrap()));
+        key.key_id == key_id && key.verifier.verify(key_id, message, signature)
+    }
+}
+
+
+fn head_message(tree_size: usize, timestamp: u64, key_id: &[u8], root: &Hash) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(&(tree_size as u64).to_be_bytes());
+    out.extend_from_slice(&timestamp.to_be_bytes());
+    put_bytes(&mut out, key_id);
+    put_bytes(&mut out, root);
+    out
+}
+
+
+pub struct TransparencyLog<S, H = Sha256Hasher> {
+    entries: Vec<Data>,
+    tree: MerkleTree<H>,
+    heads: Vec<SignedTreeHead>,
+    rotations: Vec<KeyRotation>,
+    signer: S,
+    // Entries between automatic tree heads; 0 only signs on demand
+    sign_every: usize,
+    clock: Clock,
+}
+
+
+impl<S: TreeHeadSigner> TransparencyLog<S> {
+    pub fn new(signer: S, sign_every: usize) -> TransparencyLog<S> {
+        Self::with_hasher(signer, sign_every, Sha256Hasher)
+    }
+}
+
+
+impl<S: TreeHeadSigner, H: Hasher> TransparencyLog<S, H> {
+    pub fn with_hasher(signer: S, sign_every: usize, hasher: H) -> TransparencyLog<S, H> {
+        TransparencyLog {
+            entries: Vec::new(),
+            tree: MerkleTree::construct_with(&Vec::<Data>::new(), hasher),
+            heads: Vec::new(),
+            rotations: Vec::new(),
+            signer,
+            sign_every,
+            clock: system_clock,
+        }
+    }
+
+
+    // Timestamps heads with `clock` instead of the system time
+    pub fn with_clock(mut self, clock: Clock) -> TransparencyLog<S, H> {
+        self.clock = clock;
+        self
+    }
+
+
+    // Appends an entry, returning its index, and signs a new tree head if
+    // one is due
+    pub fn append(&mut self, entry: Data) -> usize {
+        // A fully stored tree always accepts pushes
+        let index = self.tree.push(&entry).expect("log tree keeps every level");
+        self.entries.push(entry);
+        if self.sign_every > 0 && self.entries.len().is_multiple_of(self.sign_every) {
+            self.sign_head();
+        }
+        index
+    }
+
+
+    // Signs a tree head for the current size, timestamped by the log's clock
+    pub fn sign_head(&mut self) -> &SignedTreeHead {
+        let timestamp = (self.clock)();
+        let tree_size = self.entries.len();
+        let root = self.tree.root_at_size(tree_size).expect("current size is always provable");
+        let key_id = self.signer.key_id().to_vec();
+        let signature = self.signer.sign(&head_message(tree_size, timestamp, &key_id, &root));
+        self.heads.push(SignedTreeHead { tree_size, timestamp, key_id, root, signature });
+        self.heads.last().unwrap()
+    }
+
+
+    // Hands signing over to `signer`, cross-signing its key id with the
+    // outgoing key. Heads signed from now on use the new key.
+    pub fn rotate_signer(&mut self, signer: S) -> &KeyRotation {
+        let mut rotation = KeyRotation { key_id: signer.key_id().to_vec(), valid_from: (self.clock)(), signature: Vec::new() };
+        rotation.signature = self.signer.sign(&rotation.signed_bytes());
+        self.signer = signer;
+        self.rotations.push(rotation);
+        self.rotations.last().unwrap()
+    }
+
+
+    // Every key rotation, oldest first
+    pub fn rotations(&self) -> &[KeyRotation] {
+        &self.rotations
+    }
+
+
+    pub fn latest_head(&self) -> Option<&SignedTreeHead> {
+        self.heads.last()
+    }
+
+
+    // Every tree head issued, oldest first
+    pub fn heads(&self) -> &[SignedTreeHead] {
+        &self.heads
+    }
+
+
+    pub fn entry(&self, index: usize) -> Option<&Data> {
+        self.entries.get(index)
+    }
+
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+
+    // Proves entry `index` is in the log as of `tree_size` entries
+    pub fn prove_inclusion(&self, index: usize, tree_size: usize) -> Option<Proof<'static>> {
+        self.tree.prove_at_size(index, tree_size)
+    }
+
+
+    // Proves the log at `new_size` entries extends the log at `old_size`
+    pub fn prove_consistency(&self, old_size: usize, new_size: usize) -> Option<ConsistencyProof> {
+        self.tree.prove_consistency_between(old_size, new_size)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+
+    // Stands in for a real key: signatures are a keyed hash of the message
+    struct TestKey(&'static [u8]);
+
+    impl TreeHeadSigner for TestKey {
+        fn key_id(&self) -> &[u8] {
+            self.0
+        }
+
+        fn sign(&self, message: &[u8]) -> Vec<u8> {
+            Sha256Hasher.hash(&[self.0, message].concat())
+        }
+    }
+
+    impl TreeHeadVerifier for TestKey {
+        fn verify(&self, key_id: &[u8], message: &[u8], signature: &[u8]) -> bool {
+            key_id == self.0 && self.sign(message) == signature
+        }
+    }
+
+    #[test]
+    fn test_log_heads_and_proofs() {
+        let mut log = TransparencyLog::new(TestKey(b"key"), 4);
+        for i in 0..10u8 {
+            assert_eq!(log.append(vec![i]), i as usize);
+        }
+        let sizes: Vec<usize> = log.heads().iter().map(|head| head.tree_size).collect();
+        assert_eq!(sizes, vec![4, 8]);
+        let head = log.sign_head().clone();
+        assert_eq!(head.tree_size, 10);
+        assert!(head.verify(&TestKey(b"key")));
+        assert!(!head.verify(&TestKey(b"other")));
+
+        // A client holding the first head checks the latest one extends it
+        let old = &log.heads()[0];
+        let consistency = log.prove_consistency(old.tree_size, head.tree_size).unwrap();
+        assert!(consistency.verify(&old.root, &head.root));
+
+        for index in 0..4 {
+            let proof = log.prove_inclusion(index, old.tree_size).unwrap();
+            assert!(MerkleTree::verify_proof(log.entry(index).unwrap(), &proof, &old.root));
+        }
+        assert!(log.prove_inclusion(4, 4).is_none());
+        assert!(log.prove_consistency(8, 11).is_none());
+    }
+
+    #[test]
+    fn test_head_verify_and_clock() {
+        let mut log = TransparencyLog::new(TestKey(b"key-1"), 0).with_clock(|| 1_700_000_000_000);
+        log.append(vec![1]);
+        let head = log.sign_head().clone();
+        assert_eq!(head.timestamp, 1_700_000_000_000);
+        assert_eq!(head.key_id, b"key-1");
+        assert!(head.verify(&TestKey(b"key-1")));
+
+        // The key id and timestamp are both signed
+        let mut relabeled = head.clone();
+        relabeled.key_id = b"key-2".to_vec();
+        assert!(!relabeled.verify(&|_: &[u8], message: &[u8], signature: &[u8]| TestKey(b"key-1").sign(message) == signature));
+        let mut backdated = head;
+        backdated.timestamp -= 1;
+        assert!(!backdated.verify(&TestKey(b"key-1")));
+    }
+
+    #[test]
+    fn test_key_rotation() {
+        static NOW: AtomicU64 = AtomicU64::new(1_000);
+        let mut log = TransparencyLog::new(TestKey(b"key-1"), 0).with_clock(|| NOW.load(Ordering::Relaxed));
+        log.append(vec![1]);
+        let old_head = log.sign_head().clone();
+        NOW.store(2_000, Ordering::Relaxed);
+        let rotation = log.rotate_signer(TestKey(b"key-2")).clone();
+        NOW.store(3_000, Ordering::Relaxed);
+        log.append(vec![2]);
+        let new_head = log.sign_head().clone();
+        assert_eq!(new_head.key_id, b"key-2");
+
+        // A verifier trusting only the first key follows the rotation
+        let mut history = KeyHistory::new(b"key-1", TestKey(b"key-1"));
+        assert!(old_head.verify(&history));
+        assert!(!new_head.verify(&history));
+        assert_eq!(history.rotate(&rotation, TestKey(b"key-2")), Ok(()));
+        assert_eq!(history.current_key_id(), b"key-2");
+        assert!(old_head.verify(&history));
+        assert!(new_head.verify(&history));
+
+        // The old key can't sign heads after it was rotated out
+        let mut late = TransparencyLog::new(TestKey(b"key-1"), 0).with_clock(|| 5_000);
+        late.append(vec![1]);
+        assert!(!late.sign_head().verify(&history));
+
+        // Rotations must be signed by the outgoing key, and move forward
+        let mut history = KeyHistory::new(b"key-1", TestKey(b"key-1"));
+        let mut forged = rotation.clone();
+        forged.key_id = b"key-3".to_vec();
+        assert_eq!(history.rotate(&forged, TestKey(b"key-3")), Err(RotationError::BadSignature));
+        history.rotate(&rotation, TestKey(b"key-2")).unwrap();
+        let mut earlier = rotation;
+        earlier.valid_from = 1_500;
+        assert_eq!(history.rotate(&earlier, TestKey(b"key-2")), Err(RotationError::OutOfOrder));
+    }
+}
@@ -0,0 +1,178 @@
+// Distinct types for leaf data and node hashes. `Data` and `Hash` are both
+// byte vectors, so a root passed where data is expected compiles and then
+// just fails to verify. `Leaf` is Hashable and `NodeHash` isn't, and neither
+// turns into the other without going through bytes, so the typed calls
+// below only take each in its own place.
+//
+// `Data` and `Hash` stay byte vectors everywhere else: proofs, storage and
+// every encoding are built on them, and changing them would break every
+// caller at once. Instead `verify_leaf` is the typed way in, taking the
+// root as a `NodeHash` and the data as anything Hashable, which a
+// `NodeHash` is not. The untyped `verify_proof` and `verify_proof_with`
+// are deprecated in its favour.
+
+use crate::display::HexHash;
+use crate::{Data, Hash, Hashable, Hasher, MerkleTree, Proof, Sha256Hasher};
+use std::borrow::Cow;
+use std::fmt;
+
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Leaf(Data);
+
+
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeHash(Hash);
+
+
+impl Leaf {
+    pub fn new(bytes: impl Into<Data>) -> Leaf {
+        Leaf(bytes.into())
+    }
+
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+
+    pub fn into_bytes(self) -> Data {
+        self.0
+    }
+}
+
+
+impl From<Data> for Leaf {
+    fn from(bytes: Data) -> Leaf {
+        Leaf(bytes)
+    }
+}
+
+
+impl From<&[u8]> for Leaf {
+    fn from(bytes: &[u8]) -> Leaf {
+        Leaf(bytes.to_vec())
+    }
+}
+
+
+impl From<&str> for Leaf {
+    fn from(text: &str) -> Leaf {
+        Leaf(text.as_bytes().to_vec())
+    }
+}
+
+
+impl Hashable for Leaf {
+    fn leaf_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(&self.0)
+    }
+}
+
+
+impl NodeHash {
+    // Takes bytes as a hash, such as a root read back from storage
+    pub fn from_bytes(bytes: Hash) -> NodeHash {
+        NodeHash(bytes)
+    }
+
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+
+    pub fn into_bytes(self) -> Hash {
+        self.0
+    }
+}
+
+
+impl AsRef<[u8]> for NodeHash {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+
+impl fmt::Display for NodeHash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&HexHash(&self.0), f)
+    }
+}
+
+
+impl fmt::Debug for NodeHash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "NodeHash(")?;
+        fmt::Display::fmt(&HexHash(&self.0), f)?;
+        write!(f, ")")
+    }
+}
+
+
+impl MerkleTree {
+    pub fn verify_leaf<T: Hashable + ?Sized>(leaf: &T, proof: &Proof, root: &NodeHash) -> bool {
+        Self::verify_leaf_with(leaf, proof, root, &Sha256Hasher)
+    }
+}
+
+
+impl<H: Hasher> MerkleTree<H> {
+    pub fn root_hash(&self) -> NodeHash {
+        NodeHash(self.root())
+    }
+
+
+    pub fn leaf_node_hash(&self, index: usize) -> Option<NodeHash> {
+        self.leaf_hash(index).cloned().map(NodeHash)
+    }
+
+
+    pub fn prove_leaf(&self, leaf: &Leaf) -> Option<Proof<'_>> {
+        self.prove(leaf)
+    }
+
+
+    pub fn verify_leaf_with<T: Hashable + ?Sized>(leaf: &T, proof: &Proof, root: &NodeHash, hasher: &H) -> bool {
+        Self::verify_proof_with(leaf, proof, &root.0, hasher)
+    }
+}
+
+
+impl Proof<'_> {
+    pub fn compute_leaf_root(&self, leaf: &Leaf) -> NodeHash {
+        NodeHash(self.compute_root(leaf))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    #[test]
+    fn test_typed_proofs() {
+        let leaves: Vec<Leaf> = ["a", "b", "c", "d", "e"].into_iter().map(Leaf::from).collect();
+        let tree = MerkleTree::construct(&leaves);
+        let root = tree.root_hash();
+        assert_eq!(root.as_bytes(), &tree.root()[..]);
+        // A leaf hashes the same as its bytes
+        assert_eq!(root, MerkleTree::construct(&[b"a", b"b", b"c", b"d", b"e"]).root_hash());
+        for leaf in &leaves {
+            let proof = tree.prove_leaf(leaf).unwrap();
+            assert!(MerkleTree::verify_leaf(leaf, &proof, &root));
+            assert_eq!(proof.compute_leaf_root(leaf), root);
+        }
+        // The leaf that happens to hold the root's bytes isn't in the tree
+        let lookalike = Leaf::new(root.clone().into_bytes());
+        assert!(tree.prove_leaf(&lookalike).is_none());
+        assert_eq!(tree.leaf_node_hash(0).unwrap().as_bytes(), &tree.leaf_hash(0).unwrap()[..]);
+
+        // Any Hashable data verifies against a typed root
+        let proof = tree.prove_leaf(&leaves[2]).unwrap();
+        assert!(MerkleTree::verify_leaf(b"c", &proof, &root));
+        assert!(MerkleTree::verify_leaf("c", &proof, &root));
+    }
+}
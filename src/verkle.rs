@@ -0,0 +1,231 @@
+// Experimental: a Verkle-style tree, where each parent commits to its group
+// of children with a vector commitment instead of hashing them. With a
+// scheme such as KZG (the `kzg` feature) a step of a proof is one
+// commitment and one constant-size opening however wide the groups are, so
+// wide, shallow trees get short proofs. `HashCommitment` is the plain-hash
+// baseline: the same shape, but each opening carries the whole group.
+//
+// Leaves are hashed with `hash_leaf`, then grouped `width` at a time. Each
+// group's commitment, turned back into a hash by `to_hash`, is a child of
+// the level above, up to a single commitment whose hash is the root. Every
+// group is committed, including a lone last one, so a proof has exactly one
+// step per level.
+
+use crate::ct::hashes_equal;
+use crate::{Hash, Hashable, Hasher, Sha256Hasher};
+use std::fmt::Debug;
+
+
+pub trait VectorCommitment {
+    type Commitment: Clone + Debug + PartialEq;
+    type Opening: Clone + Debug + PartialEq;
+
+    // Most values one commitment can hold
+    fn width(&self) -> usize;
+
+    // Commits to up to `width` values, in order
+    fn commit(&self, values: &[Hash]) -> Self::Commitment;
+
+    // Shows `values[index]` is what `commit(values)` holds at `index`
+    fn open(&self, values: &[Hash], index: usize) -> Self::Opening;
+
+    fn verify(&self, commitment: &Self::Commitment, index: usize, value: &Hash, opening: &Self::Opening) -> bool;
+
+    // The commitment as a value in its parent's group
+    fn to_hash(&self, commitment: &Self::Commitment) -> Hash;
+}
+
+
+// Commits by hashing the whole group, and opens by revealing it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HashCommitment {
+    pub width: usize,
+}
+
+
+impl VectorCommitment for HashCommitment {
+    type Commitment = Hash;
+    type Opening = Vec<Hash>;
+
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    fn commit(&self, values: &[Hash]) -> Hash {
+        Sha256Hasher.hash_children(values)
+    }
+
+    fn open(&self, values: &[Hash], _index: usize) -> Vec<Hash> {
+        values.to_vec()
+    }
+
+    fn verify(&self, commitment: &Hash, index: usize, value: &Hash, opening: &Vec<Hash>) -> bool {
+        opening.len() <= self.width
+            && opening.get(index).is_some_and(|opened| hashes_equal(opened, value))
+            && hashes_equal(&self.commit(opening), commitment)
+    }
+
+    fn to_hash(&self, commitment: &Hash) -> Hash {
+        commitment.clone()
+    }
+}
+
+
+// One level of a proof: the group's commitment, where the value sits in the
+// group, and the opening there
+#[derive(Debug, Clone, PartialEq)]
+pub struct VerkleStep<M, O> {
+    pub commitment: M,
+    pub index: usize,
+    pub opening: O,
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VerkleProof<M, O> {
+    pub steps: Vec<VerkleStep<M, O>>,
+}
+
+
+impl<M, O> VerkleProof<M, O> {
+    pub fn verify<C, T>(&self, scheme: &C, data: &T, root: &Hash) -> bool
+    where
+        C: VectorCommitment<Commitment = M, Opening = O>,
+        T: Hashable + ?Sized,
+    {
+        self.verify_with(scheme, data, root, &Sha256Hasher)
+    }
+
+
+    // Checks each opening in turn, carrying the step's commitment up as the
+    // next step's value
+    pub fn verify_with<C, T, H>(&self, scheme: &C, data: &T, root: &Hash, hasher: &H) -> bool
+    where
+        C: VectorCommitment<Commitment = M, Opening = O>,
+        T: Hashable + ?Sized,
+        H: Hasher,
+    {
+        let mut value = hasher.hash_leaf(&data.leaf_bytes());
+        for step in &self.steps {
+            if !scheme.verify(&step.commitment, step.index, &value, &step.opening) {
+                return false;
+            }
+            value = scheme.to_hash(&step.commitment);
+        }
+        !self.steps.is_empty() && hashes_equal(&value, root)
+    }
+}
+
+
+pub struct VerkleTree<C: VectorCommitment, H = Sha256Hasher> {
+    scheme: C,
+    // Values at each level, leaf hashes first; each level above holds the
+    // hashes of the commitments to the groups below
+    values: Vec<Vec<Hash>>,
+    commitments: Vec<Vec<C::Commitment>>,
+    hasher: H,
+}
+
+
+impl<C: VectorCommitment> VerkleTree<C> {
+    pub fn construct<T: Hashable>(input: &[T], scheme: C) -> VerkleTree<C> {
+        Self::construct_with(input, scheme, Sha256Hasher)
+    }
+}
+
+
+impl<C: VectorCommitment, H: Hasher> VerkleTree<C, H> {
+    pub fn construct_with<T: Hashable>(input: &[T], scheme: C, hasher: H) -> VerkleTree<C, H> {
+        assert!(!input.is_empty(), "a verkle tree needs at least one leaf");
+        assert!(scheme.width() >= 2, "groups must hold at least two values");
+        let leaves: Vec<Hash> = input.iter().map(|leaf| hasher.hash_leaf(&leaf.leaf_bytes())).collect();
+        let mut values = vec![leaves];
+        let mut commitments = Vec::new();
+        loop {
+            let level: Vec<C::Commitment> = values.last().unwrap().chunks(scheme.width()).map(|group| scheme.commit(group)).collect();
+            let above: Vec<Hash> = level.iter().map(|commitment| scheme.to_hash(commitment)).collect();
+            commitments.push(level);
+            values.push(above);
+            if values.last().unwrap().len() == 1 {
+                break;
+            }
+        }
+        VerkleTree { scheme, values, commitments, hasher }
+    }
+
+
+    pub fn root(&self) -> Hash {
+        self.values.last().unwrap()[0].clone()
+    }
+
+
+    pub fn scheme(&self) -> &C {
+        &self.scheme
+    }
+
+
+    pub fn hasher(&self) -> &H {
+        &self.hasher
+    }
+
+
+    pub fn leaf_count(&self) -> usize {
+        self.values[0].len()
+    }
+
+
+    pub fn prove(&self, index: usize) -> Option<VerkleProof<C::Commitment, C::Opening>> {
+        if index >= self.leaf_count() {
+            return None;
+        }
+        let width = self.scheme.width();
+        let mut idx = index;
+        let mut steps = Vec::with_capacity(self.commitments.len());
+        for (level, commitments) in self.commitments.iter().enumerate() {
+            let group = idx / width;
+            let values = &self.values[level];
+            let members = &values[group * width..values.len().min((group + 1) * width)];
+            let opening = self.scheme.open(members, idx % width);
+            steps.push(VerkleStep { commitment: commitments[group].clone(), index: idx % width, opening });
+            idx = group;
+        }
+        Some(VerkleProof { steps })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Data;
+
+
+    #[test]
+    fn test_hash_commitment_proofs() {
+        for n in [1, 2, 5, 16, 17, 40] {
+            let data: Vec<Data> = (0..n as u8).map(|i| vec![i]).collect();
+            let tree = VerkleTree::construct(&data, HashCommitment { width: 4 });
+            let root = tree.root();
+            for (i, leaf) in data.iter().enumerate() {
+                let proof = tree.prove(i).unwrap();
+                assert_eq!(proof.steps.len(), tree.commitments.len());
+                assert!(proof.verify(tree.scheme(), leaf, &root));
+                assert!(!proof.verify(tree.scheme(), &vec![0xff], &root));
+            }
+            assert!(tree.prove(n).is_none());
+        }
+    }
+
+    #[test]
+    fn test_tampered_opening() {
+        let data: Vec<Data> = (0..9u8).map(|i| vec![i]).collect();
+        let tree = VerkleTree::construct(&data, HashCommitment { width: 3 });
+        let mut proof = tree.prove(4).unwrap();
+        proof.steps[0].opening[1] = vec![0; 32];
+        assert!(!proof.verify(tree.scheme(), &data[4], &tree.root()));
+        // Openings wider than the scheme allows are rejected
+        let wide = HashCommitment { width: 2 };
+        let opening = tree.prove(4).unwrap().steps[0].opening.clone();
+        assert!(!wide.verify(&wide.commit(&opening), 1, &opening[1], &opening));
+    }
+}
@@ -0,0 +1,225 @@
+// A persistent tree: every `update` or `push` makes a new version that shares
+// all untouched subtrees with the one before, so each change costs O(log n)
+// new nodes and every past root stays provable.
+//
+// Nodes split their leaves at the largest power of two below the count, which
+// is the same shape `MerkleTree` gets by promoting odd nodes.
+
+use crate::invariants::bounded_proof;
+use crate::{Data, Error, Hash, HashDirection, Hasher, Proof, Sha256Hasher};
+use std::borrow::Cow;
+use std::sync::Arc;
+
+
+enum Node {
+    Leaf(Hash),
+    Branch { hash: Hash, left: Arc<Node>, right: Arc<Node> },
+}
+
+
+impl Node {
+    fn hash(&self) -> &Hash {
+        match self {
+            Node::Leaf(hash) => hash,
+            Node::Branch { hash, .. } => hash,
+        }
+    }
+}
+
+
+#[derive(Clone)]
+struct Version {
+    root: Option<Arc<Node>>,
+    leaf_count: usize,
+}
+
+
+pub struct VersionedTree<H = Sha256Hasher> {
+    versions: Vec<Version>,
+    hasher: H,
+}
+
+
+impl VersionedTree {
+    pub fn construct(input: &[Data]) -> VersionedTree {
+        Self::construct_with(input, Sha256Hasher)
+    }
+}
+
+
+impl<H: Hasher> VersionedTree<H> {
+    // Builds version 0 from `input`
+    pub fn construct_with(input: &[Data], hasher: H) -> VersionedTree<H> {
+        let leaves: Vec<Arc<Node>> = input.iter().map(|data| Arc::new(Node::Leaf(hasher.hash_leaf(data)))).collect();
+        let root = (!leaves.is_empty()).then(|| build(&leaves, &hasher));
+        VersionedTree { versions: vec![Version { root, leaf_count: input.len() }], hasher }
+    }
+
+
+    // The latest version
+    pub fn version(&self) -> usize {
+        self.versions.len() - 1
+    }
+
+
+    pub fn root(&self) -> Option<Hash> {
+        self.root_at(self.version())
+    }
+
+
+    pub fn root_at(&self, version: usize) -> Option<Hash> {
+        Some(self.versions.get(version)?.root.as_ref()?.hash().clone())
+    }
+
+
+    pub fn leaf_count_at(&self, version: usize) -> Option<usize> {
+        self.versions.get(version).map(|v| v.leaf_count)
+    }
+
+
+    // Replaces the leaf at `index`, returning the new version
+    pub fn update(&mut self, index: usize, data: &Data) -> Result<usize, Error> {
+        let latest = self.versions.last().unwrap();
+        let leaf_count = latest.leaf_count;
+        if index >= leaf_count {
+            return Err(Error::IndexOutOfRange { index, leaf_count });
+        }
+        let leaf = Arc::new(Node::Leaf(self.hasher.hash_leaf(data)));
+        let root = replace(latest.root.as_ref().unwrap(), leaf_count, index, leaf, &self.hasher);
+        self.versions.push(Version { root: Some(root), leaf_count });
+        Ok(self.version())
+    }
+
+
+    // Appends a leaf, returning the new version
+    pub fn push(&mut self, data: &Data) -> usize {
+        let latest = self.versions.last().unwrap();
+        let leaf = Arc::new(Node::Leaf(self.hasher.hash_leaf(data)));
+        let root = match &latest.root {
+            Some(root) => append(root, latest.leaf_count, leaf, &self.hasher),
+            None => leaf,
+        };
+        let leaf_count = latest.leaf_count + 1;
+        self.versions.push(Version { root: Some(root), leaf_count });
+        self.version()
+    }
+
+
+    // Proves the leaf at `index` as it was in `version`
+    pub fn prove_at(&self, version: usize, index: usize) -> Option<Proof<'_>> {
+        let Version { root, leaf_count } = self.versions.get(version)?;
+        if index >= *leaf_count {
+            return None;
+        }
+        let (mut node, mut count, mut index) = (root.as_ref()?, *leaf_count, index);
+        let mut hashes = Vec::new();
+        while let Node::Branch { left, right, .. } = node.as_ref() {
+            let split = split_point(count);
+            if index < split {
+                hashes.push((HashDirection::Right, Cow::Borrowed(right.hash())));
+                (node, count) = (left, split);
+            } else {
+                hashes.push((HashDirection::Left, Cow::Borrowed(left.hash())));
+                (node, count, index) = (right, count - split, index - split);
+            }
+        }
+        // Collected root first, but proofs run from the leaf up
+        hashes.reverse();
+        Some(bounded_proof(hashes, *leaf_count))
+    }
+}
+
+
+// Largest power of two strictly below `count`, where the left subtree ends
+fn split_point(count: usize) -> usize {
+    1 << (usize::BITS - 1 - (count - 1).leading_zeros())
+}
+
+
+fn branch<H: Hasher>(left: Arc<Node>, right: Arc<Node>, hasher: &H) -> Arc<Node> {
+    let hash = hasher.hash_node(left.hash(), right.hash());
+    Arc::new(Node::Branch { hash, left, right })
+}
+
+
+fn build<H: Hasher>(leaves: &[Arc<Node>], hasher: &H) -> Arc<Node> {
+    if leaves.len() == 1 {
+        return leaves[0].clone();
+    }
+    let (left, right) = leaves.split_at(split_point(leaves.len()));
+    branch(build(left, hasher), build(right, hasher), hasher)
+}
+
+
+fn replace<H: Hasher>(node: &Arc<Node>, count: usize, index: usize, leaf: Arc<Node>, hasher: &H) -> Arc<Node> {
+    match node.as_ref() {
+        Node::Leaf(_) => leaf,
+        Node::Branch { left, right, .. } => {
+            let split = split_point(count);
+            if index < split {
+                branch(replace(left, split, index, leaf, hasher), right.clone(), hasher)
+            } else {
+                branch(left.clone(), replace(right, count - split, index - split, leaf, hasher), hasher)
+            }
+        }
+    }
+}
+
+
+// A full subtree gets the leaf as a new right sibling; otherwise the leaf
+// goes into the right subtree, which is the one still filling up
+fn append<H: Hasher>(node: &Arc<Node>, count: usize, leaf: Arc<Node>, hasher: &H) -> Arc<Node> {
+    match node.as_ref() {
+        Node::Branch { left, right, .. } if !count.is_power_of_two() => {
+            let split = split_point(count);
+            branch(left.clone(), append(right, count - split, leaf, hasher), hasher)
+        }
+        _ => branch(node.clone(), leaf, hasher),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MerkleTree;
+
+
+    #[test]
+    fn test_versions_match_fresh_trees() {
+        let mut data: Vec<Data> = vec![vec![0]];
+        let mut tree = VersionedTree::construct(&data);
+        let mut roots = vec![MerkleTree::construct(&data).root()];
+        for i in 1..12u8 {
+            data.push(vec![i]);
+            tree.push(&data[i as usize]);
+            roots.push(MerkleTree::construct(&data).root());
+            data[(i / 2) as usize] = vec![100 + i];
+            tree.update((i / 2) as usize, &data[(i / 2) as usize]).unwrap();
+            roots.push(MerkleTree::construct(&data).root());
+        }
+
+        for (version, root) in roots.iter().enumerate() {
+            assert_eq!(tree.root_at(version).as_ref(), Some(root));
+        }
+        assert_eq!(tree.update(12, &vec![0]), Err(Error::IndexOutOfRange { index: 12, leaf_count: 12 }));
+    }
+
+    #[test]
+    fn test_prove_at_past_version() {
+        let data: Vec<Data> = (0..7u8).map(|i| vec![i]).collect();
+        let mut tree = VersionedTree::construct(&data);
+        tree.update(3, &vec![99]).unwrap();
+        tree.push(&vec![7]);
+
+        let old_root = tree.root_at(0).unwrap();
+        for (i, leaf) in data.iter().enumerate() {
+            let proof = tree.prove_at(0, i).unwrap();
+            assert_eq!(proof.hashes, MerkleTree::construct(&data).prove(leaf).unwrap().hashes);
+            assert!(MerkleTree::verify_proof(leaf, &proof, &old_root));
+        }
+        assert!(MerkleTree::verify_proof(&vec![7], &tree.prove_at(2, 7).unwrap(), &tree.root().unwrap()));
+        assert!(tree.prove_at(0, 7).is_none());
+        assert!(tree.prove_at(3, 0).is_none());
+    }
+}
@@ -0,0 +1,43 @@
+// JavaScript bindings for verifying server-issued proofs in the browser.
+// Proofs are passed as a Uint8Array holding the compact encoding from
+// `Proof::encode`; data and roots are plain byte arrays.
+
+use crate::{MerkleTree, Proof};
+use wasm_bindgen::prelude::wasm_bindgen;
+
+
+// Returns false for a malformed proof as well as for a wrong one
+#[wasm_bindgen(js_name = verifyProof)]
+pub fn verify_proof(data: &[u8], proof: &[u8], root: &[u8]) -> bool {
+    match Proof::decode(proof) {
+        Some(proof) => MerkleTree::verify_proof(&data.to_vec(), &proof, &root.to_vec()),
+        None => false,
+    }
+}
+
+
+// Returns undefined for a malformed proof
+#[wasm_bindgen(js_name = computeRoot)]
+pub fn compute_root(data: &[u8], proof: &[u8]) -> Option<Vec<u8>> {
+    Proof::decode(proof).map(|proof| proof.compute_root(&data.to_vec()))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Data;
+
+
+    #[test]
+    fn test_wasm_exports() {
+        let data: Vec<Data> = (0..7u8).map(|i| vec![i]).collect();
+        let tree = MerkleTree::construct(&data);
+        let proof = tree.prove(&data[5]).unwrap().encode();
+        assert!(verify_proof(&data[5], &proof, &tree.root()));
+        assert!(!verify_proof(&data[4], &proof, &tree.root()));
+        assert!(!verify_proof(&data[5], &proof[1..], &tree.root()));
+        assert_eq!(compute_root(&data[5], &proof), Some(tree.root()));
+        assert_eq!(compute_root(&data[5], &[]), None);
+    }
+}
@@ -0,0 +1,122 @@
+// A `Write` sink that computes a tree root over everything written to it.
+// Bytes are split into fixed-size chunks, each chunk is a leaf, and leaves
+// are folded in as soon as they fill, keeping one pending node per height,
+// so memory stays at one chunk plus O(log n) hashes however much is written.
+// The root is the same as constructing a tree over the chunks, and with the
+// default chunk size, the same as `stream::encode` gives.
+
+use crate::{Hash, Hasher, Sha256Hasher};
+use std::io::{self, Write};
+
+
+pub struct MerkleWriter<H = Sha256Hasher> {
+    chunk_size: usize,
+    buffer: Vec<u8>,
+    // Complete subtrees not yet paired up, tallest at the bottom: (height, root)
+    stack: Vec<(u32, Hash)>,
+    leaf_count: u64,
+    hasher: H,
+}
+
+
+impl MerkleWriter {
+    pub fn new(chunk_size: usize) -> MerkleWriter {
+        Self::with_hasher(chunk_size, Sha256Hasher)
+    }
+}
+
+
+impl<H: Hasher> MerkleWriter<H> {
+    pub fn with_hasher(chunk_size: usize, hasher: H) -> MerkleWriter<H> {
+        assert!(chunk_size > 0, "chunk size must be positive");
+        MerkleWriter { chunk_size, buffer: Vec::with_capacity(chunk_size), stack: Vec::new(), leaf_count: 0, hasher }
+    }
+
+
+    // Chunks hashed so far, not counting a partly filled one
+    pub fn leaf_count(&self) -> u64 {
+        self.leaf_count
+    }
+
+
+    // Hashes the last, possibly short, chunk and returns the root. Writing
+    // nothing at all gives the root of a single empty chunk.
+    pub fn finalize(mut self) -> Hash {
+        if !self.buffer.is_empty() || self.leaf_count == 0 {
+            let chunk = std::mem::take(&mut self.buffer);
+            self.push_leaf(&chunk);
+        }
+        let (_, mut root) = self.stack.pop().unwrap();
+        while let Some((_, left)) = self.stack.pop() {
+            root = self.hasher.hash_node(&left, &root);
+        }
+        root
+    }
+
+
+    fn push_leaf(&mut self, chunk: &[u8]) {
+        let mut node = (0, self.hasher.hash_leaf(chunk));
+        while let Some((height, left)) = self.stack.pop_if(|(height, _)| *height == node.0) {
+            node = (height + 1, self.hasher.hash_node(&left, &node.1));
+        }
+        self.stack.push(node);
+        self.leaf_count += 1;
+    }
+}
+
+
+impl<H: Hasher> Write for MerkleWriter<H> {
+    fn write(&mut self, mut buf: &[u8]) -> io::Result<usize> {
+        let written = buf.len();
+        // Top up a partial chunk first, then hash whole chunks straight from `buf`
+        if !self.buffer.is_empty() {
+            let take = buf.len().min(self.chunk_size - self.buffer.len());
+            self.buffer.extend_from_slice(&buf[..take]);
+            buf = &buf[take..];
+            if self.buffer.len() < self.chunk_size {
+                return Ok(written);
+            }
+            let chunk = std::mem::take(&mut self.buffer);
+            self.push_leaf(&chunk);
+        }
+        // Hold back the last chunk even when full, since it may be the final one
+        while buf.len() > self.chunk_size {
+            let (chunk, rest) = buf.split_at(self.chunk_size);
+            self.push_leaf(chunk);
+            buf = rest;
+        }
+        self.buffer.extend_from_slice(buf);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{stream, Data, MerkleTree};
+
+
+    #[test]
+    fn test_writer_matches_tree() {
+        for len in [0, 1, 63, 64, 65, 640, 1000] {
+            let file: Vec<u8> = (0..len).map(|i| (i % 256) as u8).collect();
+            let mut writer = MerkleWriter::new(64);
+            // Odd-sized writes straddle chunk boundaries
+            for piece in file.chunks(37) {
+                writer.write_all(piece).unwrap();
+            }
+            let chunks: Vec<Data> = if file.is_empty() { vec![vec![]] } else { file.chunks(64).map(<[u8]>::to_vec).collect() };
+            assert_eq!(writer.finalize(), MerkleTree::construct(&chunks).root());
+        }
+
+        let file = vec![7u8; 5000];
+        let mut writer = MerkleWriter::new(stream::CHUNK_SIZE);
+        io::copy(&mut &file[..], &mut writer).unwrap();
+        assert_eq!(writer.finalize(), stream::encode(&file).0);
+    }
+}
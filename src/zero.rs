@@ -0,0 +1,87 @@
+// Roots of empty subtrees, one per height, for trees padded to a power of
+// two. A depth-32 tree has 2^32 leaf slots but only 33 distinct empty
+// subtree roots, so padding costs a table lookup instead of hashing zeros.
+// Tables are reference counted: compute one per hasher and depth, then hand
+// clones to every tree that pads the same way.
+
+use crate::{Hash, Hasher};
+use std::ops::Index;
+use std::sync::Arc;
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ZeroHashes {
+    // Entry h is the root of 2^h empty leaves
+    hashes: Arc<[Hash]>,
+}
+
+
+impl ZeroHashes {
+    // SSZ style: the empty leaf is a 32-byte zero chunk and nodes are
+    // hash(left | right) with no prefix
+    pub fn ssz<H: Hasher>(depth: usize, hasher: &H) -> ZeroHashes {
+        Self::chain(vec![0; 32], depth, |child| hasher.hash_concat(child, child))
+    }
+
+
+    // This crate's own hashing: the empty leaf is `hash_leaf` of no bytes
+    // and nodes use `hash_node`
+    pub fn padded<H: Hasher>(depth: usize, hasher: &H) -> ZeroHashes {
+        Self::chain(hasher.hash_leaf(&[]), depth, |child| hasher.hash_node(child, child))
+    }
+
+
+    fn chain(empty_leaf: Hash, depth: usize, parent: impl Fn(&Hash) -> Hash) -> ZeroHashes {
+        let mut hashes = vec![empty_leaf];
+        for height in 0..depth {
+            hashes.push(parent(&hashes[height]));
+        }
+        ZeroHashes { hashes: hashes.into() }
+    }
+
+
+    // The tallest empty subtree the table covers
+    pub fn depth(&self) -> usize {
+        self.hashes.len() - 1
+    }
+
+
+    pub fn get(&self, height: usize) -> Option<&Hash> {
+        self.hashes.get(height)
+    }
+
+
+    pub fn as_slice(&self) -> &[Hash] {
+        &self.hashes
+    }
+}
+
+
+impl Index<usize> for ZeroHashes {
+    type Output = Hash;
+
+    fn index(&self, height: usize) -> &Hash {
+        &self.hashes[height]
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Sha256Hasher;
+
+
+    #[test]
+    fn test_zero_hashes() {
+        let zeros = ZeroHashes::ssz(2, &Sha256Hasher);
+        assert_eq!(zeros.depth(), 2);
+        assert_eq!(hex::encode(&zeros[1]), "f5a5fd42d16a20302798ef6ed309979b43003d2320d9f0e8ea9831a92759fb4b");
+        assert_eq!(hex::encode(&zeros[2]), "db56114e00fdd4c1f85c892bf35ac9a89289aaecb1ebd0a96cde606a748b5d71");
+        assert!(zeros.get(3).is_none());
+
+        let padded = ZeroHashes::padded(3, &Sha256Hasher);
+        let empty: Vec<&[u8]> = vec![&[]; 8];
+        assert_eq!(padded[3], crate::MerkleTree::construct(&empty).root());
+    }
+}